@@ -0,0 +1,10 @@
+//! CSS3 Parser
+//! 
+//! This will become a spec compliant CSS3 parser. For now it only provides the selector parser and
+//! matching, which the HTML parser uses for `querySelector`.
+
+// Selectors Level 4
+// https://www.w3.org/TR/selectors-4/
+
+pub mod selector;
+mod tests;
@@ -0,0 +1,215 @@
+// https://www.w3.org/TR/selectors-4/#match-against-element
+use super::{AttributeOperator, AttributeSelector, Combinator, ComplexSelector, CompoundSelector, PseudoClass, SelectorList, SimpleSelector};
+
+/// What the selector engine needs to know about an element. Implemented by whatever the selectors are
+/// matched against, a handle to an element of a DOM for example. Only elements take part in matching,
+/// so every relative is the closest *element* in that direction.
+pub trait Element : Sized + Clone + PartialEq {
+    fn parent_element(&self) -> Option<Self>;
+    fn previous_sibling_element(&self) -> Option<Self>;
+    fn next_sibling_element(&self) -> Option<Self>;
+    fn first_child_element(&self) -> Option<Self>;
+    /// Whether this is the root of the document, the `<html>` element in HTML.
+    fn is_root(&self) -> bool;
+    /// Whether the element has no children, apart from comments.
+    fn is_empty(&self) -> bool;
+    /// The lower case name of an HTML element.
+    fn local_name(&self) -> &str;
+    fn id(&self) -> Option<&str>;
+    fn has_class(&self, name : &str) -> bool;
+    /// Attribute names are passed in lower case.
+    fn attribute(&self, name : &str) -> Option<&str>;
+}
+
+/// Whether `element` matches any of the selectors. `scope` is what `:scope` stands for, usually the element
+/// a query was made on.
+pub fn matches<E : Element>(selectors : &SelectorList, element : &E, scope : Option<&E>) -> bool {
+    selectors.0.iter().any(|selector| matches_complex(selector, element, scope))
+}
+
+pub fn matches_complex<E : Element>(selector : &ComplexSelector, element : &E, scope : Option<&E>) -> bool {
+    match selector.compounds.len() {
+        0 => false,
+        len => matches_from(selector, len - 1, element, scope),
+    }
+}
+
+// Match the compounds up to and including `index` from right to left, the `index`th against `element`.
+fn matches_from<E : Element>(selector : &ComplexSelector, index : usize, element : &E, scope : Option<&E>) -> bool {
+    if !matches_compound(&selector.compounds[index], element, scope) {
+        return false;
+    }
+    if index == 0 {
+        return true;
+    }
+    let next = |element : &E| matches_from(selector, index - 1, element, scope);
+    match selector.combinators[index - 1] {
+        Combinator::Child => element.parent_element().is_some_and(|parent| next(&parent)),
+        Combinator::NextSibling => element.previous_sibling_element().is_some_and(|sibling| next(&sibling)),
+        Combinator::Descendant => {
+            let mut current = element.parent_element();
+            while let Some(ancestor) = current {
+                if next(&ancestor) {
+                    return true;
+                }
+                current = ancestor.parent_element();
+            }
+            false
+        },
+        Combinator::SubsequentSibling => {
+            let mut current = element.previous_sibling_element();
+            while let Some(sibling) = current {
+                if next(&sibling) {
+                    return true;
+                }
+                current = sibling.previous_sibling_element();
+            }
+            false
+        },
+    }
+}
+
+fn matches_compound<E : Element>(compound : &CompoundSelector, element : &E, scope : Option<&E>) -> bool {
+    compound.0.iter().all(|simple| matches_simple(simple, element, scope))
+}
+
+fn matches_simple<E : Element>(simple : &SimpleSelector, element : &E, scope : Option<&E>) -> bool {
+    match simple {
+        SimpleSelector::Type(name) => element.local_name().eq_ignore_ascii_case(name),
+        SimpleSelector::Id(id) => element.id() == Some(id.as_str()),
+        SimpleSelector::Class(class) => element.has_class(class),
+        SimpleSelector::Attribute(attribute) => matches_attribute(attribute, element),
+        SimpleSelector::PseudoClass(pseudo) => matches_pseudo_class(pseudo, element, scope),
+    }
+}
+
+// https://www.w3.org/TR/selectors-4/#attribute-selectors
+fn matches_attribute<E : Element>(selector : &AttributeSelector, element : &E) -> bool {
+    let Some(value) = element.attribute(&selector.name) else {
+        return false;
+    };
+    let Some((operator, expected)) = &selector.operator else {
+        return true;
+    };
+    let (value, expected) = match selector.case_insensitive {
+        true => (value.to_lowercase(), expected.to_lowercase()),
+        false => (value.to_string(), expected.clone()),
+    };
+    match operator {
+        AttributeOperator::Equals => value == expected,
+        AttributeOperator::Includes => !expected.is_empty() && !expected.contains(char::is_whitespace) && value.split_ascii_whitespace().any(|word| word == expected),
+        AttributeOperator::DashMatch => value == expected || value.strip_prefix(expected.as_str()).is_some_and(|rest| rest.starts_with('-')),
+        // An empty string never matches for these three.
+        AttributeOperator::Prefix => !expected.is_empty() && value.starts_with(expected.as_str()),
+        AttributeOperator::Suffix => !expected.is_empty() && value.ends_with(expected.as_str()),
+        AttributeOperator::Substring => !expected.is_empty() && value.contains(expected.as_str()),
+    }
+}
+
+fn matches_pseudo_class<E : Element>(pseudo : &PseudoClass, element : &E, scope : Option<&E>) -> bool {
+    match pseudo {
+        PseudoClass::Not(list) => !matches(list, element, scope),
+        PseudoClass::Is(list) | PseudoClass::Where(list) => matches(list, element, scope),
+        PseudoClass::Has(list) => matches_has(list, element),
+        PseudoClass::Scope => match scope {
+            Some(scope) => scope == element,
+            None => element.is_root(),
+        },
+        PseudoClass::Root => element.is_root(),
+        PseudoClass::Empty => element.is_empty(),
+        PseudoClass::NthChild(nth, of) => {
+            let position = siblings_matching(element, E::previous_sibling_element, of.as_ref(), scope);
+            position.is_some_and(|position| nth.matches(position))
+        },
+        PseudoClass::NthLastChild(nth, of) => {
+            let position = siblings_matching(element, E::next_sibling_element, of.as_ref(), scope);
+            position.is_some_and(|position| nth.matches(position))
+        },
+        PseudoClass::NthOfType(nth) => nth.matches(siblings_of_type(element, E::previous_sibling_element)),
+        PseudoClass::NthLastOfType(nth) => nth.matches(siblings_of_type(element, E::next_sibling_element)),
+        PseudoClass::OnlyChild => element.previous_sibling_element().is_none() && element.next_sibling_element().is_none(),
+        PseudoClass::OnlyOfType => {
+            siblings_of_type(element, E::previous_sibling_element) == 1 && siblings_of_type(element, E::next_sibling_element) == 1
+        },
+    }
+}
+
+// The 1-based position of `element` counted in one direction among the siblings that match `of`, or `None`
+// if `element` doesn't match it itself.
+fn siblings_matching<E : Element>(element : &E, step : fn(&E) -> Option<E>, of : Option<&SelectorList>, scope : Option<&E>) -> Option<i32> {
+    let counts = |element : &E| of.is_none_or(|of| matches(of, element, scope));
+    if !counts(element) {
+        return None;
+    }
+    let mut position = 1;
+    let mut current = step(element);
+    while let Some(sibling) = current {
+        if counts(&sibling) {
+            position += 1;
+        }
+        current = step(&sibling);
+    }
+    Some(position)
+}
+
+fn siblings_of_type<E : Element>(element : &E, step : fn(&E) -> Option<E>) -> i32 {
+    let mut position = 1;
+    let mut current = step(element);
+    while let Some(sibling) = current {
+        if sibling.local_name() == element.local_name() {
+            position += 1;
+        }
+        current = step(&sibling);
+    }
+    position
+}
+
+// https://www.w3.org/TR/selectors-4/#relational
+// Every selector of a :has() starts with a :scope compound and a combinator, see the parser. The candidates
+// are the elements that combinator can reach, and everything below them for the rest of the selector.
+fn matches_has<E : Element>(list : &SelectorList, element : &E) -> bool {
+    list.0.iter().any(|selector| {
+        let candidates = match selector.combinators.first() {
+            Some(Combinator::Descendant | Combinator::Child) => Descendants::new(element.first_child_element(), Some(element.clone())),
+            Some(Combinator::NextSibling | Combinator::SubsequentSibling) => Descendants::new(element.next_sibling_element(), element.parent_element()),
+            None => return false,
+        };
+        candidates.into_iter().any(|candidate| matches_complex(selector, &candidate, Some(element)))
+    })
+}
+
+// Walks the elements from `start` onwards in document order, until it leaves the subtree of `root`.
+struct Descendants<E> {
+    next : Option<E>,
+    root : Option<E>,
+}
+
+impl<E : Element> Descendants<E> {
+    fn new(start : Option<E>, root : Option<E>) -> Self {
+        Descendants { next : start, root }
+    }
+}
+
+impl<E : Element> Iterator for Descendants<E> {
+    type Item = E;
+
+    fn next(&mut self) -> Option<E> {
+        let current = self.next.take()?;
+        self.next = match current.first_child_element() {
+            Some(child) => Some(child),
+            None => {
+                let mut node = Some(current.clone());
+                let mut next = None;
+                while let Some(candidate) = node {
+                    if let Some(sibling) = candidate.next_sibling_element() {
+                        next = Some(sibling);
+                        break;
+                    }
+                    node = candidate.parent_element().filter(|parent| Some(parent) != self.root.as_ref());
+                }
+                next
+            },
+        };
+        Some(current)
+    }
+}
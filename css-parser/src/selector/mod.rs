@@ -0,0 +1,169 @@
+//! Selectors
+//!
+//! Parsing and matching of [Selectors Level 4](https://www.w3.org/TR/selectors-4/). The parser turns
+//! text into a [`SelectorList`], and [`matches`] checks a selector against anything that implements the
+//! [`Element`] trait, so the same code serves the DOM's `querySelector` and the style system.
+//!
+//! Supported are type, universal, class, id and attribute selectors (with every attribute operator and
+//! the `i` / `s` flags), all four combinators, `:not()`, `:is()`, `:where()`, `:has()`, `:scope`, `:root`,
+//! `:empty` and the structural pseudo-classes from `:first-child` through `:nth-last-of-type()`, including
+//! the `of S` form of `:nth-child()`. Names are matched the way HTML documents do it : type selectors and
+//! attribute names without regard to case, everything else exactly.
+
+mod matching;
+mod parser;
+
+pub use matching::{matches, matches_complex, Element};
+pub use parser::{parse, SelectorParseError};
+
+/// A comma separated list of selectors, which matches if any of them does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorList(pub Vec<ComplexSelector>);
+
+/// Compound selectors joined by combinators, e.g. `div.card > a`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComplexSelector {
+    /// From left to right, there is always at least one.
+    pub compounds : Vec<CompoundSelector>,
+    /// `combinators[i]` joins `compounds[i]` and `compounds[i + 1]`.
+    pub combinators : Vec<Combinator>,
+}
+
+/// Simple selectors that all have to match the same element, e.g. `a.external[href]`. An empty compound
+/// matches every element, which is what `*` parses to.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CompoundSelector(pub Vec<SimpleSelector>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+    /// `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+    /// `a + b`
+    NextSibling,
+    /// `a ~ b`
+    SubsequentSibling,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimpleSelector {
+    /// A lower case element name.
+    Type(String),
+    Id(String),
+    Class(String),
+    Attribute(AttributeSelector),
+    PseudoClass(PseudoClass),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeSelector {
+    /// Lower case.
+    pub name : String,
+    /// `None` for `[name]`, which only checks that the attribute is there.
+    pub operator : Option<(AttributeOperator, String)>,
+    /// Set by the `i` flag.
+    pub case_insensitive : bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeOperator {
+    /// `=`
+    Equals,
+    /// `~=`, one of the whitespace separated words.
+    Includes,
+    /// `|=`, the value or the value followed by `-`.
+    DashMatch,
+    /// `^=`
+    Prefix,
+    /// `$=`
+    Suffix,
+    /// `*=`
+    Substring,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PseudoClass {
+    Not(SelectorList),
+    Is(SelectorList),
+    Where(SelectorList),
+    /// Every selector starts with a `:scope` compound that stands for the element `:has()` is checked on.
+    Has(SelectorList),
+    /// The element the selector is matched relative to, or the root when there is none.
+    Scope,
+    Root,
+    Empty,
+    /// `:nth-child()` and the pseudo-classes that are shorthands for it, like `:first-child`.
+    NthChild(Nth, Option<SelectorList>),
+    NthLastChild(Nth, Option<SelectorList>),
+    NthOfType(Nth),
+    NthLastOfType(Nth),
+    /// `:only-child`
+    OnlyChild,
+    /// `:only-of-type`
+    OnlyOfType,
+}
+
+// https://www.w3.org/TR/css-syntax-3/#anb-microsyntax
+/// The `An+B` argument of the `:nth-*()` pseudo-classes, which matches every position `A * n + B` for n >= 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nth {
+    pub a : i32,
+    pub b : i32,
+}
+
+impl Nth {
+    pub const FIRST : Nth = Nth { a : 0, b : 1 };
+
+    /// Whether the 1-based `position` is one of the matched ones.
+    pub fn matches(&self, position : i32) -> bool {
+        // In i64, so that no combination of A, B and the position can overflow.
+        let offset = i64::from(position) - i64::from(self.b);
+        match i64::from(self.a) {
+            0 => offset == 0,
+            a => offset % a == 0 && offset / a >= 0,
+        }
+    }
+}
+
+// https://www.w3.org/TR/selectors-4/#specificity-rules
+/// The number of id selectors, of class like selectors and of type selectors, compared in that order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Specificity(pub u32, pub u32, pub u32);
+
+impl std::ops::Add for Specificity {
+    type Output = Specificity;
+
+    fn add(self, other : Specificity) -> Specificity {
+        Specificity(self.0 + other.0, self.1 + other.1, self.2 + other.2)
+    }
+}
+
+impl SelectorList {
+    /// The specificity of the most specific selector, which is what `:is()`, `:not()` and `:has()` count as.
+    pub fn specificity(&self) -> Specificity {
+        self.0.iter().map(ComplexSelector::specificity).max().unwrap_or_default()
+    }
+}
+
+impl ComplexSelector {
+    pub fn specificity(&self) -> Specificity {
+        self.compounds.iter().flat_map(|compound| &compound.0).map(SimpleSelector::specificity).fold(Specificity::default(), |a, b| a + b)
+    }
+}
+
+impl SimpleSelector {
+    fn specificity(&self) -> Specificity {
+        match self {
+            SimpleSelector::Id(_) => Specificity(1, 0, 0),
+            SimpleSelector::Type(_) => Specificity(0, 0, 1),
+            SimpleSelector::Class(_) | SimpleSelector::Attribute(_) => Specificity(0, 1, 0),
+            SimpleSelector::PseudoClass(pseudo) => match pseudo {
+                PseudoClass::Where(_) | PseudoClass::Scope => Specificity::default(),
+                PseudoClass::Not(list) | PseudoClass::Is(list) | PseudoClass::Has(list) => list.specificity(),
+                PseudoClass::NthChild(_, Some(list)) | PseudoClass::NthLastChild(_, Some(list)) => Specificity(0, 1, 0) + list.specificity(),
+                _ => Specificity(0, 1, 0),
+            },
+        }
+    }
+}
@@ -0,0 +1,461 @@
+// https://www.w3.org/TR/selectors-4/#grammar
+use super::{AttributeOperator, AttributeSelector, Combinator, ComplexSelector, CompoundSelector, Nth, PseudoClass, SelectorList, SimpleSelector};
+
+// Selectors nested deeper than this inside :is() and friends are rejected, so that hostile input can't overflow the stack.
+const MAX_NESTING : usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorParseError {
+    /// The byte offset in the input where the parser gave up.
+    pub position : usize,
+    pub message : &'static str,
+}
+
+impl std::fmt::Display for SelectorParseError {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid selector at {} : {}", self.position, self.message)
+    }
+}
+
+impl std::error::Error for SelectorParseError {}
+
+/// Parse a comma separated list of selectors, e.g. `div.card > a[href^=http], p:nth-child(2n+1)`.
+pub fn parse(input : &str) -> Result<SelectorList, SelectorParseError> {
+    let mut parser = Parser { input, position : 0, depth : 0 };
+    let list = parser.selector_list(false)?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(list),
+        Some(_) => Err(parser.error("unexpected character")),
+    }
+}
+
+struct Parser<'a> {
+    input : &'a str,
+    position : usize,
+    depth : usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message : &'static str) -> SelectorParseError {
+        SelectorParseError { position : self.position, message }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.input[self.position..].chars().nth(1)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, c : char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += c.len_utf8();
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, c : char, message : &'static str) -> Result<(), SelectorParseError> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(self.error(message)),
+        }
+    }
+
+    // Skips whitespace and comments, returns whether there was any.
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.position;
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\n' | '\r' | '\x0C') => self.position += 1,
+                Some('/') if self.peek_second() == Some('*') => {
+                    match self.input[self.position + 2..].find("*/") {
+                        Some(end) => self.position += end + 4,
+                        None => self.position = self.input.len(),
+                    }
+                },
+                _ => return self.position != start,
+            }
+        }
+    }
+
+    // `relative` is set inside :has(), where every selector starts with a combinator (a descendant one if
+    // it's left out) that relates it to the element being checked.
+    fn selector_list(&mut self, relative : bool) -> Result<SelectorList, SelectorParseError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(self.error("selectors are nested too deeply"));
+        }
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_whitespace();
+            selectors.push(match relative {
+                true => self.relative_selector()?,
+                false => self.complex_selector()?,
+            });
+            self.skip_whitespace();
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.depth -= 1;
+        Ok(SelectorList(selectors))
+    }
+
+    fn relative_selector(&mut self) -> Result<ComplexSelector, SelectorParseError> {
+        let combinator = self.combinator_symbol().unwrap_or(Combinator::Descendant);
+        self.skip_whitespace();
+        let mut selector = self.complex_selector()?;
+        selector.compounds.insert(0, CompoundSelector(vec![SimpleSelector::PseudoClass(PseudoClass::Scope)]));
+        selector.combinators.insert(0, combinator);
+        Ok(selector)
+    }
+
+    fn combinator_symbol(&mut self) -> Option<Combinator> {
+        let combinator = match self.peek()? {
+            '>' => Combinator::Child,
+            '+' => Combinator::NextSibling,
+            '~' => Combinator::SubsequentSibling,
+            _ => return None,
+        };
+        self.position += 1;
+        Some(combinator)
+    }
+
+    fn complex_selector(&mut self) -> Result<ComplexSelector, SelectorParseError> {
+        let mut compounds = vec![self.compound_selector()?];
+        let mut combinators = Vec::new();
+        loop {
+            let start = self.position;
+            let whitespace = self.skip_whitespace();
+            let combinator = match self.combinator_symbol() {
+                Some(combinator) => {
+                    self.skip_whitespace();
+                    combinator
+                },
+                None if whitespace && self.starts_compound() => Combinator::Descendant,
+                None => {
+                    // Leave the whitespace for whoever comes next, like the end of a :not().
+                    self.position = start;
+                    break;
+                },
+            };
+            combinators.push(combinator);
+            compounds.push(self.compound_selector()?);
+        }
+        Ok(ComplexSelector { compounds, combinators })
+    }
+
+    fn starts_compound(&self) -> bool {
+        match self.peek() {
+            Some('*' | '#' | '.' | '[' | ':' | '\\') => true,
+            Some(c) => is_name_start(c) || c == '-',
+            None => false,
+        }
+    }
+
+    fn compound_selector(&mut self) -> Result<CompoundSelector, SelectorParseError> {
+        let mut simple = Vec::new();
+        let mut empty = true;
+        if self.eat('*') {
+            empty = false;
+        } else if self.starts_identifier() {
+            simple.push(SimpleSelector::Type(self.identifier()?.to_ascii_lowercase()));
+        }
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.position += 1;
+                    simple.push(SimpleSelector::Id(self.name()?));
+                },
+                Some('.') => {
+                    self.position += 1;
+                    simple.push(SimpleSelector::Class(self.identifier()?));
+                },
+                Some('[') => {
+                    self.position += 1;
+                    simple.push(SimpleSelector::Attribute(self.attribute_selector()?));
+                },
+                Some(':') => {
+                    self.position += 1;
+                    if self.peek() == Some(':') {
+                        return Err(self.error("pseudo-elements are not supported"));
+                    }
+                    simple.push(SimpleSelector::PseudoClass(self.pseudo_class()?));
+                },
+                _ => break,
+            }
+        }
+        if empty && simple.is_empty() {
+            return Err(self.error("expected a selector"));
+        }
+        Ok(CompoundSelector(simple))
+    }
+
+    fn attribute_selector(&mut self) -> Result<AttributeSelector, SelectorParseError> {
+        self.skip_whitespace();
+        let name = self.identifier()?.to_ascii_lowercase();
+        self.skip_whitespace();
+        let operator = match self.peek() {
+            Some(']') => None,
+            Some('=') => Some(AttributeOperator::Equals),
+            Some(c) => {
+                let operator = match c {
+                    '~' => AttributeOperator::Includes,
+                    '|' => AttributeOperator::DashMatch,
+                    '^' => AttributeOperator::Prefix,
+                    '$' => AttributeOperator::Suffix,
+                    '*' => AttributeOperator::Substring,
+                    _ => return Err(self.error("expected an attribute operator")),
+                };
+                self.position += 1;
+                if self.peek() != Some('=') {
+                    return Err(self.error("expected '='"));
+                }
+                Some(operator)
+            },
+            None => return Err(self.error("unterminated attribute selector")),
+        };
+        let mut selector = AttributeSelector { name, operator : None, case_insensitive : false };
+        if let Some(operator) = operator {
+            self.position += 1;
+            self.skip_whitespace();
+            let value = match self.peek() {
+                Some('"' | '\'') => self.string()?,
+                _ => self.identifier()?,
+            };
+            selector.operator = Some((operator, value));
+            self.skip_whitespace();
+            match self.peek() {
+                Some('i' | 'I') => {
+                    self.position += 1;
+                    selector.case_insensitive = true;
+                },
+                Some('s' | 'S') => self.position += 1,
+                _ => (),
+            }
+            self.skip_whitespace();
+        }
+        self.expect(']', "expected ']'")?;
+        Ok(selector)
+    }
+
+    fn pseudo_class(&mut self) -> Result<PseudoClass, SelectorParseError> {
+        let name = self.identifier()?.to_ascii_lowercase();
+        if !self.eat('(') {
+            return Ok(match name.as_str() {
+                "scope" => PseudoClass::Scope,
+                "root" => PseudoClass::Root,
+                "empty" => PseudoClass::Empty,
+                "first-child" => PseudoClass::NthChild(Nth::FIRST, None),
+                "last-child" => PseudoClass::NthLastChild(Nth::FIRST, None),
+                "only-child" => PseudoClass::OnlyChild,
+                "first-of-type" => PseudoClass::NthOfType(Nth::FIRST),
+                "last-of-type" => PseudoClass::NthLastOfType(Nth::FIRST),
+                "only-of-type" => PseudoClass::OnlyOfType,
+                _ => return Err(self.error("unknown pseudo-class")),
+            });
+        }
+        self.skip_whitespace();
+        let pseudo = match name.as_str() {
+            "not" => PseudoClass::Not(self.selector_list(false)?),
+            "is" | "matches" => PseudoClass::Is(self.selector_list(false)?),
+            "where" => PseudoClass::Where(self.selector_list(false)?),
+            "has" => PseudoClass::Has(self.selector_list(true)?),
+            "nth-child" => {
+                let nth = self.nth()?;
+                PseudoClass::NthChild(nth, self.of_selector()?)
+            },
+            "nth-last-child" => {
+                let nth = self.nth()?;
+                PseudoClass::NthLastChild(nth, self.of_selector()?)
+            },
+            "nth-of-type" => PseudoClass::NthOfType(self.nth()?),
+            "nth-last-of-type" => PseudoClass::NthLastOfType(self.nth()?),
+            _ => return Err(self.error("unknown pseudo-class")),
+        };
+        self.skip_whitespace();
+        self.expect(')', "expected ')'")?;
+        Ok(pseudo)
+    }
+
+    // The `of S` part of :nth-child(An+B of S).
+    fn of_selector(&mut self) -> Result<Option<SelectorList>, SelectorParseError> {
+        let start = self.position;
+        if self.skip_whitespace() && self.starts_identifier() {
+            let word = self.identifier()?;
+            if word.eq_ignore_ascii_case("of") && self.skip_whitespace() {
+                return self.selector_list(false).map(Some);
+            }
+        }
+        self.position = start;
+        Ok(None)
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#anb-microsyntax
+    fn nth(&mut self) -> Result<Nth, SelectorParseError> {
+        if self.starts_identifier() {
+            let start = self.position;
+            let word = self.identifier()?.to_ascii_lowercase();
+            match word.as_str() {
+                "odd" => return Ok(Nth { a : 2, b : 1 }),
+                "even" => return Ok(Nth { a : 2, b : 0 }),
+                _ => self.position = start,
+            }
+        }
+
+        let sign = match self.peek() {
+            Some('-') => {
+                self.position += 1;
+                -1
+            },
+            Some('+') => {
+                self.position += 1;
+                1
+            },
+            _ => 1,
+        };
+        let digits = self.integer();
+        if !self.eat('n') && !self.eat('N') {
+            // Just B.
+            return match digits {
+                Some(b) => Ok(Nth { a : 0, b : sign * b }),
+                None => Err(self.error("expected An+B")),
+            };
+        }
+        let a = sign * digits.unwrap_or(1);
+
+        let start = self.position;
+        self.skip_whitespace();
+        let sign = match self.peek() {
+            Some('+') => 1,
+            Some('-') => -1,
+            _ => {
+                self.position = start;
+                return Ok(Nth { a, b : 0 });
+            },
+        };
+        self.position += 1;
+        self.skip_whitespace();
+        match self.integer() {
+            Some(b) => Ok(Nth { a, b : sign * b }),
+            None => Err(self.error("expected a number")),
+        }
+    }
+
+    fn integer(&mut self) -> Option<i32> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        if start == self.position {
+            return None;
+        }
+        // Numbers that don't fit are clamped, any position in a document is far below that.
+        Some(self.input[start..self.position].parse::<i64>().map_or(i32::MAX, |n| n.min(i32::MAX as i64) as i32))
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#would-start-an-identifier
+    fn starts_identifier(&self) -> bool {
+        let mut chars = self.input[self.position..].chars();
+        match chars.next() {
+            Some('-') => match chars.next() {
+                Some('-') => true,
+                Some('\\') => chars.next().is_some_and(|c| c != '\n'),
+                Some(c) => is_name_start(c),
+                None => false,
+            },
+            Some('\\') => chars.next().is_some_and(|c| c != '\n'),
+            Some(c) => is_name_start(c),
+            None => false,
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, SelectorParseError> {
+        if !self.starts_identifier() {
+            return Err(self.error("expected an identifier"));
+        }
+        self.name()
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-name
+    /// An identifier, or any run of name characters after a `#`.
+    fn name(&mut self) -> Result<String, SelectorParseError> {
+        let mut name = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    self.position += 1;
+                    name.push(self.escape()?);
+                },
+                Some(c) if is_name(c) => {
+                    self.position += c.len_utf8();
+                    name.push(c);
+                },
+                _ => break,
+            }
+        }
+        match name.is_empty() {
+            true => Err(self.error("expected a name")),
+            false => Ok(name),
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-escaped-code-point
+    fn escape(&mut self) -> Result<char, SelectorParseError> {
+        let start = self.position;
+        while self.position - start < 6 && self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            self.position += 1;
+        }
+        if self.position == start {
+            return match self.next() {
+                Some('\n') | None => Err(self.error("invalid escape")),
+                Some(c) => Ok(c),
+            };
+        }
+        let code = u32::from_str_radix(&self.input[start..self.position], 16).unwrap_or(0);
+        // A single whitespace character after the hex digits is part of the escape.
+        if matches!(self.peek(), Some(' ' | '\t' | '\n')) {
+            self.position += 1;
+        }
+        Ok(match code {
+            0 => '\u{FFFD}',
+            code => char::from_u32(code).unwrap_or('\u{FFFD}'),
+        })
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-string-token
+    fn string(&mut self) -> Result<String, SelectorParseError> {
+        let quote = self.next();
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some(c) if Some(c) == quote => return Ok(value),
+                Some('\\') => match self.peek() {
+                    Some('\n') => self.position += 1,
+                    _ => value.push(self.escape()?),
+                },
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+}
+
+// https://www.w3.org/TR/css-syntax-3/#ident-start-code-point
+fn is_name_start(c : char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+// https://www.w3.org/TR/css-syntax-3/#ident-code-point
+fn is_name(c : char) -> bool {
+    is_name_start(c) || c.is_ascii_digit() || c == '-'
+}
@@ -0,0 +1,126 @@
+use crate::selector::{parse, AttributeOperator, AttributeSelector, Combinator, CompoundSelector, Nth, PseudoClass, SimpleSelector, Specificity};
+
+fn nth(selector: &str) -> Nth {
+    let list = parse(&format!(":nth-child({selector})")).unwrap();
+    match &list.0[0].compounds[0].0[0] {
+        SimpleSelector::PseudoClass(PseudoClass::NthChild(nth, None)) => *nth,
+        other => panic!("not an :nth-child() : {other:?}"),
+    }
+}
+
+#[test]
+fn selector_parse_compound_and_combinators() {
+    let list = parse("div.card > a[href^=http] , #main  p ~ *+ span").unwrap();
+    assert_eq!(list.0.len(), 2);
+
+    let first = &list.0[0];
+    assert_eq!(first.combinators, [Combinator::Child]);
+    assert_eq!(first.compounds[0].0, [SimpleSelector::Type("div".into()), SimpleSelector::Class("card".into())]);
+    assert_eq!(first.compounds[1].0, [
+        SimpleSelector::Type("a".into()),
+        SimpleSelector::Attribute(AttributeSelector {
+            name : "href".into(),
+            operator : Some((AttributeOperator::Prefix, "http".into())),
+            case_insensitive : false,
+        }),
+    ]);
+
+    let second = &list.0[1];
+    assert_eq!(second.combinators, [Combinator::Descendant, Combinator::SubsequentSibling, Combinator::NextSibling]);
+    assert_eq!(second.compounds[2], CompoundSelector::default());
+}
+
+#[test]
+fn selector_parse_attributes() {
+    let list = parse(r#"[DATA-X] [lang|="en"][title~='a b' i][class*=x s][id$=\31 0]"#).unwrap();
+    let attributes: Vec<&AttributeSelector> = list.0[0].compounds.iter()
+        .flat_map(|compound| &compound.0)
+        .map(|simple| match simple {
+            SimpleSelector::Attribute(attribute) => attribute,
+            other => panic!("not an attribute selector : {other:?}"),
+        })
+        .collect();
+    assert_eq!(attributes[0].name, "data-x");
+    assert_eq!(attributes[0].operator, None);
+    assert_eq!(attributes[1].operator, Some((AttributeOperator::DashMatch, "en".into())));
+    assert_eq!(attributes[2].operator, Some((AttributeOperator::Includes, "a b".into())));
+    assert!(attributes[2].case_insensitive);
+    assert_eq!(attributes[3].operator, Some((AttributeOperator::Substring, "x".into())));
+    assert!(!attributes[3].case_insensitive);
+    assert_eq!(attributes[4].operator, Some((AttributeOperator::Suffix, "10".into())));
+}
+
+#[test]
+fn selector_parse_nth() {
+    assert_eq!(nth("odd"), Nth { a : 2, b : 1 });
+    assert_eq!(nth("EVEN"), Nth { a : 2, b : 0 });
+    assert_eq!(nth("2n+1"), Nth { a : 2, b : 1 });
+    assert_eq!(nth(" -n + 3 "), Nth { a : -1, b : 3 });
+    assert_eq!(nth("+n"), Nth { a : 1, b : 0 });
+    assert_eq!(nth("3n - 2"), Nth { a : 3, b : -2 });
+    assert_eq!(nth("7"), Nth { a : 0, b : 7 });
+
+    let positions = |nth: Nth| (1..=10).filter(|&position| nth.matches(position)).collect::<Vec<_>>();
+    assert_eq!(positions(Nth { a : 2, b : 1 }), [1, 3, 5, 7, 9]);
+    assert_eq!(positions(Nth { a : -1, b : 3 }), [1, 2, 3]);
+    assert_eq!(positions(Nth { a : 3, b : -2 }), [1, 4, 7, 10]);
+    assert_eq!(positions(Nth::FIRST), [1]);
+
+    // Extreme values are clamped and matched without overflowing.
+    assert_eq!(nth("-2147483647"), Nth { a : 0, b : -2147483647 });
+    assert_eq!(nth("99999999999999999999999n-99999999999999999999999"), Nth { a : i32::MAX, b : -i32::MAX });
+    assert_eq!(nth("-2147483647n+2147483647"), Nth { a : -i32::MAX, b : i32::MAX });
+    for nth in [Nth { a : i32::MIN, b : i32::MAX }, Nth { a : i32::MAX, b : i32::MIN }, Nth { a : -1, b : i32::MIN }, Nth { a : 0, b : i32::MIN }] {
+        assert!(positions(nth).is_empty());
+        nth.matches(i32::MAX);
+        nth.matches(i32::MIN);
+    }
+    assert!(Nth { a : -i32::MAX, b : i32::MAX }.matches(i32::MAX));
+    assert!(Nth { a : i32::MAX, b : -i32::MAX }.matches(i32::MAX));
+}
+
+#[test]
+fn selector_parse_pseudo_classes() {
+    let list = parse("li:not(.a, .b):first-child:nth-last-of-type(2), :is(h1, h2):where(.x) , a:has(> img, + p)").unwrap();
+    let pseudo = |complex: usize, index: usize| match &list.0[complex].compounds[0].0[index] {
+        SimpleSelector::PseudoClass(pseudo) => pseudo.clone(),
+        other => panic!("not a pseudo-class : {other:?}"),
+    };
+    assert!(matches!(pseudo(0, 1), PseudoClass::Not(list) if list.0.len() == 2));
+    assert_eq!(pseudo(0, 2), PseudoClass::NthChild(Nth::FIRST, None));
+    assert_eq!(pseudo(0, 3), PseudoClass::NthLastOfType(Nth { a : 0, b : 2 }));
+    assert!(matches!(pseudo(1, 0), PseudoClass::Is(_)));
+
+    // Relative selectors are anchored at :scope.
+    let PseudoClass::Has(has) = pseudo(2, 1) else { panic!("not :has()") };
+    assert_eq!(has.0[0].combinators, [Combinator::Child]);
+    assert_eq!(has.0[0].compounds[0].0, [SimpleSelector::PseudoClass(PseudoClass::Scope)]);
+    assert_eq!(has.0[1].combinators, [Combinator::NextSibling]);
+}
+
+#[test]
+fn selector_specificity() {
+    let specificity = |selector: &str| parse(selector).unwrap().specificity();
+    assert_eq!(specificity("*"), Specificity(0, 0, 0));
+    assert_eq!(specificity("div.card > a[href]"), Specificity(0, 2, 2));
+    assert_eq!(specificity("#main li:first-child"), Specificity(1, 1, 1));
+    assert_eq!(specificity(":where(#a, .b) p"), Specificity(0, 0, 1));
+    assert_eq!(specificity(":is(#a, .b) p"), Specificity(1, 0, 1));
+    assert_eq!(specificity("li:nth-child(2 of .x)"), Specificity(0, 2, 1));
+    // A list counts as its most specific selector.
+    assert_eq!(specificity("p, #a, .b.c"), Specificity(1, 0, 0));
+}
+
+#[test]
+fn selector_parse_errors() {
+    for selector in ["", " ", "div >", "> div", "a,", ",a", "div..x", "[href", "[href=]", "[=x]", "a[href=x y]",
+        ":nth-child(2n+)", ":nth-child(n2)", ":unknown", "::before", ":not()", "a | b", "#", "'str'"] {
+        assert!(parse(selector).is_err(), "{selector:?} should be rejected");
+    }
+    let error = parse("div > > a").unwrap_err();
+    assert_eq!(error.position, 6);
+
+    let nested = format!("{}a{}", ":is(".repeat(100), ")".repeat(100));
+    assert!(parse(&nested).is_err());
+    assert!(parse(&format!("{}a{}", ":is(".repeat(10), ")".repeat(10))).is_ok());
+}
@@ -1,8 +1,9 @@
-mod tree;
-use tree::Tree;
+pub mod tree;
+mod tests;
 
 #[test]
 fn it_works() {
-    let tree : Tree<i32> = Tree::new();
-
-}
\ No newline at end of file
+    use tree::Tree;
+    let tree : Tree<i32> = Tree::new(0);
+    assert_eq!(tree.get(tree.root()), Some(&0));
+}
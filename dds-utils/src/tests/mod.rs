@@ -0,0 +1,12 @@
+#[cfg(test)]
+mod tree;
+#[cfg(test)]
+mod tree_iter;
+#[cfg(test)]
+mod tree_ids;
+#[cfg(test)]
+mod tree_persistent;
+#[cfg(test)]
+mod tree_serialize;
+#[cfg(test)]
+mod tree_diff;
@@ -0,0 +1,147 @@
+use crate::tree::{NodeId, Tree, TreeError};
+
+// The values of the children of `id`, in order. Checks that the sibling links agree in both directions.
+fn children(tree: &Tree<&'static str>, id: NodeId) -> Vec<&'static str> {
+    let mut values = Vec::new();
+    let mut previous = None;
+    let mut child = tree.first_child(id);
+    while let Some(current) = child {
+        assert_eq!(tree.parent(current), Some(id));
+        assert_eq!(tree.previous_sibling(current), previous);
+        values.push(*tree.get(current).unwrap());
+        previous = Some(current);
+        child = tree.next_sibling(current);
+    }
+    assert_eq!(tree.last_child(id), previous);
+    values
+}
+
+// root
+// ├── a
+// │   ├── a1
+// │   └── a2
+// └── b
+fn sample() -> (Tree<&'static str>, [NodeId; 5]) {
+    let mut tree = Tree::new("root");
+    let root = tree.root();
+    let a = tree.new_node("a");
+    let a1 = tree.new_node("a1");
+    let a2 = tree.new_node("a2");
+    let b = tree.new_node("b");
+    tree.append(root, a).unwrap();
+    tree.append(root, b).unwrap();
+    tree.append(a, a1).unwrap();
+    tree.append(a, a2).unwrap();
+    (tree, [root, a, a1, a2, b])
+}
+
+#[test]
+fn tree_append_and_prepend() {
+    let (mut tree, [root, a, _, _, _]) = sample();
+    assert_eq!(children(&tree, root), ["a", "b"]);
+    assert_eq!(children(&tree, a), ["a1", "a2"]);
+
+    let first = tree.new_node("first");
+    tree.prepend(root, first).unwrap();
+    let last = tree.new_node("last");
+    tree.append(root, last).unwrap();
+    assert_eq!(children(&tree, root), ["first", "a", "b", "last"]);
+
+    // Prepending to a node without children makes it the only child.
+    let only = tree.new_node("only");
+    tree.prepend(last, only).unwrap();
+    assert_eq!(children(&tree, last), ["only"]);
+}
+
+#[test]
+fn tree_insert_siblings() {
+    let (mut tree, [root, a, a1, a2, b]) = sample();
+    let before = tree.new_node("before");
+    tree.insert_before(a, before).unwrap();
+    let after = tree.new_node("after");
+    tree.insert_after(b, after).unwrap();
+    let middle = tree.new_node("middle");
+    tree.insert_after(a1, middle).unwrap();
+    assert_eq!(children(&tree, root), ["before", "a", "b", "after"]);
+    assert_eq!(children(&tree, a), ["a1", "middle", "a2"]);
+
+    // Existing nodes are moved.
+    tree.insert_before(a1, a2).unwrap();
+    assert_eq!(children(&tree, a), ["a2", "a1", "middle"]);
+    tree.insert_after(b, a1).unwrap();
+    assert_eq!(children(&tree, root), ["before", "a", "b", "a1", "after"]);
+    assert_eq!(children(&tree, a), ["a2", "middle"]);
+}
+
+#[test]
+fn tree_detach_and_remove() {
+    let (mut tree, [root, a, a1, a2, b]) = sample();
+    tree.detach(a).unwrap();
+    assert_eq!(children(&tree, root), ["b"]);
+    assert_eq!(tree.parent(a), None);
+    // The detached subtree is still intact and can be put back.
+    assert_eq!(children(&tree, a), ["a1", "a2"]);
+    tree.append(b, a).unwrap();
+    assert_eq!(children(&tree, b), ["a"]);
+
+    tree.remove(a).unwrap();
+    assert_eq!(children(&tree, b), Vec::<&str>::new());
+    for id in [a, a1, a2] {
+        assert!(!tree.contains(id));
+        assert_eq!(tree.get(id), None);
+    }
+    assert_eq!(tree.len(), 2);
+    assert_eq!(tree.remove(a), Err(TreeError::InvalidNode(a)));
+    assert_eq!(tree.append(root, a1), Err(TreeError::InvalidNode(a1)));
+}
+
+#[test]
+fn tree_reparent_children() {
+    let (mut tree, [root, a, _, _, b]) = sample();
+    let b1 = tree.new_node("b1");
+    tree.append(b, b1).unwrap();
+    tree.reparent_children(a, b).unwrap();
+    assert_eq!(children(&tree, a), Vec::<&str>::new());
+    assert_eq!(children(&tree, b), ["b1", "a1", "a2"]);
+    // Moving children into one of them would create a cycle.
+    assert_eq!(tree.reparent_children(root, b), Err(TreeError::Cycle));
+    assert_eq!(children(&tree, root), ["a", "b"]);
+}
+
+#[test]
+fn tree_invalid_operations() {
+    let (mut tree, [root, a, a1, _, b]) = sample();
+    assert_eq!(tree.append(a1, a), Err(TreeError::Cycle));
+    assert_eq!(tree.append(a, a), Err(TreeError::Cycle));
+    assert_eq!(tree.append(a, root), Err(TreeError::Root));
+    assert_eq!(tree.insert_before(root, b), Err(TreeError::Root));
+    assert_eq!(tree.insert_after(a1, a), Err(TreeError::Cycle));
+    assert_eq!(tree.insert_after(b, b), Err(TreeError::Cycle));
+    assert_eq!(tree.remove(root), Err(TreeError::Root));
+    // Failed operations leave the tree as it was.
+    assert_eq!(children(&tree, root), ["a", "b"]);
+    assert_eq!(children(&tree, a), ["a1", "a2"]);
+}
+
+#[test]
+fn tree_get_mut() {
+    let (mut tree, [root, a, _, _, _]) = sample();
+    *tree.get_mut(a).unwrap() = "changed";
+    assert_eq!(children(&tree, root), ["changed", "b"]);
+    assert_eq!(tree.get(root), Some(&"root"));
+    assert_eq!(tree.iter().map(|(_, value)| *value).collect::<Vec<_>>(), ["root", "changed", "a1", "a2", "b"]);
+}
+
+#[test]
+fn tree_deep_remove() {
+    let mut tree = Tree::new(0);
+    let mut parent = tree.root();
+    for depth in 1..100_000 {
+        let node = tree.new_node(depth);
+        tree.append(parent, node).unwrap();
+        parent = node;
+    }
+    let first = tree.first_child(tree.root()).unwrap();
+    tree.remove(first).unwrap();
+    assert_eq!(tree.len(), 1);
+}
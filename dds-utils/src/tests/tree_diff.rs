@@ -0,0 +1,181 @@
+use crate::tree::{apply, diff, Edit, EditNode, NodeId, Tree, TreeError};
+
+// Builds a tree from `root(a(a1 a2) b)`.
+fn parse(text: &str) -> Tree<String> {
+    let mut tree: Option<Tree<String>> = None;
+    let mut open: Vec<NodeId> = Vec::new();
+    let mut last: Option<NodeId> = None;
+    let mut name = String::new();
+    let flush = |name: &mut String, tree: &mut Option<Tree<String>>, open: &Vec<NodeId>, last: &mut Option<NodeId>| {
+        if name.is_empty() {
+            return;
+        }
+        let value = std::mem::take(name);
+        match tree {
+            None => {
+                let new = Tree::new(value);
+                *last = Some(new.root());
+                *tree = Some(new);
+            },
+            Some(tree) => {
+                let id = tree.new_node(value);
+                tree.append(*open.last().unwrap(), id).unwrap();
+                *last = Some(id);
+            },
+        }
+    };
+    for c in text.chars() {
+        match c {
+            '(' => {
+                flush(&mut name, &mut tree, &open, &mut last);
+                open.push(last.unwrap());
+            },
+            ')' => {
+                flush(&mut name, &mut tree, &open, &mut last);
+                open.pop();
+            },
+            ' ' => flush(&mut name, &mut tree, &open, &mut last),
+            c => name.push(c),
+        }
+    }
+    flush(&mut name, &mut tree, &open, &mut last);
+    tree.unwrap()
+}
+
+fn dump(tree: &Tree<String>, id: NodeId) -> String {
+    let children: Vec<String> = tree.children(id).map(|child| dump(tree, child)).collect();
+    match children.is_empty() {
+        true => tree.get(id).unwrap().clone(),
+        false => format!("{}({})", tree.get(id).unwrap(), children.join(" ")),
+    }
+}
+
+// Nodes are keyed on the part of their value before a ':', the rest can change without replacing the node.
+fn key(value: &str) -> String {
+    value.split(':').next().unwrap().to_string()
+}
+
+fn find(tree: &Tree<String>, value: &str) -> NodeId {
+    tree.iter().find(|(_, v)| v.as_str() == value).unwrap().0
+}
+
+// Diffs, applies and checks the result. Returns the script and the patched tree.
+fn check(old: &str, new: &str) -> (Vec<Edit<String>>, Tree<String>) {
+    let mut tree = parse(old);
+    let target = parse(new);
+    let edits = diff(&tree, &target, |value| key(value));
+    apply(&mut tree, edits.clone()).unwrap();
+    assert_eq!(dump(&tree, tree.root()), dump(&target, target.root()), "{} -> {} with {:?}", old, new, edits);
+    (edits, tree)
+}
+
+#[test]
+fn tree_diff_basic() {
+    assert_eq!(check("r(a(a1 a2) b)", "r(a(a1 a2) b)").0, Vec::new());
+
+    let (edits, _) = check("r(a:1 b)", "r(a:2 b)");
+    assert!(matches!(edits.as_slice(), [Edit::Update { value, .. }] if value == "a:2"));
+
+    let (edits, _) = check("r(a b)", "r(a x b)");
+    assert_eq!(edits, vec![Edit::Insert { parent : EditNode::Existing(parse("r").root()), index : 1, value : String::from("x") }]);
+
+    let old = parse("r(a(a1) b)");
+    let edits = diff(&old, &parse("r(b)"), |value| key(value));
+    assert_eq!(edits, vec![Edit::Remove { node : find(&old, "a") }]);
+
+    // New nodes can get new children of their own.
+    check("r", "r(a(a1(a2) a3))");
+    check("r:x", "r:y");
+    check("r(a b c)", "r");
+}
+
+#[test]
+fn tree_diff_moves() {
+    // Reordering moves as few nodes as it can, and keeps the nodes.
+    let old = parse("r(a b c d)");
+    let d = find(&old, "d");
+    let (edits, tree) = check("r(a b c d)", "r(d a b c)");
+    assert_eq!(edits.len(), 1);
+    assert_eq!(find(&tree, "d"), d);
+
+    // A subtree that moves to another parent comes along as a whole.
+    let old = parse("r(a(x(x1 x2)) b)");
+    let x = find(&old, "x");
+    let (edits, tree) = check("r(a(x(x1 x2)) b)", "r(a b(x(x1 x2)))");
+    assert_eq!(edits.len(), 1);
+    assert!(matches!(edits[0], Edit::Move { node, .. } if node == x));
+    assert_eq!(find(&tree, "x"), x);
+
+    // A child that becomes the parent.
+    check("r(a(b(c)))", "r(b(a(c)))");
+    check("r(a(b c) d(e f))", "r(d(f e) a(c b))");
+    // Moved nodes end up inside new ones.
+    check("r(a(b(c) d))", "r(x(b(c)) a(y(d)))");
+}
+
+#[test]
+fn tree_diff_random() {
+    // A small linear congruential generator, so the test doesn't need any dependencies.
+    let mut seed: u64 = 0x2545F4914F6CDD1D;
+    let mut next = |bound: u64| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) % bound
+    };
+    let mut random_tree = |size: u64| {
+        let mut tree = Tree::new(String::from("r"));
+        let mut ids = vec![tree.root()];
+        for _ in 0..size {
+            let value = format!("{}:{}", (b'a' + next(6) as u8) as char, next(3));
+            let id = tree.new_node(value);
+            let parent = ids[next(ids.len() as u64) as usize];
+            tree.append(parent, id).unwrap();
+            ids.push(id);
+        }
+        tree
+    };
+    for size in 0..60 {
+        let mut old = random_tree(size);
+        let new = random_tree(size / 2 + size % 7);
+        let edits = diff(&old, &new, |value| key(value));
+        apply(&mut old, edits).unwrap();
+        assert_eq!(dump(&old, old.root()), dump(&new, new.root()));
+    }
+}
+
+#[test]
+fn tree_diff_invalid_edits() {
+    let mut tree = parse("r(a)");
+    let root = tree.root();
+    let a = find(&tree, "a");
+    assert_eq!(apply(&mut tree, vec![Edit::Insert { parent : EditNode::Existing(root), index : 5, value : String::from("x") }]), Err(TreeError::InvalidEdit));
+    assert_eq!(apply(&mut tree, vec![Edit::Insert { parent : EditNode::Inserted(0), index : 0, value : String::from("x") }]), Err(TreeError::InvalidEdit));
+    assert_eq!(apply(&mut tree, vec![Edit::Move { node : root, parent : EditNode::Existing(a), index : 0 }]), Err(TreeError::Root));
+    assert_eq!(dump(&tree, tree.root()), "r(a)");
+    assert_eq!(tree.len(), 2);
+}
+
+#[test]
+fn tree_diff_wide() {
+    // Too many children for a table of every pair, they are lined up around the keys that are unique instead.
+    let children = |values: Vec<String>| format!("r({})", values.join(" "));
+    let old: Vec<String> = (0..3000).map(|i| format!("n{}", i)).collect();
+    let mut new = old.clone();
+    new.remove(10);
+    new.insert(2000, String::from("x"));
+    let (edits, _) = check(&children(old.clone()), &children(new));
+    assert_eq!(edits.len(), 2, "{:?}", edits);
+
+    // Keys that repeat are matched in order.
+    let repeated: Vec<String> = (0..3000).map(|i| ["a", "b", "c"][i % 3].to_string()).collect();
+    let mut changed = repeated.clone();
+    changed.drain(1000..1003);
+    changed.push(String::from("d"));
+    let (edits, _) = check(&children(repeated), &children(changed));
+    assert_eq!(edits.len(), 4, "{:?}", edits);
+
+    // Inserting lots of children takes about as long as the children are many.
+    let start = std::time::Instant::now();
+    let many: Vec<String> = (0..30000).map(|i| format!("m{}", i)).collect();
+    check("r", &children(many));
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+}
@@ -0,0 +1,105 @@
+use crate::tree::{NodeId, Tree, TreeError};
+
+fn add(tree: &mut Tree<&'static str>, parent: NodeId, value: &'static str) -> NodeId {
+    let id = tree.new_node(value);
+    tree.append(parent, id).unwrap();
+    id
+}
+
+fn values(tree: &Tree<&'static str>, id: NodeId) -> Vec<&'static str> {
+    tree.descendants(id).map(|id| *tree.get(id).unwrap()).collect()
+}
+
+#[test]
+fn tree_ids_stale_id_is_rejected() {
+    let mut tree = Tree::new("root");
+    let root = tree.root();
+    let a = add(&mut tree, root, "a");
+    tree.remove(a).unwrap();
+
+    // The slot of `a` is reused, but the old id doesn't see the new node.
+    let b = add(&mut tree, root, "b");
+    assert_eq!(tree.len(), 2);
+    assert_ne!(a, b);
+    assert_eq!(tree.get(a), None);
+    assert!(tree.get_mut(a).is_none());
+    assert_eq!(tree.get(b), Some(&"b"));
+    assert_eq!(tree.parent(a), None);
+    assert_eq!(tree.remove(a), Err(TreeError::InvalidNode(a)));
+    assert_eq!(tree.append(a, root), Err(TreeError::InvalidNode(a)));
+    assert_eq!(tree.insert_after(b, a), Err(TreeError::InvalidNode(a)));
+    assert_eq!(values(&tree, root), ["b"]);
+}
+
+#[test]
+fn tree_ids_slots_are_reused() {
+    let mut tree = Tree::new("root");
+    let root = tree.root();
+    for _ in 0..100 {
+        let a = add(&mut tree, root, "a");
+        add(&mut tree, a, "a1");
+        tree.remove(a).unwrap();
+    }
+    assert_eq!(tree.len(), 1);
+    // Only the slots needed by a single iteration were ever allocated.
+    assert_eq!(tree.iter().count(), 1);
+    let ids : Vec<NodeId> = (0..3).map(|_| tree.new_node("x")).collect();
+    assert_eq!(tree.len(), 4);
+    assert!(ids.iter().all(|id| tree.get(*id) == Some(&"x")));
+
+    // Iterating goes by slot, so a node created later can come first once it reuses a freed slot.
+    let mut tree = Tree::new("root");
+    let a = tree.new_node("a");
+    tree.new_node("b");
+    tree.remove(a).unwrap();
+    tree.new_node("c");
+    assert_eq!(tree.iter().map(|(_, value)| *value).collect::<Vec<_>>(), ["root", "c", "b"]);
+}
+
+#[test]
+fn tree_ids_compact() {
+    let mut tree = Tree::new("root");
+    let root = tree.root();
+    let a = add(&mut tree, root, "a");
+    let b = add(&mut tree, root, "b");
+    let b1 = add(&mut tree, b, "b1");
+    let c = add(&mut tree, root, "c");
+    let c1 = add(&mut tree, c, "c1");
+    tree.remove(b).unwrap();
+
+    let remapping = tree.compact();
+    assert_eq!(tree.len(), 4);
+    assert_eq!(remapping.get(root), Some(tree.root()));
+    // Nodes in front of the first gap keep their ids.
+    assert_eq!(remapping.get(a), Some(a));
+    assert_eq!(remapping.get(b), None);
+    assert_eq!(remapping.get(b1), None);
+
+    let new_c = remapping.get(c).unwrap();
+    let new_c1 = remapping.get(c1).unwrap();
+    assert_eq!(tree.get(new_c), Some(&"c"));
+    assert_eq!(tree.get(new_c1), Some(&"c1"));
+    assert_eq!(tree.parent(new_c1), Some(new_c));
+    assert_eq!(tree.previous_sibling(new_c), Some(a));
+    assert_eq!(values(&tree, tree.root()), ["a", "c", "c1"]);
+
+    // Old ids of moved nodes no longer work, even though their slots are in use again.
+    assert_eq!(tree.get(c), None);
+    assert_eq!(tree.get(c1), None);
+    assert_eq!(tree.get(b), None);
+
+    // New nodes go at the end, there are no free slots left.
+    let d = add(&mut tree, root, "d");
+    assert_eq!(tree.iter().map(|(id, _)| id).last(), Some(d));
+    assert_eq!(values(&tree, tree.root()), ["a", "c", "c1", "d"]);
+}
+
+#[test]
+fn tree_ids_compact_without_gaps() {
+    let mut tree = Tree::new("root");
+    let root = tree.root();
+    let a = add(&mut tree, root, "a");
+    let remapping = tree.compact();
+    assert_eq!(remapping.get(root), Some(root));
+    assert_eq!(remapping.get(a), Some(a));
+}
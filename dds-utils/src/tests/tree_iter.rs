@@ -0,0 +1,132 @@
+use crate::tree::{Edge, NodeId, Tree};
+
+fn add(tree: &mut Tree<&'static str>, parent: NodeId, value: &'static str) -> NodeId {
+    let id = tree.new_node(value);
+    tree.append(parent, id).unwrap();
+    id
+}
+
+// root
+// ├── a
+// │   ├── a1
+// │   ├── a2
+// │   │   └── a2x
+// │   └── a3
+// ├── b
+// └── c
+//     └── c1
+fn sample() -> Tree<&'static str> {
+    let mut tree = Tree::new("root");
+    let root = tree.root();
+    let a = add(&mut tree, root, "a");
+    add(&mut tree, a, "a1");
+    let a2 = add(&mut tree, a, "a2");
+    add(&mut tree, a2, "a2x");
+    add(&mut tree, a, "a3");
+    add(&mut tree, root, "b");
+    let c = add(&mut tree, root, "c");
+    add(&mut tree, c, "c1");
+    tree
+}
+
+fn find(tree: &Tree<&'static str>, value: &str) -> NodeId {
+    tree.iter().find(|(_, v)| **v == value).unwrap().0
+}
+
+fn values<'a>(tree: &'a Tree<&'static str>, ids: impl Iterator<Item = NodeId> + 'a) -> Vec<&'static str> {
+    ids.map(|id| *tree.get(id).unwrap()).collect()
+}
+
+#[test]
+fn tree_iter_children() {
+    let tree = sample();
+    let a = find(&tree, "a");
+    assert_eq!(values(&tree, tree.children(tree.root())), ["a", "b", "c"]);
+    assert_eq!(values(&tree, tree.reverse_children(tree.root())), ["c", "b", "a"]);
+    assert_eq!(values(&tree, tree.children(find(&tree, "b"))), Vec::<&str>::new());
+
+    // Both ends meet in the middle without handing out a node twice.
+    let mut children = tree.children(a);
+    assert_eq!(children.next().map(|id| *tree.get(id).unwrap()), Some("a1"));
+    assert_eq!(children.next_back().map(|id| *tree.get(id).unwrap()), Some("a3"));
+    assert_eq!(children.next().map(|id| *tree.get(id).unwrap()), Some("a2"));
+    assert_eq!(children.next_back(), None);
+    assert_eq!(children.next(), None);
+}
+
+#[test]
+fn tree_iter_ancestors_and_siblings() {
+    let tree = sample();
+    let a2 = find(&tree, "a2");
+    assert_eq!(values(&tree, tree.ancestors(find(&tree, "a2x"))), ["a2", "a", "root"]);
+    assert_eq!(values(&tree, tree.ancestors(tree.root())), Vec::<&str>::new());
+
+    assert_eq!(values(&tree, tree.following_siblings(find(&tree, "a1"))), ["a2", "a3"]);
+    assert_eq!(values(&tree, tree.following_siblings(find(&tree, "a1")).rev()), ["a3", "a2"]);
+    assert_eq!(values(&tree, tree.preceding_siblings(find(&tree, "a3"))), ["a2", "a1"]);
+    assert_eq!(values(&tree, tree.preceding_siblings(find(&tree, "a3")).rev()), ["a1", "a2"]);
+    assert_eq!(values(&tree, tree.following_siblings(find(&tree, "a3"))), Vec::<&str>::new());
+    assert_eq!(values(&tree, tree.preceding_siblings(find(&tree, "a1"))), Vec::<&str>::new());
+    assert_eq!(values(&tree, tree.following_siblings(a2).rev()), ["a3"]);
+    assert_eq!(values(&tree, tree.following_siblings(tree.root())), Vec::<&str>::new());
+}
+
+#[test]
+fn tree_iter_descendants() {
+    let tree = sample();
+    let all = ["a", "a1", "a2", "a2x", "a3", "b", "c", "c1"];
+    assert_eq!(values(&tree, tree.descendants(tree.root())), all);
+    let mut reversed = all;
+    reversed.reverse();
+    assert_eq!(values(&tree, tree.descendants(tree.root()).rev()), reversed);
+    assert_eq!(values(&tree, tree.descendants(find(&tree, "a"))), ["a1", "a2", "a2x", "a3"]);
+    assert_eq!(values(&tree, tree.descendants(find(&tree, "b"))), Vec::<&str>::new());
+
+    // Taking from both ends covers every node once.
+    let mut descendants = tree.descendants(tree.root());
+    let mut seen = Vec::new();
+    while let Some(id) = descendants.next() {
+        seen.push(*tree.get(id).unwrap());
+        if let Some(id) = descendants.next_back() {
+            seen.push(*tree.get(id).unwrap());
+        }
+    }
+    seen.sort();
+    let mut expected = all.to_vec();
+    expected.sort();
+    assert_eq!(seen, expected);
+}
+
+#[test]
+fn tree_iter_traverse() {
+    let tree = sample();
+    let events = |edges: Vec<Edge>| edges.into_iter().map(|edge| match edge {
+        Edge::Open(id) => format!("<{}>", tree.get(id).unwrap()),
+        Edge::Close(id) => format!("</{}>", tree.get(id).unwrap()),
+    }).collect::<String>();
+
+    let a = find(&tree, "a");
+    assert_eq!(events(tree.traverse(a).collect()), "<a><a1></a1><a2><a2x></a2x></a2><a3></a3></a>");
+    assert_eq!(events(tree.traverse(find(&tree, "b")).collect()), "<b></b>");
+
+    let mut backwards : Vec<Edge> = tree.traverse(tree.root()).rev().collect();
+    backwards.reverse();
+    assert_eq!(backwards, tree.traverse(tree.root()).collect::<Vec<_>>());
+    assert_eq!(tree.traverse(tree.root()).count(), 18);
+}
+
+#[test]
+fn tree_iter_detached_and_removed() {
+    let mut tree = sample();
+    let a = find(&tree, "a");
+    tree.detach(a).unwrap();
+    // A detached subtree can still be walked on its own, without leaking into the rest of the tree.
+    assert_eq!(values(&tree, tree.descendants(a)), ["a1", "a2", "a2x", "a3"]);
+    assert_eq!(values(&tree, tree.following_siblings(a)), Vec::<&str>::new());
+    assert_eq!(values(&tree, tree.children(tree.root())), ["b", "c"]);
+
+    tree.remove(a).unwrap();
+    assert_eq!(tree.traverse(a).count(), 0);
+    assert_eq!(tree.children(a).count(), 0);
+    assert_eq!(tree.ancestors(a).count(), 0);
+}
@@ -0,0 +1,124 @@
+use crate::tree::{persistent::{PersistentNode, PersistentTree}, Tree};
+
+// root
+// ├── a
+// │   ├── a1
+// │   └── a2
+// └── b
+//     └── b1
+fn sample() -> PersistentTree<String> {
+    let mut tree = PersistentTree::new(String::from("root"));
+    let a = tree.append(&[], String::from("a")).unwrap();
+    tree.append(&[a], String::from("a1")).unwrap();
+    tree.append(&[a], String::from("a2")).unwrap();
+    let b = tree.append(&[], String::from("b")).unwrap();
+    tree.append(&[b], String::from("b1")).unwrap();
+    tree
+}
+
+fn dump(node: &PersistentNode<String>) -> String {
+    let children: Vec<String> = node.children().map(dump).collect();
+    match children.is_empty() {
+        true => node.value().clone(),
+        false => format!("{}({})", node.value(), children.join(" ")),
+    }
+}
+
+#[test]
+fn tree_persistent_versions() {
+    let first = sample();
+    let mut second = first.clone();
+    assert!(second.ptr_eq(&first));
+
+    second.get_mut(&[0, 1]).unwrap().push('!');
+    second.insert(&[1], 0, String::from("b0")).unwrap();
+    let removed = second.remove(&[0, 0]).unwrap();
+
+    assert_eq!(dump(first.root()), "root(a(a1 a2) b(b1))");
+    assert_eq!(dump(second.root()), "root(a(a2!) b(b0 b1))");
+    assert_eq!(dump(removed.root()), "a1");
+    assert!(!second.ptr_eq(&first));
+
+    // Invalid paths are rejected without touching the tree.
+    let mut third = second.clone();
+    assert!(third.append(&[5], String::from("x")).is_none());
+    assert!(third.insert(&[0], 2, String::from("x")).is_none());
+    assert!(third.remove(&[]).is_none());
+    assert!(third.remove(&[0, 3]).is_none());
+    assert!(third.get_mut(&[0, 0, 0]).is_none());
+    assert!(third.ptr_eq(&second));
+}
+
+#[test]
+fn tree_persistent_sharing() {
+    let first = sample();
+    let mut second = first.clone();
+    second.append(&[1], String::from("b2")).unwrap();
+
+    // Only the nodes on the path to the change were copied.
+    assert!(!second.root().ptr_eq(first.root()));
+    assert!(!second.get(&[1]).unwrap().ptr_eq(first.get(&[1]).unwrap()));
+    assert!(second.get(&[0]).unwrap().ptr_eq(first.get(&[0]).unwrap()));
+    assert!(second.get(&[1, 0]).unwrap().ptr_eq(first.get(&[1, 0]).unwrap()));
+
+    // A version that isn't shared is changed in place.
+    let b = second.get(&[1]).unwrap() as *const PersistentNode<String>;
+    second.append(&[1], String::from("b3")).unwrap();
+    assert!(std::ptr::eq(second.get(&[1]).unwrap(), b));
+}
+
+#[test]
+fn tree_persistent_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<PersistentTree<String>>();
+
+    let mut tree = sample();
+    let snapshot = tree.clone();
+    let reader = std::thread::spawn(move || dump(snapshot.root()));
+    for i in 0..100 {
+        tree.append(&[0], i.to_string()).unwrap();
+    }
+    assert_eq!(reader.join().unwrap(), "root(a(a1 a2) b(b1))");
+    assert_eq!(tree.get(&[0]).unwrap().children().len(), 102);
+}
+
+#[test]
+fn tree_persistent_from_tree() {
+    let mut tree = Tree::new(String::from("root"));
+    let root = tree.root();
+    let a = tree.new_node(String::from("a"));
+    let a1 = tree.new_node(String::from("a1"));
+    let b = tree.new_node(String::from("b"));
+    let detached = tree.new_node(String::from("detached"));
+    tree.append(root, a).unwrap();
+    tree.append(a, a1).unwrap();
+    tree.append(root, b).unwrap();
+    tree.append(b, detached).unwrap();
+    tree.detach(detached).unwrap();
+
+    assert_eq!(dump(PersistentTree::from(&tree).root()), "root(a(a1) b)");
+}
+
+#[test]
+fn tree_persistent_deep() {
+    // A chain far deeper than the stack could hold if nodes were dropped recursively.
+    let mut tree = Tree::new(0);
+    let mut last = tree.root();
+    for i in 1..50_000 {
+        let node = tree.new_node(i);
+        tree.append(last, node).unwrap();
+        last = node;
+    }
+    let mut persistent = PersistentTree::from(&tree);
+    let path = vec![0; 1000];
+    let shallow = persistent.clone();
+    persistent.get_mut(&path).map(|value| *value = -1).unwrap();
+    let removed = persistent.remove(&path).unwrap();
+
+    assert_eq!(*removed.root().value(), -1);
+    assert_eq!(*shallow.get(&path).unwrap().value(), 1000);
+    // The versions share most of their nodes, they can be dropped in any order.
+    drop(shallow);
+    drop(removed);
+    drop(persistent);
+}
@@ -0,0 +1,90 @@
+use miniserde::json;
+
+use crate::tree::{NodeId, Tree};
+
+fn add(tree: &mut Tree<String>, parent: NodeId, value: &str) -> NodeId {
+    let id = tree.new_node(value.to_string());
+    tree.append(parent, id).unwrap();
+    id
+}
+
+// root
+// ├── a
+// │   └── a1
+// └── b
+fn sample() -> Tree<String> {
+    let mut tree = Tree::new(String::from("root"));
+    let root = tree.root();
+    let a = add(&mut tree, root, "a");
+    add(&mut tree, a, "a1");
+    add(&mut tree, root, "b");
+    tree
+}
+
+fn dump(tree: &Tree<String>, id: NodeId) -> String {
+    let children: Vec<String> = tree.children(id).map(|child| dump(tree, child)).collect();
+    match children.is_empty() {
+        true => tree.get(id).unwrap().clone(),
+        false => format!("{}({})", tree.get(id).unwrap(), children.join(" ")),
+    }
+}
+
+#[test]
+fn tree_serialize_nested() {
+    let tree = sample();
+    let text = json::to_string(&tree);
+    assert_eq!(text, r#"{"value":"root","children":[{"value":"a","children":[{"value":"a1","children":[]}]},{"value":"b","children":[]}]}"#);
+
+    let parsed: Tree<String> = json::from_str(&text).unwrap();
+    assert_eq!(dump(&parsed, parsed.root()), "root(a(a1) b)");
+
+    // Leaves can leave out their children.
+    let parsed: Tree<String> = json::from_str(r#"{"value":"root","children":[{"value":"a"}],"extra":[1,2]}"#).unwrap();
+    assert_eq!(dump(&parsed, parsed.root()), "root(a)");
+}
+
+#[test]
+fn tree_serialize_flat() {
+    let mut tree = sample();
+    // Detached nodes aren't written out.
+    let detached = tree.new_node(String::from("detached"));
+    let text = json::to_string(&tree.flat());
+    assert!(tree.contains(detached));
+    assert_eq!(text, r#"{"nodes":[{"value":"root","parent":null},{"value":"a","parent":0},{"value":"a1","parent":1},{"value":"b","parent":0}]}"#);
+
+    let parsed: Tree<String> = json::from_str(&text).unwrap();
+    assert_eq!(dump(&parsed, parsed.root()), "root(a(a1) b)");
+    assert_eq!(parsed.len(), 4);
+}
+
+#[test]
+fn tree_serialize_invalid() {
+    for text in [
+        r#"{"nodes":[]}"#,
+        r#"{"nodes":[{"value":"root","parent":0}]}"#,
+        r#"{"nodes":[{"value":"root"},{"value":"a","parent":1}]}"#,
+        r#"{"nodes":[{"value":"root"},{"value":"a"}]}"#,
+        r#"{"children":[]}"#,
+        r#"{"value":"root","nodes":[]}"#,
+        r#"{"value":"root","children":[{"children":[]}]}"#,
+        r#"["root"]"#,
+    ] {
+        assert!(json::from_str::<Tree<String>>(text).is_err(), "{}", text);
+    }
+}
+
+#[test]
+fn tree_serialize_deep() {
+    let mut tree = Tree::new(0u32);
+    let mut parent = tree.root();
+    for depth in 1..200 {
+        let id = tree.new_node(depth);
+        tree.append(parent, id).unwrap();
+        parent = id;
+    }
+    for text in [json::to_string(&tree), json::to_string(&tree.flat())] {
+        let parsed: Tree<u32> = json::from_str(&text).unwrap();
+        let values: Vec<u32> = parsed.descendants(parsed.root()).map(|id| *parsed.get(id).unwrap()).collect();
+        assert_eq!(values, (1..200).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,469 @@
+//! Turning one [`Tree`] into another.
+//!
+//! [`diff`] compares two trees and produces an edit script that, once [`apply`]ed to the old tree, makes it
+//! look like the new one while keeping as many of the old nodes as it can. That way whatever hangs off the
+//! old nodes (layout, styles, event handlers) survives a change that only touches part of the tree.
+//!
+//! Nodes are matched through a key that the caller derives from their values. Two nodes can only be
+//! matched if their keys are equal, and a matched node whose value changed gets an [`Edit::Update`].
+//! Matching works in two passes :
+//!
+//! 1. Top down from the roots, which are always matched. The children of two matched nodes are aligned
+//!    on their keys with a longest common subsequence, and every aligned pair is matched in turn. Long lists
+//!    of children are lined up around the keys that occur once in both instead, as in patience diff.
+//! 2. Subtrees that moved somewhere else. A new node that wasn't matched yet is matched to a leftover old
+//!    node whose subtree has the same shape and keys, along with the rest of that subtree.
+//!
+//! Everything in the new tree that wasn't matched is inserted, and everything in the old tree that wasn't
+//! matched is removed.
+
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use super::{Edge, NodeId, Tree, TreeError};
+
+/// A node that an [`Edit`] refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EditNode {
+    /// A node of the tree the script was made for.
+    Existing(NodeId),
+    /// The node created by the script's `n`th [`Edit::Insert`], counting from zero.
+    Inserted(usize),
+}
+
+/// A single step of an edit script, see [`diff`]. Positions are the index among the parent's children
+/// that the node ends up at, at the time the edit is applied.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit<T> {
+    /// Create a new node.
+    Insert { parent : EditNode, index : usize, value : T },
+    /// Move a node, along with its subtree, to a new position.
+    Move { node : NodeId, parent : EditNode, index : usize },
+    /// Replace the value of a node.
+    Update { node : NodeId, value : T },
+    /// Remove a node and its subtree.
+    Remove { node : NodeId },
+}
+
+/// Compute the edits that turn `old` into `new`, see the [module documentation](self).
+pub fn diff<T, K, F>(old : &Tree<T>, new : &Tree<T>, key : F) -> Vec<Edit<T>>
+where
+    T : Clone + PartialEq,
+    K : Eq + Hash,
+    F : Fn(&T) -> K,
+{
+    let mut matcher = Matcher { old, new, key : &key, old_to_new : HashMap::new(), new_to_old : HashMap::new() };
+    matcher.match_nodes(old.root(), new.root());
+    matcher.match_moved();
+    Script::new(old).build(old, new, &matcher.new_to_old)
+}
+
+/// Run an edit script made by [`diff`] against `tree`. Stops at the first edit that fails, the ones before it stay applied.
+pub fn apply<T>(tree : &mut Tree<T>, edits : Vec<Edit<T>>) -> Result<(), TreeError> {
+    let mut inserted = Vec::new();
+    let resolve = |inserted : &Vec<NodeId>, node : EditNode| match node {
+        EditNode::Existing(id) => Ok(id),
+        EditNode::Inserted(index) => inserted.get(index).copied().ok_or(TreeError::InvalidEdit),
+    };
+    // The last node that was put in place, with its parent and index. A script made by `diff` places the
+    // children of a node one after the other, so the next one usually goes right after it.
+    let mut cursor : Option<(NodeId, usize, NodeId)> = None;
+    for edit in edits {
+        match edit {
+            Edit::Insert { parent, index, value } => {
+                let parent = resolve(&inserted, parent)?;
+                let id = tree.new_node(value);
+                if let Err(error) = place(tree, parent, index, id, cursor) {
+                    let _ = tree.remove(id);
+                    return Err(error);
+                }
+                inserted.push(id);
+                cursor = Some((parent, index, id));
+            },
+            Edit::Move { node, parent, index } => {
+                let parent = resolve(&inserted, parent)?;
+                // Taking the node out of the cursor's parent can shift the children the cursor counts.
+                if cursor.is_some_and(|(at, _, _)| tree.parent(node) == Some(at)) {
+                    cursor = None;
+                }
+                tree.detach(node)?;
+                place(tree, parent, index, node, cursor)?;
+                cursor = Some((parent, index, node));
+            },
+            Edit::Update { node, value } => {
+                *tree.get_mut(node).ok_or(TreeError::InvalidNode(node))? = value;
+            },
+            Edit::Remove { node } => {
+                tree.remove(node)?;
+                cursor = None;
+            },
+        }
+    }
+    Ok(())
+}
+
+// Insert a detached node as the `index`th child of `parent`. Right after the cursor that takes constant time,
+// anywhere else it walks the children.
+fn place<T>(tree : &mut Tree<T>, parent : NodeId, index : usize, node : NodeId, cursor : Option<(NodeId, usize, NodeId)>) -> Result<(), TreeError> {
+    if let Some((_, _, previous)) = cursor.filter(|(at, at_index, _)| *at == parent && at_index + 1 == index) {
+        return tree.insert_after(previous, node);
+    }
+    match tree.children(parent).nth(index) {
+        Some(sibling) => tree.insert_before(sibling, node),
+        None if tree.children(parent).count() == index => tree.append(parent, node),
+        None => Err(TreeError::InvalidEdit),
+    }
+}
+
+struct Matcher<'a, T, F> {
+    old : &'a Tree<T>,
+    new : &'a Tree<T>,
+    key : &'a F,
+    old_to_new : HashMap<NodeId, NodeId>,
+    new_to_old : HashMap<NodeId, NodeId>,
+}
+
+impl<'a, T, K, F> Matcher<'a, T, F>
+where
+    K : Eq + Hash,
+    F : Fn(&T) -> K,
+{
+    fn key_of(&self, tree : &Tree<T>, id : NodeId) -> Option<K> {
+        tree.get(id).map(self.key)
+    }
+
+    // Match two nodes and then their children, top down. Uses a stack rather than recursion so deep trees
+    // don't overflow.
+    fn match_nodes(&mut self, old : NodeId, new : NodeId) {
+        let mut pending = vec![(old, new)];
+        while let Some((old, new)) = pending.pop() {
+            self.old_to_new.insert(old, new);
+            self.new_to_old.insert(new, old);
+            let old_children : Vec<NodeId> = self.old.children(old).filter(|id| !self.old_to_new.contains_key(id)).collect();
+            let new_children : Vec<NodeId> = self.new.children(new).filter(|id| !self.new_to_old.contains_key(id)).collect();
+            let old_keys : Vec<Option<K>> = old_children.iter().map(|id| self.key_of(self.old, *id)).collect();
+            let new_keys : Vec<Option<K>> = new_children.iter().map(|id| self.key_of(self.new, *id)).collect();
+            for (old_index, new_index) in common_subsequence(&old_keys, &new_keys) {
+                pending.push((old_children[old_index], new_children[new_index]));
+            }
+        }
+    }
+
+    // Match the subtrees that moved to another parent, or that the alignment of the children skipped.
+    fn match_moved(&mut self) {
+        let old_hashes = self.subtree_hashes(self.old);
+        let new_hashes = self.subtree_hashes(self.new);
+        let mut leftovers : HashMap<u64, Vec<NodeId>> = HashMap::new();
+        // Reversed, so that popping the candidates hands them out in document order.
+        for id in self.old.descendants(self.old.root()).collect::<Vec<_>>().into_iter().rev() {
+            if !self.old_to_new.contains_key(&id) {
+                if let Some(hash) = old_hashes.get(&id) {
+                    leftovers.entry(*hash).or_default().push(id);
+                }
+            }
+        }
+        // Document order, so that the largest subtree that moved is matched before its descendants.
+        for new in self.new.descendants(self.new.root()) {
+            if self.new_to_old.contains_key(&new) {
+                continue;
+            }
+            let Some(candidates) = new_hashes.get(&new).and_then(|hash| leftovers.get_mut(hash)) else {
+                continue;
+            };
+            // Candidates inside a subtree that was matched in the meantime are already taken.
+            while let Some(old) = candidates.pop() {
+                if !self.old_to_new.contains_key(&old) {
+                    self.match_nodes(old, new);
+                    break;
+                }
+            }
+        }
+    }
+
+    // Hash the keys of every subtree, bottom up.
+    fn subtree_hashes(&self, tree : &Tree<T>) -> HashMap<NodeId, u64> {
+        let mut hashes = HashMap::new();
+        // The hashers of the nodes that are open, every child feeds its hash to its parent's once it closes.
+        let mut open : Vec<DefaultHasher> = Vec::new();
+        for edge in tree.traverse(tree.root()) {
+            match edge {
+                Edge::Open(id) => {
+                    let mut hasher = DefaultHasher::new();
+                    self.key_of(tree, id).hash(&mut hasher);
+                    open.push(hasher);
+                },
+                Edge::Close(id) => if let Some(hasher) = open.pop() {
+                    let hash = hasher.finish();
+                    hashes.insert(id, hash);
+                    if let Some(parent) = open.last_mut() {
+                        hash.hash(parent);
+                    }
+                },
+            }
+        }
+        hashes
+    }
+}
+
+// Above this many cells the table of common_subsequence takes too much memory, and the children are lined
+// up around the keys that only occur once instead.
+const MAX_TABLE : usize = 1 << 20;
+
+// The pairs of indices that make up a common subsequence of `a` and `b`, the longest one for lists of
+// children that aren't too long.
+fn common_subsequence<K : Eq + Hash>(a : &[K], b : &[K]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    let mut pending = vec![(0..a.len(), 0..b.len())];
+    while let Some((mut range_a, mut range_b)) = pending.pop() {
+        // Most edits leave the start and end of a list of children alone, which keeps the rest small.
+        while !range_a.is_empty() && !range_b.is_empty() && a[range_a.start] == b[range_b.start] {
+            pairs.push((range_a.start, range_b.start));
+            range_a.start += 1;
+            range_b.start += 1;
+        }
+        while !range_a.is_empty() && !range_b.is_empty() && a[range_a.end - 1] == b[range_b.end - 1] {
+            range_a.end -= 1;
+            range_b.end -= 1;
+            pairs.push((range_a.end, range_b.end));
+        }
+        let (middle_a, middle_b) = (&a[range_a.clone()], &b[range_b.clone()]);
+        let offset = |(i, j) : (usize, usize)| (range_a.start + i, range_b.start + j);
+        if middle_a.len().saturating_mul(middle_b.len()) <= MAX_TABLE {
+            pairs.extend(table_subsequence(middle_a, middle_b).into_iter().map(offset));
+            continue;
+        }
+        let anchors = unique_anchors(middle_a, middle_b);
+        if anchors.is_empty() {
+            pairs.extend(greedy_subsequence(middle_a, middle_b).into_iter().map(offset));
+            continue;
+        }
+        // The anchors are matched, and the children in between them are lined up in turn.
+        let (mut start_a, mut start_b) = (range_a.start, range_b.start);
+        for (i, j) in anchors.into_iter().map(offset) {
+            pending.push((start_a..i, start_b..j));
+            pairs.push((i, j));
+            (start_a, start_b) = (i + 1, j + 1);
+        }
+        pending.push((start_a..range_a.end, start_b..range_b.end));
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+// A longest common subsequence, from a table of the lengths of the common subsequences of every pair of suffixes.
+fn table_subsequence<K : Eq>(a : &[K], b : &[K]) -> Vec<(usize, usize)> {
+    // lengths[i][j] is the length of the longest common subsequence of a[i..] and b[j..].
+    let width = b.len() + 1;
+    let mut lengths = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i * width + j] = match a[i] == b[j] {
+                true => lengths[(i + 1) * width + j + 1] + 1,
+                false => lengths[(i + 1) * width + j].max(lengths[i * width + j + 1]),
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+// The keys that occur exactly once in both lists, as in patience diff. Of those, the longest run that is in
+// the same order in both, found as the longest increasing subsequence of their indices in `a`.
+fn unique_anchors<K : Eq + Hash>(a : &[K], b : &[K]) -> Vec<(usize, usize)> {
+    // The number of times a key occurs in `a` and `b`, and where it last did.
+    let mut counts : HashMap<&K, (usize, usize, usize, usize)> = HashMap::new();
+    for (i, key) in a.iter().enumerate() {
+        let entry = counts.entry(key).or_default();
+        entry.0 += 1;
+        entry.1 = i;
+    }
+    for (j, key) in b.iter().enumerate() {
+        if let Some(entry) = counts.get_mut(key) {
+            entry.2 += 1;
+            entry.3 = j;
+        }
+    }
+    let unique : Vec<(usize, usize)> = b.iter().enumerate()
+        .filter_map(|(j, key)| counts.get(key).filter(|entry| entry.0 == 1 && entry.2 == 1).map(|entry| (entry.1, j)))
+        .collect();
+
+    // tails[k] is the index in `unique` of the smallest last element of an increasing run of length k + 1.
+    let mut tails : Vec<usize> = Vec::new();
+    let mut previous : Vec<Option<usize>> = Vec::with_capacity(unique.len());
+    for (index, (i, _)) in unique.iter().enumerate() {
+        let length = tails.partition_point(|tail| unique[*tail].0 < *i);
+        previous.push(length.checked_sub(1).map(|before| tails[before]));
+        match tails.get_mut(length) {
+            Some(tail) => *tail = index,
+            None => tails.push(index),
+        }
+    }
+    let mut anchors = Vec::with_capacity(tails.len());
+    let mut current = tails.last().copied();
+    while let Some(index) = current {
+        anchors.push(unique[index]);
+        current = previous[index];
+    }
+    anchors.reverse();
+    anchors
+}
+
+// Matches every element of `b` to the first element of `a` with the same key that comes after the previous
+// match. Used when no key is unique, e.g. a long list of children that all have the same key.
+fn greedy_subsequence<K : Eq + Hash>(a : &[K], b : &[K]) -> Vec<(usize, usize)> {
+    let mut positions : HashMap<&K, std::collections::VecDeque<usize>> = HashMap::new();
+    for (i, key) in a.iter().enumerate() {
+        positions.entry(key).or_default().push_back(i);
+    }
+    let mut pairs = Vec::new();
+    let mut next = 0;
+    for (j, key) in b.iter().enumerate() {
+        let Some(candidates) = positions.get_mut(key) else {
+            continue;
+        };
+        while candidates.front().is_some_and(|i| *i < next) {
+            candidates.pop_front();
+        }
+        if let Some(i) = candidates.pop_front() {
+            pairs.push((i, j));
+            next = i + 1;
+        }
+    }
+    pairs
+}
+
+// Keeps track of what the old tree looks like as the edits are made, to know which nodes have to move and
+// where they are.
+struct Script<T> {
+    edits : Vec<Edit<T>>,
+    inserted : usize,
+    parents : HashMap<EditNode, EditNode>,
+    children : HashMap<EditNode, Vec<EditNode>>,
+}
+
+impl<T : Clone + PartialEq> Script<T> {
+    fn new(old : &Tree<T>) -> Self {
+        let mut script = Script { edits : Vec::new(), inserted : 0, parents : HashMap::new(), children : HashMap::new() };
+        for parent in std::iter::once(old.root()).chain(old.descendants(old.root())) {
+            let children : Vec<EditNode> = old.children(parent).map(EditNode::Existing).collect();
+            for child in &children {
+                script.parents.insert(*child, EditNode::Existing(parent));
+            }
+            script.children.insert(EditNode::Existing(parent), children);
+        }
+        script
+    }
+
+    // Put the children of every new node in place, parents first. By the time a node's children are placed,
+    // the children before them are already the right ones, so every child goes to its index in the new tree.
+    fn build(mut self, old : &Tree<T>, new : &Tree<T>, new_to_old : &HashMap<NodeId, NodeId>) -> Vec<Edit<T>> {
+        let kept : HashSet<NodeId> = new_to_old.values().copied().collect();
+        // The old nodes that are kept or have a descendant that is.
+        let mut keeps = HashSet::new();
+        for edge in old.traverse(old.root()) {
+            if let Edge::Close(id) = edge {
+                if kept.contains(&id) || old.children(id).any(|child| keeps.contains(&child)) {
+                    keeps.insert(id);
+                }
+            }
+        }
+        // Subtrees without a node that is kept are removed first, so that they don't get in the way of the
+        // nodes around them. The others can only go once the nodes that are kept have been moved out.
+        let removed_first = |id : &NodeId| !keeps.contains(id);
+        let removed_last = |id : &NodeId| !kept.contains(id) && keeps.contains(id);
+        self.remove(old, &kept, removed_first);
+
+        let mut targets = HashMap::new();
+        targets.insert(new.root(), EditNode::Existing(old.root()));
+        self.update(old, old.root(), new, new.root());
+
+        for parent in std::iter::once(new.root()).chain(new.descendants(new.root())) {
+            let Some(target) = targets.get(&parent).copied() else {
+                continue;
+            };
+            for (index, child) in new.children(parent).enumerate() {
+                let placed = match new_to_old.get(&child) {
+                    Some(old_child) => {
+                        self.update(old, *old_child, new, child);
+                        self.place(EditNode::Existing(*old_child), target, index);
+                        EditNode::Existing(*old_child)
+                    },
+                    None => match new.get(child) {
+                        Some(value) => self.insert(target, index, value.clone()),
+                        None => continue,
+                    },
+                };
+                targets.insert(child, placed);
+            }
+        }
+
+        self.remove(old, &kept, removed_last);
+        self.edits
+    }
+
+    // Remove the nodes that `select` picks, unless one of their ancestors goes with them. Nodes that aren't
+    // kept never move, so their parent is still the one from the old tree.
+    fn remove(&mut self, old : &Tree<T>, kept : &HashSet<NodeId>, select : impl Fn(&NodeId) -> bool) {
+        for id in old.descendants(old.root()) {
+            let parent_kept = old.parent(id).is_some_and(|parent| kept.contains(&parent));
+            if parent_kept && select(&id) {
+                if let Some(EditNode::Existing(parent)) = self.parents.remove(&EditNode::Existing(id)) {
+                    if let Some(children) = self.children.get_mut(&EditNode::Existing(parent)) {
+                        children.retain(|child| *child != EditNode::Existing(id));
+                    }
+                }
+                self.edits.push(Edit::Remove { node : id });
+            }
+        }
+    }
+
+    fn update(&mut self, old : &Tree<T>, old_id : NodeId, new : &Tree<T>, new_id : NodeId) {
+        if let (Some(old_value), Some(new_value)) = (old.get(old_id), new.get(new_id)) {
+            if old_value != new_value {
+                self.edits.push(Edit::Update { node : old_id, value : new_value.clone() });
+            }
+        }
+    }
+
+    fn insert(&mut self, parent : EditNode, index : usize, value : T) -> EditNode {
+        let node = EditNode::Inserted(self.inserted);
+        self.inserted += 1;
+        self.edits.push(Edit::Insert { parent, index, value });
+        self.link(node, parent, index);
+        node
+    }
+
+    fn place(&mut self, node : EditNode, parent : EditNode, index : usize) {
+        let current = self.parents.get(&node).copied();
+        if current == Some(parent) && self.children.get(&parent).and_then(|children| children.get(index)) == Some(&node) {
+            return;
+        }
+        if let (EditNode::Existing(id), Some(current)) = (node, current) {
+            if let Some(children) = self.children.get_mut(&current) {
+                children.retain(|child| *child != node);
+            }
+            self.edits.push(Edit::Move { node : id, parent, index });
+            self.link(node, parent, index);
+        }
+    }
+
+    fn link(&mut self, node : EditNode, parent : EditNode, index : usize) {
+        self.parents.insert(node, parent);
+        let children = self.children.entry(parent).or_default();
+        children.insert(index.min(children.len()), node);
+    }
+}
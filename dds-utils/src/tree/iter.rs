@@ -0,0 +1,218 @@
+//! Iterators over the nodes of a [`Tree`]. None of them allocate, they only follow the links between nodes.
+
+use super::{NodeId, Tree};
+
+/// An event of a depth first walk through a tree, see [`Tree::traverse`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Edge {
+    /// The walk enters the node, before any of its children.
+    Open(NodeId),
+    /// The walk leaves the node, after all of its children.
+    Close(NodeId),
+}
+
+// Generates an iterator that walks from `front` to `back` along a sibling link. The two ends meet in the
+// middle when it is used from both sides.
+macro_rules! sibling_iterator {
+    ($(#[$attr:meta])* $name:ident, $next:ident, $next_back:ident) => {
+        $(#[$attr])*
+        pub struct $name<'a, T> {
+            tree : &'a Tree<T>,
+            front : Option<NodeId>,
+            back : Option<NodeId>,
+        }
+
+        impl<'a, T> Iterator for $name<'a, T> {
+            type Item = NodeId;
+
+            fn next(&mut self) -> Option<NodeId> {
+                let current = self.front?;
+                if self.front == self.back {
+                    self.front = None;
+                    self.back = None;
+                } else {
+                    self.front = self.tree.$next(current);
+                }
+                Some(current)
+            }
+        }
+
+        impl<'a, T> DoubleEndedIterator for $name<'a, T> {
+            fn next_back(&mut self) -> Option<NodeId> {
+                let current = self.back?;
+                if self.front == self.back {
+                    self.front = None;
+                    self.back = None;
+                } else {
+                    self.back = self.tree.$next_back(current);
+                }
+                Some(current)
+            }
+        }
+    };
+}
+
+sibling_iterator!(
+    /// The children of a node, from first to last.
+    Children, next_sibling, previous_sibling
+);
+sibling_iterator!(
+    /// The siblings after a node, from the closest one onwards.
+    FollowingSiblings, next_sibling, previous_sibling
+);
+sibling_iterator!(
+    /// The siblings before a node, from the closest one backwards.
+    PrecedingSiblings, previous_sibling, next_sibling
+);
+
+/// The ancestors of a node, starting with its parent.
+pub struct Ancestors<'a, T> {
+    tree : &'a Tree<T>,
+    next : Option<NodeId>,
+}
+
+impl<'a, T> Iterator for Ancestors<'a, T> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let current = self.next?;
+        self.next = self.tree.parent(current);
+        Some(current)
+    }
+}
+
+/// Opens and closes every node of a subtree in document order, see [`Tree::traverse`].
+pub struct Traverse<'a, T> {
+    tree : &'a Tree<T>,
+    root : NodeId,
+    front : Option<Edge>,
+    back : Option<Edge>,
+}
+
+impl<'a, T> Traverse<'a, T> {
+    fn following(&self, edge : Edge) -> Option<Edge> {
+        match edge {
+            Edge::Open(id) => match self.tree.first_child(id) {
+                Some(child) => Some(Edge::Open(child)),
+                None => Some(Edge::Close(id)),
+            },
+            Edge::Close(id) if id == self.root => None,
+            Edge::Close(id) => match self.tree.next_sibling(id) {
+                Some(sibling) => Some(Edge::Open(sibling)),
+                None => self.tree.parent(id).map(Edge::Close),
+            },
+        }
+    }
+
+    fn preceding(&self, edge : Edge) -> Option<Edge> {
+        match edge {
+            Edge::Close(id) => match self.tree.last_child(id) {
+                Some(child) => Some(Edge::Close(child)),
+                None => Some(Edge::Open(id)),
+            },
+            Edge::Open(id) if id == self.root => None,
+            Edge::Open(id) => match self.tree.previous_sibling(id) {
+                Some(sibling) => Some(Edge::Close(sibling)),
+                None => self.tree.parent(id).map(Edge::Open),
+            },
+        }
+    }
+}
+
+impl<'a, T> Iterator for Traverse<'a, T> {
+    type Item = Edge;
+
+    fn next(&mut self) -> Option<Edge> {
+        let current = self.front?;
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.front = self.following(current);
+        }
+        Some(current)
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Traverse<'a, T> {
+    fn next_back(&mut self) -> Option<Edge> {
+        let current = self.back?;
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.back = self.preceding(current);
+        }
+        Some(current)
+    }
+}
+
+/// The nodes below a node in pre-order, which is the order they appear in a document.
+pub struct Descendants<'a, T>(Traverse<'a, T>);
+
+impl<'a, T> Iterator for Descendants<'a, T> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        self.0.find_map(|edge| match edge {
+            Edge::Open(id) => Some(id),
+            Edge::Close(_) => None,
+        })
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Descendants<'a, T> {
+    fn next_back(&mut self) -> Option<NodeId> {
+        while let Some(edge) = self.0.next_back() {
+            if let Edge::Open(id) = edge {
+                return Some(id);
+            }
+        }
+        None
+    }
+}
+
+impl<T> Tree<T> {
+    pub fn children(&self, id : NodeId) -> Children<'_, T> {
+        Children { tree : self, front : self.first_child(id), back : self.last_child(id) }
+    }
+
+    /// The children of a node, from last to first.
+    pub fn reverse_children(&self, id : NodeId) -> std::iter::Rev<Children<'_, T>> {
+        self.children(id).rev()
+    }
+
+    pub fn ancestors(&self, id : NodeId) -> Ancestors<'_, T> {
+        Ancestors { tree : self, next : self.parent(id) }
+    }
+
+    pub fn preceding_siblings(&self, id : NodeId) -> PrecedingSiblings<'_, T> {
+        let back = self.parent(id).and_then(|parent| self.first_child(parent));
+        let front = self.previous_sibling(id);
+        PrecedingSiblings { tree : self, front, back : front.and(back) }
+    }
+
+    pub fn following_siblings(&self, id : NodeId) -> FollowingSiblings<'_, T> {
+        let back = self.parent(id).and_then(|parent| self.last_child(parent));
+        let front = self.next_sibling(id);
+        FollowingSiblings { tree : self, front, back : front.and(back) }
+    }
+
+    /// The nodes below `id`, not including `id` itself.
+    pub fn descendants(&self, id : NodeId) -> Descendants<'_, T> {
+        let mut traverse = self.traverse(id);
+        // Skip the node itself, the walk closes it last so that end can stay as it is.
+        traverse.next();
+        Descendants(traverse)
+    }
+
+    /// Walk the subtree of `id` depth first. Every node is opened before its children and closed after them,
+    /// which gives both pre-order and post-order hooks in a single pass.
+    pub fn traverse(&self, id : NodeId) -> Traverse<'_, T> {
+        let (front, back) = match self.contains(id) {
+            true => (Some(Edge::Open(id)), Some(Edge::Close(id))),
+            false => (None, None),
+        };
+        Traverse { tree : self, root : id, front, back }
+    }
+}
@@ -1,23 +1,408 @@
+//! An arena backed tree.
+//!
+//! Every node lives in a single `Vec` and refers to its relatives through [`NodeId`]s, which keeps the
+//! tree cheap to mutate and free of reference counting. This is the structure the HTML tree builder
+//! constructs the DOM in, so it supports the operations the specification's algorithms are written in
+//! terms of : appending, inserting next to a sibling, detaching and moving all children to a new parent.
+
 use std::num::NonZeroUsize;
 
+mod diff;
+mod iter;
+pub mod persistent;
+mod serialize;
+pub use diff::{apply, diff, Edit, EditNode};
+pub use iter::{Ancestors, Children, Descendants, Edge, FollowingSiblings, PrecedingSiblings, Traverse};
+pub use serialize::Flat;
+
+#[derive(Clone)]
 pub struct Tree<T> {
-    nodes : Vec<Node<T>>,
+    // Removed nodes leave an empty slot behind, so the ids of the other nodes stay the same. Empty slots
+    // are listed in `free` and reused by the next new nodes.
+    nodes : Vec<Slot<T>>,
+    free : Vec<usize>,
+    // Every node that is put in a slot gets the next generation, so an id is never valid for two different nodes.
+    generation : u32,
+}
+
+#[derive(Clone)]
+struct Slot<T> {
+    generation : u32,
+    node : Option<Node<T>>,
 }
 
+#[derive(Clone)]
 struct Node<T> {
     parent: Option<NodeId>,
     prev_sibling: Option<NodeId>,
     next_sibling: Option<NodeId>,
-    children: Option<Vec<NodeId>>,
+    first_child: Option<NodeId>,
+    last_child: Option<NodeId>,
     value : T,
 }
 
-pub struct NodeId(NonZeroUsize);
+/// A reference to a node in a [`Tree`].
+///
+/// The id is the node's slot in the tree plus the generation of the node. Once a node is removed its slot
+/// can be reused, and the generation tells the new node apart from the old one, so an id that outlived its
+/// node is rejected instead of pointing at whatever took its place. The index is stored plus one, so that
+/// `Option<NodeId>` takes up no more space than the id itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId {
+    index : NonZeroUsize,
+    generation : u32,
+}
+
+impl NodeId {
+    fn new(index : usize, generation : u32) -> NodeId {
+        NodeId { index : NonZeroUsize::MIN.saturating_add(index), generation }
+    }
+
+    fn index(self) -> usize {
+        self.index.get() - 1
+    }
+}
+
+/// Maps the ids from before a [`Tree::compact`] to the ones after it.
+pub struct Remapping {
+    // The generation and new id of the node that was in each slot before compacting.
+    ids : Vec<Option<(u32, NodeId)>>,
+}
+
+impl Remapping {
+    /// The new id of a node, or `None` if `old` wasn't valid before compacting either.
+    pub fn get(&self, old : NodeId) -> Option<NodeId> {
+        match self.ids.get(old.index())? {
+            Some((generation, new)) if *generation == old.generation => Some(*new),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeError {
+    /// The id doesn't belong to a node in the tree, or the node has been removed.
+    InvalidNode(NodeId),
+    /// The root node can't be removed, moved or given siblings.
+    Root,
+    /// The operation would make a node its own ancestor.
+    Cycle,
+    /// An edit refers to a child position or an inserted node that doesn't exist.
+    InvalidEdit,
+}
+
+impl std::fmt::Display for TreeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TreeError::InvalidNode(id) => write!(f, "Node {}v{} is not part of the tree", id.index(), id.generation),
+            TreeError::Root => write!(f, "The root node can't be moved or removed"),
+            TreeError::Cycle => write!(f, "A node can't be inserted into its own subtree"),
+            TreeError::InvalidEdit => write!(f, "The edit doesn't fit the tree"),
+        }
+    }
+}
+
+impl std::error::Error for TreeError {}
 
 impl<T> Tree<T> {
     pub fn new(root : T) -> Tree<T> {
         Tree {
-            nodes: vec![Node::new(root)],
+            nodes: vec![Slot { generation : 0, node : Some(Node::new(root)) }],
+            free: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    pub fn root(&self) -> NodeId {
+        NodeId::new(0, self.nodes[0].generation)
+    }
+
+    /// Create a node that isn't attached to anything yet. Use one of the insertion methods to put it in the tree.
+    pub fn new_node(&mut self, value : T) -> NodeId {
+        let generation = self.next_generation();
+        let slot = Slot { generation, node : Some(Node::new(value)) };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = slot;
+                index
+            },
+            None => {
+                self.nodes.push(slot);
+                self.nodes.len() - 1
+            },
+        };
+        NodeId::new(index, generation)
+    }
+
+    /// The number of nodes in the tree, including detached ones. There is always at least the root.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.nodes.len() - self.free.len()
+    }
+
+    pub fn contains(&self, id : NodeId) -> bool {
+        self.node(id).is_some()
+    }
+
+    pub fn get(&self, id : NodeId) -> Option<&T> {
+        self.node(id).map(|node| &node.value)
+    }
+
+    pub fn get_mut(&mut self, id : NodeId) -> Option<&mut T> {
+        self.node_mut(id).map(|node| &mut node.value)
+    }
+
+    pub fn parent(&self, id : NodeId) -> Option<NodeId> {
+        self.node(id)?.parent
+    }
+
+    pub fn first_child(&self, id : NodeId) -> Option<NodeId> {
+        self.node(id)?.first_child
+    }
+
+    pub fn last_child(&self, id : NodeId) -> Option<NodeId> {
+        self.node(id)?.last_child
+    }
+
+    pub fn previous_sibling(&self, id : NodeId) -> Option<NodeId> {
+        self.node(id)?.prev_sibling
+    }
+
+    pub fn next_sibling(&self, id : NodeId) -> Option<NodeId> {
+        self.node(id)?.next_sibling
+    }
+
+    /// Every node, whether it is attached to the tree or not, in the order of the slots they are stored in. That is
+    /// the order they were created in until a node is removed, after which new nodes reuse the slots that were freed.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &T)> {
+        self.nodes.iter().enumerate().filter_map(|(index, slot)| Some((NodeId::new(index, slot.generation), &slot.node.as_ref()?.value)))
+    }
+
+    /// Make `child` the last child of `parent`. If `child` is already in the tree it is moved, along with its subtree.
+    pub fn append(&mut self, parent : NodeId, child : NodeId) -> Result<(), TreeError> {
+        self.check_insert(parent, child)?;
+        self.detach(child)?;
+        let last = self.node(parent).and_then(|node| node.last_child);
+        self.link(child, Some(parent), last, None);
+        Ok(())
+    }
+
+    /// Make `child` the first child of `parent`.
+    pub fn prepend(&mut self, parent : NodeId, child : NodeId) -> Result<(), TreeError> {
+        self.check_insert(parent, child)?;
+        self.detach(child)?;
+        let first = self.node(parent).and_then(|node| node.first_child);
+        self.link(child, Some(parent), None, first);
+        Ok(())
+    }
+
+    /// Insert `node` as the sibling right before `sibling`.
+    pub fn insert_before(&mut self, sibling : NodeId, node : NodeId) -> Result<(), TreeError> {
+        let parent = self.parent_for_sibling(sibling, node)?;
+        self.detach(node)?;
+        let previous = self.node(sibling).and_then(|sibling| sibling.prev_sibling);
+        self.link(node, Some(parent), previous, Some(sibling));
+        Ok(())
+    }
+
+    /// Insert `node` as the sibling right after `sibling`.
+    pub fn insert_after(&mut self, sibling : NodeId, node : NodeId) -> Result<(), TreeError> {
+        let parent = self.parent_for_sibling(sibling, node)?;
+        self.detach(node)?;
+        let next = self.node(sibling).and_then(|sibling| sibling.next_sibling);
+        self.link(node, Some(parent), Some(sibling), next);
+        Ok(())
+    }
+
+    /// Take `id` and its subtree out of the tree. The nodes stay valid and can be inserted somewhere else.
+    pub fn detach(&mut self, id : NodeId) -> Result<(), TreeError> {
+        let node = self.node_mut(id).ok_or(TreeError::InvalidNode(id))?;
+        let parent = node.parent.take();
+        let previous = node.prev_sibling.take();
+        let next = node.next_sibling.take();
+
+        match previous {
+            Some(previous) => self.update(previous, |node| node.next_sibling = next),
+            None => if let Some(parent) = parent {
+                self.update(parent, |node| node.first_child = next);
+            },
+        }
+        match next {
+            Some(next) => self.update(next, |node| node.prev_sibling = previous),
+            None => if let Some(parent) = parent {
+                self.update(parent, |node| node.last_child = previous);
+            },
+        }
+        Ok(())
+    }
+
+    /// Remove `id` and everything below it from the tree. Their ids are no longer valid afterwards.
+    pub fn remove(&mut self, id : NodeId) -> Result<(), TreeError> {
+        if id == self.root() {
+            return Err(TreeError::Root);
+        }
+        self.detach(id)?;
+        // Walk the subtree without recursion, deeply nested documents would overflow the stack otherwise.
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            if let Some(node) = self.take_node(id) {
+                let mut child = node.first_child;
+                while let Some(id) = child {
+                    child = self.node(id).and_then(|node| node.next_sibling);
+                    pending.push(id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Move all children of `from` to the end of `to`'s children, keeping their order.
+    // https://html.spec.whatwg.org/multipage/parsing.html#adoption-agency-algorithm
+    pub fn reparent_children(&mut self, from : NodeId, to : NodeId) -> Result<(), TreeError> {
+        self.node(from).ok_or(TreeError::InvalidNode(from))?;
+        self.node(to).ok_or(TreeError::InvalidNode(to))?;
+        if self.is_ancestor_or_self(from, to) {
+            return Err(TreeError::Cycle);
+        }
+        while let Some(child) = self.node(from).and_then(|node| node.first_child) {
+            self.detach(child)?;
+            let last = self.node(to).and_then(|node| node.last_child);
+            self.link(child, Some(to), last, None);
+        }
+        Ok(())
+    }
+
+    /// Move all nodes to the front so that no empty slots are left, and drop the memory that was used by
+    /// removed nodes. This changes the ids of the nodes that are moved, use the returned [`Remapping`] to
+    /// look up their new ids. Ids that aren't updated are rejected afterwards, like the ids of removed nodes.
+    pub fn compact(&mut self) -> Remapping {
+        let mut ids = Vec::with_capacity(self.nodes.len());
+        let mut next = 0;
+        for index in 0..self.nodes.len() {
+            let slot = &self.nodes[index];
+            if slot.node.is_none() {
+                ids.push(None);
+                continue;
+            }
+            let old_generation = slot.generation;
+            // Nodes that stay where they are keep their id.
+            let generation = if index == next { old_generation } else { self.next_generation() };
+            ids.push(Some((old_generation, NodeId::new(next, generation))));
+            next += 1;
+        }
+        let remapping = Remapping { ids };
+
+        let new_id = |id : Option<NodeId>| id.and_then(|id| remapping.get(id));
+        let nodes = std::mem::take(&mut self.nodes);
+        self.nodes = nodes.into_iter().zip(&remapping.ids).filter_map(|(slot, id)| {
+            let (_, id) = (*id)?;
+            let mut node = slot.node?;
+            node.parent = new_id(node.parent);
+            node.prev_sibling = new_id(node.prev_sibling);
+            node.next_sibling = new_id(node.next_sibling);
+            node.first_child = new_id(node.first_child);
+            node.last_child = new_id(node.last_child);
+            Some(Slot { generation : id.generation, node : Some(node) })
+        }).collect();
+        self.free.clear();
+        remapping
+    }
+
+    // Empties the node's slot and puts it on the free list.
+    fn take_node(&mut self, id : NodeId) -> Option<Node<T>> {
+        let slot = self.nodes.get_mut(id.index())?;
+        if slot.generation != id.generation {
+            return None;
+        }
+        let node = slot.node.take()?;
+        self.free.push(id.index());
+        Some(node)
+    }
+
+    fn next_generation(&mut self) -> u32 {
+        self.generation = self.generation.wrapping_add(1);
+        self.generation
+    }
+
+    fn node(&self, id : NodeId) -> Option<&Node<T>> {
+        let slot = self.nodes.get(id.index())?;
+        if slot.generation != id.generation {
+            return None;
+        }
+        slot.node.as_ref()
+    }
+
+    fn node_mut(&mut self, id : NodeId) -> Option<&mut Node<T>> {
+        let slot = self.nodes.get_mut(id.index())?;
+        if slot.generation != id.generation {
+            return None;
+        }
+        slot.node.as_mut()
+    }
+
+    fn update(&mut self, id : NodeId, f : impl FnOnce(&mut Node<T>)) {
+        if let Some(node) = self.node_mut(id) {
+            f(node);
+        }
+    }
+
+    fn is_ancestor_or_self(&self, ancestor : NodeId, mut id : NodeId) -> bool {
+        loop {
+            if id == ancestor {
+                return true;
+            }
+            match self.parent(id) {
+                Some(parent) => id = parent,
+                None => return false,
+            }
+        }
+    }
+
+    // `child` can go below `parent` as long as both exist and `child` isn't the root or one of `parent`'s ancestors.
+    fn check_insert(&self, parent : NodeId, child : NodeId) -> Result<(), TreeError> {
+        self.node(parent).ok_or(TreeError::InvalidNode(parent))?;
+        self.node(child).ok_or(TreeError::InvalidNode(child))?;
+        if child == self.root() {
+            return Err(TreeError::Root);
+        }
+        // A node without children can only be its own ancestor, which saves walking up from deeply nested parents.
+        let has_children = self.node(child).is_some_and(|node| node.first_child.is_some());
+        if child == parent || (has_children && self.is_ancestor_or_self(child, parent)) {
+            return Err(TreeError::Cycle);
+        }
+        Ok(())
+    }
+
+    fn parent_for_sibling(&self, sibling : NodeId, node : NodeId) -> Result<NodeId, TreeError> {
+        self.node(sibling).ok_or(TreeError::InvalidNode(sibling))?;
+        // A node without a parent is either the root or detached, neither can have siblings.
+        let parent = self.parent(sibling).ok_or(TreeError::Root)?;
+        if node == sibling {
+            return Err(TreeError::Cycle);
+        }
+        self.check_insert(parent, node)?;
+        Ok(parent)
+    }
+
+    // Link a detached node in between `previous` and `next`, which have to be adjacent children of `parent`.
+    fn link(&mut self, id : NodeId, parent : Option<NodeId>, previous : Option<NodeId>, next : Option<NodeId>) {
+        self.update(id, |node| {
+            node.parent = parent;
+            node.prev_sibling = previous;
+            node.next_sibling = next;
+        });
+
+        match previous {
+            Some(previous) => self.update(previous, |node| node.next_sibling = Some(id)),
+            None => if let Some(parent) = parent {
+                self.update(parent, |node| node.first_child = Some(id));
+            },
+        }
+        match next {
+            Some(next) => self.update(next, |node| node.prev_sibling = Some(id)),
+            None => if let Some(parent) = parent {
+                self.update(parent, |node| node.last_child = Some(id));
+            },
         }
     }
 }
@@ -28,9 +413,9 @@ impl<T> Node<T> {
             parent: None,
             prev_sibling: None,
             next_sibling: None,
-            children: None,
+            first_child: None,
+            last_child: None,
             value,
         }
     }
 }
-
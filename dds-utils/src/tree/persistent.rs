@@ -0,0 +1,162 @@
+//! A tree that can be snapshotted.
+//!
+//! Nodes are reference counted and own their children, so cloning a [`PersistentTree`] only copies a
+//! pointer. Changing a version copies the nodes on the path from the root to the change and shares
+//! everything else with the other versions, which keep seeing the tree as it was. Since nothing is ever
+//! changed in place once it is shared, a snapshot can be handed to other threads while the original
+//! keeps being edited.
+//!
+//! Nodes don't know their parents, so they are addressed by their path : the index of the child to take
+//! at every level, starting from the root. The root itself is the empty path.
+
+use std::sync::Arc;
+
+use super::{Edge, Tree};
+
+pub struct PersistentNode<T> {
+    value : T,
+    children : Vec<Arc<PersistentNode<T>>>,
+}
+
+// Cloning a node copies its value and the pointers to its children, not the children themselves.
+impl<T : Clone> Clone for PersistentNode<T> {
+    fn clone(&self) -> Self {
+        Self { value : self.value.clone(), children : self.children.clone() }
+    }
+}
+
+// Dropping the children one by one would recurse once per level, which overflows the stack for deep trees.
+// The children that no other version shares are taken apart here instead, so they drop without any children.
+impl<T> Drop for PersistentNode<T> {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(child) = stack.pop() {
+            if let Ok(mut node) = Arc::try_unwrap(child) {
+                stack.append(&mut node.children);
+            }
+        }
+    }
+}
+
+impl<T> PersistentNode<T> {
+    fn new(value : T) -> Self {
+        Self { value, children : Vec::new() }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn child(&self, index : usize) -> Option<&PersistentNode<T>> {
+        self.children.get(index).map(|child| &**child)
+    }
+
+    pub fn children(&self) -> impl DoubleEndedIterator<Item = &PersistentNode<T>> + ExactSizeIterator {
+        self.children.iter().map(|child| &**child)
+    }
+
+    /// Whether both are the same node, rather than two nodes that happen to be equal. Unchanged subtrees of
+    /// two versions of a tree are the same node.
+    pub fn ptr_eq(&self, other : &PersistentNode<T>) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+pub struct PersistentTree<T> {
+    root : Arc<PersistentNode<T>>,
+}
+
+/// Takes a snapshot, which costs no more than a pointer copy.
+impl<T> Clone for PersistentTree<T> {
+    fn clone(&self) -> Self {
+        Self { root : Arc::clone(&self.root) }
+    }
+}
+
+impl<T> PersistentTree<T> {
+    pub fn new(root : T) -> Self {
+        Self { root : Arc::new(PersistentNode::new(root)) }
+    }
+
+    pub fn root(&self) -> &PersistentNode<T> {
+        &self.root
+    }
+
+    pub fn get(&self, path : &[usize]) -> Option<&PersistentNode<T>> {
+        path.iter().try_fold(&*self.root, |node, index| node.child(*index))
+    }
+
+    /// Whether both versions are exactly the same, without comparing any nodes.
+    pub fn ptr_eq(&self, other : &PersistentTree<T>) -> bool {
+        Arc::ptr_eq(&self.root, &other.root)
+    }
+}
+
+impl<T : Clone> PersistentTree<T> {
+    pub fn get_mut(&mut self, path : &[usize]) -> Option<&mut T> {
+        self.node_mut(path).map(|node| &mut node.value)
+    }
+
+    /// Add a child to the end of the node at `parent`, returns its index.
+    pub fn append(&mut self, parent : &[usize], value : T) -> Option<usize> {
+        let parent = self.node_mut(parent)?;
+        parent.children.push(Arc::new(PersistentNode::new(value)));
+        Some(parent.children.len() - 1)
+    }
+
+    /// Insert a child at `index` among the children of the node at `parent`.
+    pub fn insert(&mut self, parent : &[usize], index : usize, value : T) -> Option<()> {
+        if index > self.get(parent)?.children.len() {
+            return None;
+        }
+        let parent = self.node_mut(parent)?;
+        parent.children.insert(index, Arc::new(PersistentNode::new(value)));
+        Some(())
+    }
+
+    /// Take the node at `path` out of the tree, its subtree is returned as a tree of its own. The root can't be removed.
+    pub fn remove(&mut self, path : &[usize]) -> Option<PersistentTree<T>> {
+        let (index, parent) = path.split_last()?;
+        self.get(path)?;
+        let parent = self.node_mut(parent)?;
+        Some(PersistentTree { root : parent.children.remove(*index) })
+    }
+
+    // Copy every node on the path that is shared with another version, so that it can be changed.
+    fn node_mut(&mut self, path : &[usize]) -> Option<&mut PersistentNode<T>> {
+        // Check the path first so that nothing gets copied for an invalid one.
+        self.get(path)?;
+        let mut node = Arc::make_mut(&mut self.root);
+        for index in path {
+            node = Arc::make_mut(&mut node.children[*index]);
+        }
+        Some(node)
+    }
+}
+
+/// Copy the nodes that are attached to the root of `tree`.
+impl<T : Clone> From<&Tree<T>> for PersistentTree<T> {
+    fn from(tree : &Tree<T>) -> Self {
+        // The nodes that have been opened but not closed yet, their children are collected as they close.
+        let mut open : Vec<PersistentNode<T>> = Vec::new();
+        let mut root = None;
+        for edge in tree.traverse(tree.root()) {
+            match edge {
+                Edge::Open(id) => if let Some(value) = tree.get(id) {
+                    open.push(PersistentNode::new(value.clone()));
+                },
+                Edge::Close(_) => if let Some(node) = open.pop() {
+                    match open.last_mut() {
+                        Some(parent) => parent.children.push(Arc::new(node)),
+                        None => root = Some(node),
+                    }
+                },
+            }
+        }
+        match root {
+            Some(root) => Self { root : Arc::new(root) },
+            // The root is always in the tree, so the walk always opens and closes it.
+            None => unreachable!("the traversal didn't close the root"),
+        }
+    }
+}
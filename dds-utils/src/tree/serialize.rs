@@ -0,0 +1,348 @@
+//! `miniserde` support for [`Tree`].
+//!
+//! A tree serializes as nested JSON, every node is an object with its `value` and an array of `children` :
+//!
+//! `{"value":"root","children":[{"value":"a","children":[]}]}`
+//!
+//! [`Tree::flat`] writes the same nodes as a table instead, in document order. Every entry names the index
+//! of its parent, which always comes before it, and the root is the only entry without one :
+//!
+//! `{"nodes":[{"value":"root","parent":null},{"value":"a","parent":0}]}`
+//!
+//! Either form deserializes back into a `Tree`. Only the nodes attached to the root are written out, and
+//! the ids of the nodes aren't part of either form.
+
+use std::borrow::Cow;
+
+use miniserde::{de::{self, Deserialize, Visitor}, make_place, ser::{self, Fragment, Serialize}, Error, Result};
+
+use super::{Children, Edge, NodeId, Traverse, Tree};
+
+impl<T : Serialize> Serialize for Tree<T> {
+    fn begin(&self) -> Fragment<'_> {
+        nested(self, self.root())
+    }
+}
+
+impl<T> Tree<T> {
+    /// Serializes the tree as a flat table of nodes, see the [module documentation](self).
+    pub fn flat(&self) -> Flat<'_, T> {
+        Flat { tree : self }
+    }
+}
+
+fn nested<T : Serialize>(tree : &Tree<T>, id : NodeId) -> Fragment<'_> {
+    match tree.get(id) {
+        Some(value) => Fragment::Map(Box::new(NestedFields { value, children : NestedChildren { tree, id }, index : 0 })),
+        None => Fragment::Null,
+    }
+}
+
+struct NestedNode<'a, T> {
+    tree : &'a Tree<T>,
+    id : NodeId,
+}
+
+impl<'a, T : Serialize> Serialize for NestedNode<'a, T> {
+    fn begin(&self) -> Fragment<'_> {
+        nested(self.tree, self.id)
+    }
+}
+
+struct NestedFields<'a, T> {
+    value : &'a T,
+    children : NestedChildren<'a, T>,
+    index : usize,
+}
+
+impl<'a, T : Serialize> ser::Map for NestedFields<'a, T> {
+    fn next(&mut self) -> Option<(Cow<'_, str>, &dyn Serialize)> {
+        let index = self.index;
+        self.index += 1;
+        match index {
+            0 => Some((Cow::Borrowed("value"), self.value)),
+            1 => Some((Cow::Borrowed("children"), &self.children)),
+            _ => None,
+        }
+    }
+}
+
+struct NestedChildren<'a, T> {
+    tree : &'a Tree<T>,
+    id : NodeId,
+}
+
+impl<'a, T : Serialize> Serialize for NestedChildren<'a, T> {
+    fn begin(&self) -> Fragment<'_> {
+        Fragment::Seq(Box::new(NestedSeq { tree : self.tree, children : self.tree.children(self.id), current : None }))
+    }
+}
+
+struct NestedSeq<'a, T> {
+    tree : &'a Tree<T>,
+    children : Children<'a, T>,
+    current : Option<NestedNode<'a, T>>,
+}
+
+impl<'a, T : Serialize> ser::Seq for NestedSeq<'a, T> {
+    fn next(&mut self) -> Option<&dyn Serialize> {
+        let id = self.children.next()?;
+        self.current = Some(NestedNode { tree : self.tree, id });
+        self.current.as_ref().map(|node| node as &dyn Serialize)
+    }
+}
+
+/// A [`Tree`] that serializes as a flat table of nodes, see [`Tree::flat`].
+pub struct Flat<'a, T> {
+    tree : &'a Tree<T>,
+}
+
+impl<'a, T : Serialize> Serialize for Flat<'a, T> {
+    fn begin(&self) -> Fragment<'_> {
+        Fragment::Map(Box::new(FlatFields { nodes : FlatNodes { tree : self.tree }, done : false }))
+    }
+}
+
+struct FlatFields<'a, T> {
+    nodes : FlatNodes<'a, T>,
+    done : bool,
+}
+
+impl<'a, T : Serialize> ser::Map for FlatFields<'a, T> {
+    fn next(&mut self) -> Option<(Cow<'_, str>, &dyn Serialize)> {
+        if self.done {
+            return None;
+        }
+        self.done = true;
+        Some((Cow::Borrowed("nodes"), &self.nodes))
+    }
+}
+
+struct FlatNodes<'a, T> {
+    tree : &'a Tree<T>,
+}
+
+impl<'a, T : Serialize> Serialize for FlatNodes<'a, T> {
+    fn begin(&self) -> Fragment<'_> {
+        let traverse = self.tree.traverse(self.tree.root());
+        Fragment::Seq(Box::new(FlatSeq { tree : self.tree, traverse, open : Vec::new(), count : 0, current : None }))
+    }
+}
+
+struct FlatSeq<'a, T> {
+    tree : &'a Tree<T>,
+    traverse : Traverse<'a, T>,
+    // The indices of the nodes that have been opened but not closed yet, the last one is the parent of the next node.
+    open : Vec<usize>,
+    count : usize,
+    current : Option<FlatEntry<'a, T>>,
+}
+
+impl<'a, T : Serialize> ser::Seq for FlatSeq<'a, T> {
+    fn next(&mut self) -> Option<&dyn Serialize> {
+        loop {
+            match self.traverse.next()? {
+                Edge::Open(id) => {
+                    let value = self.tree.get(id)?;
+                    let parent = self.open.last().copied();
+                    self.open.push(self.count);
+                    self.count += 1;
+                    self.current = Some(FlatEntry { value, parent });
+                    return self.current.as_ref().map(|entry| entry as &dyn Serialize);
+                },
+                Edge::Close(_) => {
+                    self.open.pop();
+                },
+            }
+        }
+    }
+}
+
+struct FlatEntry<'a, T> {
+    value : &'a T,
+    parent : Option<usize>,
+}
+
+impl<'a, T : Serialize> Serialize for FlatEntry<'a, T> {
+    fn begin(&self) -> Fragment<'_> {
+        Fragment::Map(Box::new(FlatEntryFields { entry : self, index : 0 }))
+    }
+}
+
+struct FlatEntryFields<'a, T> {
+    entry : &'a FlatEntry<'a, T>,
+    index : usize,
+}
+
+impl<'a, T : Serialize> ser::Map for FlatEntryFields<'a, T> {
+    fn next(&mut self) -> Option<(Cow<'_, str>, &dyn Serialize)> {
+        let index = self.index;
+        self.index += 1;
+        match index {
+            0 => Some((Cow::Borrowed("value"), self.entry.value)),
+            1 => Some((Cow::Borrowed("parent"), &self.entry.parent)),
+            _ => None,
+        }
+    }
+}
+
+// miniserde hands out a visitor that lives in the `Option` being deserialized into, `Place` wraps that option
+// so that the visitor can be implemented for it.
+make_place!(Place);
+
+impl<T : Deserialize> Deserialize for Tree<T> {
+    fn begin(out : &mut Option<Self>) -> &mut dyn Visitor {
+        Place::new(out)
+    }
+}
+
+impl<T : Deserialize> Visitor for Place<Tree<T>> {
+    fn map(&mut self) -> Result<Box<dyn de::Map + '_>> {
+        Ok(Box::new(TreeBuilder { out : &mut self.out, value : None, children : None, nodes : None }))
+    }
+}
+
+// Accepts both forms, which one it is only becomes clear from the keys.
+struct TreeBuilder<'a, T> {
+    out : &'a mut Option<Tree<T>>,
+    value : Option<T>,
+    children : Option<Vec<NestedValue<T>>>,
+    nodes : Option<Vec<FlatValue<T>>>,
+}
+
+impl<'a, T : Deserialize> de::Map for TreeBuilder<'a, T> {
+    fn key(&mut self, key : &str) -> Result<&mut dyn Visitor> {
+        match key {
+            "value" => Ok(Deserialize::begin(&mut self.value)),
+            "children" => Ok(Deserialize::begin(&mut self.children)),
+            "nodes" => Ok(Deserialize::begin(&mut self.nodes)),
+            _ => Ok(<dyn Visitor>::ignore()),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let tree = match (self.value.take(), self.children.take(), self.nodes.take()) {
+            (Some(value), children, None) => from_nested(NestedValue { value, children : children.unwrap_or_default() }),
+            (None, None, Some(nodes)) => from_flat(nodes)?,
+            _ => return Err(Error),
+        };
+        *self.out = Some(tree);
+        Ok(())
+    }
+}
+
+struct NestedValue<T> {
+    value : T,
+    children : Vec<NestedValue<T>>,
+}
+
+impl<T : Deserialize> Deserialize for NestedValue<T> {
+    fn begin(out : &mut Option<Self>) -> &mut dyn Visitor {
+        Place::new(out)
+    }
+}
+
+impl<T : Deserialize> Visitor for Place<NestedValue<T>> {
+    fn map(&mut self) -> Result<Box<dyn de::Map + '_>> {
+        Ok(Box::new(NestedBuilder { out : &mut self.out, value : None, children : None }))
+    }
+}
+
+struct NestedBuilder<'a, T> {
+    out : &'a mut Option<NestedValue<T>>,
+    value : Option<T>,
+    children : Option<Vec<NestedValue<T>>>,
+}
+
+impl<'a, T : Deserialize> de::Map for NestedBuilder<'a, T> {
+    fn key(&mut self, key : &str) -> Result<&mut dyn Visitor> {
+        match key {
+            "value" => Ok(Deserialize::begin(&mut self.value)),
+            "children" => Ok(Deserialize::begin(&mut self.children)),
+            _ => Ok(<dyn Visitor>::ignore()),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let value = self.value.take().ok_or(Error)?;
+        *self.out = Some(NestedValue { value, children : self.children.take().unwrap_or_default() });
+        Ok(())
+    }
+}
+
+struct FlatValue<T> {
+    value : T,
+    parent : Option<usize>,
+}
+
+impl<T : Deserialize> Deserialize for FlatValue<T> {
+    fn begin(out : &mut Option<Self>) -> &mut dyn Visitor {
+        Place::new(out)
+    }
+}
+
+impl<T : Deserialize> Visitor for Place<FlatValue<T>> {
+    fn map(&mut self) -> Result<Box<dyn de::Map + '_>> {
+        Ok(Box::new(FlatBuilder { out : &mut self.out, value : None, parent : None }))
+    }
+}
+
+struct FlatBuilder<'a, T> {
+    out : &'a mut Option<FlatValue<T>>,
+    value : Option<T>,
+    parent : Option<Option<usize>>,
+}
+
+impl<'a, T : Deserialize> de::Map for FlatBuilder<'a, T> {
+    fn key(&mut self, key : &str) -> Result<&mut dyn Visitor> {
+        match key {
+            "value" => Ok(Deserialize::begin(&mut self.value)),
+            "parent" => Ok(Deserialize::begin(&mut self.parent)),
+            _ => Ok(<dyn Visitor>::ignore()),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let value = self.value.take().ok_or(Error)?;
+        *self.out = Some(FlatValue { value, parent : self.parent.take().flatten() });
+        Ok(())
+    }
+}
+
+// Builds the tree without recursing, so deep trees don't overflow the stack.
+fn from_nested<T>(root : NestedValue<T>) -> Tree<T> {
+    let mut tree = Tree::new(root.value);
+    let mut stack = vec![(tree.root(), root.children.into_iter())];
+    while let Some((parent, children)) = stack.last_mut() {
+        let parent = *parent;
+        match children.next() {
+            Some(child) => {
+                let id = tree.new_node(child.value);
+                let _ = tree.append(parent, id);
+                stack.push((id, child.children.into_iter()));
+            },
+            None => {
+                stack.pop();
+            },
+        }
+    }
+    tree
+}
+
+fn from_flat<T>(nodes : Vec<FlatValue<T>>) -> Result<Tree<T>> {
+    let mut nodes = nodes.into_iter();
+    let root = nodes.next().ok_or(Error)?;
+    if root.parent.is_some() {
+        return Err(Error);
+    }
+    let mut tree = Tree::new(root.value);
+    let mut ids = vec![tree.root()];
+    for node in nodes {
+        // The parent has to come first, which also rules out cycles.
+        let parent = node.parent.and_then(|parent| ids.get(parent).copied()).ok_or(Error)?;
+        let id = tree.new_node(node.value);
+        tree.append(parent, id).map_err(|_| Error)?;
+        ids.push(id);
+    }
+    Ok(tree)
+}
@@ -1,16 +1,51 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use html_parser::{sax::{EventHandler, EventSink}, HtmlParser, ParseState};
 
-fn fibonacci(n: u64) -> u64 {
-    match n {
-        0 => 1,
-        1 => 1,
-        n => fibonacci(n-1) + fibonacci(n-2),
+// Builds a document of roughly `size` bytes out of the kind of markup found on a typical page.
+fn generate_document(size: usize) -> String {
+    let mut html = String::from("<!DOCTYPE html><html><head><title>Benchmark</title><style>p > a { color: red; }</style></head><body>");
+    let mut i = 0;
+    while html.len() < size {
+        html.push_str(&format!(
+            "<div class=\"section\" id=\"s{i}\"><h2>Section {i}</h2><p>Some <b>bold</b> and <a href=\"/page/{i}\">linked</a> text.\
+            <p>Another paragraph that is closed implicitly.<ul><li>First<li>Second<li>Third</ul>\
+            <table><tr><td>{i}<td>Cell<tr><td>Row<td>Two</table><!-- end of section {i} --></div>\n",
+            i = i
+        ));
+        i += 1;
     }
+    html.push_str("</body></html>");
+    html
 }
 
+struct NullHandler;
+
+impl EventHandler for NullHandler {}
+
 fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("tree_builder", |b| b.iter(|| fibonacci(black_box(40))));
+    let mut group = c.benchmark_group("tree_builder");
+    group.sample_size(10);
+    for size in [1 << 20, 8 << 20] {
+        let input = generate_document(size);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("single_threaded", size), &input, |b, input| {
+            b.iter(|| HtmlParser::parse(black_box(input), ParseState::new()).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("pipelined", size), &input, |b, input| {
+            b.iter(|| HtmlParser::parse_pipelined(black_box(input), ParseState::new()).unwrap())
+        });
+
+        // Without building a tree the tokenizer's share of the work is larger, which is where the pipeline helps the most.
+        group.bench_with_input(BenchmarkId::new("single_threaded_events", size), &input, |b, input| {
+            b.iter(|| HtmlParser::parse(black_box(input), ParseState::with_sink(EventSink::new(NullHandler))).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("pipelined_events", size), &input, |b, input| {
+            b.iter(|| HtmlParser::parse_pipelined(black_box(input), ParseState::with_sink(EventSink::new(NullHandler))).unwrap())
+        });
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
-criterion_main!(benches);
\ No newline at end of file
+criterion_main!(benches);
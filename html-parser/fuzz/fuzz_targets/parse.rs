@@ -0,0 +1,14 @@
+#![no_main]
+
+use html_parser::{limits::ParserLimits, HtmlParser, ParseState};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        // Errors are fine, only panics and hangs are bugs.
+        let _ = HtmlParser::parse(input, ParseState::new());
+        let _ = HtmlParser::parse(input, ParseState::new().with_limits(ParserLimits::untrusted()));
+        // The streaming parser first sees the input before it is closed, which takes different code paths at the end of the input.
+        let _ = HtmlParser::parse_stream(data, ParseState::new());
+    }
+});
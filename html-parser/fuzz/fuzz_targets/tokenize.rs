@@ -0,0 +1,13 @@
+#![no_main]
+
+use html_parser::{preproccesor::PreProccessor, tokenizer::Tokenizer};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        if let Ok(html) = PreProccessor::new(input) {
+            // Errors are fine, only panics and hangs are bugs.
+            for _ in Tokenizer::new(html) {}
+        }
+    }
+});
@@ -0,0 +1,100 @@
+//! Checks HTML files for parse errors and conformance problems, see [`html_parser::lint`].
+//!
+//! ```text
+//! html-lint [--format human|json] FILE...
+//! ```
+//!
+//! `-` reads from standard input. The exit code is 0 if nothing was found, 1 if there are only warnings,
+//! 2 if there are errors and 3 if a file couldn't be read or the arguments are wrong.
+
+use std::io::Read;
+
+use html_parser::lint::{lint, Diagnostic, Severity};
+use miniserde::json::{self, Array, Value};
+
+const USAGE : &str = "usage: html-lint [--format human|json] FILE...";
+
+enum Format {
+    Human,
+    Json,
+}
+
+fn read(file : &str) -> std::io::Result<String> {
+    match file {
+        "-" => {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input)?;
+            Ok(input)
+        },
+        path => std::fs::read_to_string(path),
+    }
+}
+
+fn main() {
+    let mut format = Format::Human;
+    let mut files = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--format" => args.next(),
+            _ => arg.strip_prefix("--format=").map(String::from),
+        };
+        match (arg.as_str(), value.as_deref()) {
+            (_, Some("human")) => format = Format::Human,
+            (_, Some("json")) => format = Format::Json,
+            ("-h" | "--help", _) => {
+                println!("{}", USAGE);
+                return;
+            },
+            (arg, None) if arg == "-" || !arg.starts_with('-') => files.push(arg.to_string()),
+            _ => {
+                eprintln!("{}", USAGE);
+                std::process::exit(3);
+            },
+        }
+    }
+    if files.is_empty() {
+        eprintln!("{}", USAGE);
+        std::process::exit(3);
+    }
+
+    let mut results : Vec<(String, Vec<Diagnostic>)> = Vec::new();
+    let mut failed = false;
+    for file in files {
+        let diagnostics = read(&file).map_err(|e| e.to_string()).and_then(|html| lint(&html).map_err(|e| e.to_string()));
+        match diagnostics {
+            Ok(diagnostics) => results.push((file, diagnostics)),
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                failed = true;
+            },
+        }
+    }
+
+    match format {
+        Format::Human => {
+            for (file, diagnostics) in &results {
+                for diagnostic in diagnostics {
+                    println!("{}", diagnostic.to_human(file));
+                }
+            }
+            let count = |severity| results.iter().flat_map(|(_, diagnostics)| diagnostics).filter(|diagnostic| diagnostic.severity == severity).count();
+            println!("{} errors, {} warnings", count(Severity::Error), count(Severity::Warning));
+        },
+        Format::Json => {
+            let array : Array = results.iter()
+                .flat_map(|(file, diagnostics)| diagnostics.iter().map(move |diagnostic| diagnostic.to_json(file)))
+                .collect();
+            println!("{}", json::to_string(&Value::Array(array)));
+        },
+    }
+
+    let worst = results.iter().flat_map(|(_, diagnostics)| diagnostics).map(|diagnostic| diagnostic.severity).max();
+    let code = match worst {
+        _ if failed => 3,
+        Some(Severity::Error) => 2,
+        Some(Severity::Warning) => 1,
+        None => 0,
+    };
+    std::process::exit(code);
+}
@@ -0,0 +1,153 @@
+//! Character reference matching for the tokenizer.
+//!
+//! NOTE: Only a small set of the named character references is supported, the full table has over
+//! 2000 entries. Names that aren't in the table are left in the text as is, the same way the
+//! specification treats names that don't match anything.
+
+use crate::error::TokenizerErrorCode;
+
+pub(crate) enum CharacterReference {
+    /// The reference and the number of bytes it takes up after the '&'.
+    Found(char, usize),
+    /// Not a character reference, the '&' is just text.
+    NotFound,
+    /// The input ends before it is clear whether this is a reference or not.
+    Incomplete,
+}
+
+// Names with and without the trailing semicolon are separate entries, like in the specification.
+// Only the legacy references are allowed to leave out the semicolon.
+const NAMED_REFERENCES : &[(&str, char)] = &[
+    ("amp;", '&'), ("amp", '&'),
+    ("lt;", '<'), ("lt", '<'),
+    ("gt;", '>'), ("gt", '>'),
+    ("quot;", '"'), ("quot", '"'),
+    ("apos;", '\''),
+    ("nbsp;", '\u{00A0}'), ("nbsp", '\u{00A0}'),
+    ("copy;", '\u{00A9}'), ("copy", '\u{00A9}'),
+    ("reg;", '\u{00AE}'), ("reg", '\u{00AE}'),
+    ("trade;", '\u{2122}'),
+    ("shy;", '\u{00AD}'), ("shy", '\u{00AD}'),
+    ("deg;", '\u{00B0}'), ("deg", '\u{00B0}'),
+    ("plusmn;", '\u{00B1}'), ("plusmn", '\u{00B1}'),
+    ("times;", '\u{00D7}'), ("times", '\u{00D7}'),
+    ("divide;", '\u{00F7}'), ("divide", '\u{00F7}'),
+    ("middot;", '\u{00B7}'), ("middot", '\u{00B7}'),
+    ("sect;", '\u{00A7}'), ("sect", '\u{00A7}'),
+    ("para;", '\u{00B6}'), ("para", '\u{00B6}'),
+    ("cent;", '\u{00A2}'), ("cent", '\u{00A2}'),
+    ("pound;", '\u{00A3}'), ("pound", '\u{00A3}'),
+    ("yen;", '\u{00A5}'), ("yen", '\u{00A5}'),
+    ("euro;", '\u{20AC}'),
+    ("laquo;", '\u{00AB}'), ("laquo", '\u{00AB}'),
+    ("raquo;", '\u{00BB}'), ("raquo", '\u{00BB}'),
+    ("iexcl;", '\u{00A1}'), ("iexcl", '\u{00A1}'),
+    ("iquest;", '\u{00BF}'), ("iquest", '\u{00BF}'),
+    ("ndash;", '\u{2013}'),
+    ("mdash;", '\u{2014}'),
+    ("lsquo;", '\u{2018}'),
+    ("rsquo;", '\u{2019}'),
+    ("ldquo;", '\u{201C}'),
+    ("rdquo;", '\u{201D}'),
+    ("bull;", '\u{2022}'),
+    ("hellip;", '\u{2026}'),
+];
+
+// https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state
+const C1_REPLACEMENTS : [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// Match the character reference at the start of `rest`, which is everything after the '&'.
+/// `complete` is false while more input may still follow `rest`. Parse errors are added to `errors`.
+// https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+pub(crate) fn match_character_reference(rest : &str, in_attribute : bool, complete : bool, errors : &mut Vec<TokenizerErrorCode>) -> CharacterReference {
+    match rest.as_bytes().first() {
+        Some(b'#') => match_numeric(rest, complete, errors),
+        Some(c) if c.is_ascii_alphanumeric() => match_named(rest, in_attribute, complete, errors),
+        Some(_) => CharacterReference::NotFound,
+        None if complete => CharacterReference::NotFound,
+        None => CharacterReference::Incomplete,
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#named-character-reference-state
+fn match_named(rest : &str, in_attribute : bool, complete : bool, errors : &mut Vec<TokenizerErrorCode>) -> CharacterReference {
+    // A longer name might still match once more input arrives.
+    if !complete && rest.bytes().all(|c| c.is_ascii_alphanumeric()) {
+        return CharacterReference::Incomplete;
+    }
+    let longest = NAMED_REFERENCES.iter().filter(|(name, _)| rest.starts_with(name)).max_by_key(|(name, _)| name.len());
+    match longest {
+        Some((name, c)) => {
+            // For historical reasons, "&copy=" and "&copyx" in attribute values are not references.
+            let next = rest.as_bytes().get(name.len());
+            if in_attribute && !name.ends_with(';') && matches!(next, Some(c) if *c == b'=' || c.is_ascii_alphanumeric()) {
+                CharacterReference::NotFound
+            } else {
+                if !name.ends_with(';') {
+                    errors.push(TokenizerErrorCode::MissingSemicolonAfterCharacterReference);
+                }
+                CharacterReference::Found(*c, name.len())
+            }
+        },
+        None => {
+            // https://html.spec.whatwg.org/multipage/parsing.html#ambiguous-ampersand-state
+            let name_length = rest.bytes().take_while(|c| c.is_ascii_alphanumeric()).count();
+            if rest.as_bytes().get(name_length) == Some(&b';') {
+                errors.push(TokenizerErrorCode::UnknownNamedCharacterReference);
+            }
+            CharacterReference::NotFound
+        },
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-state
+fn match_numeric(rest : &str, complete : bool, errors : &mut Vec<TokenizerErrorCode>) -> CharacterReference {
+    let bytes = rest.as_bytes();
+    let (radix, start) = match bytes.get(1) {
+        Some(b'x') | Some(b'X') => (16, 2),
+        _ => (10, 1),
+    };
+    let digits = bytes[start.min(bytes.len())..].iter().take_while(|c| (**c as char).is_digit(radix)).count();
+    let end = start + digits;
+    if end >= bytes.len() && !complete {
+        return CharacterReference::Incomplete;
+    }
+    if digits == 0 {
+        errors.push(TokenizerErrorCode::AbsenceOfDigitsInNumericCharacterReference);
+        return CharacterReference::NotFound;
+    }
+    let consumed = if bytes.get(end) == Some(&b';') {
+        end + 1
+    } else {
+        errors.push(TokenizerErrorCode::MissingSemicolonAfterCharacterReference);
+        end
+    };
+
+    // Anything above the last code point is replaced either way, so there is no need to keep counting.
+    let value = bytes[start..end].iter().fold(0u32, |value, c| {
+        value.saturating_mul(radix).saturating_add((*c as char).to_digit(radix).unwrap_or(0)).min(0x110000)
+    });
+    // https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state
+    let error = match value {
+        0 => Some(TokenizerErrorCode::NullCharacterReference),
+        0x110000.. => Some(TokenizerErrorCode::CharacterReferenceOutsideUnicodeRange),
+        0xD800..=0xDFFF => Some(TokenizerErrorCode::SurrogateCharacterReference),
+        0xFDD0..=0xFDEF => Some(TokenizerErrorCode::NoncharacterCharacterReference),
+        _ if value & 0xFFFE == 0xFFFE => Some(TokenizerErrorCode::NoncharacterCharacterReference),
+        0x0D | 0x7F..=0x9F => Some(TokenizerErrorCode::ControlCharacterReference),
+        0x01..=0x1F if !matches!(value, 0x09 | 0x0A | 0x0C) => Some(TokenizerErrorCode::ControlCharacterReference),
+        _ => None,
+    };
+    errors.extend(error);
+    let c = match value {
+        0 => '\u{FFFD}',
+        0x80..=0x9F => C1_REPLACEMENTS[(value - 0x80) as usize],
+        _ => char::from_u32(value).unwrap_or('\u{FFFD}'), // Surrogates and values outside of the unicode range.
+    };
+    CharacterReference::Found(c, consumed)
+}
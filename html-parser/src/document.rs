@@ -0,0 +1,440 @@
+//! Querying and navigating a parsed document.
+//!
+//! [`Document`] wraps the tree that [`HtmlParser::parse`] builds. Nodes are looked at through views :
+//! a [`NodeRef`] for any node, and [`ElementRef`], [`TextRef`] and [`CommentRef`] once the type of the
+//! node is known. Views are cheap to copy and borrow the document, changes go through the document itself.
+
+use std::collections::HashMap;
+
+use crate::{dom::{Element, ElementKind, Node}, error::HtmlParseError, serializer::{self, SerializeOptions}, sink::PositionSink, tokenizer::SourcePosition, HtmlParser, NodeId, ParseState, Tree};
+
+pub struct Document {
+    tree : Tree<Node>,
+    // Where the nodes start in the input, only filled in by Document::parse_with_positions.
+    positions : HashMap<NodeId, SourcePosition>,
+}
+
+impl Document {
+    /// The root of `tree` is expected to be the document node, which is what the parser builds.
+    pub fn new(tree : Tree<Node>) -> Self {
+        Self { tree, positions : HashMap::new() }
+    }
+
+    pub fn parse(html : &str) -> Result<Self, Box<dyn std::error::Error>> {
+        HtmlParser::parse(html, ParseState::new()).map(Self::new)
+    }
+
+    /// Like [`Document::parse`], but also keeps where every node starts in `html`, see
+    /// [`NodeRef::source_position`].
+    pub fn parse_with_positions(html : &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (tree, positions) = HtmlParser::parse(html, ParseState::with_sink(PositionSink::new()))?;
+        Ok(Self { tree, positions })
+    }
+
+    /// Like [`Document::parse_with_positions`], along with the parse errors, see [`HtmlParser::parse_with_errors`].
+    pub fn parse_with_errors(html : &str) -> Result<(Self, Vec<HtmlParseError>), Box<dyn std::error::Error>> {
+        let ((tree, positions), errors) = HtmlParser::parse_with_errors(html, ParseState::with_sink(PositionSink::new()))?;
+        Ok((Self { tree, positions }, errors))
+    }
+
+    pub(crate) fn from_parts(tree : Tree<Node>, positions : HashMap<NodeId, SourcePosition>) -> Self {
+        Self { tree, positions }
+    }
+
+    pub(crate) fn into_parts(self) -> (Tree<Node>, HashMap<NodeId, SourcePosition>) {
+        (self.tree, self.positions)
+    }
+
+    pub fn tree(&self) -> &Tree<Node> {
+        &self.tree
+    }
+
+    /// Gives access to the whole tree, to insert or remove nodes.
+    pub fn tree_mut(&mut self) -> &mut Tree<Node> {
+        &mut self.tree
+    }
+
+    pub fn into_tree(self) -> Tree<Node> {
+        self.tree
+    }
+
+    /// The document node.
+    pub fn root(&self) -> NodeRef<'_> {
+        NodeRef { document : self, id : self.tree.root() }
+    }
+
+    pub fn get(&self, id : NodeId) -> Option<NodeRef<'_>> {
+        self.tree.get(id).map(|_| NodeRef { document : self, id })
+    }
+
+    // https://dom.spec.whatwg.org/#document-element
+    /// The `<html>` element.
+    pub fn document_element(&self) -> Option<ElementRef<'_>> {
+        self.root().children().find_map(|node| node.as_element())
+    }
+
+    pub fn head(&self) -> Option<ElementRef<'_>> {
+        self.document_element()?.child_elements().find(|element| element.kind == ElementKind::Head)
+    }
+
+    pub fn body(&self) -> Option<ElementRef<'_>> {
+        self.document_element()?.child_elements().find(|element| matches!(element.kind, ElementKind::Body | ElementKind::Frameset))
+    }
+
+    pub fn title(&self) -> Option<String> {
+        let title = self.get_elements_by_tag_name("title").into_iter().next()?;
+        Some(title.text_content().split_ascii_whitespace().collect::<Vec<_>>().join(" "))
+    }
+
+    // https://dom.spec.whatwg.org/#dom-nonelementparentnode-getelementbyid
+    /// The first element in document order with the given id.
+    pub fn get_element_by_id(&self, id : &str) -> Option<ElementRef<'_>> {
+        if id.is_empty() {
+            return None;
+        }
+        self.root().descendant_elements().find(|element| element.id() == Some(id))
+    }
+
+    /// See [`NodeRef::get_elements_by_tag_name`].
+    pub fn get_elements_by_tag_name(&self, name : &str) -> Vec<ElementRef<'_>> {
+        self.root().get_elements_by_tag_name(name)
+    }
+
+    /// See [`NodeRef::get_elements_by_class_name`].
+    pub fn get_elements_by_class_name(&self, names : &str) -> Vec<ElementRef<'_>> {
+        self.root().get_elements_by_class_name(names)
+    }
+
+    /// The element with the given id, to change its attributes or classes.
+    pub fn element_mut(&mut self, id : NodeId) -> Option<&mut Element> {
+        match self.tree.get_mut(id)? {
+            Node::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    /// Returns false if `id` isn't an element.
+    pub fn set_attribute(&mut self, id : NodeId, name : &str, value : &str) -> bool {
+        self.element_mut(id).map(|element| element.set_attribute(name, value)).is_some()
+    }
+
+    pub fn remove_attribute(&mut self, id : NodeId, name : &str) -> Option<String> {
+        self.element_mut(id)?.remove_attribute(name)
+    }
+
+    // https://dom.spec.whatwg.org/#dom-node-textcontent
+    /// Replace the children of an element with a single text node, or the text of a text or comment node.
+    /// Returns false for the document and doctypes, which don't have any text content.
+    pub fn set_text_content(&mut self, id : NodeId, text : &str) -> bool {
+        match self.tree.get_mut(id) {
+            Some(Node::Text(data)) | Some(Node::Comment(data)) => {
+                *data = text.to_string();
+                true
+            },
+            Some(Node::Element(_)) => {
+                let children : Vec<NodeId> = self.tree.children(id).collect();
+                for child in children {
+                    let _ = self.tree.remove(child);
+                }
+                if !text.is_empty() {
+                    let child = self.tree.new_node(Node::Text(text.to_string()));
+                    let _ = self.tree.append(id, child);
+                }
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// The markup for the whole document, see [`crate::serializer`].
+    pub fn to_html(&self) -> String {
+        serializer::to_html(&self.tree)
+    }
+
+    pub fn to_html_with(&self, options : &SerializeOptions) -> String {
+        serializer::serialize_children(&self.tree, self.tree.root(), options)
+    }
+}
+
+/// Any node of a [`Document`].
+#[derive(Clone, Copy)]
+pub struct NodeRef<'a> {
+    document : &'a Document,
+    id : NodeId,
+}
+
+impl<'a> NodeRef<'a> {
+    fn wrap(&self, id : Option<NodeId>) -> Option<NodeRef<'a>> {
+        id.map(|id| NodeRef { document : self.document, id })
+    }
+
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn document(&self) -> &'a Document {
+        self.document
+    }
+
+    /// Where the node starts in the input, if the document was parsed with
+    /// [`Document::parse_with_positions`]. Nodes added after parsing don't have a position.
+    pub fn source_position(&self) -> Option<SourcePosition> {
+        self.document.positions.get(&self.id).copied()
+    }
+
+    pub fn node(&self) -> &'a Node {
+        // Views are only made for nodes that are in the tree, and the tree can't change while they exist.
+        match self.document.tree.get(self.id) {
+            Some(node) => node,
+            None => unreachable!("a view of a node that isn't in the document"),
+        }
+    }
+
+    pub fn as_element(&self) -> Option<ElementRef<'a>> {
+        match self.node() {
+            Node::Element(element) => Some(ElementRef { node : *self, element }),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<TextRef<'a>> {
+        match self.node() {
+            Node::Text(text) => Some(TextRef { node : *self, text }),
+            _ => None,
+        }
+    }
+
+    pub fn as_comment(&self) -> Option<CommentRef<'a>> {
+        match self.node() {
+            Node::Comment(text) => Some(CommentRef { node : *self, text }),
+            _ => None,
+        }
+    }
+
+    pub fn parent(&self) -> Option<NodeRef<'a>> {
+        self.wrap(self.document.tree.parent(self.id))
+    }
+
+    pub fn parent_element(&self) -> Option<ElementRef<'a>> {
+        self.parent()?.as_element()
+    }
+
+    pub fn first_child(&self) -> Option<NodeRef<'a>> {
+        self.wrap(self.document.tree.first_child(self.id))
+    }
+
+    pub fn last_child(&self) -> Option<NodeRef<'a>> {
+        self.wrap(self.document.tree.last_child(self.id))
+    }
+
+    pub fn previous_sibling(&self) -> Option<NodeRef<'a>> {
+        self.wrap(self.document.tree.previous_sibling(self.id))
+    }
+
+    pub fn next_sibling(&self) -> Option<NodeRef<'a>> {
+        self.wrap(self.document.tree.next_sibling(self.id))
+    }
+
+    pub fn children(&self) -> impl DoubleEndedIterator<Item = NodeRef<'a>> + 'a {
+        let document = self.document;
+        document.tree.children(self.id).map(move |id| NodeRef { document, id })
+    }
+
+    pub fn child_elements(&self) -> impl DoubleEndedIterator<Item = ElementRef<'a>> + 'a {
+        self.children().filter_map(|node| node.as_element())
+    }
+
+    /// Starting with the parent.
+    pub fn ancestors(&self) -> impl Iterator<Item = NodeRef<'a>> + 'a {
+        let document = self.document;
+        document.tree.ancestors(self.id).map(move |id| NodeRef { document, id })
+    }
+
+    /// In document order, not including the node itself.
+    pub fn descendants(&self) -> impl DoubleEndedIterator<Item = NodeRef<'a>> + 'a {
+        let document = self.document;
+        document.tree.descendants(self.id).map(move |id| NodeRef { document, id })
+    }
+
+    pub fn descendant_elements(&self) -> impl DoubleEndedIterator<Item = ElementRef<'a>> + 'a {
+        self.descendants().filter_map(|node| node.as_element())
+    }
+
+    // https://dom.spec.whatwg.org/#concept-getelementsbytagname
+    /// The elements below this node with the given name, in document order. `*` matches every element.
+    /// HTML names are compared without regard to case.
+    pub fn get_elements_by_tag_name(&self, name : &str) -> Vec<ElementRef<'a>> {
+        let lower = name.to_ascii_lowercase();
+        self.descendant_elements()
+            .filter(|element| name == "*" || element.name() == lower)
+            .collect()
+    }
+
+    // https://dom.spec.whatwg.org/#concept-getelementsbyclassname
+    /// The elements below this node that have all of the space separated classes, in document order.
+    pub fn get_elements_by_class_name(&self, names : &str) -> Vec<ElementRef<'a>> {
+        let names : Vec<&str> = names.split_ascii_whitespace().collect();
+        if names.is_empty() {
+            return Vec::new();
+        }
+        self.descendant_elements()
+            .filter(|element| names.iter().all(|name| element.has_class(name)))
+            .collect()
+    }
+
+    // https://dom.spec.whatwg.org/#dom-node-textcontent
+    /// The text of a text or comment node, or all the text below an element. The document and doctypes
+    /// don't have any text content.
+    pub fn text_content(&self) -> Option<String> {
+        match self.node() {
+            Node::Document | Node::Doctype { .. } => None,
+            Node::Text(text) | Node::Comment(text) => Some(text.clone()),
+            Node::Element(_) => Some(self.descendants().filter_map(|node| node.as_text()).map(|text| text.text()).collect()),
+        }
+    }
+}
+
+impl<'a> PartialEq for NodeRef<'a> {
+    fn eq(&self, other : &Self) -> bool {
+        std::ptr::eq(self.document, other.document) && self.id == other.id
+    }
+}
+
+impl<'a> std::fmt::Debug for NodeRef<'a> {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("NodeRef").field(&self.id).field(self.node()).finish()
+    }
+}
+
+/// An element of a [`Document`]. Dereferences to the [`Element`], for its name and attributes.
+#[derive(Clone, Copy)]
+pub struct ElementRef<'a> {
+    node : NodeRef<'a>,
+    element : &'a Element,
+}
+
+impl<'a> ElementRef<'a> {
+    /// The id of the node in the tree. [`Element::id`] is the `id` attribute.
+    pub fn node_id(&self) -> NodeId {
+        self.node.id
+    }
+
+    pub fn as_node(&self) -> NodeRef<'a> {
+        self.node
+    }
+
+    pub fn element(&self) -> &'a Element {
+        self.element
+    }
+
+    /// See [`NodeRef::source_position`].
+    pub fn source_position(&self) -> Option<SourcePosition> {
+        self.node.source_position()
+    }
+
+    pub fn parent(&self) -> Option<NodeRef<'a>> {
+        self.node.parent()
+    }
+
+    pub fn parent_element(&self) -> Option<ElementRef<'a>> {
+        self.node.parent_element()
+    }
+
+    pub fn children(&self) -> impl DoubleEndedIterator<Item = NodeRef<'a>> + 'a {
+        self.node.children()
+    }
+
+    pub fn child_elements(&self) -> impl DoubleEndedIterator<Item = ElementRef<'a>> + 'a {
+        self.node.child_elements()
+    }
+
+    pub fn previous_element_sibling(&self) -> Option<ElementRef<'a>> {
+        let document = self.node.document;
+        document.tree.preceding_siblings(self.node.id).find_map(|id| NodeRef { document, id }.as_element())
+    }
+
+    pub fn next_element_sibling(&self) -> Option<ElementRef<'a>> {
+        let document = self.node.document;
+        document.tree.following_siblings(self.node.id).find_map(|id| NodeRef { document, id }.as_element())
+    }
+
+    pub fn descendant_elements(&self) -> impl DoubleEndedIterator<Item = ElementRef<'a>> + 'a {
+        self.node.descendant_elements()
+    }
+
+    pub fn get_elements_by_tag_name(&self, name : &str) -> Vec<ElementRef<'a>> {
+        self.node.get_elements_by_tag_name(name)
+    }
+
+    pub fn get_elements_by_class_name(&self, names : &str) -> Vec<ElementRef<'a>> {
+        self.node.get_elements_by_class_name(names)
+    }
+
+    pub fn text_content(&self) -> String {
+        self.node.text_content().unwrap_or_default()
+    }
+
+    // https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-innerhtml
+    pub fn inner_html(&self) -> String {
+        serializer::serialize_children(&self.node.document.tree, self.node.id, &SerializeOptions::default())
+    }
+
+    // https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-outerhtml
+    pub fn outer_html(&self) -> String {
+        serializer::serialize_node(&self.node.document.tree, self.node.id, &SerializeOptions::default())
+    }
+}
+
+impl<'a> std::ops::Deref for ElementRef<'a> {
+    type Target = Element;
+
+    fn deref(&self) -> &Element {
+        self.element
+    }
+}
+
+impl<'a> PartialEq for ElementRef<'a> {
+    fn eq(&self, other : &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<'a> std::fmt::Debug for ElementRef<'a> {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ElementRef").field(&self.node.id).field(self.element).finish()
+    }
+}
+
+/// A text node of a [`Document`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextRef<'a> {
+    node : NodeRef<'a>,
+    text : &'a str,
+}
+
+impl<'a> TextRef<'a> {
+    pub fn as_node(&self) -> NodeRef<'a> {
+        self.node
+    }
+
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+}
+
+/// A comment of a [`Document`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CommentRef<'a> {
+    node : NodeRef<'a>,
+    text : &'a str,
+}
+
+impl<'a> CommentRef<'a> {
+    pub fn as_node(&self) -> NodeRef<'a> {
+        self.node
+    }
+
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+}
@@ -0,0 +1,343 @@
+//! The node types that make up a parsed document.
+//!
+//! The tree builder never deals with these directly, it hands them to a [`crate::sink::TreeSink`]
+//! which decides how (and whether) they get stored.
+
+use crate::tokenizer::Attribute;
+
+// https://infra.spec.whatwg.org/#namespaces
+pub const HTML_NAMESPACE : &str = "http://www.w3.org/1999/xhtml";
+pub const SVG_NAMESPACE : &str = "http://www.w3.org/2000/svg";
+pub const MATHML_NAMESPACE : &str = "http://www.w3.org/1998/Math/MathML";
+
+// Generates ElementKind along with the conversions to and from tag names, so that the list of
+// known elements only has to be written down once.
+macro_rules! element_kinds {
+    ($($kind:ident => $name:literal,)*) => {
+        /// Every element the parser knows by name. Anything else ends up as [`ElementKind::Other`].
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum ElementKind {
+            $($kind,)*
+            Other(String),
+        }
+
+        impl ElementKind {
+            /// Expects a lower case tag name, which is what the tokenizer emits.
+            pub fn from_name(name: &str) -> ElementKind {
+                match name {
+                    $($name => ElementKind::$kind,)*
+                    _ => ElementKind::Other(name.to_string()),
+                }
+            }
+
+            pub fn name(&self) -> &str {
+                match self {
+                    $(ElementKind::$kind => $name,)*
+                    ElementKind::Other(name) => name.as_str(),
+                }
+            }
+        }
+    };
+}
+
+element_kinds! {
+    A => "a",
+    Abbr => "abbr",
+    Address => "address",
+    Applet => "applet",
+    Area => "area",
+    Article => "article",
+    Aside => "aside",
+    Audio => "audio",
+    B => "b",
+    Base => "base",
+    Basefont => "basefont",
+    Bdi => "bdi",
+    Bdo => "bdo",
+    Bgsound => "bgsound",
+    Big => "big",
+    Blockquote => "blockquote",
+    Body => "body",
+    Br => "br",
+    Button => "button",
+    Canvas => "canvas",
+    Caption => "caption",
+    Center => "center",
+    Cite => "cite",
+    Code => "code",
+    Col => "col",
+    Colgroup => "colgroup",
+    Data => "data",
+    Datalist => "datalist",
+    Dd => "dd",
+    Del => "del",
+    Details => "details",
+    Dfn => "dfn",
+    Dialog => "dialog",
+    Dir => "dir",
+    Div => "div",
+    Dl => "dl",
+    Dt => "dt",
+    Em => "em",
+    Embed => "embed",
+    Fieldset => "fieldset",
+    Figcaption => "figcaption",
+    Figure => "figure",
+    Font => "font",
+    Footer => "footer",
+    Form => "form",
+    Frame => "frame",
+    Frameset => "frameset",
+    H1 => "h1",
+    H2 => "h2",
+    H3 => "h3",
+    H4 => "h4",
+    H5 => "h5",
+    H6 => "h6",
+    Head => "head",
+    Header => "header",
+    Hgroup => "hgroup",
+    Hr => "hr",
+    Html => "html",
+    I => "i",
+    Iframe => "iframe",
+    Img => "img",
+    Input => "input",
+    Ins => "ins",
+    Kbd => "kbd",
+    Keygen => "keygen",
+    Label => "label",
+    Legend => "legend",
+    Li => "li",
+    Link => "link",
+    Listing => "listing",
+    Main => "main",
+    Map => "map",
+    Mark => "mark",
+    Marquee => "marquee",
+    Math => "math",
+    Menu => "menu",
+    Meta => "meta",
+    Meter => "meter",
+    Nav => "nav",
+    Nobr => "nobr",
+    Noembed => "noembed",
+    Noframes => "noframes",
+    Noscript => "noscript",
+    Object => "object",
+    Ol => "ol",
+    Optgroup => "optgroup",
+    Option => "option",
+    Output => "output",
+    P => "p",
+    Param => "param",
+    Picture => "picture",
+    Plaintext => "plaintext",
+    Pre => "pre",
+    Progress => "progress",
+    Q => "q",
+    Rb => "rb",
+    Rp => "rp",
+    Rt => "rt",
+    Rtc => "rtc",
+    Ruby => "ruby",
+    S => "s",
+    Samp => "samp",
+    Script => "script",
+    Search => "search",
+    Section => "section",
+    Select => "select",
+    Slot => "slot",
+    Small => "small",
+    Source => "source",
+    Span => "span",
+    Strike => "strike",
+    Strong => "strong",
+    Style => "style",
+    Sub => "sub",
+    Summary => "summary",
+    Sup => "sup",
+    Svg => "svg",
+    Table => "table",
+    Tbody => "tbody",
+    Td => "td",
+    Template => "template",
+    Textarea => "textarea",
+    Tfoot => "tfoot",
+    Th => "th",
+    Thead => "thead",
+    Time => "time",
+    Title => "title",
+    Tr => "tr",
+    Track => "track",
+    Tt => "tt",
+    U => "u",
+    Ul => "ul",
+    Var => "var",
+    Video => "video",
+    Wbr => "wbr",
+    Xmp => "xmp",
+}
+
+impl ElementKind {
+    // https://html.spec.whatwg.org/multipage/parsing.html#special
+    pub fn is_special(&self) -> bool {
+        use ElementKind::*;
+        matches!(self,
+            Address | Applet | Area | Article | Aside | Base | Basefont | Bgsound | Blockquote | Body
+            | Br | Button | Caption | Center | Col | Colgroup | Dd | Details | Dir | Div | Dl | Dt
+            | Embed | Fieldset | Figcaption | Figure | Footer | Form | Frame | Frameset | H1 | H2
+            | H3 | H4 | H5 | H6 | Head | Header | Hgroup | Hr | Html | Iframe | Img | Input
+            | Keygen | Li | Link | Listing | Main | Marquee | Menu | Meta | Nav | Noembed
+            | Noframes | Noscript | Object | Ol | P | Param | Plaintext | Pre | Script | Search
+            | Section | Select | Source | Style | Summary | Table | Tbody | Td | Template
+            | Textarea | Tfoot | Th | Thead | Title | Tr | Track | Ul | Wbr | Xmp)
+    }
+
+    pub fn is_heading(&self) -> bool {
+        use ElementKind::*;
+        matches!(self, H1 | H2 | H3 | H4 | H5 | H6)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub kind : ElementKind,
+    pub attributes : Vec<Attribute>,
+}
+
+impl Element {
+    pub fn new(kind : ElementKind) -> Self {
+        Self {
+            kind,
+            attributes : Vec::new(),
+        }
+    }
+
+    pub fn from_tag(name : &str, attributes : Vec<Attribute>) -> Self {
+        Self {
+            kind : ElementKind::from_name(name),
+            attributes,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.kind.name()
+    }
+
+    /// The parser doesn't implement the rules for foreign content yet, so only `<svg>` and `<math>`
+    /// themselves end up outside the HTML namespace, their descendants don't.
+    pub fn namespace(&self) -> &'static str {
+        match self.kind {
+            ElementKind::Svg => SVG_NAMESPACE,
+            ElementKind::Math => MATHML_NAMESPACE,
+            _ => HTML_NAMESPACE,
+        }
+    }
+
+    pub fn attribute(&self, name : &str) -> Option<&str> {
+        self.attributes.iter().find(|a| a.name == name).map(|a| a.value.as_str())
+    }
+
+    /// Set an attribute, replacing its value if the element already has it. Attribute names are lower case in HTML.
+    pub fn set_attribute(&mut self, name : &str, value : &str) {
+        let name = name.to_ascii_lowercase();
+        match self.attributes.iter_mut().find(|a| a.name == name) {
+            Some(attribute) => attribute.value = value.to_string(),
+            None => self.attributes.push(Attribute::new(&name, value)),
+        }
+    }
+
+    /// Returns the value the attribute had, if the element had it.
+    pub fn remove_attribute(&mut self, name : &str) -> Option<String> {
+        let name = name.to_ascii_lowercase();
+        let index = self.attributes.iter().position(|a| a.name == name)?;
+        Some(self.attributes.remove(index).value)
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.attribute("id")
+    }
+
+    // https://dom.spec.whatwg.org/#concept-ordered-set-parser
+    /// The classes in the `class` attribute, in order and without duplicates.
+    pub fn classes(&self) -> Vec<&str> {
+        let mut classes : Vec<&str> = Vec::new();
+        for class in self.attribute("class").unwrap_or("").split_ascii_whitespace() {
+            if !classes.contains(&class) {
+                classes.push(class);
+            }
+        }
+        classes
+    }
+
+    pub fn has_class(&self, class : &str) -> bool {
+        self.attribute("class").is_some_and(|classes| classes.split_ascii_whitespace().any(|c| c == class))
+    }
+
+    /// Add a class if the element doesn't have it yet. Like `classList.add`, this rewrites the attribute
+    /// without duplicates or extra whitespace.
+    pub fn add_class(&mut self, class : &str) {
+        let mut classes : Vec<String> = self.classes().into_iter().map(String::from).collect();
+        if !classes.iter().any(|c| c == class) {
+            classes.push(class.to_string());
+        }
+        self.set_attribute("class", &classes.join(" "));
+    }
+
+    pub fn remove_class(&mut self, class : &str) {
+        if self.attribute("class").is_none() {
+            return;
+        }
+        let classes : Vec<&str> = self.classes().into_iter().filter(|c| *c != class).collect();
+        let classes = classes.join(" ");
+        self.set_attribute("class", &classes);
+    }
+
+    /// Add the class if it's missing and remove it otherwise. Returns whether the element has it afterwards.
+    pub fn toggle_class(&mut self, class : &str) -> bool {
+        match self.has_class(class) {
+            true => self.remove_class(class),
+            false => self.add_class(class),
+        }
+        self.has_class(class)
+    }
+}
+
+/// A single node in the DOM tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Document,
+    Doctype {
+        name : Option<String>,
+        public_id : Option<String>,
+        system_id : Option<String>,
+    },
+    Element(Element),
+    Text(String),
+    Comment(String),
+}
+
+impl Node {
+    /// Identifies a node across two versions of a document, for [`dds_utils::tree::diff`]. Elements are
+    /// told apart by their name and `id`, every other node only by its type.
+    pub fn diff_key(&self) -> String {
+        match self {
+            Node::Document => String::from("#document"),
+            Node::Doctype { .. } => String::from("#doctype"),
+            Node::Element(element) => match element.attribute("id") {
+                Some(id) => format!("{}#{}", element.name(), id),
+                None => element.name().to_string(),
+            },
+            Node::Text(_) => String::from("#text"),
+            Node::Comment(_) => String::from("#comment"),
+        }
+    }
+
+    pub fn as_element(&self) -> Option<&Element> {
+        match self {
+            Node::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+}
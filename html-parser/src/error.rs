@@ -1,13 +1,76 @@
 use std::{fmt::Display, error::Error};
 
-use crate::{tokenizer::Tokenizer, tokenizer::Token, states::InsertionMode};
+use crate::{tokenizer::{SourcePosition, Token, Tokenizer}, states::{InsertionMode, TokenizationState}};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum HtmlParseError {
     InsertionModeCaseNotHandled(InsertionMode),
     ReconsumeNonExistingToken,
     UnexpectedToken(Token),
     GenericParseError,
+    /// The document uses a feature that the tree builder doesn't implement.
+    Unsupported(&'static str),
+    /// The document has more nodes than the limit allows.
+    NodeLimitExceeded(usize),
+    /// The input is larger than the limit allows.
+    InputTooLarge(usize),
+    /// A parse error in the document, found by the tokenizer or the tree builder. Parse errors don't stop
+    /// the parser, they are only collected by [`crate::HtmlParser::parse_with_errors`].
+    ParseError { code : ParseErrorCode, position : SourcePosition },
+    /// The range of an edit isn't in the input or doesn't start and end on character boundaries.
+    InvalidEdit(std::ops::Range<usize>),
+    /// A saved parser state can't be read back, or the parser can't be saved in the state it is in. See
+    /// [`crate::snapshot`].
+    InvalidSnapshot(&'static str),
+    /// A line of a recorded token stream can't be read, see [`crate::tokenizer::TokenStream::from_json_lines`].
+    InvalidTokenStream { line : usize, reason : &'static str },
+}
+
+impl HtmlParseError {
+    /// Where the error is in the input, for parse errors.
+    pub fn position(&self) -> Option<SourcePosition> {
+        match self {
+            HtmlParseError::ParseError { position, .. } => Some(*position),
+            _ => None,
+        }
+    }
+
+    /// The code of the error, for parse errors.
+    pub fn code(&self) -> Option<&ParseErrorCode> {
+        match self {
+            HtmlParseError::ParseError { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+}
+
+/// What a [`HtmlParseError::ParseError`] is about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorCode {
+    Tokenizer(TokenizerErrorCode),
+    // https://html.spec.whatwg.org/multipage/parsing.html#tree-construction
+    /// A token that the tree builder doesn't expect in the insertion mode it is in. The specification doesn't
+    /// name these errors, so the token is described instead, e.g. `end tag </p>`.
+    UnexpectedToken { mode : InsertionMode, token : String },
+}
+
+impl ParseErrorCode {
+    /// A short name for the error, e.g. "eof-in-tag" or "unexpected-token".
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParseErrorCode::Tokenizer(code) => code.as_str(),
+            ParseErrorCode::UnexpectedToken { .. } => "unexpected-token",
+        }
+    }
+}
+
+impl std::fmt::Display for ParseErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorCode::Tokenizer(code) => write!(f, "{}", code),
+            ParseErrorCode::UnexpectedToken { mode, token } => write!(f, "unexpected {} in the {:?} insertion mode", token, mode),
+        }
+    }
 }
 
 
@@ -29,6 +92,13 @@ impl std::fmt::Display for HtmlParseError {
             HtmlParseError::ReconsumeNonExistingToken => write!(f, "ReconsumeNonExistingToken. Fatal implementation error."),
             HtmlParseError::UnexpectedToken(t) => write!(f, "UnexpectedToken {:?}", t),
             HtmlParseError::GenericParseError => write!(f, "GenericParseError. Explicit cause is not documented."),
+            HtmlParseError::Unsupported(feature) => write!(f, "Unsupported. {} is not implemented.", feature),
+            HtmlParseError::NodeLimitExceeded(max) => write!(f, "NodeLimitExceeded. The document has more than {} nodes.", max),
+            HtmlParseError::InputTooLarge(max) => write!(f, "InputTooLarge. The input is larger than {} bytes.", max),
+            HtmlParseError::ParseError { code, position } => write!(f, "ParseError. {} at {}.", code, position),
+            HtmlParseError::InvalidEdit(range) => write!(f, "InvalidEdit. {:?} is not a range of the input.", range),
+            HtmlParseError::InvalidSnapshot(reason) => write!(f, "InvalidSnapshot. {}", reason),
+            HtmlParseError::InvalidTokenStream { line, reason } => write!(f, "InvalidTokenStream. Line {} : {}.", line, reason),
         }
     }
 }
@@ -37,6 +107,12 @@ impl std::fmt::Display for HtmlParseError {
 pub enum HtmlTokenizerError {
     UndefinedError(Token),
     UnexpectedNullCharacter,
+    /// The tokenizer was switched to a state that isn't implemented. It carries on in a different
+    /// state, so the next token can still be read.
+    Unsupported(TokenizationState),
+    /// The tokenizer was switched to one of the attribute states while there was no attribute to add to.
+    /// It carries on before the next attribute name.
+    MissingAttribute(TokenizationState),
 }
 
 impl std::error::Error for HtmlTokenizerError {}
@@ -47,6 +123,92 @@ impl std::fmt::Display for HtmlTokenizerError {
         match &self {
             HtmlTokenizerError::UndefinedError(token) => write!(f, "Encountered an undefined error when tokenizing. Last token = {:?}.", token),
             HtmlTokenizerError::UnexpectedNullCharacter => write!(f, "Encountered a NULL character when there wasn't supposed to be any."),
+            HtmlTokenizerError::Unsupported(state) => write!(f, "The {:?} state is not implemented.", state),
+            HtmlTokenizerError::MissingAttribute(state) => write!(f, "The {:?} state was entered without an attribute.", state),
         }
     }
-}
\ No newline at end of file
+}
+/// The parse errors reported by the tokenizer, see [`crate::tokenizer::Tokenizer::record_errors`].
+/// Parse errors never stop the tokenizer, they only mean that the document isn't conforming.
+// https://html.spec.whatwg.org/multipage/parsing.html#parse-errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TokenizerErrorCode {
+    AbruptClosingOfEmptyComment,
+    AbsenceOfDigitsInNumericCharacterReference,
+    CdataInHtmlContent,
+    CharacterReferenceOutsideUnicodeRange,
+    ControlCharacterReference,
+    DuplicateAttribute,
+    EndTagWithAttributes,
+    EndTagWithTrailingSolidus,
+    EofBeforeTagName,
+    EofInComment,
+    EofInDoctype,
+    EofInTag,
+    IncorrectlyClosedComment,
+    IncorrectlyOpenedComment,
+    InvalidCharacterSequenceAfterDoctypeName,
+    InvalidFirstCharacterOfTagName,
+    MissingAttributeValue,
+    MissingDoctypeName,
+    MissingEndTagName,
+    MissingSemicolonAfterCharacterReference,
+    MissingWhitespaceBeforeDoctypeName,
+    MissingWhitespaceBetweenAttributes,
+    NoncharacterCharacterReference,
+    NullCharacterReference,
+    SurrogateCharacterReference,
+    UnexpectedCharacterInAttributeName,
+    UnexpectedCharacterInUnquotedAttributeValue,
+    UnexpectedEqualsSignBeforeAttributeName,
+    UnexpectedNullCharacter,
+    UnexpectedQuestionMarkInsteadOfTagName,
+    UnexpectedSolidusInTag,
+    UnknownNamedCharacterReference,
+}
+
+impl TokenizerErrorCode {
+    /// The name of the error in the specification, e.g. "eof-in-tag".
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenizerErrorCode::AbruptClosingOfEmptyComment => "abrupt-closing-of-empty-comment",
+            TokenizerErrorCode::AbsenceOfDigitsInNumericCharacterReference => "absence-of-digits-in-numeric-character-reference",
+            TokenizerErrorCode::CdataInHtmlContent => "cdata-in-html-content",
+            TokenizerErrorCode::CharacterReferenceOutsideUnicodeRange => "character-reference-outside-unicode-range",
+            TokenizerErrorCode::ControlCharacterReference => "control-character-reference",
+            TokenizerErrorCode::DuplicateAttribute => "duplicate-attribute",
+            TokenizerErrorCode::EndTagWithAttributes => "end-tag-with-attributes",
+            TokenizerErrorCode::EndTagWithTrailingSolidus => "end-tag-with-trailing-solidus",
+            TokenizerErrorCode::EofBeforeTagName => "eof-before-tag-name",
+            TokenizerErrorCode::EofInComment => "eof-in-comment",
+            TokenizerErrorCode::EofInDoctype => "eof-in-doctype",
+            TokenizerErrorCode::EofInTag => "eof-in-tag",
+            TokenizerErrorCode::IncorrectlyClosedComment => "incorrectly-closed-comment",
+            TokenizerErrorCode::IncorrectlyOpenedComment => "incorrectly-opened-comment",
+            TokenizerErrorCode::InvalidCharacterSequenceAfterDoctypeName => "invalid-character-sequence-after-doctype-name",
+            TokenizerErrorCode::InvalidFirstCharacterOfTagName => "invalid-first-character-of-tag-name",
+            TokenizerErrorCode::MissingAttributeValue => "missing-attribute-value",
+            TokenizerErrorCode::MissingDoctypeName => "missing-doctype-name",
+            TokenizerErrorCode::MissingEndTagName => "missing-end-tag-name",
+            TokenizerErrorCode::MissingSemicolonAfterCharacterReference => "missing-semicolon-after-character-reference",
+            TokenizerErrorCode::MissingWhitespaceBeforeDoctypeName => "missing-whitespace-before-doctype-name",
+            TokenizerErrorCode::MissingWhitespaceBetweenAttributes => "missing-whitespace-between-attributes",
+            TokenizerErrorCode::NoncharacterCharacterReference => "noncharacter-character-reference",
+            TokenizerErrorCode::NullCharacterReference => "null-character-reference",
+            TokenizerErrorCode::SurrogateCharacterReference => "surrogate-character-reference",
+            TokenizerErrorCode::UnexpectedCharacterInAttributeName => "unexpected-character-in-attribute-name",
+            TokenizerErrorCode::UnexpectedCharacterInUnquotedAttributeValue => "unexpected-character-in-unquoted-attribute-value",
+            TokenizerErrorCode::UnexpectedEqualsSignBeforeAttributeName => "unexpected-equals-sign-before-attribute-name",
+            TokenizerErrorCode::UnexpectedNullCharacter => "unexpected-null-character",
+            TokenizerErrorCode::UnexpectedQuestionMarkInsteadOfTagName => "unexpected-question-mark-instead-of-tag-name",
+            TokenizerErrorCode::UnexpectedSolidusInTag => "unexpected-solidus-in-tag",
+            TokenizerErrorCode::UnknownNamedCharacterReference => "unknown-named-character-reference",
+        }
+    }
+}
+
+impl std::fmt::Display for TokenizerErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -0,0 +1,136 @@
+//! Syntax highlighting for HTML, built on the [`LosslessTokenizer`].
+//!
+//! The input is split into pieces that each have a [`Class`], and every byte of the input is in exactly one
+//! piece. [`to_ansi`] colours the pieces for a terminal and [`to_html`] wraps them in `<span>` elements with
+//! a CSS class, so that the highlighted source can be put in a `<pre>`.
+
+use std::ops::Range;
+
+use crate::{lossless::LosslessTokenizer, serializer::escape_text, tokenizer::Token};
+
+/// What a piece of the input is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Class {
+    Text,
+    /// A character reference like `&amp;` in text.
+    CharacterReference,
+    /// The parts of a tag that aren't a name or a value : `<`, `</`, `>`, `/>`, `=` and whitespace.
+    Tag,
+    TagName,
+    AttributeName,
+    /// An attribute value along with its quotes.
+    AttributeValue,
+    Comment,
+    Doctype,
+    /// Input the tokenizer drops, like `</>`.
+    Invalid,
+}
+
+impl Class {
+    /// The CSS class [`to_html`] uses, `None` for plain text.
+    pub fn css_class(&self) -> Option<&'static str> {
+        match self {
+            Class::Text => None,
+            Class::CharacterReference => Some("html-reference"),
+            Class::Tag => Some("html-tag"),
+            Class::TagName => Some("html-tag-name"),
+            Class::AttributeName => Some("html-attribute-name"),
+            Class::AttributeValue => Some("html-attribute-value"),
+            Class::Comment => Some("html-comment"),
+            Class::Doctype => Some("html-doctype"),
+            Class::Invalid => Some("html-invalid"),
+        }
+    }
+
+    /// The SGR parameters [`to_ansi`] uses, `None` for plain text.
+    pub fn ansi_style(&self) -> Option<&'static str> {
+        match self {
+            Class::Text => None,
+            Class::CharacterReference => Some("35"),
+            Class::Tag => Some("34"),
+            Class::TagName => Some("1;34"),
+            Class::AttributeName => Some("36"),
+            Class::AttributeValue => Some("32"),
+            Class::Comment => Some("2"),
+            Class::Doctype => Some("33"),
+            Class::Invalid => Some("1;31"),
+        }
+    }
+}
+
+/// A part of the input and its class.
+pub type Piece = (Class, Range<usize>);
+
+/// Split `source` into classified pieces, in order. Pieces next to each other have different classes.
+pub fn classify(source : &str) -> Result<Vec<Piece>, Box<dyn std::error::Error>> {
+    let mut pieces : Vec<Piece> = Vec::new();
+    let mut push = |class : Class, range : Range<usize>| {
+        if range.is_empty() {
+            return;
+        }
+        match pieces.last_mut() {
+            Some((last, last_range)) if *last == class && last_range.end == range.start => last_range.end = range.end,
+            _ => pieces.push((class, range)),
+        }
+    };
+    for token in LosslessTokenizer::new(source)? {
+        let class = match &token.token {
+            None => Class::Invalid,
+            Some(Token::Character(_)) if token.text.len() > 1 && token.text.starts_with('&') => Class::CharacterReference,
+            Some(Token::Character(_) | Token::EOF) => Class::Text,
+            Some(Token::Comment(_)) => Class::Comment,
+            Some(Token::DOCTYPE(..)) => Class::Doctype,
+            Some(Token::StartTag(..) | Token::EndTag(..)) => Class::Tag,
+        };
+        if class != Class::Tag {
+            push(class, token.span);
+            continue;
+        }
+        // The pieces of a tag are in order, whatever is between them is punctuation or whitespace.
+        let mut at = token.span.start;
+        let names = token.name.iter().map(|name| (Class::TagName, name.clone()));
+        let attributes = token.attributes.iter().flat_map(|attribute| {
+            let value = attribute.value.clone().map(|value| match attribute.quote {
+                Some(_) => value.start - 1..value.end + 1,
+                None => value,
+            });
+            [(Class::AttributeName, attribute.name.clone())].into_iter().chain(value.map(|value| (Class::AttributeValue, value)))
+        });
+        for (class, range) in names.chain(attributes) {
+            push(Class::Tag, at..range.start);
+            at = range.end;
+            push(class, range);
+        }
+        push(Class::Tag, at..token.span.end);
+    }
+    Ok(pieces)
+}
+
+/// `source` with ANSI escape codes around everything but text, for a terminal.
+pub fn to_ansi(source : &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut output = String::with_capacity(source.len() * 2);
+    for (class, range) in classify(source)? {
+        match class.ansi_style() {
+            Some(style) => output.push_str(&format!("\x1b[{}m{}\x1b[0m", style, &source[range])),
+            None => output.push_str(&source[range]),
+        }
+    }
+    Ok(output)
+}
+
+/// `source` escaped, with `<span class="...">` around everything but text. See [`Class::css_class`] for
+/// the classes.
+pub fn to_html(source : &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut output = String::with_capacity(source.len() * 2);
+    for (class, range) in classify(source)? {
+        match class.css_class() {
+            Some(css_class) => {
+                output.push_str(&format!("<span class=\"{}\">", css_class));
+                escape_text(&source[range], &mut output);
+                output.push_str("</span>");
+            },
+            None => escape_text(&source[range], &mut output),
+        }
+    }
+    Ok(output)
+}
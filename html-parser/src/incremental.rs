@@ -0,0 +1,539 @@
+//! Reparsing a document after an edit of its source, without parsing all of it again.
+//!
+//! While an [`IncrementalDocument`] is parsed, it records checkpoints : places in between two tokens from
+//! which the parser can be restarted, along with the stack of open elements at that point. A checkpoint is
+//! only taken where the state of the parser is simple enough to be put back together : right after a `>`,
+//! in the "in body" insertion mode, with the tokenizer in the data state and no text node still being
+//! added to.
+//!
+//! An edit restarts the parser from the last checkpoint before the edit and stops it as soon as it reaches a
+//! checkpoint after the edit where the parser is in the same state as it was in the previous parse. Only the
+//! nodes created between the two checkpoints are replaced, everything else keeps its [`NodeId`]. Edits
+//! before the first checkpoint, which is at the start of the `<body>`, parse the whole document again.
+//!
+//! Checkpoints that follow each other mostly have the same elements at the bottom of their stacks, so the
+//! stacks share those instead of each checkpoint keeping a copy. The memory used by the checkpoints grows
+//! with the number of tags rather than with the number of tags times the depth of the document.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::{dom::{ElementKind, Node}, error::HtmlParseError, preproccesor::PreProccessor, sink::PositionSink, states::{InsertionMode, TokenizationState},
+    tokenizer::{Attribute, SourcePosition, Token, Tokenizer, TokenizerSnapshot}, limits::ParserLimits, Document, HtmlParser, NodeId, OpenElement,
+    ParseState, Tree};
+
+/// A document along with its source and what is needed to update it after an edit.
+pub struct IncrementalDocument {
+    source : String,
+    document : Document,
+    limits : ParserLimits,
+    // In the order of their offsets.
+    checkpoints : Vec<Checkpoint>,
+}
+
+/// What [`IncrementalDocument::edit`] changed in the tree.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Changes {
+    /// The roots of the subtrees that were removed. These ids aren't valid anymore.
+    pub removed : Vec<NodeId>,
+    /// The roots of the subtrees that were inserted, in document order.
+    pub inserted : Vec<NodeId>,
+    /// Elements that were kept but whose attributes changed, which only happens to `<html>` and `<body>`
+    /// when the edit adds or removes a second `<html>` or `<body>` tag.
+    pub updated : Vec<NodeId>,
+    /// The part of the new source that was parsed again.
+    pub reparsed : Range<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Checkpoint {
+    tokenizer : TokenizerSnapshot,
+    // The last child of the document when the checkpoint was taken. Everything after it came later.
+    document_last_child : Option<NodeId>,
+    open_elements : OpenStack,
+    head_pointer : Option<NodeId>,
+    form_pointer : Option<NodeId>,
+    frame_set_ok : bool,
+    // The nodes inserted so far, which count towards ParserLimits::max_nodes.
+    node_count : usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct OpenNode {
+    id : NodeId,
+    kind : ElementKind,
+    last_child : Option<NodeId>,
+    // The tree builder adds attributes to <html> and <body> when it sees those tags again.
+    attributes : usize,
+}
+
+impl Checkpoint {
+    fn offset(&self) -> usize {
+        self.tokenizer.position.offset
+    }
+
+    // The document followed by the open elements, with their last children.
+    fn parents(&self, tree : &Tree<Node>) -> Vec<(NodeId, Option<NodeId>)> {
+        std::iter::once((tree.root(), self.document_last_child))
+            .chain(self.open_elements.to_vec().into_iter().map(|element| (element.id, element.last_child)))
+            .collect()
+    }
+}
+
+// A stack of open elements, as a list from the top down whose bottom is shared with the stacks of other
+// checkpoints.
+#[derive(Clone, Default)]
+struct OpenStack {
+    top : Option<Arc<OpenLink>>,
+    len : usize,
+}
+
+struct OpenLink {
+    node : OpenNode,
+    below : Option<Arc<OpenLink>>,
+}
+
+// Dropping the links one by one would recurse once per element, which overflows the stack for deep documents.
+impl Drop for OpenLink {
+    fn drop(&mut self) {
+        let mut below = self.below.take();
+        while let Some(link) = below {
+            below = match Arc::try_unwrap(link) {
+                Ok(mut link) => link.below.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+impl OpenStack {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    // From the bottom of the stack up.
+    fn to_vec(&self) -> Vec<OpenNode> {
+        let mut nodes = Vec::with_capacity(self.len);
+        let mut link = self.top.as_deref();
+        while let Some(current) = link {
+            nodes.push(current.node.clone());
+            link = current.below.as_deref();
+        }
+        nodes.reverse();
+        nodes
+    }
+}
+
+impl std::fmt::Debug for OpenStack {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.to_vec()).finish()
+    }
+}
+
+// Builds the stacks of checkpoints that follow each other, each one sharing as much as it can with the previous one.
+#[derive(Default)]
+struct StackBuilder {
+    // The links of the last stack built, from the bottom up.
+    links : Vec<Arc<OpenLink>>,
+}
+
+impl StackBuilder {
+    // A stack of `nodes`, from the bottom up.
+    fn build(&mut self, mut nodes : Vec<OpenNode>) -> OpenStack {
+        let shared = self.links.iter().zip(&nodes).take_while(|(link, node)| link.node == **node).count();
+        let above = nodes.split_off(shared);
+        self.stack(shared, above)
+    }
+
+    // The bottom `shared` elements of the last stack with `above` on top of them.
+    fn stack(&mut self, shared : usize, above : Vec<OpenNode>) -> OpenStack {
+        self.links.truncate(shared);
+        for node in above {
+            let below = self.links.last().cloned();
+            self.links.push(Arc::new(OpenLink { node, below }));
+        }
+        OpenStack { top : self.links.last().cloned(), len : self.links.len() }
+    }
+}
+
+// Carries on from an existing stack.
+impl From<&OpenStack> for StackBuilder {
+    fn from(stack : &OpenStack) -> Self {
+        let mut links = Vec::with_capacity(stack.len);
+        let mut link = stack.top.clone();
+        while let Some(current) = link {
+            link = current.below.clone();
+            links.push(current);
+        }
+        links.reverse();
+        Self { links }
+    }
+}
+
+impl IncrementalDocument {
+    /// Parse `html` with `limits`, which also apply to the parses of later edits.
+    pub fn parse(html : &str, limits : ParserLimits) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut state = ParseState::with_sink(PositionSink::new()).with_limits(limits);
+        state.check_input_size(html.len())?;
+        let mut tokens = Tokenizer::new(PreProccessor::new(html)?);
+        tokens.set_limits(limits);
+        let mut checkpoints = Vec::new();
+        run(&mut tokens, &mut state, html.len(), &mut checkpoints, &mut StackBuilder::default(), |_, _| false)?;
+        state.set_source_position(tokens.token_position());
+        state.process_token(Token::EOF)?;
+        let (tree, positions) = state.finish();
+        Ok(Self { source : html.to_string(), document : Document::from_parts(tree, positions), limits, checkpoints })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The parsed document, with source positions.
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    pub fn into_document(self) -> Document {
+        self.document
+    }
+
+    /// Replace the bytes of the source in `range` with `replacement` and update the document.
+    ///
+    /// The tree ends up the same as if the new source had been parsed from scratch, and so do the source
+    /// positions. If parsing fails, the document is parsed again from scratch, and if that fails as well
+    /// the error is returned and the document is left empty.
+    pub fn edit(&mut self, range : Range<usize>, replacement : &str) -> Result<Changes, Box<dyn std::error::Error>> {
+        if range.start > range.end || !self.source.is_char_boundary(range.start) || !self.source.is_char_boundary(range.end) {
+            return Err(Box::new(HtmlParseError::InvalidEdit(range)));
+        }
+        let mut source = String::with_capacity(self.source.len() - range.len() + replacement.len());
+        source.push_str(&self.source[..range.start]);
+        source.push_str(replacement);
+        source.push_str(&self.source[range.end..]);
+        // The tokens before a checkpoint don't depend on what comes after it, because they all end with a `>`.
+        let restart = self.checkpoints.partition_point(|checkpoint| checkpoint.offset() <= range.start);
+        if restart > 0 {
+            let old_source = std::mem::replace(&mut self.source, source);
+            match self.reparse(&old_source, restart - 1, range, replacement.len()) {
+                Ok(changes) => return Ok(changes),
+                Err(_) => source = std::mem::take(&mut self.source),
+            }
+        }
+        self.parse_all(source)
+    }
+
+    fn parse_all(&mut self, source : String) -> Result<Changes, Box<dyn std::error::Error>> {
+        let removed = self.document.tree().children(self.document.tree().root()).collect();
+        let reparsed = 0..source.len();
+        let parsed = Self::parse(&source, self.limits);
+        *self = match parsed {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                *self = Self { source, document : Document::new(Tree::new(Node::Document)), limits : self.limits, checkpoints : Vec::new() };
+                return Err(error);
+            },
+        };
+        let inserted = self.document.tree().children(self.document.tree().root()).collect();
+        Ok(Changes { removed, inserted, updated : Vec::new(), reparsed })
+    }
+
+    // Parse again from the checkpoint at `restart`, the new source is already in self.source.
+    fn reparse(&mut self, old_source : &str, restart : usize, range : Range<usize>, inserted_length : usize) -> Result<Changes, Box<dyn std::error::Error>> {
+        let later = self.checkpoints.split_off(restart + 1);
+        let Some(start) = self.checkpoints.pop() else {
+            return Err(Box::new(HtmlParseError::InvalidEdit(range)));
+        };
+        let (mut tree, positions) = std::mem::replace(&mut self.document, Document::new(Tree::new(Node::Document))).into_parts();
+        let start_elements = start.open_elements.to_vec();
+
+        // Take out everything that was added to the document and the open elements after the checkpoint. It
+        // is either replaced, or put back once the parser is in the same state as it was before.
+        let parents = start.parents(&tree);
+        let mut tails = Vec::with_capacity(parents.len());
+        for &(parent, last_child) in &parents {
+            let tail = children_after(&tree, parent, last_child);
+            for id in &tail {
+                let _ = tree.detach(*id);
+            }
+            tails.push(tail);
+        }
+        // The same goes for the attributes added to <html> and <body>.
+        let added_attributes : Vec<Vec<Attribute>> = start_elements.iter().map(|element| match tree.get_mut(element.id) {
+            Some(Node::Element(node)) => node.attributes.split_off(element.attributes.min(node.attributes.len())),
+            _ => Vec::new(),
+        }).collect();
+
+        let mut state = ParseState::with_sink(PositionSink::from_parts(tree, positions)).with_limits(self.limits);
+        state.check_input_size(self.source.len())?;
+        state.mode = InsertionMode::InBody;
+        state.original_mode = InsertionMode::InBody;
+        state.open_elements = start_elements.iter().map(|element| OpenElement { handle : element.id, kind : element.kind.clone() }).collect();
+        state.head_pointer = start.head_pointer;
+        state.form_pointer = start.form_pointer;
+        state.frame_set_ok = start.frame_set_ok;
+        state.node_count = start.node_count;
+        let mut tokens = Tokenizer::resume(PreProccessor::new(&self.source)?, &start.tokenizer);
+        tokens.set_limits(self.limits);
+
+        // Stop at the first checkpoint after the edit where the state is the same as at the checkpoint with the
+        // same offset in the old source. The parser would do the same as before from there.
+        let edit_end = range.start + inserted_length;
+        let mut caught_up = None;
+        let stop = |checkpoint : &Checkpoint, tree : &Tree<Node>| {
+            let Some(old_offset) = (checkpoint.offset() + range.end).checked_sub(edit_end).filter(|_| checkpoint.offset() >= edit_end) else {
+                return false;
+            };
+            let Ok(index) = later.binary_search_by_key(&old_offset, Checkpoint::offset) else {
+                return false;
+            };
+            let old = &later[index];
+            let same = old.open_elements.len() == checkpoint.open_elements.len()
+                && old.head_pointer == checkpoint.head_pointer && old.form_pointer == checkpoint.form_pointer
+                && old.frame_set_ok == checkpoint.frame_set_ok
+                && old.open_elements.to_vec().iter().zip(&checkpoint.open_elements.to_vec()).enumerate().all(|(index, (old, new))| {
+                    // Only elements from before the restart can be the same, and they have to have been given
+                    // the same attributes.
+                    let before = start_elements.get(index);
+                    old.id == new.id && before.is_some_and(|before| before.id == old.id
+                        && old.attributes.checked_sub(before.attributes).is_some_and(|added| {
+                            attributes(tree, new.id).get(before.attributes..) == added_attributes[index].get(..added)
+                        }))
+                });
+            if same {
+                caught_up = Some(index);
+            }
+            same
+        };
+        let mut stacks = StackBuilder::from(&start.open_elements);
+        let end = run(&mut tokens, &mut state, self.source.len(), &mut self.checkpoints, &mut stacks, stop)?;
+        if end.is_none() {
+            state.set_source_position(tokens.token_position());
+            state.process_token(Token::EOF)?;
+        }
+        let (mut tree, mut positions) = state.finish();
+
+        let mut changes = Changes { reparsed : start.offset()..end.as_ref().map_or(self.source.len(), Checkpoint::offset), ..Changes::default() };
+        // In document order, the children of the deepest open element come first.
+        for &(parent, last_child) in parents.iter().rev() {
+            changes.inserted.extend(children_after(&tree, parent, last_child));
+        }
+        let (Some(caught_up), Some(end)) = (caught_up, end) else {
+            // Parsed up to the end, none of the old nodes are left.
+            for tail in tails {
+                remove(&mut tree, &mut positions, tail, &mut changes.removed);
+            }
+            self.finish_edit(tree, positions, &start_elements, added_attributes, None, &mut changes);
+            return Ok(changes);
+        };
+
+        // Of what came after the restart, the nodes from before the checkpoint the parser caught up with are
+        // replaced and the ones after it are put back.
+        let old = later[caught_up].clone();
+        let old_parents = old.parents(&tree);
+        let shift = Shift::new(old_source, &self.source, start.tokenizer.position, range, inserted_length);
+        let mut new_last_children = Vec::with_capacity(old_parents.len());
+        for (index, mut tail) in tails.into_iter().enumerate() {
+            let Some(&(parent, old_last_child)) = old_parents.get(index) else {
+                // The element was closed before the parser caught up.
+                remove(&mut tree, &mut positions, tail, &mut changes.removed);
+                continue;
+            };
+            let split = match old_last_child {
+                Some(old_last_child) => tail.iter().position(|id| *id == old_last_child).map_or(0, |position| position + 1),
+                None => 0,
+            };
+            let kept = tail.split_off(split);
+            remove(&mut tree, &mut positions, tail, &mut changes.removed);
+            new_last_children.push(tree.last_child(parent));
+            for id in kept {
+                let _ = tree.append(parent, id);
+                let descendants : Vec<NodeId> = std::iter::once(id).chain(tree.descendants(id)).collect();
+                for descendant in descendants {
+                    if let Some(position) = positions.get_mut(&descendant) {
+                        *position = shift.apply(*position);
+                    }
+                }
+            }
+        }
+
+        // The checkpoints after the one the parser caught up with are still valid, once their offsets are
+        // moved and the last children that were replaced are updated.
+        let old_elements = old.open_elements.to_vec();
+        let replaced = old_elements.iter().zip(&new_last_children[1..]).any(|(old, new)| old.last_child != *new);
+        let mut stacks = StackBuilder::from(&end.open_elements);
+        self.checkpoints.push(end);
+        for mut checkpoint in later.iter().skip(caught_up + 1).cloned() {
+            checkpoint.tokenizer.position = shift.apply(checkpoint.tokenizer.position);
+            if checkpoint.document_last_child == old.document_last_child {
+                checkpoint.document_last_child = new_last_children[0];
+            }
+            if replaced {
+                let mut elements = checkpoint.open_elements.to_vec();
+                for (index, element) in elements.iter_mut().enumerate() {
+                    let Some(old) = old_elements.get(index) else {
+                        break;
+                    };
+                    if element.id == old.id && element.last_child == old.last_child {
+                        element.last_child = new_last_children[index + 1];
+                    }
+                }
+                checkpoint.open_elements = stacks.build(elements);
+            }
+            self.checkpoints.push(checkpoint);
+        }
+        self.finish_edit(tree, positions, &start_elements, added_attributes, Some(&old_elements), &mut changes);
+        Ok(changes)
+    }
+
+    // Put back the attributes the old parse added after the checkpoint the parser caught up with, and report
+    // the elements whose attributes aren't the same as before.
+    fn finish_edit(&mut self, mut tree : Tree<Node>, positions : HashMap<NodeId, SourcePosition>, start : &[OpenNode],
+        added_attributes : Vec<Vec<Attribute>>, caught_up : Option<&[OpenNode]>, changes : &mut Changes) {
+        for (index, (element, added)) in start.iter().zip(added_attributes).enumerate() {
+            let Some(Node::Element(node)) = tree.get_mut(element.id) else {
+                continue;
+            };
+            let old_count = caught_up.and_then(|old| old.get(index)).map(|old| old.attributes - element.attributes);
+            if let Some(old_count) = old_count {
+                node.attributes.extend(added[old_count..].iter().cloned());
+            }
+            let before = node.attributes[..element.attributes.min(node.attributes.len())].iter().chain(&added);
+            if !node.attributes.iter().eq(before) {
+                changes.updated.push(element.id);
+            }
+        }
+        self.document = Document::from_parts(tree, positions);
+    }
+}
+
+// Feed tokens to the tree builder and take a checkpoint after every token where that is possible, until the
+// end of the input or until `stop` accepts a checkpoint, which is then returned instead of being kept.
+fn run(tokens : &mut Tokenizer, state : &mut ParseState<PositionSink>, length : usize, checkpoints : &mut Vec<Checkpoint>,
+    stacks : &mut StackBuilder, mut stop : impl FnMut(&Checkpoint, &Tree<Node>) -> bool) -> Result<Option<Checkpoint>, Box<dyn std::error::Error>> {
+    while let Some(wrapped_token) = tokens.next() {
+        let token = HtmlParser::recover_token(wrapped_token)?;
+        // These end with a `>`, so they don't depend on what comes after them.
+        let closed = matches!(token, Token::StartTag(..) | Token::EndTag(..) | Token::Comment(_) | Token::DOCTYPE(..));
+        state.set_source_position(tokens.token_position());
+        state.process_token(token)?;
+        if let Some(tokenizer_state) = state.take_tokenizer_state() {
+            tokens.switch_to(tokenizer_state);
+        }
+        let Some(checkpoint) = closed.then(|| checkpoint(tokens, state, length, stacks)).flatten() else {
+            continue;
+        };
+        if stop(&checkpoint, state.sink.tree()) {
+            return Ok(Some(checkpoint));
+        }
+        checkpoints.push(checkpoint);
+    }
+    Ok(None)
+}
+
+fn checkpoint(tokens : &Tokenizer, state : &ParseState<PositionSink>, length : usize, stacks : &mut StackBuilder) -> Option<Checkpoint> {
+    let snapshot = tokens.snapshot()?;
+    // A token at the very end can be one that the end of the input cut short.
+    if snapshot.state != TokenizationState::Data || snapshot.position.offset >= length || state.mode != InsertionMode::InBody
+        || state.skip_newline || state.reconsume || !state.pending_table_text.is_empty() {
+        return None;
+    }
+    let tree = state.sink.tree();
+    // The bottom of the stack is usually the same as at the last checkpoint, where it has been checked already.
+    let shared = stacks.links.iter().zip(&state.open_elements).take_while(|(link, element)| link.node.id == element.handle
+        && link.node.last_child == tree.last_child(element.handle) && link.node.attributes == attributes(tree, element.handle).len()).count();
+    let mut open_elements = Vec::with_capacity(state.open_elements.len() - shared);
+    for element in &state.open_elements[shared..] {
+        let last_child = tree.last_child(element.handle);
+        // Text inserted next would be merged into the last text node.
+        if matches!(last_child.and_then(|id| tree.get(id)), Some(Node::Text(_))) {
+            return None;
+        }
+        let attributes = attributes(tree, element.handle).len();
+        open_elements.push(OpenNode { id : element.handle, kind : element.kind.clone(), last_child, attributes });
+    }
+    Some(Checkpoint {
+        tokenizer : snapshot,
+        document_last_child : tree.last_child(tree.root()),
+        open_elements : stacks.stack(shared, open_elements),
+        head_pointer : state.head_pointer,
+        form_pointer : state.form_pointer,
+        frame_set_ok : state.frame_set_ok,
+        node_count : state.node_count,
+    })
+}
+
+fn attributes(tree : &Tree<Node>, id : NodeId) -> &[Attribute] {
+    match tree.get(id) {
+        Some(Node::Element(element)) => &element.attributes,
+        _ => &[],
+    }
+}
+
+fn children_after(tree : &Tree<Node>, parent : NodeId, last_child : Option<NodeId>) -> Vec<NodeId> {
+    let mut children = Vec::new();
+    let mut next = match last_child {
+        Some(last_child) => tree.next_sibling(last_child),
+        None => tree.first_child(parent),
+    };
+    while let Some(id) = next {
+        children.push(id);
+        next = tree.next_sibling(id);
+    }
+    children
+}
+
+fn remove(tree : &mut Tree<Node>, positions : &mut HashMap<NodeId, SourcePosition>, nodes : Vec<NodeId>, removed : &mut Vec<NodeId>) {
+    for id in nodes {
+        let descendants : Vec<NodeId> = std::iter::once(id).chain(tree.descendants(id)).collect();
+        for descendant in descendants {
+            positions.remove(&descendant);
+        }
+        let _ = tree.remove(id);
+        removed.push(id);
+    }
+}
+
+// Moves the positions after an edit to where they are in the new source.
+struct Shift {
+    old_end : SourcePosition,
+    new_end : SourcePosition,
+}
+
+impl Shift {
+    // `from` is a position before the edit, where counting lines starts.
+    fn new(old_source : &str, new_source : &str, from : SourcePosition, range : Range<usize>, inserted_length : usize) -> Self {
+        Self {
+            old_end : advance(from, &old_source[from.offset..range.end]),
+            new_end : advance(from, &new_source[from.offset..range.start + inserted_length]),
+        }
+    }
+
+    fn apply(&self, position : SourcePosition) -> SourcePosition {
+        if position.offset < self.old_end.offset {
+            return position;
+        }
+        SourcePosition {
+            offset : position.offset - self.old_end.offset + self.new_end.offset,
+            line : position.line - self.old_end.line + self.new_end.line,
+            column : match position.line == self.old_end.line {
+                true => position.column - self.old_end.column + self.new_end.column,
+                false => position.column,
+            },
+        }
+    }
+}
+
+fn advance(mut position : SourcePosition, text : &str) -> SourcePosition {
+    for c in text.chars() {
+        if c == '\n' {
+            position.line += 1;
+            position.column = 1;
+        } else {
+            position.column += 1;
+        }
+    }
+    position.offset += text.len();
+    position
+}
@@ -0,0 +1,237 @@
+//! A JSON representation of the DOM, for dumping parse results and sending documents between processes.
+//!
+//! [`to_json`] and [`to_flat_json`] write a `Tree<Node>` in the two forms described in [`dds_utils::tree::Flat`],
+//! and [`Node`] and [`Attribute`] implement miniserde's `Deserialize` to read either of them back. Every node
+//! is an object with a `type` and the fields that belong to it :
+//!
+//! - `{"type":"document"}`
+//! - `{"type":"doctype","name":"html","public_id":null,"system_id":null}`
+//! - `{"type":"element","name":"a","namespace":"http://www.w3.org/1999/xhtml","attributes":[{"name":"href","value":"/"}]}`
+//! - `{"type":"text","data":"Hello"}`
+//! - `{"type":"comment","data":" note "}`
+//!
+//! The namespace is only there for the reader's benefit. It is worked out from the element's ancestors, the
+//! same way the parser switches to foreign content, and follows from the names again when the JSON is read back.
+
+use std::borrow::Cow;
+
+use miniserde::{de::{self, Deserialize, Visitor}, json, make_place, ser::{self, Fragment, Serialize}, Error, Result};
+
+use dds_utils::tree::Edge;
+
+use crate::{dom::{Element, Node, HTML_NAMESPACE}, serializer::namespace_in, tokenizer::Attribute, Tree};
+
+/// Serialize a parsed document as nested JSON.
+pub fn to_json(dom : &Tree<Node>) -> String {
+    json::to_string(&with_namespaces(dom))
+}
+
+/// Serialize a parsed document as a flat table of nodes.
+pub fn to_flat_json(dom : &Tree<Node>) -> String {
+    json::to_string(&with_namespaces(dom).as_ref().map(Tree::flat))
+}
+
+/// Read a document written by [`to_json`] or [`to_flat_json`].
+pub fn from_json(text : &str) -> Result<Tree<Node>> {
+    json::from_str(text)
+}
+
+// A node along with the namespace it is in, which depends on its ancestors.
+struct JsonNode<'a> {
+    node : &'a Node,
+    namespace : &'static str,
+}
+
+// A copy of the tree that refers to the nodes of `dom`, with the namespace of every element worked out from
+// its parent's, see serializer::namespace_in. None if the root isn't there, which serializes as null.
+fn with_namespaces(dom : &Tree<Node>) -> Option<Tree<JsonNode<'_>>> {
+    let root = dom.root();
+    let mut tree = Tree::new(JsonNode { node : dom.get(root)?, namespace : HTML_NAMESPACE });
+    // The copies of the nodes that have been opened but not closed yet, with their namespace and name.
+    let mut open = vec![(tree.root(), HTML_NAMESPACE, "")];
+    for edge in dom.traverse(root).skip(1) {
+        match edge {
+            Edge::Open(id) => {
+                let (node, &(parent, parent_namespace, parent_name)) = (dom.get(id)?, open.last()?);
+                let (namespace, name) = match node {
+                    Node::Element(element) => (namespace_in(parent_namespace, parent_name, element.name()), element.name()),
+                    _ => (HTML_NAMESPACE, ""),
+                };
+                let copy = tree.new_node(JsonNode { node, namespace });
+                let _ = tree.append(parent, copy);
+                open.push((copy, namespace, name));
+            },
+            Edge::Close(_) => {
+                open.pop();
+            },
+        }
+    }
+    Some(tree)
+}
+
+impl<'a> Serialize for JsonNode<'a> {
+    fn begin(&self) -> Fragment<'_> {
+        let (kind, name) = match self.node {
+            Node::Document => ("document", ""),
+            Node::Doctype { .. } => ("doctype", ""),
+            Node::Element(element) => ("element", element.name()),
+            Node::Text(_) => ("text", ""),
+            Node::Comment(_) => ("comment", ""),
+        };
+        Fragment::Map(Box::new(NodeFields { node : self.node, kind, name, namespace : self.namespace, index : 0 }))
+    }
+}
+
+struct NodeFields<'a> {
+    node : &'a Node,
+    kind : &'static str,
+    // The element's name and namespace, which aren't stored in the element as strings.
+    name : &'a str,
+    namespace : &'static str,
+    index : usize,
+}
+
+impl<'a> ser::Map for NodeFields<'a> {
+    fn next(&mut self) -> Option<(Cow<'_, str>, &dyn Serialize)> {
+        let index = self.index;
+        self.index += 1;
+        let (key, value) : (&str, &dyn Serialize) = match (self.node, index) {
+            (_, 0) => ("type", &self.kind),
+            (Node::Doctype { name, .. }, 1) => ("name", name),
+            (Node::Doctype { public_id, .. }, 2) => ("public_id", public_id),
+            (Node::Doctype { system_id, .. }, 3) => ("system_id", system_id),
+            (Node::Element(_), 1) => ("name", &self.name),
+            (Node::Element(_), 2) => ("namespace", &self.namespace),
+            (Node::Element(element), 3) => ("attributes", &element.attributes),
+            (Node::Text(data) | Node::Comment(data), 1) => ("data", data),
+            _ => return None,
+        };
+        Some((Cow::Borrowed(key), value))
+    }
+}
+
+impl Serialize for Attribute {
+    fn begin(&self) -> Fragment<'_> {
+        Fragment::Map(Box::new(AttributeFields { attribute : self, index : 0 }))
+    }
+}
+
+struct AttributeFields<'a> {
+    attribute : &'a Attribute,
+    index : usize,
+}
+
+impl<'a> ser::Map for AttributeFields<'a> {
+    fn next(&mut self) -> Option<(Cow<'_, str>, &dyn Serialize)> {
+        let index = self.index;
+        self.index += 1;
+        match index {
+            0 => Some((Cow::Borrowed("name"), &self.attribute.name)),
+            1 => Some((Cow::Borrowed("value"), &self.attribute.value)),
+            _ => None,
+        }
+    }
+}
+
+// miniserde hands out a visitor that lives in the `Option` being deserialized into, `Place` wraps that option
+// so that the visitor can be implemented for it.
+make_place!(Place);
+
+impl Deserialize for Node {
+    fn begin(out : &mut Option<Self>) -> &mut dyn Visitor {
+        Place::new(out)
+    }
+}
+
+impl Visitor for Place<Node> {
+    fn map(&mut self) -> Result<Box<dyn de::Map + '_>> {
+        Ok(Box::new(NodeBuilder {
+            out : &mut self.out,
+            kind : None,
+            name : None,
+            public_id : None,
+            system_id : None,
+            attributes : None,
+            data : None,
+        }))
+    }
+}
+
+// The fields of every type of node, `finish` checks that the ones the type needs are there.
+struct NodeBuilder<'a> {
+    out : &'a mut Option<Node>,
+    kind : Option<String>,
+    name : Option<Option<String>>,
+    public_id : Option<Option<String>>,
+    system_id : Option<Option<String>>,
+    attributes : Option<Vec<Attribute>>,
+    data : Option<String>,
+}
+
+impl<'a> de::Map for NodeBuilder<'a> {
+    fn key(&mut self, key : &str) -> Result<&mut dyn Visitor> {
+        match key {
+            "type" => Ok(Deserialize::begin(&mut self.kind)),
+            "name" => Ok(Deserialize::begin(&mut self.name)),
+            "public_id" => Ok(Deserialize::begin(&mut self.public_id)),
+            "system_id" => Ok(Deserialize::begin(&mut self.system_id)),
+            "attributes" => Ok(Deserialize::begin(&mut self.attributes)),
+            "data" => Ok(Deserialize::begin(&mut self.data)),
+            _ => Ok(<dyn Visitor>::ignore()),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let node = match self.kind.as_deref() {
+            Some("document") => Node::Document,
+            Some("doctype") => Node::Doctype {
+                name : self.name.take().flatten(),
+                public_id : self.public_id.take().flatten(),
+                system_id : self.system_id.take().flatten(),
+            },
+            Some("element") => {
+                let name = self.name.take().flatten().ok_or(Error)?;
+                Node::Element(Element::from_tag(&name, self.attributes.take().unwrap_or_default()))
+            },
+            Some("text") => Node::Text(self.data.take().ok_or(Error)?),
+            Some("comment") => Node::Comment(self.data.take().ok_or(Error)?),
+            _ => return Err(Error),
+        };
+        *self.out = Some(node);
+        Ok(())
+    }
+}
+
+impl Deserialize for Attribute {
+    fn begin(out : &mut Option<Self>) -> &mut dyn Visitor {
+        Place::new(out)
+    }
+}
+
+impl Visitor for Place<Attribute> {
+    fn map(&mut self) -> Result<Box<dyn de::Map + '_>> {
+        Ok(Box::new(AttributeBuilder { out : &mut self.out, name : None, value : None }))
+    }
+}
+
+struct AttributeBuilder<'a> {
+    out : &'a mut Option<Attribute>,
+    name : Option<String>,
+    value : Option<String>,
+}
+
+impl<'a> de::Map for AttributeBuilder<'a> {
+    fn key(&mut self, key : &str) -> Result<&mut dyn Visitor> {
+        match key {
+            "name" => Ok(Deserialize::begin(&mut self.name)),
+            "value" => Ok(Deserialize::begin(&mut self.value)),
+            _ => Ok(<dyn Visitor>::ignore()),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let name = self.name.take().ok_or(Error)?;
+        *self.out = Some(Attribute { name, value : self.value.take().unwrap_or_default() });
+        Ok(())
+    }
+}
@@ -5,7 +5,8 @@
 //! the HTML5 specification. It is however built according to the HTML standard as much as possible.
 //! 
 //! The main difference is the lack of any scripting capabilities. This allows for better / easier 
-//! concurrency as the tree builder will never halt the tokenizer, which allows them to work in parallel.
+//! concurrency as the tree builder will never halt the tokenizer, which allows them to work in parallel
+//! (see [`HtmlParser::parse_pipelined`]).
 //! Since scripting capabilities are not needed for the DDS project, it makes sense to just leave them out and
 //! to instead optimize everything for the parser's intended use case.
 //! 
@@ -21,63 +22,223 @@
 //! - [ ] 
 #![allow(unused)]
 
+mod character_references;
+pub mod document;
+pub mod dom;
 pub mod error;
+pub mod highlight;
+pub mod incremental;
+pub mod json;
+pub mod limits;
+pub mod lint;
+pub mod lossless;
+pub mod markdown;
+pub mod metadata;
+pub mod minifier;
 pub mod parser;
+pub mod pipeline;
 pub mod preproccesor;
+mod query;
+pub mod sanitizer;
+pub mod sax;
+pub mod serializer;
+pub mod sink;
+pub mod snapshot;
 pub mod states;
+pub mod text;
 pub mod tokenizer;
 mod tests;
 
-use std::{default, string::ParseError};
-use indextree::Arena;
+use std::{default, string::ParseError, io::Read};
 use log::*;
-use crate::{tokenizer::Token, states::InsertionMode, error::HtmlParseError, preproccesor::PreProccessor, tokenizer::Tokenizer};
+use crate::{tokenizer::Token, states::{InsertionMode, TokenizationState}, error::HtmlParseError, preproccesor::PreProccessor, tokenizer::Tokenizer, sink::{TreeSink, ArenaSink}, limits::ParserLimits};
 use parser::*;
 
+pub use document::Document;
+pub use dom::{Element, ElementKind, Node};
+pub use dds_utils::tree::{NodeId, Tree};
 
-pub trait Tree<T> {
-    fn get_node_id(&mut self, node: &T);
-}
-
-#[derive(Debug)]
-pub enum ElementKind {
-    Html,
-    Head,
-    Body,
-    Br,
-}
-
-#[derive(Debug)]
-pub struct Element {
+// An entry in the stack of open elements. The kind is kept next to the handle so the tree builder
+// never has to ask the sink what an element is.
+#[derive(Clone)]
+pub(crate) struct OpenElement<H> {
+    handle : H,
     kind : ElementKind,
 }
 
-pub struct ParseState {
+#[derive(Clone)]
+pub struct ParseState<S : TreeSink = ArenaSink> {
     frame_set_ok : bool,
     reconsume : bool,
-    tree: Arena<Element>,
-    mode: InsertionMode,
-    open_elements: Vec<indextree::NodeId>,
+    sink : S,
+    mode : InsertionMode,
+    original_mode : InsertionMode,
+    open_elements : Vec<OpenElement<S::Handle>>,
     previous : Option<Token>,
-    head_pointer : Option<indextree::NodeId>,
+    head_pointer : Option<S::Handle>,
+    form_pointer : Option<S::Handle>,
+    skip_newline : bool,
+    pending_table_text : String,
+    // Set by the tree builder when the tokenizer needs to switch state, e.g. after a <title> start tag.
+    tokenizer_state : Option<TokenizationState>,
+    limits : ParserLimits,
+    // Number of nodes inserted so far, checked against the limits after every token.
+    node_count : usize,
+    // True while characters are being added to the same text node.
+    in_text : bool,
+    // Where the token being processed starts, see ParseState::set_source_position.
+    position : tokenizer::SourcePosition,
+    // Parse errors are only kept when asked for, see ParseState::record_errors.
+    record_errors : bool,
+    errors : Vec<HtmlParseError>,
 }
 
 impl ParseState {
     pub fn new() -> Self {
+        Self::with_sink(ArenaSink::new())
+    }
+}
+
+impl Default for ParseState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S : TreeSink> ParseState<S> {
+    pub fn with_sink(sink : S) -> Self {
         Self {
             mode: InsertionMode::Initial,
+            original_mode: InsertionMode::Initial,
             open_elements: Vec::new(),
-            tree : Arena::new(),
+            sink,
             reconsume : false,
             previous : None,
             head_pointer : None,
+            form_pointer : None,
             frame_set_ok : true,
+            skip_newline : false,
+            pending_table_text : String::new(),
+            tokenizer_state : None,
+            limits : ParserLimits::unlimited(),
+            node_count : 0,
+            in_text : false,
+            position : tokenizer::SourcePosition::default(),
+            record_errors : false,
+            errors : Vec::new(),
         }
     }
+
+    /// Set the limits for parsing with this state, see [`ParserLimits`].
+    pub fn with_limits(mut self, limits : ParserLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn limits(&self) -> &ParserLimits {
+        &self.limits
+    }
+
+    /// Keep track of parse errors from now on, they can be read with [`ParseState::take_errors`].
+    pub fn record_errors(&mut self) {
+        self.record_errors = true;
+    }
+
+    /// Returns the parse errors recorded since the last call, all of them [`HtmlParseError::ParseError`].
+    pub fn take_errors(&mut self) -> Vec<HtmlParseError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    // Fails if an input of `size` bytes is over the limit.
+    fn check_input_size(&self, size : usize) -> Result<(), HtmlParseError> {
+        if size > self.limits.max_input_size {
+            return Err(HtmlParseError::InputTooLarge(self.limits.max_input_size));
+        }
+        Ok(())
+    }
+
+    /// Run a single token through the tree builder, including any re-processing it causes.
+    pub fn process_token(&mut self, token : Token) -> Result<(), HtmlParseError> {
+        // The newline directly after a <pre>, <listing> or <textarea> start tag is ignored.
+        if std::mem::replace(&mut self.skip_newline, false) && token == Token::Character('\n') {
+            return Ok(());
+        }
+        // NOTE: Previously handled tokens are only needed when re-consume a previous token. This could be optimised so that the previous token is only set when necessary (aka if the token needs to be re-consumed later on).
+        self.previous = Some(token.clone());
+        let mut current_token = token;
+        loop {
+            // These functions should (hopefully) be inlined by the compiler.
+            match self.mode {
+                InsertionMode::Initial => parse_initial(current_token, self),
+                InsertionMode::BeforeHtml => parse_before_html(current_token, self),
+                InsertionMode::BeforeHead => parse_before_head(current_token, self),
+                InsertionMode::InHead => parse_in_head(current_token, self),
+                InsertionMode::InHeadNoScript => parse_in_head_noscript(current_token, self),
+                InsertionMode::AfterHead => parse_after_head(current_token, self),
+                InsertionMode::InBody => parse_in_body(current_token, self),
+                InsertionMode::Text => parse_text(current_token, self),
+                InsertionMode::InTable => parse_in_table(current_token, self),
+                InsertionMode::InTableText => parse_in_table_text(current_token, self),
+                InsertionMode::InCaption => parse_in_caption(current_token, self),
+                InsertionMode::InColumnGroup => parse_in_column_group(current_token, self),
+                InsertionMode::InTableBody => parse_in_table_body(current_token, self),
+                InsertionMode::InRow => parse_in_row(current_token, self),
+                InsertionMode::InCell => parse_in_cell(current_token, self),
+                InsertionMode::InSelect => parse_in_select(current_token, self),
+                InsertionMode::InSelectTable => parse_in_select_table(current_token, self),
+                InsertionMode::AfterBody => parse_after_body(current_token, self),
+                InsertionMode::InFrameset => parse_in_frameset(current_token, self),
+                InsertionMode::AfterFrameset => parse_after_frameset(current_token, self),
+                InsertionMode::AfterAfterBody => parse_after_after_body(current_token, self),
+                InsertionMode::AfterAfterFrameset => parse_after_after_frameset(current_token, self),
+            }?;
+
+            if self.node_count > self.limits.max_nodes {
+                return Err(HtmlParseError::NodeLimitExceeded(self.limits.max_nodes));
+            }
+
+            // Check if we need to re-consume the token in the new insertion mode.
+            if !self.reconsume {
+                return Ok(());
+            }
+            self.reconsume = false;
+            current_token = match self.previous {
+                Some(ref token) => {
+                    if cfg!(feature = "parser-log") {info!("Reconsumed token {:?}", token);}
+                    token.clone()
+                },
+                // Cannot re-consume a previously processed token if no token has been processed.
+                None => return Err(HtmlParseError::ReconsumeNonExistingToken),
+            };
+        }
+    }
+
+    /// Tell the sink where the next token starts, see [`TreeSink::set_source_position`].
+    pub fn set_source_position(&mut self, position : tokenizer::SourcePosition) {
+        self.position = position;
+        self.sink.set_source_position(position);
+    }
+
+    /// Returns the tokenization state that the tree builder last asked for, if any.
+    /// This has to be applied to the tokenizer before it produces the next token.
+    pub fn take_tokenizer_state(&mut self) -> Option<TokenizationState> {
+        self.tokenizer_state.take()
+    }
+
+    /// Close any elements that are still open and hand back whatever the sink produced.
+    pub fn finish(mut self) -> S::Output {
+        while let Some(element) = self.open_elements.pop() {
+            self.sink.pop(&element.handle, &element.kind);
+        }
+        self.sink.finish()
+    }
 }
 
 pub struct HtmlParser {}
 
+// The size of the chunks read by HtmlParser::parse_stream.
+const STREAM_CHUNK_SIZE : usize = 64 * 1024;
+
 impl HtmlParser {
     pub fn new() -> Self {
         Self {}
@@ -85,77 +246,164 @@ impl HtmlParser {
 
     // Parse a token stream into a DOM Tree.
     // https://html.spec.whatwg.org/multipage/parsing.html#tree-construction
-    pub fn parse(input: &str, mut state: ParseState) -> Result<Arena<Element>, Box<dyn std::error::Error>>
-    //where T : Tree<Element> + Default
-    {
+    pub fn parse<S : TreeSink>(input: &str, mut state: ParseState<S>) -> Result<S::Output, Box<dyn std::error::Error>> {
+        state.check_input_size(input.len())?;
         let html = PreProccessor::new(input)?;
         let mut tokens = Tokenizer::new(html);
-        
-        // Exit the loop after all tokens have been parsed.
-        loop {
-            // Here we need to get the next token we need to process.
-            // Check if we need to re-consume a previously processed token.
-            let current_token = if state.reconsume {
-                if let Some(ref token) = state.previous {
-                    if cfg!(feature = "parser-log") {info!("Reconsumed token {:?}", token);}
-                    state.reconsume = false;
-                    token.clone()
-                } else {
-                    // Cannot re-consume a previously processed token if no token has been processed.
-                    return Err(Box::new(HtmlParseError::ReconsumeNonExistingToken));
+        tokens.set_limits(state.limits);
+        Self::run(&mut tokens, &mut state)?;
+        state.set_source_position(tokens.token_position());
+        state.process_token(Token::EOF)?;
+
+        // Return a fully constructed tree.
+        Ok(state.finish())
+    }
+
+    /// Run a sequence of tokens through the tree builder, e.g. a [`tokenizer::TokenStream`] recorded with
+    /// [`HtmlParser::parse_recording`]. The tokens are used as they are, so when the tree builder asks for
+    /// what follows to be tokenized differently, like the text of a `<title>`, nothing changes. Tokens after
+    /// an EOF token are ignored, and one is added at the end if there is none.
+    pub fn parse_tokens<I : IntoIterator<Item = Token>, S : TreeSink>(tokens: I, mut state: ParseState<S>) -> Result<S::Output, Box<dyn std::error::Error>> {
+        for token in tokens {
+            let eof = token == Token::EOF;
+            state.process_token(token)?;
+            state.take_tokenizer_state();
+            if eof {
+                return Ok(state.finish());
+            }
+        }
+        state.process_token(Token::EOF)?;
+        Ok(state.finish())
+    }
+
+    /// Like [`HtmlParser::parse`], but also records the tokens given to the tree builder, so that they can be
+    /// saved and replayed with [`HtmlParser::parse_tokens`]. The tokens are returned even if parsing fails, up
+    /// to the one it failed on.
+    pub fn parse_recording<S : TreeSink>(input: &str, mut state: ParseState<S>) -> (Result<S::Output, Box<dyn std::error::Error>>, tokenizer::TokenStream) {
+        let mut recorded = Vec::new();
+        let mut parse = || -> Result<(), Box<dyn std::error::Error>> {
+            state.check_input_size(input.len())?;
+            let mut tokens = Tokenizer::new(PreProccessor::new(input)?);
+            tokens.set_limits(state.limits);
+            while let Some(wrapped_token) = tokens.next() {
+                let token = Self::recover_token(wrapped_token)?;
+                recorded.push(token.clone());
+                state.set_source_position(tokens.token_position());
+                state.process_token(token)?;
+                if let Some(tokenizer_state) = state.take_tokenizer_state() {
+                    tokens.switch_to(tokenizer_state);
                 }
-            // Get the next token from the tokenizer.
-            } else if let Some(wrapped_token) = tokens.next() {
-                let token = match wrapped_token {
-                    Ok(token) => token,
-                    Err(e) => {
-                        match e {
-                            error::HtmlTokenizerError::UndefinedError(token) => token,
-                            error::HtmlTokenizerError::UnexpectedNullCharacter => return Err(Box::new(e)),
-                        }
-                    }
-                };
-                if cfg!(feature = "parser-log") {info!("Token : {:?}", token);}
-                // NOTE: Previously handled tokens are only needed when re-consume a previous token. This could be optimised so that the previous token is only set when necessary (aka if the token needs to be re-consumed later on).
-                state.previous = Some(token.clone());
-                token
-            } else {
-                // Exit the loop if tokens.next() returns None, which means we have read all tokens. This is the equivalent of hitting EOF.
-                break;
-            };
+            }
+            recorded.push(Token::EOF);
+            state.set_source_position(tokens.token_position());
+            state.process_token(Token::EOF)?;
+            Ok(())
+        };
+        let result = parse();
+        (result.map(|_| state.finish()), tokenizer::TokenStream { tokens : recorded })
+    }
 
-            // These functions should (hopefully) be inlined by the compiler.
-            let result = match state.mode {
-                InsertionMode::Initial => parse_initial(current_token, &mut state),
-                InsertionMode::BeforeHtml => parse_before_html(current_token, &mut state),
-                InsertionMode::BeforeHead => parse_before_head(current_token, &mut state),
-                InsertionMode::InHead => parse_in_head(current_token, &mut state),
-                InsertionMode::InHeadNoScript => parse_in_head_noscript(current_token, &mut state),
-                InsertionMode::AfterHead => parse_after_head(current_token, &mut state),
-                InsertionMode::InBody => parse_in_body(current_token, &mut state),
-                InsertionMode::Text => parse_text(current_token, &mut state),
-                InsertionMode::InTable => parse_in_table(current_token, &mut state),
-                InsertionMode::InTableText => parse_in_table_text(current_token, &mut state),
-                InsertionMode::InCaption => parse_in_caption(current_token, &mut state),
-                InsertionMode::InColumnGroup => parse_in_column_group(current_token, &mut state),
-                InsertionMode::InTableBody => parse_in_table_body(current_token, &mut state),
-                InsertionMode::InRow => parse_in_row(current_token, &mut state),
-                InsertionMode::InCell => parse_in_cell(current_token, &mut state),
-                InsertionMode::InSelect => parse_in_select(current_token, &mut state),
-                InsertionMode::InSelectTable => parse_in_select_table(current_token, &mut state),
-                InsertionMode::AfterBody => parse_after_body(current_token, &mut state),
-                InsertionMode::InFrameset => parse_in_frameset(current_token, &mut state),
-                InsertionMode::AfterFrameset => parse_after_frameset(current_token, &mut state),
-                InsertionMode::AfterAfterBody => parse_after_body(current_token, &mut state),
-                InsertionMode::AfterAfterFrameset => parse_after_after_frameset(current_token, &mut state),
+    /// Like [`HtmlParser::parse`], but also returns the parse errors of the tokenizer and the tree builder
+    /// in the order of their position in the input. Documents with parse errors are still parsed, the
+    /// errors only mean that the document isn't conforming.
+    pub fn parse_with_errors<S : TreeSink>(input: &str, mut state: ParseState<S>) -> Result<(S::Output, Vec<HtmlParseError>), Box<dyn std::error::Error>> {
+        state.check_input_size(input.len())?;
+        state.record_errors();
+        let mut tokens = Tokenizer::new(PreProccessor::new(input)?);
+        tokens.set_limits(state.limits);
+        tokens.record_errors();
+        Self::run(&mut tokens, &mut state)?;
+        state.set_source_position(tokens.token_position());
+        state.process_token(Token::EOF)?;
+        let mut errors = state.take_errors();
+        errors.extend(tokens.take_parse_errors());
+        errors.sort_by_key(|error| error.position());
+        Ok((state.finish(), errors))
+    }
+
+    /// Parse a document that is read in chunks from `reader`. Only the current chunk and the tree
+    /// builder's state are kept in memory, so combined with a sink that doesn't store the document
+    /// (see [`sax`]) this can handle inputs of any size.
+    pub fn parse_stream<R : Read, S : TreeSink>(reader: R, state: ParseState<S>) -> Result<S::Output, Box<dyn std::error::Error>> {
+        let mut tokens = Tokenizer::new_streaming(PreProccessor::new("")?);
+        tokens.set_limits(state.limits);
+        Self::resume_stream(reader, tokens, state, |_, _| Ok(()))
+    }
+
+    /// Carry on with a parse started by [`HtmlParser::parse_stream`], e.g. one saved with [`snapshot::save`].
+    /// `reader` has to start at [`Tokenizer::input_offset`] of `tokens`.
+    ///
+    /// `checkpoint` is called after every chunk of input, once the tree builder has caught up with the
+    /// tokenizer. This is where the state of the parser can be saved, and an error it returns stops the parse.
+    pub fn resume_stream<R, S, F>(mut reader: R, mut tokens: Tokenizer, mut state: ParseState<S>, mut checkpoint: F) -> Result<S::Output, Box<dyn std::error::Error>>
+    where R : Read, S : TreeSink, F : FnMut(&Tokenizer, &ParseState<S>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut buf = vec![0; STREAM_CHUNK_SIZE];
+        // Number of bytes at the start of buf that belong to a character that was cut off by the previous read.
+        let mut carry = 0;
+        loop {
+            let read = match reader.read(&mut buf[carry..]) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Box::new(e)),
+            };
+            // What was fed to the tokenizer so far, followed by what is in buf.
+            let filled = carry + read;
+            state.check_input_size(tokens.input_offset() + filled)?;
+            let (text, rest) = match std::str::from_utf8(&buf[..filled]) {
+                Ok(text) => (text, 0),
+                Err(e) if e.error_len().is_none() => (std::str::from_utf8(&buf[..e.valid_up_to()])?, filled - e.valid_up_to()),
+                Err(e) => return Err(Box::new(e)),
             };
-            
-            if let Err(e) = result {
-                return Err(Box::new(e));
+            tokens.feed(text);
+            buf.copy_within(filled - rest..filled, 0);
+            carry = rest;
+            Self::run(&mut tokens, &mut state)?;
+            checkpoint(&tokens, &state)?;
+        }
+        // Input that ends in the middle of a character is invalid.
+        std::str::from_utf8(&buf[..carry])?;
+
+        tokens.close();
+        Self::run(&mut tokens, &mut state)?;
+        state.set_source_position(tokens.token_position());
+        state.process_token(Token::EOF)?;
+        Ok(state.finish())
+    }
+
+    // Feed every token the tokenizer can currently produce into the tree builder.
+    fn run<S : TreeSink>(tokens: &mut Tokenizer, state: &mut ParseState<S>) -> Result<(), Box<dyn std::error::Error>> {
+        while let Some(wrapped_token) = tokens.next() {
+            let token = Self::recover_token(wrapped_token)?;
+            if cfg!(feature = "parser-log") {info!("Token : {:?}", token);}
+            state.set_source_position(tokens.token_position());
+            state.process_token(token)?;
+
+            // The tree builder may have decided that what follows should be tokenized differently.
+            if let Some(tokenizer_state) = state.take_tokenizer_state() {
+                tokens.switch_to(tokenizer_state);
             }
         }
+        Ok(())
+    }
 
-        // Return a fully constructed tree.
-        Ok(state.tree)
+    // Most tokenizer errors still carry a usable token, only the rest are fatal.
+    fn recover_token(wrapped_token: Result<Token, error::HtmlTokenizerError>) -> Result<Token, Box<dyn std::error::Error>> {
+        match wrapped_token {
+            Ok(token) => Ok(token),
+            Err(e) => {
+                match e {
+                    error::HtmlTokenizerError::UndefinedError(token) => Ok(token),
+                    error::HtmlTokenizerError::UnexpectedNullCharacter | error::HtmlTokenizerError::Unsupported(_)
+                    | error::HtmlTokenizerError::MissingAttribute(_) => Err(Box::new(e)),
+                }
+            }
+        }
     }
-}
\ No newline at end of file
+}
+
+impl Default for HtmlParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,57 @@
+//! Resource limits for parsing untrusted input.
+
+/// Upper bounds on the resources a single parse may use. The default has no limits at all, use
+/// [`ParserLimits::untrusted`] for documents from an untrusted source.
+///
+/// Where the specification allows for it, going over a limit changes how the document is parsed
+/// instead of failing :
+/// - Elements that would be nested deeper than `max_depth` are inserted next to the deepest element
+///   instead of inside of it, the same way browsers handle deeply nested documents.
+/// - Attributes after the first `max_attributes` on an element are dropped.
+/// - Tag and attribute names are cut off after `max_name_length` bytes, attribute values after
+///   `max_attribute_length` bytes.
+///
+/// The remaining limits stop the parse with an error :
+/// - Creating more than `max_nodes` nodes fails with [`crate::error::HtmlParseError::NodeLimitExceeded`].
+/// - Input longer than `max_input_size` bytes fails with [`crate::error::HtmlParseError::InputTooLarge`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserLimits {
+    pub max_depth : usize,
+    pub max_attributes : usize,
+    pub max_name_length : usize,
+    pub max_attribute_length : usize,
+    pub max_nodes : usize,
+    pub max_input_size : usize,
+}
+
+impl ParserLimits {
+    /// No limits, every value is `usize::MAX`.
+    pub fn unlimited() -> Self {
+        Self {
+            max_depth : usize::MAX,
+            max_attributes : usize::MAX,
+            max_name_length : usize::MAX,
+            max_attribute_length : usize::MAX,
+            max_nodes : usize::MAX,
+            max_input_size : usize::MAX,
+        }
+    }
+
+    /// Limits that no legitimate document should run into. The maximum depth matches the one used by browsers.
+    pub fn untrusted() -> Self {
+        Self {
+            max_depth : 512,
+            max_attributes : 256,
+            max_name_length : 1024,
+            max_attribute_length : 1024 * 1024,
+            max_nodes : 1_000_000,
+            max_input_size : 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
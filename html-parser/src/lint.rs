@@ -0,0 +1,324 @@
+//! Checking documents for conformance problems, which is what the `html-lint` binary runs.
+//!
+//! [`lint`] reports the parse errors of the tokenizer and the tree builder, and then looks at the tree for
+//! problems that parse fine but aren't allowed by the [authoring requirements](https://html.spec.whatwg.org/multipage/dom.html#content-models) :
+//! - Children an element isn't allowed to have, like a `<div>` in a `<ul>` or a `<p>` in a `<span>`, and
+//!   interactive content like links or buttons inside of a link or a button.
+//! - Images without an `alt` attribute and ids that are empty or used more than once.
+//! - [Obsolete elements](https://html.spec.whatwg.org/multipage/obsolete.html) like `<center>` and `<font>`,
+//!   and obsolete presentational attributes like `align` or `bgcolor`.
+//! - A missing `<title>`, or a missing `lang` on the `<html>` element.
+//!
+//! This isn't a full validator. Only the content models that are most often broken are checked, and the
+//! contents of `<svg>` and `<math>` are skipped since the parser doesn't implement foreign content.
+
+use std::collections::HashMap;
+
+use dds_utils::tree::Edge;
+use miniserde::json::{Number, Object, Value};
+
+use crate::{document::{Document, ElementRef}, dom::{ElementKind, Node}, tokenizer::SourcePosition};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The document is conforming, but uses something that should be replaced.
+    Warning,
+    /// The document isn't conforming.
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// A problem found by [`lint`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity : Severity,
+    /// A short name for the kind of problem, e.g. `missing-alt` or the code of a parse error like `eof-in-tag`.
+    pub code : &'static str,
+    pub message : String,
+    pub position : Option<SourcePosition>,
+}
+
+impl Diagnostic {
+    fn new(severity : Severity, code : &'static str, message : String, position : Option<SourcePosition>) -> Self {
+        Self { severity, code, message, position }
+    }
+
+    /// The diagnostic on a line of its own, e.g. `index.html:3:5: error[missing-alt]: <img> without an alt attribute`.
+    pub fn to_human(&self, file : &str) -> String {
+        match self.position {
+            Some(position) => format!("{}:{}:{}: {}[{}]: {}", file, position.line, position.column, self.severity.as_str(), self.code, self.message),
+            None => format!("{}: {}[{}]: {}", file, self.severity.as_str(), self.code, self.message),
+        }
+    }
+
+    /// The diagnostic as an object with the fields `file`, `line`, `column`, `severity`, `code` and
+    /// `message`. The line and column are null if the position isn't known.
+    pub fn to_json(&self, file : &str) -> Value {
+        let number = |n : usize| Value::Number(Number::U64(n as u64));
+        let mut object = Object::new();
+        object.insert("file".to_string(), Value::String(file.to_string()));
+        object.insert("line".to_string(), self.position.map_or(Value::Null, |position| number(position.line)));
+        object.insert("column".to_string(), self.position.map_or(Value::Null, |position| number(position.column)));
+        object.insert("severity".to_string(), Value::String(self.severity.as_str().to_string()));
+        object.insert("code".to_string(), Value::String(self.code.to_string()));
+        object.insert("message".to_string(), Value::String(self.message.clone()));
+        Value::Object(object)
+    }
+}
+
+/// Parse `html` and report its parse errors and conformance problems, in the order they appear in the input.
+pub fn lint(html : &str) -> Result<Vec<Diagnostic>, Box<dyn std::error::Error>> {
+    let (document, errors) = Document::parse_with_errors(html)?;
+    let mut diagnostics : Vec<Diagnostic> = errors.iter()
+        .filter_map(|error| Some(Diagnostic::new(Severity::Error, error.code()?.as_str(), error.code()?.to_string(), error.position())))
+        .collect();
+    diagnostics.extend(check_document(&document));
+    diagnostics.sort_by_key(|diagnostic| diagnostic.position);
+    Ok(diagnostics)
+}
+
+// https://html.spec.whatwg.org/multipage/obsolete.html#non-conforming-features
+const OBSOLETE_ELEMENTS : &[&str] = &[
+    "acronym", "applet", "basefont", "bgsound", "big", "blink", "center", "dir", "font", "frame", "frameset",
+    "isindex", "keygen", "listing", "marquee", "menuitem", "multicol", "nextid", "nobr", "noembed", "noframes",
+    "param", "plaintext", "rb", "rtc", "spacer", "strike", "tt", "xmp",
+];
+
+// Obsolete attributes and the elements they are obsolete on. An empty list means every element.
+const OBSOLETE_ATTRIBUTES : &[(&str, &[&str])] = &[
+    ("align", &[]),
+    ("alink", &["body"]),
+    ("archive", &["object"]),
+    ("axis", &["td", "th"]),
+    ("background", &[]),
+    ("bgcolor", &[]),
+    ("border", &["img", "object", "table"]),
+    ("cellpadding", &["table"]),
+    ("cellspacing", &["table"]),
+    ("char", &[]),
+    ("charoff", &[]),
+    ("charset", &["a", "link", "script"]),
+    ("classid", &["object"]),
+    ("clear", &["br"]),
+    ("codebase", &["object"]),
+    ("codetype", &["object"]),
+    ("compact", &[]),
+    ("coords", &["a"]),
+    ("declare", &["object"]),
+    ("frame", &["table"]),
+    ("frameborder", &["iframe"]),
+    ("height", &["table", "td", "th", "tr"]),
+    ("hspace", &[]),
+    ("language", &["script"]),
+    ("link", &["body"]),
+    ("longdesc", &["iframe", "img"]),
+    ("lowsrc", &["img"]),
+    ("manifest", &["html"]),
+    ("marginheight", &["body", "iframe"]),
+    ("marginwidth", &["body", "iframe"]),
+    ("name", &["img"]),
+    ("nohref", &["area"]),
+    ("noshade", &["hr"]),
+    ("nowrap", &["td", "th"]),
+    ("profile", &["head"]),
+    ("rev", &["a", "link"]),
+    ("rules", &["table"]),
+    ("scheme", &["meta"]),
+    ("scrolling", &["iframe"]),
+    ("shape", &["a"]),
+    ("standby", &["object"]),
+    ("summary", &["table"]),
+    ("text", &["body"]),
+    ("type", &["ul"]),
+    ("valign", &[]),
+    ("version", &["html"]),
+    ("vlink", &["body"]),
+    ("vspace", &[]),
+    ("width", &["col", "hr", "pre", "table", "td", "th"]),
+];
+
+fn is_obsolete_attribute(element : &str, attribute : &str) -> bool {
+    OBSOLETE_ATTRIBUTES.iter().any(|(name, elements)| *name == attribute && (elements.is_empty() || elements.contains(&element)))
+}
+
+// https://html.spec.whatwg.org/multipage/dom.html#flow-content
+// Elements that are flow content but not phrasing content, so they can't be in an element that only allows phrasing content.
+fn is_flow_only(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    kind.is_heading() || matches!(kind,
+        Address | Article | Aside | Blockquote | Details | Dialog | Div | Dl | Fieldset | Figcaption | Figure
+        | Footer | Form | Header | Hgroup | Hr | Main | Menu | Nav | Ol | P | Pre | Search | Section | Table | Ul)
+}
+
+// Elements whose content model is phrasing content.
+fn allows_phrasing_only(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    kind.is_heading() || matches!(kind,
+        Abbr | B | Bdi | Bdo | Button | Cite | Code | Data | Dfn | Em | I | Kbd | Label | Legend | Mark | Meter | Output
+        | P | Pre | Progress | Q | S | Samp | Small | Span | Strong | Sub | Sup | Time | U | Var)
+}
+
+// The only children some elements may have, besides inter-element whitespace. Script supporting elements
+// are allowed everywhere.
+fn allowed_children(parent : &ElementKind) -> Option<&'static [ElementKind]> {
+    use ElementKind::*;
+    match parent {
+        Ul | Ol | Menu => Some(&[Li]),
+        Dl => Some(&[Dt, Dd, Div]),
+        Table => Some(&[Caption, Colgroup, Thead, Tbody, Tfoot, Tr]),
+        Thead | Tbody | Tfoot => Some(&[Tr]),
+        Tr => Some(&[Td, Th]),
+        Colgroup => Some(&[Col]),
+        Select => Some(&[Option, Optgroup, Hr]),
+        Optgroup => Some(&[Option]),
+        Head => Some(&[Base, Link, Meta, Noscript, Style, Title]),
+        _ => None,
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/dom.html#interactive-content
+fn is_interactive(element : &ElementRef<'_>) -> bool {
+    use ElementKind::*;
+    match element.kind {
+        A => element.attribute("href").is_some(),
+        Audio | Video => element.attribute("controls").is_some(),
+        Img => element.attribute("usemap").is_some(),
+        Input => !element.attribute("type").is_some_and(|kind| kind.eq_ignore_ascii_case("hidden")),
+        Button | Details | Embed | Iframe | Label | Select | Textarea => true,
+        _ => false,
+    }
+}
+
+fn is_inter_element_whitespace(text : &str) -> bool {
+    text.chars().all(|c| matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0C'))
+}
+
+/// The conformance problems of a parsed document, without its parse errors. Parse the document with
+/// [`Document::parse_with_positions`] for the diagnostics to have positions.
+pub fn check_document(document : &Document) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut ids : HashMap<&str, Option<SourcePosition>> = HashMap::new();
+    // The links and buttons the walk is in, and the svg or math element it is skipping.
+    let mut interactive = Vec::new();
+    let mut skip = None;
+    let mut has_title = false;
+    let tree = document.tree();
+    for edge in tree.traverse(tree.root()) {
+        if let Some(foreign) = skip {
+            if edge == Edge::Close(foreign) {
+                skip = None;
+            }
+            continue;
+        }
+        let id = match edge {
+            Edge::Open(id) => id,
+            Edge::Close(id) => {
+                if interactive.last() == Some(&id) {
+                    interactive.pop();
+                }
+                continue;
+            },
+        };
+        let Some(node) = document.get(id) else {
+            continue;
+        };
+        let parent = node.parent_element();
+        let element = match node.node() {
+            Node::Text(text) => {
+                let contained = parent.filter(|parent| allowed_children(&parent.kind).is_some());
+                if let Some(parent) = contained.filter(|_| !is_inter_element_whitespace(text)) {
+                    diagnostics.push(Diagnostic::new(Severity::Error, "disallowed-child",
+                        format!("text isn't allowed in <{}>", parent.name()), node.source_position()));
+                }
+                continue;
+            },
+            _ => match node.as_element() {
+                Some(element) => element,
+                None => continue,
+            },
+        };
+        let position = element.source_position();
+        let name = element.name();
+        if matches!(element.kind, ElementKind::Svg | ElementKind::Math) {
+            skip = Some(id);
+        }
+        has_title |= element.kind == ElementKind::Title;
+
+        if OBSOLETE_ELEMENTS.contains(&name) {
+            diagnostics.push(Diagnostic::new(Severity::Error, "obsolete-element", format!("<{}> is obsolete", name), position));
+        }
+        for attribute in &element.attributes {
+            if is_obsolete_attribute(name, &attribute.name) {
+                diagnostics.push(Diagnostic::new(Severity::Warning, "obsolete-attribute",
+                    format!("the {} attribute on <{}> is obsolete, use CSS instead", attribute.name, name), position));
+            }
+        }
+
+        if let Some(parent) = parent {
+            let allowed = match allowed_children(&parent.kind) {
+                Some(allowed) => allowed.contains(&element.kind) || matches!(element.kind, ElementKind::Script | ElementKind::Template),
+                None => !(allows_phrasing_only(&parent.kind) && is_flow_only(&element.kind)),
+            };
+            if !allowed {
+                diagnostics.push(Diagnostic::new(Severity::Error, "disallowed-child",
+                    format!("<{}> isn't allowed as a child of <{}>", name, parent.name()), position));
+            }
+        }
+        if is_interactive(&element) {
+            if let Some(outer) = interactive.last().and_then(|outer| document.get(*outer)).and_then(|outer| outer.as_element()) {
+                diagnostics.push(Diagnostic::new(Severity::Error, "interactive-content",
+                    format!("<{}> isn't allowed inside of <{}>", name, outer.name()), position));
+            }
+        }
+        if matches!(element.kind, ElementKind::A | ElementKind::Button) {
+            interactive.push(id);
+        }
+
+        let needs_alt = match element.kind {
+            ElementKind::Img => true,
+            ElementKind::Area => element.attribute("href").is_some(),
+            ElementKind::Input => element.attribute("type").is_some_and(|kind| kind.eq_ignore_ascii_case("image")),
+            _ => false,
+        };
+        if needs_alt && element.attribute("alt").is_none() {
+            diagnostics.push(Diagnostic::new(Severity::Error, "missing-alt", format!("<{}> without an alt attribute", name), position));
+        }
+
+        // https://html.spec.whatwg.org/multipage/dom.html#the-id-attribute
+        if let Some(value) = element.element().attribute("id") {
+            if value.is_empty() || value.contains(|c : char| c.is_ascii_whitespace()) {
+                diagnostics.push(Diagnostic::new(Severity::Error, "invalid-id",
+                    format!("the id {:?} is empty or contains whitespace", value), position));
+            } else if let Some(first) = ids.get(value) {
+                let message = match first {
+                    Some(first) => format!("the id {:?} is already used at {}", value, first),
+                    None => format!("the id {:?} is already used", value),
+                };
+                diagnostics.push(Diagnostic::new(Severity::Error, "duplicate-id", message, position));
+            } else {
+                ids.insert(value, position);
+            }
+        }
+    }
+
+    if let Some(html) = document.document_element() {
+        if !has_title {
+            // https://html.spec.whatwg.org/multipage/semantics.html#the-head-element
+            let head = document.head().and_then(|head| head.source_position());
+            diagnostics.push(Diagnostic::new(Severity::Error, "missing-title", "the document has no <title>".to_string(), head));
+        }
+        if html.attribute("lang").is_none() {
+            diagnostics.push(Diagnostic::new(Severity::Warning, "missing-lang",
+                "the <html> element has no lang attribute".to_string(), html.source_position()));
+        }
+    }
+    diagnostics
+}
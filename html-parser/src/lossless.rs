@@ -0,0 +1,182 @@
+//! Tokens that know exactly which part of the input they come from.
+//!
+//! The tokenizer normalizes what it reads : tag and attribute names are lower cased, character references
+//! are decoded and the quotes and whitespace inside of tags are gone. A [`LosslessTokenizer`] returns every
+//! token along with the text it was read from and where its tag name and attributes are, and the text of
+//! all the tokens put together is the input, byte for byte. This is what tools that highlight HTML or
+//! rewrite only parts of it need, see [`crate::highlight`].
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use crate::{preproccesor::PreProccessor, tokenizer::{Token, Tokenizer}, HtmlParser, ParseState};
+
+/// A token along with the text it comes from. Offsets are in bytes from the start of the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceToken<'a> {
+    /// `None` for input that the tokenizer drops without producing a token, like `</>` or a tag that is cut
+    /// off by the end of the input.
+    pub token : Option<Token>,
+    /// Exactly what the token was read from, e.g. `&amp;` for a `&` character.
+    pub text : &'a str,
+    pub span : Range<usize>,
+    /// The tag name of a start or end tag as it is written, without `<` or `</`.
+    pub name : Option<Range<usize>>,
+    /// Every attribute written in a tag, in order. Unlike the attributes of the token, this includes the
+    /// duplicates that the tokenizer drops.
+    pub attributes : Vec<AttributeSpan>,
+}
+
+/// Where an attribute of a tag is in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeSpan {
+    pub name : Range<usize>,
+    /// The value without its quotes. Empty for `name=` and `None` when there is no `=` at all.
+    pub value : Option<Range<usize>>,
+    /// `'` or `"` if the value is quoted.
+    pub quote : Option<char>,
+}
+
+/// Tokenizes a document without losing anything of the input, see the [module documentation](self).
+///
+/// The tree builder runs alongside the tokenizer, the way it does when parsing, so that the contents of
+/// elements like `<script>`, `<style>` or `<textarea>` are tokenized as text. If the tree builder fails, for
+/// example because of the limits it was given, the rest of the input is tokenized without it.
+pub struct LosslessTokenizer<'a> {
+    source : &'a str,
+    tokenizer : Tokenizer,
+    state : Option<ParseState>,
+    // The end of the last token returned, everything before it has been accounted for.
+    end : usize,
+    queue : VecDeque<SourceToken<'a>>,
+}
+
+impl<'a> LosslessTokenizer<'a> {
+    pub fn new(source : &'a str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_state(source, ParseState::new())
+    }
+
+    /// Use `state` to follow the tree builder, e.g. with limits set.
+    pub fn with_state(source : &'a str, state : ParseState) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self { source, tokenizer : Tokenizer::new(PreProccessor::new(source)?), state : Some(state), end : 0, queue : VecDeque::new() })
+    }
+
+    // Input that the tokenizer skipped over, from the end of the last token up to `offset`.
+    fn skipped(&mut self, offset : usize) {
+        if offset > self.end {
+            self.push(None, self.end..offset);
+        }
+    }
+
+    fn push(&mut self, token : Option<Token>, span : Range<usize>) {
+        self.end = span.end;
+        let text = &self.source[span.clone()];
+        let (name, attributes) = match token {
+            Some(Token::StartTag(..) | Token::EndTag(..)) => tag_spans(text, span.start),
+            _ => (None, Vec::new()),
+        };
+        self.queue.push_back(SourceToken { token, text, span, name, attributes });
+    }
+
+    fn process(&mut self, token : &Token) {
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        if state.process_token(token.clone()).is_err() {
+            self.state = None;
+        } else if let Some(tokenizer_state) = state.take_tokenizer_state() {
+            self.tokenizer.switch_to(tokenizer_state);
+        }
+    }
+}
+
+impl<'a> Iterator for LosslessTokenizer<'a> {
+    type Item = SourceToken<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(token) = self.queue.pop_front() {
+            return Some(token);
+        }
+        let Some(wrapped_token) = self.tokenizer.next() else {
+            // What is left is a tag cut off by the end of the input, which the tokenizer drops.
+            self.skipped(self.source.len());
+            return self.queue.pop_front();
+        };
+        let mut tokens = Vec::new();
+        tokens.extend(HtmlParser::recover_token(wrapped_token).ok());
+        while self.tokenizer.has_pending() {
+            tokens.extend(self.tokenizer.next().and_then(|token| HtmlParser::recover_token(token).ok()));
+        }
+        let span = self.tokenizer.token_span();
+        let span = span.start.max(self.end)..span.end.max(self.end);
+        self.skipped(span.start);
+        tokens.iter().for_each(|token| self.process(token));
+        // Characters that come out of the same text, like `</b` in a <title> that turns out not to be an end
+        // tag, get a character each. Otherwise the first token has the whole text.
+        let text = &self.source[span.clone()];
+        if tokens.len() > 1 && text.chars().count() == tokens.len() {
+            let mut start = span.start;
+            for (token, c) in tokens.into_iter().zip(text.chars()) {
+                self.push(Some(token), start..start + c.len_utf8());
+                start += c.len_utf8();
+            }
+        } else {
+            let mut tokens = tokens.into_iter();
+            let first = tokens.next();
+            self.push(first, span.clone());
+            for token in tokens {
+                self.push(Some(token), span.end..span.end);
+            }
+        }
+        self.queue.pop_front()
+    }
+}
+
+fn is_whitespace(byte : u8) -> bool {
+    matches!(byte, b'\t' | b'\n' | b'\x0C' | b' ')
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#tag-name-state
+// Reads the tag again with the same rules as the tokenizer, to find out where the name and the attributes
+// are. Everything the rules look for is ASCII, so this can go byte by byte.
+fn tag_spans(text : &str, offset : usize) -> (Option<Range<usize>>, Vec<AttributeSpan>) {
+    let bytes = text.as_bytes();
+    let until = |mut i : usize, stop : &dyn Fn(u8) -> bool| {
+        while i < bytes.len() && !stop(bytes[i]) {
+            i += 1;
+        }
+        i
+    };
+    let start = if text.starts_with("</") { 2 } else { 1 };
+    let mut i = until(start, &|byte| is_whitespace(byte) || byte == b'/' || byte == b'>');
+    let name = offset + start..offset + i;
+    let mut attributes = Vec::new();
+    loop {
+        i = until(i, &|byte| !is_whitespace(byte) && byte != b'/');
+        if i >= bytes.len() || bytes[i] == b'>' {
+            break;
+        }
+        // The first character of a name can be `=`.
+        let name_start = i;
+        i = until(i + 1, &|byte| is_whitespace(byte) || matches!(byte, b'/' | b'>' | b'='));
+        let mut attribute = AttributeSpan { name : offset + name_start..offset + i, value : None, quote : None };
+        let equals = until(i, &|byte| !is_whitespace(byte));
+        if bytes.get(equals) == Some(&b'=') {
+            let value_start = until(equals + 1, &|byte| !is_whitespace(byte));
+            match bytes.get(value_start) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    let value_end = until(value_start + 1, &|byte| byte == quote);
+                    attribute.value = Some(offset + value_start + 1..offset + value_end);
+                    attribute.quote = Some(char::from(quote));
+                    i = (value_end + 1).min(bytes.len());
+                },
+                _ => {
+                    i = until(value_start, &|byte| is_whitespace(byte) || byte == b'>');
+                    attribute.value = Some(offset + value_start..offset + i);
+                },
+            }
+        }
+        attributes.push(attribute);
+    }
+    (Some(name), attributes)
+}
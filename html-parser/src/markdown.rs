@@ -0,0 +1,400 @@
+//! Converting a document to [CommonMark](https://spec.commonmark.org/).
+//!
+//! Headings, paragraphs, emphasis, links, images, lists, block quotes, code and rules become their
+//! Markdown equivalents. Tables and strike through use the [GitHub Flavored Markdown](https://github.github.com/gfm/)
+//! extensions. Elements that Markdown can't express, like `<dl>`, `<details>`, `<sub>` or a table with
+//! merged cells, are kept as HTML written by the [`crate::serializer`], which CommonMark allows inline and
+//! as blocks.
+//!
+//! Whitespace in text is collapsed the way a browser would render it, except in `<pre>`, and text that
+//! looks like Markdown syntax is escaped.
+
+use crate::{document::{Document, ElementRef, NodeRef}, dom::{ElementKind, Node}};
+
+// Elements nested deeper than this are written as plain text, so that hostile input can't overflow the stack.
+const MAX_DEPTH : usize = 64;
+
+/// Convert the body of a document.
+pub fn to_markdown(document : &Document) -> String {
+    finish(blocks(std::iter::once(document.root()), 0))
+}
+
+/// Convert a single element and everything in it.
+pub fn element_to_markdown(element : ElementRef<'_>) -> String {
+    finish(blocks(std::iter::once(element.as_node()), 0))
+}
+
+fn finish(blocks : Vec<String>) -> String {
+    match blocks.is_empty() {
+        true => String::new(),
+        false => blocks.join("\n\n") + "\n",
+    }
+}
+
+// Elements that aren't rendered, and so have no Markdown.
+fn is_skipped(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    matches!(kind, Head | Title | Base | Link | Meta | Script | Style | Template | Noscript | Noembed | Noframes)
+}
+
+// Elements that only group their content, which is converted as if they weren't there.
+fn is_container(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    matches!(kind,
+        Html | Body | Div | Section | Article | Main | Header | Footer | Nav | Aside | Address | Center | Form
+        | Fieldset | Figure | Figcaption | Hgroup | Search | Li | Caption)
+}
+
+fn is_block(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    is_container(kind) || kind.is_heading() || matches!(kind,
+        P | Pre | Listing | Plaintext | Xmp | Ul | Ol | Menu | Dir | Blockquote | Hr | Table | Thead | Tbody
+        | Tfoot | Tr | Td | Th | Dl | Dt | Dd | Details | Summary | Dialog | Frameset | Legend | Optgroup | Option
+        | Select | Textarea | Iframe | Video | Audio | Object | Canvas)
+}
+
+fn heading_level(kind : &ElementKind) -> usize {
+    match kind {
+        ElementKind::H1 => 1,
+        ElementKind::H2 => 2,
+        ElementKind::H3 => 3,
+        ElementKind::H4 => 4,
+        ElementKind::H5 => 5,
+        _ => 6,
+    }
+}
+
+// The Markdown for a run of nodes, as a list of blocks. Text and inline elements between blocks become
+// paragraphs.
+fn blocks<'a>(nodes : impl Iterator<Item = NodeRef<'a>>, depth : usize) -> Vec<String> {
+    let mut output = Vec::new();
+    let mut paragraph = String::new();
+    for node in nodes {
+        match node.node() {
+            Node::Document => output.extend(blocks(node.children(), depth)),
+            Node::Text(text) => paragraph.push_str(&escape(&collapse(text))),
+            Node::Element(element) if is_skipped(&element.kind) => (),
+            Node::Element(element) if is_block(&element.kind) => {
+                push_paragraph(&mut output, &paragraph);
+                paragraph.clear();
+                if let Some(element) = node.as_element() {
+                    output.extend(block(element, depth + 1));
+                }
+            },
+            Node::Element(_) => {
+                if let Some(element) = node.as_element() {
+                    paragraph.push_str(&inline(element, depth + 1));
+                }
+            },
+            Node::Doctype { .. } | Node::Comment(_) => (),
+        }
+    }
+    push_paragraph(&mut output, &paragraph);
+    output
+}
+
+fn push_paragraph(blocks : &mut Vec<String>, paragraph : &str) {
+    let paragraph = tidy(paragraph);
+    if !paragraph.is_empty() {
+        blocks.push(paragraph);
+    }
+}
+
+fn block(element : ElementRef<'_>, depth : usize) -> Vec<String> {
+    if depth > MAX_DEPTH {
+        return vec![tidy(&escape(&collapse(&element.text_content())))];
+    }
+    let kind = &element.kind;
+    match kind {
+        kind if kind.is_heading() => {
+            // A heading is a single line, breaks become spaces.
+            let text = tidy(&inline_children(element, depth)).replace("\\\n", " ");
+            match text.is_empty() {
+                true => Vec::new(),
+                false => vec![format!("{} {}", "#".repeat(heading_level(kind)), text)],
+            }
+        },
+        ElementKind::P => {
+            let mut output = Vec::new();
+            push_paragraph(&mut output, &inline_children(element, depth));
+            output
+        },
+        ElementKind::Pre | ElementKind::Listing | ElementKind::Plaintext | ElementKind::Xmp => vec![code_block(element)],
+        ElementKind::Ul | ElementKind::Ol | ElementKind::Menu | ElementKind::Dir => vec![list(element, depth)],
+        ElementKind::Blockquote => {
+            let content = blocks(element.children(), depth).join("\n\n");
+            vec![prefix_lines(&content, "> ", ">")]
+        },
+        ElementKind::Hr => vec![String::from("---")],
+        ElementKind::Table => vec![table(element, depth).unwrap_or_else(|| element.outer_html())],
+        kind if is_container(kind) => blocks(element.children(), depth),
+        _ => vec![element.outer_html()],
+    }
+}
+
+// https://spec.commonmark.org/0.31.2/#fenced-code-blocks
+fn code_block(element : ElementRef<'_>) -> String {
+    let language = element.child_elements()
+        .find(|child| child.kind == ElementKind::Code)
+        .and_then(|code| code.element().classes().into_iter().find_map(|class| class.strip_prefix("language-").or_else(|| class.strip_prefix("lang-"))))
+        .unwrap_or("")
+        .to_string();
+    let code = element.text_content();
+    let code = code.strip_suffix('\n').unwrap_or(&code);
+    // The fence has to be longer than any run of backticks in the code.
+    let fence = "`".repeat(longest_run(code, '`').max(2) + 1);
+    format!("{fence}{language}\n{code}\n{fence}")
+}
+
+// https://spec.commonmark.org/0.31.2/#lists
+fn list(element : ElementRef<'_>, depth : usize) -> String {
+    let ordered = matches!(element.kind, ElementKind::Ol);
+    let start : usize = element.attribute("start").and_then(|start| start.trim().parse().ok()).unwrap_or(1);
+    let mut loose = false;
+    let mut items = Vec::new();
+    for (index, item) in element.child_elements().filter(|child| !is_skipped(&child.kind)).enumerate() {
+        let marker = match ordered {
+            true => format!("{}. ", start + index),
+            false => String::from("- "),
+        };
+        // Items with paragraphs in them make the list loose, with blank lines between the items.
+        let paragraphs = item.child_elements().any(|child| child.kind == ElementKind::P);
+        loose |= paragraphs;
+        let content = match item.kind {
+            ElementKind::Li => blocks(item.children(), depth),
+            _ => blocks(std::iter::once(item.as_node()), depth),
+        };
+        let content = content.join(if paragraphs { "\n\n" } else { "\n" });
+        let indent = " ".repeat(marker.len());
+        let mut lines = content.lines();
+        let mut text = marker.trim_end().to_string();
+        if let Some(first) = lines.next() {
+            text = format!("{}{}", marker, first);
+        }
+        for line in lines {
+            text.push('\n');
+            if !line.is_empty() {
+                text.push_str(&indent);
+                text.push_str(line);
+            }
+        }
+        items.push(text);
+    }
+    items.join(if loose { "\n\n" } else { "\n" })
+}
+
+// https://github.github.com/gfm/#tables-extension-
+// `None` if the table can't be written as Markdown, because it has merged cells or blocks in its cells.
+fn table(element : ElementRef<'_>, depth : usize) -> Option<String> {
+    let rows : Vec<ElementRef> = element.descendant_elements().filter(|row| row.kind == ElementKind::Tr).collect();
+    let mut cells : Vec<Vec<String>> = Vec::new();
+    for row in &rows {
+        let mut row_cells = Vec::new();
+        for cell in row.child_elements().filter(|cell| matches!(cell.kind, ElementKind::Td | ElementKind::Th)) {
+            let merged = ["colspan", "rowspan"].iter().any(|name| cell.attribute(name).is_some_and(|span| span.trim() != "1"));
+            let nested = cell.descendant_elements().any(|child| is_block(&child.kind) && child.kind != ElementKind::P);
+            if merged || nested {
+                return None;
+            }
+            let text = tidy(&inline_children(cell, depth)).replace("\\\n", "<br>").replace('|', "\\|");
+            row_cells.push(text.replace('\n', " "));
+        }
+        cells.push(row_cells);
+    }
+    let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return None;
+    }
+    let line = |row : &[String]| {
+        let mut line = String::from("|");
+        for column in 0..columns {
+            line.push(' ');
+            line.push_str(row.get(column).map(String::as_str).unwrap_or(""));
+            line.push_str(" |");
+        }
+        line
+    };
+    let mut lines = vec![line(&cells[0]), line(&vec![String::from("---"); columns])];
+    lines.extend(cells[1..].iter().map(|row| line(row)));
+    let caption = element.child_elements().find(|child| child.kind == ElementKind::Caption);
+    if let Some(caption) = caption {
+        let caption = tidy(&inline_children(caption, depth));
+        if !caption.is_empty() {
+            lines.insert(0, format!("{}\n", caption));
+        }
+    }
+    Some(lines.join("\n"))
+}
+
+fn inline_children(element : ElementRef<'_>, depth : usize) -> String {
+    let mut text = String::new();
+    for child in element.children() {
+        match child.node() {
+            Node::Text(data) => text.push_str(&escape(&collapse(data))),
+            Node::Element(_) => {
+                if let Some(child) = child.as_element() {
+                    text.push_str(&inline(child, depth + 1));
+                }
+            },
+            _ => (),
+        }
+    }
+    text
+}
+
+fn inline(element : ElementRef<'_>, depth : usize) -> String {
+    if depth > MAX_DEPTH {
+        return escape(&collapse(&element.text_content()));
+    }
+    match element.kind {
+        ref kind if is_skipped(kind) => String::new(),
+        ElementKind::B | ElementKind::Strong => wrap(&inline_children(element, depth), "**"),
+        ElementKind::I | ElementKind::Em | ElementKind::Cite | ElementKind::Dfn | ElementKind::Var => wrap(&inline_children(element, depth), "*"),
+        ElementKind::Del | ElementKind::S | ElementKind::Strike => wrap(&inline_children(element, depth), "~~"),
+        ElementKind::Code | ElementKind::Samp | ElementKind::Tt => code_span(&collapse(&element.text_content())),
+        ElementKind::Br => String::from("\\\n"),
+        ElementKind::A => {
+            let text = inline_children(element, depth);
+            match element.attribute("href") {
+                Some(href) => format!("[{}]({}{})", text.trim(), destination(href), title(element.attribute("title"))),
+                None => text,
+            }
+        },
+        ElementKind::Img => match element.attribute("src") {
+            Some(src) => format!("![{}]({}{})", escape(&collapse(element.attribute("alt").unwrap_or(""))).trim(), destination(src), title(element.attribute("title"))),
+            None => String::new(),
+        },
+        ElementKind::Sub | ElementKind::Sup | ElementKind::U | ElementKind::Mark | ElementKind::Ins | ElementKind::Kbd
+            | ElementKind::Abbr | ElementKind::Q | ElementKind::Ruby | ElementKind::Bdo | ElementKind::Input
+            | ElementKind::Button | ElementKind::Svg | ElementKind::Math => element.outer_html(),
+        // Blocks inside inline content, like a <div> in a link, only keep their text.
+        ref kind if is_block(kind) => format!(" {} ", inline_children(element, depth)),
+        _ => inline_children(element, depth),
+    }
+}
+
+// Emphasis can't start or end with whitespace, so it goes outside of the markers.
+fn wrap(text : &str, marker : &str) -> String {
+    let trimmed = text.trim_matches(' ');
+    if trimmed.is_empty() {
+        return text.to_string();
+    }
+    let before = if text.starts_with(' ') { " " } else { "" };
+    let after = if text.ends_with(' ') { " " } else { "" };
+    format!("{before}{marker}{trimmed}{marker}{after}")
+}
+
+// https://spec.commonmark.org/0.31.2/#code-spans
+fn code_span(code : &str) -> String {
+    if code.is_empty() {
+        return String::new();
+    }
+    let fence = "`".repeat(longest_run(code, '`') + 1);
+    let padding = if code.starts_with('`') || code.ends_with('`') { " " } else { "" };
+    format!("{fence}{padding}{code}{padding}{fence}")
+}
+
+fn longest_run(text : &str, c : char) -> usize {
+    text.split(|other| other != c).map(str::len).max().unwrap_or(0)
+}
+
+// https://spec.commonmark.org/0.31.2/#link-destination
+fn destination(url : &str) -> String {
+    match url.contains([' ', '(', ')', '<', '>']) || url.is_empty() {
+        true => format!("<{}>", url.replace('<', "%3C").replace('>', "%3E").replace('\n', "")),
+        false => url.to_string(),
+    }
+}
+
+fn title(title : Option<&str>) -> String {
+    match title {
+        Some(title) if !title.is_empty() => format!(" \"{}\"", title.replace('\\', "\\\\").replace('"', "\\\"")),
+        _ => String::new(),
+    }
+}
+
+// Whitespace becomes single spaces, the way HTML is rendered.
+fn collapse(text : &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        match c {
+            ' ' | '\t' | '\n' | '\r' | '\x0C' => {
+                if !std::mem::replace(&mut space, true) {
+                    collapsed.push(' ');
+                }
+            },
+            c => {
+                space = false;
+                collapsed.push(c);
+            },
+        }
+    }
+    collapsed
+}
+
+// https://spec.commonmark.org/0.31.2/#backslash-escapes
+// Characters that could start inline syntax anywhere in the text. What only matters at the start of a
+// line is escaped by `tidy`.
+fn escape(text : &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (index, c) in text.char_indices() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '~' => escaped.push('\\'),
+            // Only something that looks like an entity or character reference has to be escaped.
+            '&' if is_reference(&text[index + 1..]) => escaped.push('\\'),
+            _ => (),
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn is_reference(text : &str) -> bool {
+    let end = text.find(|c : char| !(c.is_ascii_alphanumeric() || c == '#')).unwrap_or(text.len());
+    end > 0 && text[end..].starts_with(';')
+}
+
+// Trims every line of a paragraph, drops the empty ones and escapes what would start a block.
+fn tidy(paragraph : &str) -> String {
+    let lines : Vec<String> = paragraph.split('\n')
+        .map(|line| {
+            let line = line.trim_matches(' ');
+            // No spaces before a hard break.
+            match (line.len() - line.trim_end_matches('\\').len()) % 2 {
+                1 => format!("{}\\", line[..line.len() - 1].trim_end()),
+                _ => line.to_string(),
+            }
+        })
+        .filter(|line| !line.is_empty() && line != "\\")
+        .map(|line| escape_line_start(&line))
+        .collect();
+    let mut text = lines.join("\n");
+    // A break at the end of a paragraph doesn't do anything. An even number of backslashes is escaped text.
+    let backslashes = text.len() - text.trim_end_matches('\\').len();
+    if backslashes % 2 == 1 {
+        text.pop();
+        text.truncate(text.trim_end().len());
+    }
+    text
+}
+
+// https://spec.commonmark.org/0.31.2/#blocks-and-inlines
+fn escape_line_start(line : &str) -> String {
+    let digits = line.len() - line.trim_start_matches(|c : char| c.is_ascii_digit()).len();
+    let rest = &line[digits..];
+    match line.chars().next() {
+        Some('#' | '-' | '+' | '=' | '|') => format!("\\{}", line),
+        Some('0'..='9') if digits <= 9 && (rest.starts_with(". ") || rest.starts_with(") ") || rest == "." || rest == ")") => {
+            format!("{}\\{}", &line[..digits], rest)
+        },
+        _ => line.to_string(),
+    }
+}
+
+fn prefix_lines(text : &str, prefix : &str, empty : &str) -> String {
+    text.lines().map(|line| match line.is_empty() {
+        true => empty.to_string(),
+        false => format!("{}{}", prefix, line),
+    }).collect::<Vec<_>>().join("\n")
+}
@@ -0,0 +1,320 @@
+//! The metadata of a document : its title, `<meta>` and `<link>` elements, JSON-LD and microdata.
+//!
+//! [`extract`] reads everything from the parsed DOM, so comments, `<script>` content and character
+//! references are handled the way a browser handles them. Parse the document with
+//! [`Document::parse_with_positions`] to get the line and column every item comes from.
+//!
+//! URLs are returned as they are written in the document, they aren't resolved against the base URL.
+//! Elements inside a `<template>` aren't part of the document and are skipped.
+
+use std::collections::HashSet;
+
+use dds_utils::tree::Edge;
+
+use crate::{document::{Document, ElementRef}, dom::ElementKind, tokenizer::SourcePosition, NodeId};
+
+// Microdata items nested deeper than this are left out, so that hostile input can't overflow the stack.
+const MAX_DEPTH : usize = 64;
+// Items that refer to each other through `itemref` can be expanded again along many different paths. Once
+// this many elements have been looked at for items that were expanded before, no more items are expanded,
+// which bounds the total work.
+const MAX_REPEATED_VISITS : usize = 100_000;
+
+/// A value along with where it comes from in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Located<T> {
+    pub value : T,
+    pub position : Option<SourcePosition>,
+}
+
+/// A `<meta>` element with a `content` attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Meta {
+    /// The `name` attribute, e.g. `description` or `twitter:card`.
+    pub name : Option<String>,
+    /// The `property` attribute of [RDFa](https://www.w3.org/TR/rdfa-lite/), which OpenGraph uses, e.g. `og:title`.
+    pub property : Option<String>,
+    pub http_equiv : Option<String>,
+    pub content : String,
+    pub position : Option<SourcePosition>,
+}
+
+/// A `<link>` element with a `rel` and an `href`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    /// The link types in lower case, e.g. `["alternate"]` or `["icon"]`.
+    pub rel : Vec<String>,
+    pub href : String,
+    /// The `type` attribute.
+    pub media_type : Option<String>,
+    pub hreflang : Option<String>,
+    pub media : Option<String>,
+    pub sizes : Option<String>,
+    pub title : Option<String>,
+    pub position : Option<SourcePosition>,
+}
+
+impl Link {
+    pub fn has_rel(&self, rel : &str) -> bool {
+        self.rel.iter().any(|link_type| link_type.eq_ignore_ascii_case(rel))
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/microdata.html#items
+/// A microdata item, an element with an `itemscope` attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    /// The URLs in the `itemtype` attribute.
+    pub types : Vec<String>,
+    /// The `itemid` attribute.
+    pub id : Option<String>,
+    pub properties : Vec<Property>,
+    pub position : Option<SourcePosition>,
+}
+
+impl Item {
+    /// The values of the properties called `name`, in document order.
+    pub fn property<'a>(&'a self, name : &'a str) -> impl Iterator<Item = &'a PropertyValue> {
+        self.properties.iter().filter(move |property| property.name == name).map(|property| &property.value)
+    }
+}
+
+/// A name value pair of an [`Item`]. An element with several names in its `itemprop` gives a property for
+/// each of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    pub name : String,
+    pub value : PropertyValue,
+    pub position : Option<SourcePosition>,
+}
+
+// https://html.spec.whatwg.org/multipage/microdata.html#values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropertyValue {
+    Text(String),
+    /// The value of an element like `<a>` or `<img>` whose property is a URL.
+    Url(String),
+    Item(Item),
+}
+
+/// Everything [`extract`] found, in document order.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Metadata {
+    /// The text of the first `<title>`, with whitespace collapsed.
+    pub title : Option<Located<String>>,
+    /// The `href` of the first `<base>` that has one.
+    pub base : Option<Located<String>>,
+    /// The `lang` attribute of the `<html>` element.
+    pub lang : Option<Located<String>>,
+    /// The `dir` attribute of the `<html>` element.
+    pub dir : Option<Located<String>>,
+    pub metas : Vec<Meta>,
+    pub links : Vec<Link>,
+    /// The text of every `<script type="application/ld+json">`, which isn't parsed.
+    pub json_ld : Vec<Located<String>>,
+    /// The microdata items that aren't the property of another item.
+    pub items : Vec<Item>,
+}
+
+impl Metadata {
+    /// The content of the first `<meta>` whose `name` or `property` is `key`, compared without regard to case.
+    pub fn meta(&self, key : &str) -> Option<&str> {
+        self.metas.iter()
+            .find(|meta| [&meta.name, &meta.property].into_iter().flatten().any(|name| name.eq_ignore_ascii_case(key)))
+            .map(|meta| meta.content.as_str())
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.meta("description")
+    }
+
+    /// The [OpenGraph](https://ogp.me/) properties, like `og:title`, with their content.
+    pub fn open_graph(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.prefixed("og:")
+    }
+
+    /// The Twitter card properties, like `twitter:card`, with their content.
+    pub fn twitter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.prefixed("twitter:")
+    }
+
+    // Twitter uses `name` and OpenGraph uses `property`, but pages mix them up, so both are looked at.
+    fn prefixed<'a>(&'a self, prefix : &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.metas.iter().filter_map(move |meta| {
+            let key = [&meta.property, &meta.name].into_iter().flatten()
+                .find(|key| key.get(..prefix.len()).is_some_and(|start| start.eq_ignore_ascii_case(prefix)))?;
+            Some((key.as_str(), meta.content.as_str()))
+        })
+    }
+
+    /// The links that have `rel` among their link types.
+    pub fn links_with_rel<'a>(&'a self, rel : &'a str) -> impl Iterator<Item = &'a Link> {
+        self.links.iter().filter(move |link| link.has_rel(rel))
+    }
+
+    /// The `href` of the first `<link rel="canonical">`.
+    pub fn canonical(&self) -> Option<&str> {
+        self.links_with_rel("canonical").next().map(|link| link.href.as_str())
+    }
+}
+
+fn located(value : &str, element : &ElementRef<'_>) -> Located<String> {
+    Located { value : value.to_string(), position : element.source_position() }
+}
+
+fn attribute(element : &ElementRef<'_>, name : &str) -> Option<String> {
+    element.attribute(name).map(String::from)
+}
+
+/// Collect the metadata of `document`.
+pub fn extract(document : &Document) -> Metadata {
+    let mut metadata = Metadata::default();
+    if let Some(html) = document.document_element() {
+        metadata.lang = html.attribute("lang").map(|lang| located(lang, &html));
+        metadata.dir = html.attribute("dir").map(|dir| located(dir, &html));
+    }
+    let tree = document.tree();
+    let mut skip = None;
+    for edge in tree.traverse(tree.root()) {
+        if let Some(template) = skip {
+            if edge == Edge::Close(template) {
+                skip = None;
+            }
+            continue;
+        }
+        let Edge::Open(id) = edge else {
+            continue;
+        };
+        let Some(element) = document.get(id).and_then(|node| node.as_element()) else {
+            continue;
+        };
+        match element.kind {
+            ElementKind::Template => skip = Some(id),
+            ElementKind::Title if metadata.title.is_none() => {
+                let title = element.text_content().split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+                metadata.title = Some(located(&title, &element));
+            },
+            ElementKind::Base if metadata.base.is_none() => {
+                metadata.base = element.attribute("href").map(|href| located(href, &element));
+            },
+            ElementKind::Meta => if let Some(content) = element.attribute("content") {
+                metadata.metas.push(Meta {
+                    name : attribute(&element, "name"),
+                    property : attribute(&element, "property"),
+                    http_equiv : attribute(&element, "http-equiv"),
+                    content : content.to_string(),
+                    position : element.source_position(),
+                });
+            },
+            ElementKind::Link => if let (Some(rel), Some(href)) = (element.attribute("rel"), element.attribute("href")) {
+                metadata.links.push(Link {
+                    rel : rel.split_ascii_whitespace().map(|link_type| link_type.to_ascii_lowercase()).collect(),
+                    href : href.to_string(),
+                    media_type : attribute(&element, "type"),
+                    hreflang : attribute(&element, "hreflang"),
+                    media : attribute(&element, "media"),
+                    sizes : attribute(&element, "sizes"),
+                    title : attribute(&element, "title"),
+                    position : element.source_position(),
+                });
+            },
+            ElementKind::Script if element.attribute("type").is_some_and(is_json_ld) => {
+                metadata.json_ld.push(located(&element.text_content(), &element));
+            },
+            _ => (),
+        }
+        // https://html.spec.whatwg.org/multipage/microdata.html#top-level-microdata-items
+        if element.attribute("itemscope").is_some() && element.attribute("itemprop").is_none() {
+            metadata.items.push(item(element, &mut Expansion::default()));
+        }
+    }
+    metadata
+}
+
+fn is_json_ld(media_type : &str) -> bool {
+    media_type.trim_matches(|c : char| c.is_ascii_whitespace()).eq_ignore_ascii_case("application/ld+json")
+}
+
+#[derive(Default)]
+struct Expansion {
+    // The items that are being expanded, from the top-level item down.
+    items : Vec<NodeId>,
+    expanded : HashSet<NodeId>,
+    // The elements looked at for items that had already been expanded.
+    repeated_visits : usize,
+}
+
+fn item(element : ElementRef<'_>, expansion : &mut Expansion) -> Item {
+    expansion.items.push(element.node_id());
+    let (item_properties, visits) = item_properties(element);
+    if !expansion.expanded.insert(element.node_id()) {
+        expansion.repeated_visits += visits;
+    }
+    let mut properties = Vec::new();
+    for property in item_properties {
+        let value = match property.attribute("itemscope") {
+            // https://html.spec.whatwg.org/multipage/microdata.html#associating-names-with-items
+            // An item that is already being expanded is an error, it would be expanded forever.
+            Some(_) if expansion.items.len() >= MAX_DEPTH || expansion.repeated_visits >= MAX_REPEATED_VISITS
+                || expansion.items.contains(&property.node_id()) => continue,
+            Some(_) => PropertyValue::Item(item(property, expansion)),
+            None => property_value(&property),
+        };
+        for name in property.attribute("itemprop").unwrap_or("").split_ascii_whitespace() {
+            properties.push(Property { name : name.to_string(), value : value.clone(), position : property.source_position() });
+        }
+    }
+    expansion.items.pop();
+    Item {
+        types : element.attribute("itemtype").unwrap_or("").split_ascii_whitespace().map(String::from).collect(),
+        id : attribute(&element, "itemid"),
+        properties,
+        position : element.source_position(),
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/microdata.html#associating-names-with-items
+// The elements with an `itemprop` that belong to the item : its descendants and the elements its `itemref`
+// points to, along with their descendants, but not what is inside of another item.
+// Also returns the number of elements looked at.
+fn item_properties(root : ElementRef<'_>) -> (Vec<ElementRef<'_>>, usize) {
+    let document = root.as_node().document();
+    let mut pending : Vec<ElementRef<'_>> = root.attribute("itemref").unwrap_or("").split_ascii_whitespace()
+        .filter_map(|id| document.get_element_by_id(id))
+        .rev()
+        .collect();
+    pending.extend(root.child_elements().rev());
+    let mut visited = HashSet::new();
+    let mut properties = Vec::new();
+    while let Some(element) = pending.pop() {
+        // An itemref can point to an ancestor of the item or to an element that was already found.
+        if element == root || !visited.insert(element.node_id()) {
+            continue;
+        }
+        if element.attribute("itemprop").is_some() {
+            properties.push(element);
+        }
+        if element.attribute("itemscope").is_none() {
+            pending.extend(element.child_elements().rev());
+        }
+    }
+    (properties, visited.len())
+}
+
+// https://html.spec.whatwg.org/multipage/microdata.html#values
+fn property_value(element : &ElementRef<'_>) -> PropertyValue {
+    use ElementKind::*;
+    let url = |name| PropertyValue::Url(element.attribute(name).unwrap_or("").to_string());
+    match element.kind {
+        Meta => PropertyValue::Text(element.attribute("content").unwrap_or("").to_string()),
+        Audio | Embed | Iframe | Img | Source | Track | Video => url("src"),
+        A | Area | Link => url("href"),
+        Object => url("data"),
+        Data | Meter => PropertyValue::Text(element.attribute("value").unwrap_or("").to_string()),
+        _ => match element.attribute("datetime").filter(|_| element.kind == Time) {
+            Some(datetime) => PropertyValue::Text(datetime.to_string()),
+            None => PropertyValue::Text(element.text_content()),
+        },
+    }
+}
+
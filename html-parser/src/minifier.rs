@@ -0,0 +1,384 @@
+//! Making HTML smaller without changing what it means.
+//!
+//! [`minify`] rewrites the tokens of a document. A tree builder runs alongside the tokenizer, the way it
+//! does when parsing, so that the contents of `<script>`, `<style>` or `<textarea>` are tokenized
+//! correctly, and so that the minifier knows which element an end tag closes and what its parent is. This is
+//! what the [rules for optional tags](https://html.spec.whatwg.org/multipage/syntax.html#optional-tags) are
+//! written in terms of.
+//!
+//! The output parses to the same tree as the input, except for what [`MinifyOptions`] allows to change :
+//! whitespace that a browser wouldn't render and comments.
+
+use crate::{dom::ElementKind, preproccesor::PreProccessor, tokenizer::{Attribute, Token, Tokenizer}, HtmlParser, ParseState};
+
+/// What [`minify`] is allowed to do. Everything is enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MinifyOptions {
+    /// Collapse runs of whitespace into a single space and remove whitespace next to block elements, except in
+    /// `<pre>`, `<textarea>` and elements whose content is raw text. This changes the text in the tree, but
+    /// not how it is rendered.
+    pub collapse_whitespace : bool,
+    /// Remove comments, except for conditional comments like `<!--[if IE]>...<![endif]-->`.
+    pub remove_comments : bool,
+    /// Leave out the start and end tags that the parser implies, like `</p>`, `</li>` or `<tbody>`.
+    pub remove_optional_tags : bool,
+    /// Leave out the quotes around attribute values that don't need them.
+    pub remove_attribute_quotes : bool,
+    /// Write boolean attributes like `disabled="disabled"` as `disabled`.
+    pub collapse_boolean_attributes : bool,
+}
+
+impl Default for MinifyOptions {
+    fn default() -> Self {
+        Self {
+            collapse_whitespace : true,
+            remove_comments : true,
+            remove_optional_tags : true,
+            remove_attribute_quotes : true,
+            collapse_boolean_attributes : true,
+        }
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/indices.html#attributes-3
+const BOOLEAN_ATTRIBUTES : &[&str] = &[
+    "allowfullscreen", "async", "autofocus", "autoplay", "checked", "controls", "default", "defer", "disabled",
+    "formnovalidate", "hidden", "inert", "ismap", "itemscope", "loop", "multiple", "muted", "nomodule", "novalidate",
+    "open", "playsinline", "readonly", "required", "reversed", "selected",
+];
+
+// A token after the tree builder has seen it, with what the minifier needs to know about its context.
+enum Item {
+    Doctype(Option<String>, Option<String>, Option<String>),
+    StartTag {
+        kind : ElementKind,
+        attributes : Vec<Attribute>,
+        // The current node before the start tag, if the element was inserted into it without closing anything.
+        parent : Option<ElementKind>,
+    },
+    EndTag {
+        kind : ElementKind,
+        // Whether the end tag closed the current node, as opposed to being ignored or closing other elements too.
+        closes_current : bool,
+        // The current node once the end tag has been processed.
+        parent : Option<ElementKind>,
+    },
+    Text {
+        text : String,
+        // Whitespace is kept in <pre> and raw text elements.
+        preformatted : bool,
+        // The content of raw text elements is written as it is, without escaping.
+        raw : bool,
+    },
+    Comment(String),
+}
+
+/// Minify a whole document.
+pub fn minify(html : &str, options : &MinifyOptions) -> Result<String, Box<dyn std::error::Error>> {
+    let mut items = items(html, options)?;
+    if options.collapse_whitespace {
+        collapse_whitespace(&mut items);
+    }
+    Ok(write(&items, options))
+}
+
+// Elements whose content is kept as it is when whitespace is collapsed.
+fn is_preformatted(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    matches!(kind, Pre | Textarea | Listing | Plaintext | Script | Style | Xmp | Iframe | Noembed | Noframes)
+}
+
+// Elements whose content the tokenizer reads as raw text, in which character references aren't decoded.
+fn is_raw_text(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    matches!(kind, Script | Style | Xmp | Iframe | Noembed | Noframes | Plaintext)
+}
+
+// https://html.spec.whatwg.org/multipage/dom.html#phrasing-content
+// Whitespace next to these elements is rendered, so it is only collapsed. Unknown elements are inline too.
+fn is_inline(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    matches!(kind,
+        A | Abbr | Audio | B | Bdi | Bdo | Big | Br | Button | Canvas | Cite | Code | Data | Del | Dfn | Em
+        | Embed | Font | I | Iframe | Img | Input | Ins | Kbd | Label | Map | Mark | Math | Meter | Nobr | Object
+        | Output | Picture | Progress | Q | Rp | Rt | Ruby | S | Samp | Select | Slot | Small | Span | Strike
+        | Strong | Sub | Sup | Svg | Textarea | Time | Tt | U | Var | Video | Wbr | Other(_))
+}
+
+// Inline elements that are rendered as a box of their own, so whitespace after them is kept.
+fn is_replaced(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    matches!(kind, Audio | Button | Canvas | Embed | Iframe | Img | Input | Math | Meter | Object | Progress | Select | Svg | Textarea | Video)
+}
+
+// Tokenize the input the way the parser would, by running the tree builder alongside the tokenizer.
+fn items(html : &str, options : &MinifyOptions) -> Result<Vec<Item>, Box<dyn std::error::Error>> {
+    let mut tokens = Tokenizer::new(PreProccessor::new(html)?);
+    let mut state = ParseState::new();
+    let mut items = Vec::new();
+    while let Some(wrapped_token) = tokens.next() {
+        let token = HtmlParser::recover_token(wrapped_token)?;
+        let current = state.open_elements.last().map(|element| element.kind.clone());
+        let depth = state.open_elements.len();
+        state.process_token(token.clone())?;
+        if let Some(tokenizer_state) = state.take_tokenizer_state() {
+            tokens.switch_to(tokenizer_state);
+        }
+        let parent = state.open_elements.last().map(|element| element.kind.clone());
+        let item = match token {
+            Token::DOCTYPE(name, public_id, system_id, _) => Item::Doctype(name, public_id, system_id),
+            Token::StartTag(name, _, attributes) => {
+                let parent = current.filter(|_| state.open_elements.len() == depth + 1);
+                Item::StartTag { kind : ElementKind::from_name(&name), attributes, parent }
+            },
+            Token::EndTag(name, _, _) => {
+                let kind = ElementKind::from_name(&name);
+                let closes_current = current.as_ref() == Some(&kind) && state.open_elements.len() + 1 == depth;
+                Item::EndTag { kind, closes_current, parent }
+            },
+            Token::Comment(text) if options.remove_comments && !is_conditional_comment(&text) => continue,
+            Token::Comment(text) => Item::Comment(text),
+            Token::Character(c) => {
+                let preformatted = state.open_elements.iter().any(|element| is_preformatted(&element.kind));
+                let raw = parent.as_ref().is_some_and(is_raw_text);
+                match items.last_mut() {
+                    Some(Item::Text { text, preformatted : p, raw : r }) if *p == preformatted && *r == raw => {
+                        text.push(c);
+                        continue;
+                    },
+                    _ => Item::Text { text : c.to_string(), preformatted, raw },
+                }
+            },
+            Token::EOF => continue,
+        };
+        items.push(item);
+    }
+    Ok(items)
+}
+
+// Downlevel-hidden conditional comments like `<!--[if IE]>...<![endif]-->`, and the comments around
+// downlevel-revealed ones like `<!--[if !IE]><!-->...<!--<![endif]-->`.
+fn is_conditional_comment(text : &str) -> bool {
+    let text = text.trim();
+    text.starts_with("[if") || text.starts_with("<![endif]") || text.ends_with("[endif]")
+}
+
+// Whether an item starts or ends a line box, so that whitespace next to it isn't rendered.
+fn is_block_boundary(item : &Item) -> bool {
+    match item {
+        Item::StartTag { kind, .. } | Item::EndTag { kind, .. } => !is_inline(kind) || *kind == ElementKind::Br,
+        Item::Doctype(..) => true,
+        Item::Text { .. } | Item::Comment(_) => false,
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/rendering.html#phrasing-content-3 (white-space: normal)
+fn collapse_whitespace(items : &mut Vec<Item>) {
+    // Whether the text rendered so far ends with a space, or is at the start of a line, so that a following
+    // space would be dropped. The last text item is remembered to take its trailing space away at the end of
+    // the line.
+    let mut after_space = true;
+    let mut last_text : Option<usize> = None;
+    for index in 0..items.len() {
+        if is_block_boundary(&items[index]) {
+            if let Some(Item::Text { text, .. }) = last_text.and_then(|last| items.get_mut(last)) {
+                if text.ends_with(' ') {
+                    text.pop();
+                }
+            }
+            after_space = true;
+            last_text = None;
+            continue;
+        }
+        match &mut items[index] {
+            Item::Text { preformatted : true, .. } => {
+                after_space = false;
+                last_text = None;
+            },
+            Item::Text { text, .. } => {
+                let mut collapsed = String::with_capacity(text.len());
+                for c in text.chars() {
+                    if matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0C') {
+                        if !after_space {
+                            collapsed.push(' ');
+                            after_space = true;
+                        }
+                    } else {
+                        collapsed.push(c);
+                        after_space = false;
+                    }
+                }
+                *text = collapsed;
+                if !text.is_empty() {
+                    last_text = Some(index);
+                }
+            },
+            Item::StartTag { kind, .. } | Item::EndTag { kind, .. } if is_replaced(kind) => {
+                after_space = false;
+                last_text = None;
+            },
+            _ => (),
+        }
+    }
+    if let Some(Item::Text { text, .. }) = last_text.and_then(|last| items.get_mut(last)) {
+        if text.ends_with(' ') {
+            text.pop();
+        }
+    }
+    items.retain(|item| !matches!(item, Item::Text { text, .. } if text.is_empty()));
+}
+
+// https://html.spec.whatwg.org/multipage/syntax.html#optional-tags
+fn start_tag_is_optional(kind : &ElementKind, parent : Option<&ElementKind>, next : Option<&Item>, omitted_end : Option<&ElementKind>) -> bool {
+    use ElementKind::*;
+    match kind {
+        Html => !matches!(next, Some(Item::Comment(_))),
+        Head => matches!(next, None | Some(Item::StartTag { .. }) | Some(Item::EndTag { kind : Head, .. })),
+        // Elements that would be put in the head if the body wasn't started explicitly.
+        Body => !matches!(next, Some(Item::Comment(_)) | Some(Item::StartTag { kind :
+            Base | Basefont | Bgsound | Link | Meta | Noframes | Noscript | Script | Style | Template | Title, .. }))
+            && !starts_with_whitespace(next),
+        // The parser only implies these in a table whose current node is the table itself, a <thead> that is
+        // still open would get the rows otherwise.
+        Colgroup => parent == Some(&Table) && matches!(next, Some(Item::StartTag { kind : Col, .. }))
+            && omitted_end != Some(&Colgroup),
+        Tbody => parent == Some(&Table) && matches!(next, Some(Item::StartTag { kind : Tr, .. }))
+            && !matches!(omitted_end, Some(Tbody | Thead | Tfoot)),
+        _ => false,
+    }
+}
+
+fn end_tag_is_optional(kind : &ElementKind, closes_current : bool, parent : Option<&ElementKind>, next : Option<&Item>) -> bool {
+    use ElementKind::*;
+    let followed_by = |kinds : &[ElementKind]| matches!(next, Some(Item::StartTag { kind, .. }) if kinds.contains(kind));
+    // The end of the parent element, either through its end tag or the end of the input.
+    let parent_ends = match next {
+        None => true,
+        Some(Item::EndTag { kind, .. }) => parent == Some(kind),
+        _ => false,
+    };
+    match kind {
+        Html | Body => !matches!(next, Some(Item::Comment(_))),
+        // The other end tags are only left out when they close the current node, otherwise the parser would
+        // have done something else with them.
+        _ if !closes_current => false,
+        Head | Colgroup | Caption => !matches!(next, Some(Item::Comment(_))) && !starts_with_whitespace(next),
+        Li => followed_by(&[Li]) || parent_ends,
+        Dt => followed_by(&[Dt, Dd]),
+        Dd => followed_by(&[Dd, Dt]) || parent_ends,
+        P => followed_by(&[
+                Address, Article, Aside, Blockquote, Details, Dialog, Div, Dl, Fieldset, Figcaption, Figure, Footer, Form,
+                H1, H2, H3, H4, H5, H6, Header, Hgroup, Hr, Main, Menu, Nav, Ol, P, Pre, Search, Section, Table, Ul,
+            ])
+            || parent_ends && !matches!(parent, Some(A | Audio | Del | Ins | Map | Noscript | Video | Other(_))),
+        Rt | Rp => followed_by(&[Rt, Rp]) || parent_ends,
+        Optgroup => followed_by(&[Optgroup, Hr]) || parent_ends,
+        Option => followed_by(&[Option, Optgroup, Hr]) || parent_ends,
+        Thead => followed_by(&[Tbody, Tfoot]),
+        Tbody => followed_by(&[Tbody, Tfoot]) || parent_ends,
+        Tfoot => parent_ends,
+        Tr => followed_by(&[Tr]) || parent_ends,
+        Td | Th => followed_by(&[Td, Th]) || parent_ends,
+        _ => false,
+    }
+}
+
+fn starts_with_whitespace(item : Option<&Item>) -> bool {
+    matches!(item, Some(Item::Text { text, .. }) if text.starts_with(['\t', '\n', '\x0C', '\r', ' ']))
+}
+
+fn write(items : &[Item], options : &MinifyOptions) -> String {
+    let mut output = String::new();
+    // The end tag that was just left out, the start tags of tbody and colgroup can't be left out after it.
+    let mut omitted_end = None;
+    for (index, item) in items.iter().enumerate() {
+        let next = items.get(index + 1);
+        match item {
+            Item::Doctype(name, public_id, system_id) => write_doctype(&mut output, name.as_deref(), public_id.as_deref(), system_id.as_deref()),
+            Item::StartTag { kind, attributes, parent } => {
+                if options.remove_optional_tags && attributes.is_empty() && start_tag_is_optional(kind, parent.as_ref(), next, omitted_end) {
+                    omitted_end = None;
+                    continue;
+                }
+                output.push('<');
+                output.push_str(kind.name());
+                for attribute in attributes {
+                    write_attribute(&mut output, attribute, options);
+                }
+                output.push('>');
+            },
+            Item::EndTag { kind, closes_current, parent } => {
+                if options.remove_optional_tags && end_tag_is_optional(kind, *closes_current, parent.as_ref(), next) {
+                    omitted_end = Some(kind);
+                    continue;
+                }
+                output.push_str("</");
+                output.push_str(kind.name());
+                output.push('>');
+            },
+            Item::Text { text, raw : true, .. } => output.push_str(text),
+            Item::Text { text, .. } => escape(&mut output, text, None),
+            Item::Comment(text) => {
+                output.push_str("<!--");
+                output.push_str(text);
+                output.push_str("-->");
+            },
+        }
+        omitted_end = None;
+    }
+    output
+}
+
+fn write_doctype(output : &mut String, name : Option<&str>, public_id : Option<&str>, system_id : Option<&str>) {
+    output.push_str("<!DOCTYPE");
+    if let Some(name) = name {
+        output.push(' ');
+        output.push_str(name);
+    }
+    match (public_id, system_id) {
+        (Some(public_id), Some(system_id)) => output.push_str(&format!(" PUBLIC \"{}\" \"{}\"", public_id, system_id)),
+        (Some(public_id), None) => output.push_str(&format!(" PUBLIC \"{}\"", public_id)),
+        (None, Some(system_id)) => output.push_str(&format!(" SYSTEM \"{}\"", system_id)),
+        (None, None) => (),
+    }
+    output.push('>');
+}
+
+// https://html.spec.whatwg.org/multipage/syntax.html#attributes-2
+fn write_attribute(output : &mut String, attribute : &Attribute, options : &MinifyOptions) {
+    output.push(' ');
+    output.push_str(&attribute.name);
+    let value = &attribute.value;
+    let is_boolean = BOOLEAN_ATTRIBUTES.contains(&attribute.name.as_str()) && value.eq_ignore_ascii_case(&attribute.name);
+    // An attribute without a value has the empty string as its value.
+    if value.is_empty() || options.collapse_boolean_attributes && is_boolean {
+        return;
+    }
+    output.push('=');
+    let unquoted = !value.contains(['\t', '\n', '\x0C', '\r', ' ', '"', '\'', '=', '<', '>', '`']);
+    if options.remove_attribute_quotes && unquoted {
+        escape(output, value, None);
+        return;
+    }
+    // Whichever quote needs less escaping.
+    let quote = if value.contains('"') && !value.contains('\'') { '\'' } else { '"' };
+    output.push(quote);
+    escape(output, value, Some(quote));
+    output.push(quote);
+}
+
+// Only escape what would otherwise be read as markup : ampersands that could start a character reference,
+// less-than signs that could start a tag and the quote around an attribute value.
+fn escape(output : &mut String, text : &str, quote : Option<char>) {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let next = chars.peek().copied();
+        match c {
+            '&' if next.is_none_or(|next| next.is_ascii_alphanumeric() || next == '#') => output.push_str("&amp;"),
+            '<' if quote.is_none() && next.is_none_or(|next| next.is_ascii_alphabetic() || matches!(next, '/' | '!' | '?')) => output.push_str("&lt;"),
+            '"' if quote == Some('"') => output.push_str("&quot;"),
+            '\'' if quote == Some('\'') => output.push_str("&#39;"),
+            c => output.push(c),
+        }
+    }
+}
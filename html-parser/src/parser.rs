@@ -1,25 +1,267 @@
-use std::{default, string::ParseError};
-use indextree::Arena;
 use log::*;
-use crate::{ParseState, tokenizer::{Token, TagKind}, states::InsertionMode, error::HtmlParseError, preproccesor::PreProccessor, tokenizer::Tokenizer, Element, ElementKind};
+use crate::{ParseState, OpenElement, tokenizer::{Token, Attribute}, states::{InsertionMode, TokenizationState}, error::{HtmlParseError, ParseErrorCode}, dom::{Element, ElementKind}, sink::TreeSink};
+
+// NOTE: The following parts of the tree construction stage are not implemented yet :
+// - The list of active formatting elements and the adoption agency algorithm. Formatting elements are treated like any other element.
+// - Foster parenting. Content that is misnested inside of a table is inserted into the table instead of in front of it.
+// - Templates, foreign content (SVG / MathML) and quirks mode. A <template> is inserted like any other element.
+
+// tab, LF, FF, Space, Carrige Return
+fn is_whitespace(c : char) -> bool {
+    matches!(c, '\t' | '\u{000A}' | '\u{000C}' | ' ' | '\r')
+}
+
+// Parse errors are not fatal, the token is either ignored or handled in a well defined way. They are only
+// kept when asked for, see ParseState::record_errors.
+fn parse_error<S : TreeSink>(state : &mut ParseState<S>, token : &Token) {
+    if cfg!(feature = "parser-log") {warn!("Parse error on token {:?}", token);}
+    if state.record_errors {
+        let token = match token {
+            Token::DOCTYPE(..) => "doctype".to_string(),
+            Token::Character(c) => format!("character {:?}", c),
+            Token::StartTag(name, ..) => format!("start tag <{}>", name),
+            Token::EndTag(name, ..) => format!("end tag </{}>", name),
+            Token::Comment(_) => "comment".to_string(),
+            Token::EOF => "end of file".to_string(),
+        };
+        let code = ParseErrorCode::UnexpectedToken { mode : state.mode, token };
+        state.errors.push(HtmlParseError::ParseError { code, position : state.position });
+    }
+}
+
+fn current_node_is<S : TreeSink>(state : &ParseState<S>, kind : ElementKind) -> bool {
+    state.open_elements.last().is_some_and(|element| element.kind == kind)
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#appropriate-place-for-inserting-a-node
+fn insertion_parent<S : TreeSink>(state : &ParseState<S>) -> S::Handle {
+    match state.open_elements.last() {
+        Some(element) => element.handle.clone(),
+        None => state.sink.document(),
+    }
+}
+
+// Same as insertion_parent, except that elements which would end up nested deeper than the maximum
+// depth are inserted next to the deepest allowed element instead. They take the place of that element
+// on the stack of open elements, see insert_element.
+fn element_insertion_parent<S : TreeSink>(state : &ParseState<S>) -> S::Handle {
+    let max_depth = state.limits.max_depth;
+    if state.open_elements.len() < max_depth {
+        return insertion_parent(state);
+    }
+    match max_depth.checked_sub(2) {
+        Some(index) => state.open_elements[index].handle.clone(),
+        None => state.sink.document(),
+    }
+}
+
+// Every node that is created goes through here, text nodes only once per run of text.
+fn count_node<S : TreeSink>(state : &mut ParseState<S>) {
+    state.node_count += 1;
+    state.in_text = false;
+}
+
+fn insert_element<S : TreeSink>(state : &mut ParseState<S>, element : Element) -> S::Handle {
+    count_node(state);
+    let parent = element_insertion_parent(state);
+    // An element inserted next to the deepest element replaces it on the stack, which then matches the
+    // tree and never gets deeper than the limit. Otherwise deep nesting makes every scope check slow.
+    // The replaced element can be a cell or a select, so the insertion mode has to follow what is left.
+    if state.open_elements.len() >= state.limits.max_depth {
+        pop(state);
+        reset_insertion_mode(state);
+    }
+    let kind = element.kind.clone();
+    let handle = state.sink.append_element(&parent, element);
+    state.open_elements.push(OpenElement { handle : handle.clone(), kind });
+    handle
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#insert-an-html-element
+fn insert_html_element<S : TreeSink>(state : &mut ParseState<S>, name : &str, attributes : &[Attribute]) -> S::Handle {
+    insert_element(state, Element::from_tag(name, attributes.to_vec()))
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-character
+fn insert_character<S : TreeSink>(state : &mut ParseState<S>, c : char) {
+    // Text can't be inserted into the document itself.
+    if let Some(element) = state.open_elements.last() {
+        let parent = element.handle.clone();
+        if !state.in_text {
+            count_node(state);
+            state.in_text = true;
+        }
+        let mut buf = [0; 4];
+        state.sink.append_text(&parent, c.encode_utf8(&mut buf));
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-comment
+fn insert_comment<S : TreeSink>(state : &mut ParseState<S>, comment : &str) {
+    count_node(state);
+    let parent = insertion_parent(state);
+    state.sink.append_comment(&parent, comment);
+}
+
+fn insert_comment_in_document<S : TreeSink>(state : &mut ParseState<S>, comment : &str) {
+    count_node(state);
+    let document = state.sink.document();
+    state.sink.append_comment(&document, comment);
+}
+
+fn pop<S : TreeSink>(state : &mut ParseState<S>) -> Option<ElementKind> {
+    let element = state.open_elements.pop()?;
+    state.in_text = false;
+    state.sink.pop(&element.handle, &element.kind);
+    Some(element.kind)
+}
+
+// Pop elements until an element matching `target` has been popped.
+fn pop_until<S : TreeSink>(state : &mut ParseState<S>, target : impl Fn(&ElementKind) -> bool) {
+    while let Some(kind) = pop(state) {
+        if target(&kind) {
+            break;
+        }
+    }
+}
+
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+    Select,
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-scope
+fn has_element_in_scope<S : TreeSink>(state : &ParseState<S>, target : impl Fn(&ElementKind) -> bool, scope : Scope) -> bool {
+    for element in state.open_elements.iter().rev() {
+        if target(&element.kind) {
+            return true;
+        }
+        let boundary = match scope {
+            Scope::Table => matches!(element.kind, ElementKind::Html | ElementKind::Table | ElementKind::Template),
+            Scope::Select => !matches!(element.kind, ElementKind::Optgroup | ElementKind::Option),
+            _ => {
+                matches!(element.kind, ElementKind::Applet | ElementKind::Caption | ElementKind::Html | ElementKind::Table | ElementKind::Td
+                    | ElementKind::Th | ElementKind::Marquee | ElementKind::Object | ElementKind::Template)
+                || (matches!(scope, Scope::ListItem) && matches!(element.kind, ElementKind::Ol | ElementKind::Ul))
+                || (matches!(scope, Scope::Button) && element.kind == ElementKind::Button)
+            }
+        };
+        if boundary {
+            return false;
+        }
+    }
+    false
+}
+
+fn has_in_scope<S : TreeSink>(state : &ParseState<S>, kind : ElementKind, scope : Scope) -> bool {
+    has_element_in_scope(state, |k| *k == kind, scope)
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#generate-implied-end-tags
+fn generate_implied_end_tags<S : TreeSink>(state : &mut ParseState<S>, except : Option<&ElementKind>) {
+    while let Some(element) = state.open_elements.last() {
+        let implied = matches!(element.kind, ElementKind::Dd | ElementKind::Dt | ElementKind::Li | ElementKind::Optgroup | ElementKind::Option
+            | ElementKind::P | ElementKind::Rb | ElementKind::Rp | ElementKind::Rt | ElementKind::Rtc);
+        if !implied || Some(&element.kind) == except {
+            break;
+        }
+        pop(state);
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#close-a-p-element
+fn close_p_element<S : TreeSink>(state : &mut ParseState<S>) {
+    generate_implied_end_tags(state, Some(&ElementKind::P));
+    pop_until(state, |kind| *kind == ElementKind::P);
+}
+
+fn close_p_element_in_button_scope<S : TreeSink>(state : &mut ParseState<S>) {
+    if has_in_scope(state, ElementKind::P, Scope::Button) {
+        close_p_element(state);
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#clear-the-stack-back-to-a-table-context
+fn clear_stack_back_to<S : TreeSink>(state : &mut ParseState<S>, context : &[ElementKind]) {
+    while let Some(element) = state.open_elements.last() {
+        if element.kind == ElementKind::Html || context.contains(&element.kind) {
+            break;
+        }
+        pop(state);
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#reset-the-insertion-mode-appropriately
+fn reset_insertion_mode<S : TreeSink>(state : &mut ParseState<S>) {
+    let mut mode = InsertionMode::InBody;
+    for (index, element) in state.open_elements.iter().enumerate().rev() {
+        let last = index == 0;
+        mode = match element.kind {
+            ElementKind::Select => {
+                let in_table = state.open_elements[..index].iter().rev()
+                    .take_while(|ancestor| ancestor.kind != ElementKind::Template)
+                    .any(|ancestor| ancestor.kind == ElementKind::Table);
+                if in_table && !last { InsertionMode::InSelectTable } else { InsertionMode::InSelect }
+            }
+            ElementKind::Td | ElementKind::Th if !last => InsertionMode::InCell,
+            ElementKind::Tr => InsertionMode::InRow,
+            ElementKind::Tbody | ElementKind::Thead | ElementKind::Tfoot => InsertionMode::InTableBody,
+            ElementKind::Caption => InsertionMode::InCaption,
+            ElementKind::Colgroup => InsertionMode::InColumnGroup,
+            ElementKind::Table => InsertionMode::InTable,
+            ElementKind::Head if !last => InsertionMode::InHead,
+            ElementKind::Body => InsertionMode::InBody,
+            ElementKind::Frameset => InsertionMode::InFrameset,
+            ElementKind::Html => if state.head_pointer.is_none() { InsertionMode::BeforeHead } else { InsertionMode::AfterHead },
+            _ if last => InsertionMode::InBody,
+            _ => continue,
+        };
+        break;
+    }
+    state.mode = mode;
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#generic-raw-text-element-parsing-algorithm
+// https://html.spec.whatwg.org/multipage/parsing.html#generic-rcdata-element-parsing-algorithm
+fn parse_generic_text_element<S : TreeSink>(state : &mut ParseState<S>, name : &str, attributes : &[Attribute], tokenizer_state : TokenizationState) {
+    insert_html_element(state, name, attributes);
+    state.tokenizer_state = Some(tokenizer_state);
+    state.original_mode = state.mode;
+    state.mode = InsertionMode::Text;
+}
+
+/// Whether processing a start tag with this name can make the tree builder switch the tokenizer
+/// to a different state. Has to be kept in sync with the places that set `tokenizer_state`.
+pub(crate) fn may_switch_tokenizer(name : &str) -> bool {
+    matches!(name, "title" | "textarea" | "style" | "xmp" | "iframe" | "noembed" | "noframes" | "script" | "plaintext")
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#stop-parsing
+fn stop_parsing<S : TreeSink>(state : &mut ParseState<S>) {
+    while pop(state).is_some() {}
+}
 
 //https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
-pub fn parse_initial(token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
+pub fn parse_initial<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
     match &token {
-        Token::Character(c) => {
-            match c {
-                '\t' | '\u{000A}' | '\u{000C}' | ' ' | '\r' => {
-                    //Do nothing
-                }, // tab, LF, FF, Space, Carrige Return
-                _ => {
-                    state.mode = InsertionMode::BeforeHtml;
-                    state.reconsume = true;
-                }
+        Token::Character(c) if is_whitespace(*c) => {
+            //Do nothing
+        },
+        Token::Comment(comment) => insert_comment_in_document(state, comment),
+        Token::DOCTYPE(name, public_id, system_id, _) => {
+            if name.as_deref() != Some("html") || public_id.is_some() || system_id.as_deref().is_some_and(|id| id != "about:legacy-compat") {
+                parse_error(state, &token);
             }
+            count_node(state);
+            state.sink.append_doctype(name.as_deref(), public_id.as_deref(), system_id.as_deref());
+            state.mode = InsertionMode::BeforeHtml;
         },
-        Token::Comment(comment) => todo!(),
-        Token::DOCTYPE(x,y,z,w) => todo!(),
         _ => {
+            // A document without a doctype is rendered in quirks mode.
+            parse_error(state, &token);
             state.mode = InsertionMode::BeforeHtml;
             state.reconsume = true;
         }
@@ -28,234 +270,1024 @@ pub fn parse_initial(token : Token, state : &mut ParseState) -> Result<(), HtmlP
 }
 
 //https://html.spec.whatwg.org/multipage/parsing.html#the-before-html-insertion-mode
-pub fn parse_before_html(token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
+pub fn parse_before_html<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
     match &token {
-        Token::DOCTYPE(x,y,z,w) => return Err(HtmlParseError::UnexpectedToken(token)), // Parse error
-        Token::Comment(comment) => todo!(),
-        Token::Character(c) => {
-            match c {
-                '\t' | '\u{000A}' | '\u{000C}' | ' ' | '\r' => {
-                    //Do nothing
-                }, // tab, LF, FF, Space, Carrige Return
-                _ => ()
-            }
+        Token::DOCTYPE(..) => parse_error(state, &token),
+        Token::Comment(comment) => insert_comment_in_document(state, comment),
+        Token::Character(c) if is_whitespace(*c) => {
+            //Do nothing
         },
-        token => {
-            let new_element = Element{
-                kind : ElementKind::Html,
-            };
-            state.open_elements.push(state.tree.new_node(new_element));
+        Token::StartTag(name, _, attributes) if name == "html" => {
+            insert_html_element(state, name, attributes);
             state.mode = InsertionMode::BeforeHead;
-            if let Token::StartTag(name, _, _) = token {
-                if name != "html" {
-                    state.reconsume = true;
-                }
-            }
-            if cfg!(feature = "parser-log") {trace!("PARSE_BEFORE_HTML {:?}", token);}
+        },
+        Token::EndTag(name, _, _) if !matches!(name.as_str(), "head" | "body" | "html" | "br") => parse_error(state, &token),
+        _ => {
+            insert_element(state, Element::new(ElementKind::Html));
+            state.mode = InsertionMode::BeforeHead;
+            state.reconsume = true;
         }
     }
+    if cfg!(feature = "parser-log") {trace!("PARSE_BEFORE_HTML {:?}", token);}
     Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#the-before-head-insertion-mode
-pub fn parse_before_head (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    #[inline]
-    fn default(token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-        let new_element = Element{
-            kind : ElementKind::Head,
-        };
-        state.open_elements.push(state.tree.new_node(new_element));
-        Ok(())
-    }
+pub fn parse_before_head<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
     match &token {
-        Token::Character(c) => {
-            match c {
-                '\t' | '\u{000A}' | '\u{000C}' | ' ' | '\r' => {
-                    //Do nothing
-                }, // tab, LF, FF, Space, Carrige Return
-                _ => ()
-            }
+        Token::Character(c) if is_whitespace(*c) => {
+            //Do nothing
         },
-        Token::Comment(comment) => todo!(),
-        Token::DOCTYPE(x,y,z,w) => return Err(HtmlParseError::UnexpectedToken(token)), // Parse error
-        Token::StartTag(name, is_self_closing, attributes) => {
-            if name == "html" {
-                trace!("Shouldn't happen {:?}", token);
-                return parse_in_body(token, state);
-            } else if name == "head" {
-                let new_element = Element{
-                    kind : ElementKind::Head,
-                };
-                let node = state.tree.new_node(new_element);
-                state.head_pointer = Some(node);
-                state.open_elements.push(node);
-                state.mode = InsertionMode::InHead;
-                if cfg!(feature = "parser-log") {trace!("PARSE_BEFORE_HEAD {:?}", token);}
-            }
-        },
-        Token::EndTag(name, is_self_closing, attributes) => {
-            match name.as_str() {
-                "head" | "body" | "html" | "br" => {
-                    trace!("PARSE_BEFORE_HTML {:?}", token);
-                    return default(token, state);
-                }
-                _ => {
-                    return Err(HtmlParseError::GenericParseError);
-                }
-            }
+        Token::Comment(comment) => insert_comment(state, comment),
+        Token::DOCTYPE(..) => parse_error(state, &token),
+        Token::StartTag(name, _, _) if name == "html" => return parse_in_body(token, state),
+        Token::StartTag(name, _, attributes) if name == "head" => {
+            let node = insert_html_element(state, name, attributes);
+            state.head_pointer = Some(node);
+            state.mode = InsertionMode::InHead;
         },
+        Token::EndTag(name, _, _) if !matches!(name.as_str(), "head" | "body" | "html" | "br") => parse_error(state, &token),
         _ => {
-            return default(token, state);
+            let node = insert_element(state, Element::new(ElementKind::Head));
+            state.head_pointer = Some(node);
+            state.mode = InsertionMode::InHead;
+            state.reconsume = true;
         },
     }
+    if cfg!(feature = "parser-log") {trace!("PARSE_BEFORE_HEAD {:?}", token);}
     Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inhead
-pub fn parse_in_head (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
+pub fn parse_in_head<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    #[inline]
+    fn anything_else<S : TreeSink>(state : &mut ParseState<S>) {
+        pop(state);
+        state.mode = InsertionMode::AfterHead;
+        state.reconsume = true;
+    }
     match &token {
-        Token::EndTag(name,_,_) => {
-            if name == "head" {
-                if let None = state.open_elements.pop() { 
+        Token::Character(c) if is_whitespace(*c) => insert_character(state, *c),
+        Token::Comment(comment) => insert_comment(state, comment),
+        Token::DOCTYPE(..) => parse_error(state, &token),
+        Token::StartTag(name, _, attributes) => match name.as_str() {
+            "html" => return parse_in_body(token, state),
+            "base" | "basefont" | "bgsound" | "link" | "meta" => {
+                insert_html_element(state, name, attributes);
+                pop(state);
+            },
+            "title" => parse_generic_text_element(state, name, attributes, TokenizationState::RCDATA),
+            "noframes" | "style" => parse_generic_text_element(state, name, attributes, TokenizationState::RAWTEXT),
+            // Scripting is never enabled, so the contents of <noscript> are parsed as markup.
+            "noscript" => {
+                insert_html_element(state, name, attributes);
+                state.mode = InsertionMode::InHeadNoScript;
+            },
+            "script" => parse_generic_text_element(state, name, attributes, TokenizationState::ScriptData),
+            // NOTE: The "in template" insertion mode is not implemented, the contents are parsed in the current mode.
+            "template" => {
+                insert_html_element(state, name, attributes);
+                state.frame_set_ok = false;
+            },
+            "head" => parse_error(state, &token),
+            _ => anything_else(state),
+        },
+        Token::EndTag(name, _, _) => match name.as_str() {
+            "template" => {
+                if state.open_elements.iter().any(|element| element.kind == ElementKind::Template) {
+                    generate_implied_end_tags(state, None);
+                    pop_until(state, |kind| *kind == ElementKind::Template);
+                } else {
+                    parse_error(state, &token);
+                }
+            },
+            "head" => {
+                if pop(state).is_none() {
                     warn!("Tried to pop open_elements stack, but there was nothing to pop.");
                 }
                 state.mode = InsertionMode::AfterHead;
-                if cfg!(feature = "parser-log") {trace!("PARSE_IN_HEAD {:?}", token);}
-            }
-        }
-        _ => {
-
-        }
+            },
+            "body" | "html" | "br" => anything_else(state),
+            _ => parse_error(state, &token),
+        },
+        _ => anything_else(state),
     }
+    if cfg!(feature = "parser-log") {trace!("PARSE_IN_HEAD {:?}", token);}
     Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inheadnoscript
-pub fn parse_in_head_noscript (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_in_head_noscript<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    #[inline]
+    fn anything_else<S : TreeSink>(token : &Token, state : &mut ParseState<S>) {
+        parse_error(state, token);
+        pop(state);
+        state.mode = InsertionMode::InHead;
+        state.reconsume = true;
+    }
+    match &token {
+        Token::DOCTYPE(..) => parse_error(state, &token),
+        Token::Character(c) if is_whitespace(*c) => return parse_in_head(token, state),
+        Token::Comment(_) => return parse_in_head(token, state),
+        Token::StartTag(name, _, _) => match name.as_str() {
+            "html" => return parse_in_body(token, state),
+            "basefont" | "bgsound" | "link" | "meta" | "noframes" | "style" => return parse_in_head(token, state),
+            "head" | "noscript" => parse_error(state, &token),
+            _ => anything_else(&token, state),
+        },
+        Token::EndTag(name, _, _) => match name.as_str() {
+            "noscript" => {
+                pop(state);
+                state.mode = InsertionMode::InHead;
+            },
+            "br" => anything_else(&token, state),
+            _ => parse_error(state, &token),
+        },
+        _ => anything_else(&token, state),
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#the-after-head-insertion-mode
-pub fn parse_after_head (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
+pub fn parse_after_head<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    #[inline]
+    fn anything_else<S : TreeSink>(state : &mut ParseState<S>) {
+        insert_element(state, Element::new(ElementKind::Body));
+        state.mode = InsertionMode::InBody;
+        state.reconsume = true;
+    }
     match &token {
-        Token::StartTag(name,_,_) => {
-            state.open_elements.push(state.tree.new_node(Element { kind: ElementKind::Body }));
-            state.frame_set_ok = false;
-            state.mode = InsertionMode::InBody;
-            if cfg!(feature = "parser-log") {trace!("PARSE_AFTER_HEAD {:?}", token);}
-        }
-        _ => {
-
-        }
+        Token::Character(c) if is_whitespace(*c) => insert_character(state, *c),
+        Token::Comment(comment) => insert_comment(state, comment),
+        Token::DOCTYPE(..) => parse_error(state, &token),
+        Token::StartTag(name, _, attributes) => match name.as_str() {
+            "html" => return parse_in_body(token, state),
+            "body" => {
+                insert_html_element(state, name, attributes);
+                state.frame_set_ok = false;
+                state.mode = InsertionMode::InBody;
+            },
+            "frameset" => {
+                insert_html_element(state, name, attributes);
+                state.mode = InsertionMode::InFrameset;
+            },
+            "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style" | "template" | "title" => {
+                parse_error(state, &token);
+                if let Some(head) = state.head_pointer.clone() {
+                    state.open_elements.push(OpenElement { handle : head, kind : ElementKind::Head });
+                    let result = parse_in_head(token, state);
+                    // The head element might not be the current node anymore, e.g. after a <title> has been opened.
+                    if let Some(index) = state.open_elements.iter().rposition(|element| element.kind == ElementKind::Head) {
+                        state.open_elements.remove(index);
+                    }
+                    return result;
+                }
+            },
+            "head" => parse_error(state, &token),
+            _ => anything_else(state),
+        },
+        Token::EndTag(name, _, _) => match name.as_str() {
+            "body" | "html" | "br" => anything_else(state),
+            _ => parse_error(state, &token),
+        },
+        _ => anything_else(state),
     }
+    if cfg!(feature = "parser-log") {trace!("PARSE_AFTER_HEAD {:?}", token);}
     Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
-pub fn parse_in_body (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
+pub fn parse_in_body<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
     match &token {
-        Token::EndTag(name, _, _) => {
-            if name == "body" {
-                state.mode = InsertionMode::AfterBody;
-                Ok(())
-            } else {
-                todo!();
+        Token::Character(c) => match c {
+            '\0' => parse_error(state, &token),
+            c if is_whitespace(*c) => insert_character(state, *c),
+            c => {
+                insert_character(state, *c);
+                state.frame_set_ok = false;
+            },
+        },
+        Token::Comment(comment) => insert_comment(state, comment),
+        Token::DOCTYPE(..) => parse_error(state, &token),
+        Token::StartTag(name, is_self_closing, attributes) => match name.as_str() {
+            "html" => {
+                parse_error(state, &token);
+                if let Some(html) = state.open_elements.first() {
+                    let handle = html.handle.clone();
+                    state.sink.add_attributes(&handle, attributes.clone());
+                }
+            },
+            "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style" | "template" | "title" => return parse_in_head(token, state),
+            "body" => {
+                parse_error(state, &token);
+                if let Some(body) = state.open_elements.get(1).filter(|element| element.kind == ElementKind::Body) {
+                    let handle = body.handle.clone();
+                    state.sink.add_attributes(&handle, attributes.clone());
+                }
+                state.frame_set_ok = false;
+            },
+            // NOTE: Replacing the body with a frameset is not supported, so the token is always ignored.
+            "frameset" => parse_error(state, &token),
+            "address" | "article" | "aside" | "blockquote" | "center" | "details" | "dialog" | "dir" | "div" | "dl" | "fieldset"
+            | "figcaption" | "figure" | "footer" | "header" | "hgroup" | "main" | "menu" | "nav" | "ol" | "p" | "search"
+            | "section" | "summary" | "ul" => {
+                close_p_element_in_button_scope(state);
+                insert_html_element(state, name, attributes);
+            },
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                close_p_element_in_button_scope(state);
+                if state.open_elements.last().is_some_and(|element| element.kind.is_heading()) {
+                    parse_error(state, &token);
+                    pop(state);
+                }
+                insert_html_element(state, name, attributes);
+            },
+            "pre" | "listing" => {
+                close_p_element_in_button_scope(state);
+                insert_html_element(state, name, attributes);
+                state.skip_newline = true;
+                state.frame_set_ok = false;
+            },
+            "form" => {
+                if state.form_pointer.is_some() {
+                    parse_error(state, &token);
+                } else {
+                    close_p_element_in_button_scope(state);
+                    let node = insert_html_element(state, name, attributes);
+                    state.form_pointer = Some(node);
+                }
+            },
+            "li" | "dd" | "dt" => {
+                state.frame_set_ok = false;
+                let closes = |kind : &ElementKind| if name == "li" {
+                    *kind == ElementKind::Li
+                } else {
+                    matches!(kind, ElementKind::Dd | ElementKind::Dt)
+                };
+                let mut open = None;
+                for element in state.open_elements.iter().rev() {
+                    if closes(&element.kind) {
+                        open = Some(element.kind.clone());
+                        break;
+                    }
+                    if element.kind.is_special() && !matches!(element.kind, ElementKind::Address | ElementKind::Div | ElementKind::P) {
+                        break;
+                    }
+                }
+                if let Some(kind) = open {
+                    generate_implied_end_tags(state, Some(&kind));
+                    pop_until(state, |k| *k == kind);
+                }
+                close_p_element_in_button_scope(state);
+                insert_html_element(state, name, attributes);
+            },
+            "plaintext" => {
+                close_p_element_in_button_scope(state);
+                insert_html_element(state, name, attributes);
+                state.tokenizer_state = Some(TokenizationState::PLAINTEXT);
+            },
+            "button" => {
+                if has_in_scope(state, ElementKind::Button, Scope::Default) {
+                    parse_error(state, &token);
+                    generate_implied_end_tags(state, None);
+                    pop_until(state, |kind| *kind == ElementKind::Button);
+                }
+                insert_html_element(state, name, attributes);
+                state.frame_set_ok = false;
+            },
+            "applet" | "marquee" | "object" => {
+                insert_html_element(state, name, attributes);
+                state.frame_set_ok = false;
+            },
+            "table" => {
+                close_p_element_in_button_scope(state);
+                insert_html_element(state, name, attributes);
+                state.frame_set_ok = false;
+                state.mode = InsertionMode::InTable;
+            },
+            "area" | "br" | "embed" | "img" | "keygen" | "wbr" => {
+                insert_html_element(state, name, attributes);
+                pop(state);
+                state.frame_set_ok = false;
+            },
+            "input" => {
+                insert_html_element(state, name, attributes);
+                pop(state);
+                let hidden = attributes.iter().any(|a| a.name == "type" && a.value.eq_ignore_ascii_case("hidden"));
+                if !hidden {
+                    state.frame_set_ok = false;
+                }
+            },
+            "param" | "source" | "track" => {
+                insert_html_element(state, name, attributes);
+                pop(state);
+            },
+            "hr" => {
+                close_p_element_in_button_scope(state);
+                insert_html_element(state, name, attributes);
+                pop(state);
+                state.frame_set_ok = false;
+            },
+            "image" => {
+                parse_error(state, &token);
+                return parse_in_body(Token::StartTag("img".to_string(), *is_self_closing, attributes.clone()), state);
+            },
+            "textarea" => {
+                state.skip_newline = true;
+                state.frame_set_ok = false;
+                parse_generic_text_element(state, name, attributes, TokenizationState::RCDATA);
+            },
+            "xmp" => {
+                close_p_element_in_button_scope(state);
+                state.frame_set_ok = false;
+                parse_generic_text_element(state, name, attributes, TokenizationState::RAWTEXT);
+            },
+            "iframe" => {
+                state.frame_set_ok = false;
+                parse_generic_text_element(state, name, attributes, TokenizationState::RAWTEXT);
+            },
+            "noembed" => parse_generic_text_element(state, name, attributes, TokenizationState::RAWTEXT),
+            "select" => {
+                insert_html_element(state, name, attributes);
+                state.frame_set_ok = false;
+                state.mode = match state.mode {
+                    InsertionMode::InTable | InsertionMode::InCaption | InsertionMode::InTableBody | InsertionMode::InRow
+                    | InsertionMode::InCell => InsertionMode::InSelectTable,
+                    _ => InsertionMode::InSelect,
+                };
+            },
+            "optgroup" | "option" => {
+                if current_node_is(state, ElementKind::Option) {
+                    pop(state);
+                }
+                insert_html_element(state, name, attributes);
+            },
+            "rb" | "rtc" => {
+                if has_in_scope(state, ElementKind::Ruby, Scope::Default) {
+                    generate_implied_end_tags(state, None);
+                }
+                insert_html_element(state, name, attributes);
+            },
+            "rp" | "rt" => {
+                if has_in_scope(state, ElementKind::Ruby, Scope::Default) {
+                    generate_implied_end_tags(state, Some(&ElementKind::Rtc));
+                }
+                insert_html_element(state, name, attributes);
+            },
+            // NOTE: Foreign content isn't supported, so the children of these elements are parsed as HTML.
+            "math" | "svg" => {
+                insert_html_element(state, name, attributes);
+                if *is_self_closing {
+                    pop(state);
+                }
+            },
+            "caption" | "col" | "colgroup" | "frame" | "head" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr" => parse_error(state, &token),
+            _ => {
+                insert_html_element(state, name, attributes);
+            },
+        },
+        Token::EndTag(name, _, _) => match name.as_str() {
+            "template" => return parse_in_head(token, state),
+            "body" | "html" => {
+                if !has_in_scope(state, ElementKind::Body, Scope::Default) {
+                    parse_error(state, &token);
+                } else {
+                    state.mode = InsertionMode::AfterBody;
+                    state.reconsume = name == "html";
+                }
+            },
+            "address" | "article" | "aside" | "blockquote" | "button" | "center" | "details" | "dialog" | "dir" | "div" | "dl"
+            | "fieldset" | "figcaption" | "figure" | "footer" | "header" | "hgroup" | "listing" | "main" | "menu" | "nav" | "ol"
+            | "pre" | "search" | "section" | "summary" | "ul" | "applet" | "marquee" | "object" => {
+                let kind = ElementKind::from_name(name);
+                if !has_in_scope(state, kind.clone(), Scope::Default) {
+                    parse_error(state, &token);
+                } else {
+                    generate_implied_end_tags(state, None);
+                    pop_until(state, |k| *k == kind);
+                }
+            },
+            "form" => {
+                let in_scope = has_in_scope(state, ElementKind::Form, Scope::Default);
+                if state.form_pointer.take().is_none() || !in_scope {
+                    parse_error(state, &token);
+                } else {
+                    generate_implied_end_tags(state, None);
+                    pop_until(state, |kind| *kind == ElementKind::Form);
+                }
+            },
+            "p" => {
+                if !has_in_scope(state, ElementKind::P, Scope::Button) {
+                    parse_error(state, &token);
+                    insert_element(state, Element::new(ElementKind::P));
+                }
+                close_p_element(state);
+            },
+            "li" => {
+                if !has_in_scope(state, ElementKind::Li, Scope::ListItem) {
+                    parse_error(state, &token);
+                } else {
+                    generate_implied_end_tags(state, Some(&ElementKind::Li));
+                    pop_until(state, |kind| *kind == ElementKind::Li);
+                }
+            },
+            "dd" | "dt" => {
+                let kind = ElementKind::from_name(name);
+                if !has_in_scope(state, kind.clone(), Scope::Default) {
+                    parse_error(state, &token);
+                } else {
+                    generate_implied_end_tags(state, Some(&kind));
+                    pop_until(state, |k| *k == kind);
+                }
+            },
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if !has_element_in_scope(state, ElementKind::is_heading, Scope::Default) {
+                    parse_error(state, &token);
+                } else {
+                    generate_implied_end_tags(state, None);
+                    pop_until(state, ElementKind::is_heading);
+                }
+            },
+            "br" => {
+                parse_error(state, &token);
+                return parse_in_body(Token::StartTag("br".to_string(), false, Vec::new()), state);
+            },
+            _ => any_other_end_tag(&token, name, state),
+        },
+        Token::EOF => stop_parsing(state),
+    }
+    Ok(())
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody (Any other end tag)
+fn any_other_end_tag<S : TreeSink>(token : &Token, name : &str, state : &mut ParseState<S>) {
+    let kind = ElementKind::from_name(name);
+    for index in (0..state.open_elements.len()).rev() {
+        if state.open_elements[index].kind == kind {
+            generate_implied_end_tags(state, Some(&kind));
+            while state.open_elements.len() > index {
+                pop(state);
             }
+            return;
         }
-        _ => {
-            todo!()
+        if state.open_elements[index].kind.is_special() {
+            parse_error(state, token);
+            return;
         }
     }
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incdata
-pub fn parse_text (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_text<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    match &token {
+        Token::Character(c) => insert_character(state, *c),
+        Token::EOF => {
+            parse_error(state, &token);
+            pop(state);
+            state.mode = state.original_mode;
+            state.reconsume = true;
+        },
+        Token::EndTag(..) => {
+            pop(state);
+            state.mode = state.original_mode;
+        },
+        _ => (),
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intable
-pub fn parse_in_table (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_in_table<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    const TABLE_CONTEXT : &[ElementKind] = &[ElementKind::Table, ElementKind::Template];
+    match &token {
+        Token::Character(_) if state.open_elements.last().is_some_and(|element| matches!(element.kind,
+            ElementKind::Table | ElementKind::Tbody | ElementKind::Template | ElementKind::Tfoot | ElementKind::Thead | ElementKind::Tr)) => {
+            state.pending_table_text.clear();
+            state.original_mode = state.mode;
+            state.mode = InsertionMode::InTableText;
+            state.reconsume = true;
+        },
+        Token::Comment(comment) => insert_comment(state, comment),
+        Token::DOCTYPE(..) => parse_error(state, &token),
+        Token::StartTag(name, _, attributes) => match name.as_str() {
+            "caption" => {
+                clear_stack_back_to(state, TABLE_CONTEXT);
+                insert_html_element(state, name, attributes);
+                state.mode = InsertionMode::InCaption;
+            },
+            "colgroup" => {
+                clear_stack_back_to(state, TABLE_CONTEXT);
+                insert_html_element(state, name, attributes);
+                state.mode = InsertionMode::InColumnGroup;
+            },
+            "col" => {
+                clear_stack_back_to(state, TABLE_CONTEXT);
+                insert_element(state, Element::new(ElementKind::Colgroup));
+                state.mode = InsertionMode::InColumnGroup;
+                state.reconsume = true;
+            },
+            "tbody" | "tfoot" | "thead" => {
+                clear_stack_back_to(state, TABLE_CONTEXT);
+                insert_html_element(state, name, attributes);
+                state.mode = InsertionMode::InTableBody;
+            },
+            "td" | "th" | "tr" => {
+                clear_stack_back_to(state, TABLE_CONTEXT);
+                insert_element(state, Element::new(ElementKind::Tbody));
+                state.mode = InsertionMode::InTableBody;
+                state.reconsume = true;
+            },
+            "table" => {
+                parse_error(state, &token);
+                if has_in_scope(state, ElementKind::Table, Scope::Table) {
+                    pop_until(state, |kind| *kind == ElementKind::Table);
+                    reset_insertion_mode(state);
+                    state.reconsume = true;
+                }
+            },
+            "style" | "script" | "template" => return parse_in_head(token, state),
+            "input" if attributes.iter().any(|a| a.name == "type" && a.value.eq_ignore_ascii_case("hidden")) => {
+                parse_error(state, &token);
+                insert_html_element(state, name, attributes);
+                pop(state);
+            },
+            "form" => {
+                parse_error(state, &token);
+                if state.form_pointer.is_none() {
+                    let node = insert_html_element(state, name, attributes);
+                    state.form_pointer = Some(node);
+                    pop(state);
+                }
+            },
+            _ => {
+                parse_error(state, &token);
+                return parse_in_body(token, state);
+            },
+        },
+        Token::EndTag(name, _, _) => match name.as_str() {
+            "table" => {
+                if !has_in_scope(state, ElementKind::Table, Scope::Table) {
+                    parse_error(state, &token);
+                } else {
+                    pop_until(state, |kind| *kind == ElementKind::Table);
+                    reset_insertion_mode(state);
+                }
+            },
+            "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr" => parse_error(state, &token),
+            "template" => return parse_in_head(token, state),
+            _ => {
+                parse_error(state, &token);
+                return parse_in_body(token, state);
+            },
+        },
+        Token::EOF => return parse_in_body(token, state),
+        _ => {
+            parse_error(state, &token);
+            return parse_in_body(token, state);
+        },
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intabletext
-pub fn parse_in_table_text (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_in_table_text<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    match &token {
+        Token::Character('\0') => parse_error(state, &token),
+        Token::Character(c) => state.pending_table_text.push(*c),
+        _ => {
+            let text = std::mem::take(&mut state.pending_table_text);
+            if text.chars().any(|c| !is_whitespace(c)) {
+                // Parse error, the characters are processed using the "anything else" rules of the "in table" insertion mode.
+                for c in text.chars() {
+                    parse_in_body(Token::Character(c), state)?;
+                }
+            } else {
+                for c in text.chars() {
+                    insert_character(state, c);
+                }
+            }
+            state.mode = state.original_mode;
+            state.reconsume = true;
+        },
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incaption
-pub fn parse_in_caption (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_in_caption<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    #[inline]
+    fn close_caption<S : TreeSink>(token : &Token, state : &mut ParseState<S>) -> bool {
+        if !has_in_scope(state, ElementKind::Caption, Scope::Table) {
+            parse_error(state, token);
+            return false;
+        }
+        generate_implied_end_tags(state, None);
+        pop_until(state, |kind| *kind == ElementKind::Caption);
+        state.mode = InsertionMode::InTable;
+        true
+    }
+    match &token {
+        Token::EndTag(name, _, _) if name == "caption" => {
+            close_caption(&token, state);
+        },
+        Token::StartTag(name, _, _) if matches!(name.as_str(), "caption" | "col" | "colgroup" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr") => {
+            state.reconsume = close_caption(&token, state);
+        },
+        Token::EndTag(name, _, _) if name == "table" => {
+            state.reconsume = close_caption(&token, state);
+        },
+        Token::EndTag(name, _, _) if matches!(name.as_str(), "body" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr") => {
+            parse_error(state, &token);
+        },
+        _ => return parse_in_body(token, state),
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incolgroup
-pub fn parse_in_column_group (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_in_column_group<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    match &token {
+        Token::Character(c) if is_whitespace(*c) => insert_character(state, *c),
+        Token::Comment(comment) => insert_comment(state, comment),
+        Token::DOCTYPE(..) => parse_error(state, &token),
+        Token::StartTag(name, _, _) if name == "html" => return parse_in_body(token, state),
+        Token::StartTag(name, _, attributes) if name == "col" => {
+            insert_html_element(state, name, attributes);
+            pop(state);
+        },
+        Token::StartTag(name, _, _) if name == "template" => return parse_in_head(token, state),
+        Token::EndTag(name, _, _) if name == "template" => return parse_in_head(token, state),
+        Token::EndTag(name, _, _) if name == "colgroup" => {
+            if current_node_is(state, ElementKind::Colgroup) {
+                pop(state);
+                state.mode = InsertionMode::InTable;
+            } else {
+                parse_error(state, &token);
+            }
+        },
+        Token::EndTag(name, _, _) if name == "col" => parse_error(state, &token),
+        Token::EOF => return parse_in_body(token, state),
+        _ => {
+            if current_node_is(state, ElementKind::Colgroup) {
+                pop(state);
+                state.mode = InsertionMode::InTable;
+                state.reconsume = true;
+            } else {
+                parse_error(state, &token);
+            }
+        },
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intbody
-pub fn parse_in_table_body (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_in_table_body<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    const TABLE_BODY_CONTEXT : &[ElementKind] = &[ElementKind::Tbody, ElementKind::Tfoot, ElementKind::Thead, ElementKind::Template];
+    let is_table_section = |kind : &ElementKind| matches!(kind, ElementKind::Tbody | ElementKind::Thead | ElementKind::Tfoot);
+    match &token {
+        Token::StartTag(name, _, attributes) if name == "tr" => {
+            clear_stack_back_to(state, TABLE_BODY_CONTEXT);
+            insert_html_element(state, name, attributes);
+            state.mode = InsertionMode::InRow;
+        },
+        Token::StartTag(name, _, _) if name == "th" || name == "td" => {
+            parse_error(state, &token);
+            clear_stack_back_to(state, TABLE_BODY_CONTEXT);
+            insert_element(state, Element::new(ElementKind::Tr));
+            state.mode = InsertionMode::InRow;
+            state.reconsume = true;
+        },
+        Token::EndTag(name, _, _) if matches!(name.as_str(), "tbody" | "tfoot" | "thead") => {
+            if !has_in_scope(state, ElementKind::from_name(name), Scope::Table) {
+                parse_error(state, &token);
+            } else {
+                clear_stack_back_to(state, TABLE_BODY_CONTEXT);
+                pop(state);
+                state.mode = InsertionMode::InTable;
+            }
+        },
+        Token::StartTag(name, _, _) if matches!(name.as_str(), "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead") => {
+            if !has_element_in_scope(state, is_table_section, Scope::Table) {
+                parse_error(state, &token);
+            } else {
+                clear_stack_back_to(state, TABLE_BODY_CONTEXT);
+                pop(state);
+                state.mode = InsertionMode::InTable;
+                state.reconsume = true;
+            }
+        },
+        Token::EndTag(name, _, _) if name == "table" => {
+            if !has_element_in_scope(state, is_table_section, Scope::Table) {
+                parse_error(state, &token);
+            } else {
+                clear_stack_back_to(state, TABLE_BODY_CONTEXT);
+                pop(state);
+                state.mode = InsertionMode::InTable;
+                state.reconsume = true;
+            }
+        },
+        Token::EndTag(name, _, _) if matches!(name.as_str(), "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" | "tr") => {
+            parse_error(state, &token);
+        },
+        _ => return parse_in_table(token, state),
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intr
-pub fn parse_in_row (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_in_row<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    const ROW_CONTEXT : &[ElementKind] = &[ElementKind::Tr, ElementKind::Template];
+    #[inline]
+    fn close_row<S : TreeSink>(token : &Token, state : &mut ParseState<S>) -> bool {
+        if !has_in_scope(state, ElementKind::Tr, Scope::Table) {
+            parse_error(state, token);
+            return false;
+        }
+        clear_stack_back_to(state, ROW_CONTEXT);
+        pop(state);
+        state.mode = InsertionMode::InTableBody;
+        true
+    }
+    match &token {
+        Token::StartTag(name, _, attributes) if name == "th" || name == "td" => {
+            clear_stack_back_to(state, ROW_CONTEXT);
+            insert_html_element(state, name, attributes);
+            state.mode = InsertionMode::InCell;
+        },
+        Token::EndTag(name, _, _) if name == "tr" => {
+            close_row(&token, state);
+        },
+        Token::StartTag(name, _, _) if matches!(name.as_str(), "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" | "tr") => {
+            state.reconsume = close_row(&token, state);
+        },
+        Token::EndTag(name, _, _) if name == "table" => {
+            state.reconsume = close_row(&token, state);
+        },
+        Token::EndTag(name, _, _) if matches!(name.as_str(), "tbody" | "tfoot" | "thead") => {
+            if !has_in_scope(state, ElementKind::from_name(name), Scope::Table) {
+                parse_error(state, &token);
+            } else {
+                state.reconsume = close_row(&token, state);
+            }
+        },
+        Token::EndTag(name, _, _) if matches!(name.as_str(), "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th") => {
+            parse_error(state, &token);
+        },
+        _ => return parse_in_table(token, state),
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intd
-pub fn parse_in_cell (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_in_cell<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    // https://html.spec.whatwg.org/multipage/parsing.html#close-the-cell
+    #[inline]
+    fn close_cell<S : TreeSink>(state : &mut ParseState<S>) {
+        generate_implied_end_tags(state, None);
+        pop_until(state, |kind| matches!(kind, ElementKind::Td | ElementKind::Th));
+        state.mode = InsertionMode::InRow;
+    }
+    match &token {
+        Token::EndTag(name, _, _) if name == "td" || name == "th" => {
+            let kind = ElementKind::from_name(name);
+            if !has_in_scope(state, kind.clone(), Scope::Table) {
+                parse_error(state, &token);
+            } else {
+                generate_implied_end_tags(state, None);
+                pop_until(state, |k| *k == kind);
+                state.mode = InsertionMode::InRow;
+            }
+        },
+        Token::StartTag(name, _, _) if matches!(name.as_str(), "caption" | "col" | "colgroup" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr") => {
+            if !has_element_in_scope(state, |kind| matches!(kind, ElementKind::Td | ElementKind::Th), Scope::Table) {
+                parse_error(state, &token);
+            } else {
+                close_cell(state);
+                state.reconsume = true;
+            }
+        },
+        Token::EndTag(name, _, _) if matches!(name.as_str(), "body" | "caption" | "col" | "colgroup" | "html") => parse_error(state, &token),
+        Token::EndTag(name, _, _) if matches!(name.as_str(), "table" | "tbody" | "tfoot" | "thead" | "tr") => {
+            if !has_in_scope(state, ElementKind::from_name(name), Scope::Table) {
+                parse_error(state, &token);
+            } else {
+                close_cell(state);
+                state.reconsume = true;
+            }
+        },
+        _ => return parse_in_body(token, state),
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselect
-pub fn parse_in_select (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_in_select<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    match &token {
+        Token::Character('\0') => parse_error(state, &token),
+        Token::Character(c) => insert_character(state, *c),
+        Token::Comment(comment) => insert_comment(state, comment),
+        Token::DOCTYPE(..) => parse_error(state, &token),
+        Token::StartTag(name, _, attributes) => match name.as_str() {
+            "html" => return parse_in_body(token, state),
+            "option" => {
+                if current_node_is(state, ElementKind::Option) {
+                    pop(state);
+                }
+                insert_html_element(state, name, attributes);
+            },
+            "optgroup" | "hr" => {
+                if current_node_is(state, ElementKind::Option) {
+                    pop(state);
+                }
+                if current_node_is(state, ElementKind::Optgroup) {
+                    pop(state);
+                }
+                insert_html_element(state, name, attributes);
+                if name == "hr" {
+                    pop(state);
+                }
+            },
+            "select" => {
+                parse_error(state, &token);
+                if has_in_scope(state, ElementKind::Select, Scope::Select) {
+                    pop_until(state, |kind| *kind == ElementKind::Select);
+                    reset_insertion_mode(state);
+                }
+            },
+            "input" | "keygen" | "textarea" => {
+                parse_error(state, &token);
+                if has_in_scope(state, ElementKind::Select, Scope::Select) {
+                    pop_until(state, |kind| *kind == ElementKind::Select);
+                    reset_insertion_mode(state);
+                    state.reconsume = true;
+                }
+            },
+            "script" | "template" => return parse_in_head(token, state),
+            _ => parse_error(state, &token),
+        },
+        Token::EndTag(name, _, _) => match name.as_str() {
+            "optgroup" => {
+                let len = state.open_elements.len();
+                if current_node_is(state, ElementKind::Option) && len > 1 && state.open_elements[len - 2].kind == ElementKind::Optgroup {
+                    pop(state);
+                }
+                if current_node_is(state, ElementKind::Optgroup) {
+                    pop(state);
+                } else {
+                    parse_error(state, &token);
+                }
+            },
+            "option" => {
+                if current_node_is(state, ElementKind::Option) {
+                    pop(state);
+                } else {
+                    parse_error(state, &token);
+                }
+            },
+            "select" => {
+                if !has_in_scope(state, ElementKind::Select, Scope::Select) {
+                    parse_error(state, &token);
+                } else {
+                    pop_until(state, |kind| *kind == ElementKind::Select);
+                    reset_insertion_mode(state);
+                }
+            },
+            "template" => return parse_in_head(token, state),
+            _ => parse_error(state, &token),
+        },
+        Token::EOF => return parse_in_body(token, state),
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselectintable
-pub fn parse_in_select_table (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_in_select_table<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    const TABLE_ELEMENTS : [&str; 8] = ["caption", "table", "tbody", "tfoot", "thead", "tr", "td", "th"];
+    match &token {
+        Token::StartTag(name, _, _) if TABLE_ELEMENTS.contains(&name.as_str()) => {
+            parse_error(state, &token);
+            pop_until(state, |kind| *kind == ElementKind::Select);
+            reset_insertion_mode(state);
+            state.reconsume = true;
+        },
+        Token::EndTag(name, _, _) if TABLE_ELEMENTS.contains(&name.as_str()) => {
+            parse_error(state, &token);
+            if has_in_scope(state, ElementKind::from_name(name), Scope::Table) {
+                pop_until(state, |kind| *kind == ElementKind::Select);
+                reset_insertion_mode(state);
+                state.reconsume = true;
+            }
+        },
+        _ => return parse_in_select(token, state),
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intemplate
-pub fn parse_in_template (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_in_template<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    Err(HtmlParseError::Unsupported("The \"in template\" insertion mode"))
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-afterbody
-pub fn parse_after_body (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
+pub fn parse_after_body<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
     match &token {
-        Token::EndTag(name, _, _) => {
-            if name == "html" {
-                state.mode = InsertionMode::AfterAfterBody;
-                Ok(())
-            } else {
-                todo!()
+        Token::Character(c) if is_whitespace(*c) => return parse_in_body(token, state),
+        Token::Comment(comment) => {
+            // The comment is inserted as the last child of the html element.
+            if let Some(html) = state.open_elements.first() {
+                let handle = html.handle.clone();
+                count_node(state);
+                state.sink.append_comment(&handle, comment);
             }
-        }
+        },
+        Token::DOCTYPE(..) => parse_error(state, &token),
+        Token::StartTag(name, _, _) if name == "html" => return parse_in_body(token, state),
+        Token::EndTag(name, _, _) if name == "html" => {
+            state.mode = InsertionMode::AfterAfterBody;
+        },
+        Token::EOF => stop_parsing(state),
         _ => {
-            todo!()
+            parse_error(state, &token);
+            state.mode = InsertionMode::InBody;
+            state.reconsume = true;
         }
     }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inframeset
-pub fn parse_in_frameset (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_in_frameset<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    match &token {
+        Token::Character(c) if is_whitespace(*c) => insert_character(state, *c),
+        Token::Comment(comment) => insert_comment(state, comment),
+        Token::StartTag(name, _, _) if name == "html" => return parse_in_body(token, state),
+        Token::StartTag(name, _, attributes) if name == "frameset" => {
+            insert_html_element(state, name, attributes);
+        },
+        Token::EndTag(name, _, _) if name == "frameset" => {
+            if current_node_is(state, ElementKind::Html) {
+                parse_error(state, &token);
+            } else {
+                pop(state);
+                if !current_node_is(state, ElementKind::Frameset) {
+                    state.mode = InsertionMode::AfterFrameset;
+                }
+            }
+        },
+        Token::StartTag(name, _, attributes) if name == "frame" => {
+            insert_html_element(state, name, attributes);
+            pop(state);
+        },
+        Token::StartTag(name, _, _) if name == "noframes" => return parse_in_head(token, state),
+        Token::EOF => stop_parsing(state),
+        _ => parse_error(state, &token),
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-afterframeset
-pub fn parse_after_frameset (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_after_frameset<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    match &token {
+        Token::Character(c) if is_whitespace(*c) => insert_character(state, *c),
+        Token::Comment(comment) => insert_comment(state, comment),
+        Token::StartTag(name, _, _) if name == "html" => return parse_in_body(token, state),
+        Token::EndTag(name, _, _) if name == "html" => {
+            state.mode = InsertionMode::AfterAfterFrameset;
+        },
+        Token::StartTag(name, _, _) if name == "noframes" => return parse_in_head(token, state),
+        Token::EOF => stop_parsing(state),
+        _ => parse_error(state, &token),
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#the-after-after-body-insertion-mode
-pub fn parse_after_after_body (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
+pub fn parse_after_after_body<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    match &token {
+        Token::Comment(comment) => insert_comment_in_document(state, comment),
+        Token::DOCTYPE(..) => return parse_in_body(token, state),
+        Token::Character(c) if is_whitespace(*c) => return parse_in_body(token, state),
+        Token::StartTag(name, _, _) if name == "html" => return parse_in_body(token, state),
+        Token::EOF => stop_parsing(state),
+        _ => {
+            parse_error(state, &token);
+            state.mode = InsertionMode::InBody;
+            state.reconsume = true;
+        }
+    }
+    Ok(())
 }
 
 // https://html.spec.whatwg.org/multipage/parsing.html#the-after-after-frameset-insertion-mode
-pub fn parse_after_after_frameset (token : Token, state : &mut ParseState) -> Result<(), HtmlParseError> {
-    todo!()
-}
\ No newline at end of file
+pub fn parse_after_after_frameset<S : TreeSink>(token : Token, state : &mut ParseState<S>) -> Result<(), HtmlParseError> {
+    match &token {
+        Token::Comment(comment) => insert_comment_in_document(state, comment),
+        Token::DOCTYPE(..) => return parse_in_body(token, state),
+        Token::Character(c) if is_whitespace(*c) => return parse_in_body(token, state),
+        Token::StartTag(name, _, _) if name == "html" => return parse_in_body(token, state),
+        Token::StartTag(name, _, _) if name == "noframes" => return parse_in_head(token, state),
+        Token::EOF => stop_parsing(state),
+        _ => parse_error(state, &token),
+    }
+    Ok(())
+}
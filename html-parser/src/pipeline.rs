@@ -0,0 +1,89 @@
+//! Pipelined parsing, with the tokenizer and the tree builder running on separate threads.
+//!
+//! The tokenizer runs on a worker thread and sends its tokens to the tree builder in batches over a
+//! bounded channel. Since there is no scripting the tree builder never has to stop the tokenizer,
+//! with one exception : after start tags like `<title>` or `<script>` the tree builder may switch the
+//! tokenizer to a different state. The worker sends off its batch after every such tag and waits for
+//! the tree builder's answer on a second channel before it carries on.
+
+use std::{sync::mpsc::{self, Receiver, SyncSender}, thread};
+
+use crate::{error::HtmlTokenizerError, parser::may_switch_tokenizer, preproccesor::PreProccessor, sink::TreeSink,
+    states::TokenizationState, tokenizer::{SourcePosition, Token, Tokenizer}, HtmlParser, ParseState};
+
+// Number of tokens sent over the channel at once.
+const BATCH_SIZE : usize = 1024;
+// Number of batches the tokenizer can get ahead of the tree builder before it blocks.
+const CHANNEL_CAPACITY : usize = 8;
+
+struct Batch {
+    // Every token along with where it starts, for TreeSink::set_source_position.
+    tokens : Vec<(Result<Token, HtmlTokenizerError>, SourcePosition)>,
+    // The last token of the batch is a start tag that can change the tokenizer's state, and the
+    // worker is waiting for a reply before tokenizing anything else.
+    awaiting_reply : bool,
+    // Where the input ends, on the last batch only.
+    end : Option<SourcePosition>,
+}
+
+// Runs on the worker thread. Returns when the input is exhausted or when the tree builder hangs up.
+fn tokenize(mut tokens : Tokenizer, batches : SyncSender<Batch>, replies : Receiver<Option<TokenizationState>>) {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while let Some(token) = tokens.next() {
+        let awaiting_reply = match &token {
+            Ok(Token::StartTag(name, _, _)) => may_switch_tokenizer(name),
+            _ => false,
+        };
+        batch.push((token, tokens.token_position()));
+        if awaiting_reply || batch.len() == BATCH_SIZE {
+            let full = std::mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE));
+            if batches.send(Batch { tokens : full, awaiting_reply, end : None }).is_err() {
+                return;
+            }
+        }
+        if awaiting_reply {
+            match replies.recv() {
+                Ok(Some(state)) => tokens.switch_to(state),
+                Ok(None) => (),
+                Err(_) => return,
+            }
+        }
+    }
+    let _ = batches.send(Batch { tokens : batch, awaiting_reply : false, end : Some(tokens.token_position()) });
+}
+
+impl HtmlParser {
+    /// Same as [`HtmlParser::parse`], but the tokenizer runs on its own thread. This only pays off for
+    /// large documents, for small ones the cost of starting a thread outweighs the gain.
+    pub fn parse_pipelined<S : TreeSink>(input: &str, mut state: ParseState<S>) -> Result<S::Output, Box<dyn std::error::Error>> {
+        state.check_input_size(input.len())?;
+        let html = PreProccessor::new(input)?;
+        let mut tokens = Tokenizer::new(html);
+        tokens.set_limits(state.limits);
+        let (batch_sender, batches) = mpsc::sync_channel(CHANNEL_CAPACITY);
+        let (reply_sender, replies) = mpsc::sync_channel(1);
+        let worker = thread::spawn(move || tokenize(tokens, batch_sender, replies));
+
+        // Returning early drops both channels, which also stops the worker.
+        let mut end = SourcePosition::default();
+        for batch in batches.iter() {
+            for (wrapped_token, position) in batch.tokens {
+                let token = Self::recover_token(wrapped_token)?;
+                state.set_source_position(position);
+                state.process_token(token)?;
+            }
+            end = batch.end.unwrap_or(end);
+            let tokenizer_state = state.take_tokenizer_state();
+            if batch.awaiting_reply {
+                reply_sender.send(tokenizer_state)?;
+            }
+        }
+
+        if worker.join().is_err() {
+            return Err("the tokenizer thread panicked".into());
+        }
+        state.set_source_position(end);
+        state.process_token(Token::EOF)?;
+        Ok(state.finish())
+    }
+}
@@ -1,8 +1,10 @@
+#[derive(Clone)]
 pub struct PreProccessor {
     pub raw: String,
     _encoding: EncodingConfidence,
 }
 
+#[derive(Clone)]
 pub enum EncodingConfidence {
     _Tentative(Encoding),
     _Certain(Encoding),
@@ -10,6 +12,7 @@ pub enum EncodingConfidence {
 }
 
 //TODO : Add more encodings from the spec
+#[derive(Clone)]
 pub enum Encoding {
     _Utf8,
 }
@@ -0,0 +1,110 @@
+//! `querySelector` and friends, on top of the selector engine of the CSS parser.
+
+use css_parser::selector::{self, SelectorList, SelectorParseError};
+use crate::{document::{Document, ElementRef}, dom::Node};
+
+impl<'a> selector::Element for ElementRef<'a> {
+    fn parent_element(&self) -> Option<Self> {
+        ElementRef::parent_element(self)
+    }
+
+    fn previous_sibling_element(&self) -> Option<Self> {
+        self.previous_element_sibling()
+    }
+
+    fn next_sibling_element(&self) -> Option<Self> {
+        self.next_element_sibling()
+    }
+
+    fn first_child_element(&self) -> Option<Self> {
+        self.child_elements().next()
+    }
+
+    fn is_root(&self) -> bool {
+        self.parent().is_some_and(|parent| matches!(parent.node(), Node::Document))
+    }
+
+    // https://www.w3.org/TR/selectors-4/#the-empty-pseudo
+    fn is_empty(&self) -> bool {
+        self.children().all(|child| match child.node() {
+            Node::Text(text) => text.is_empty(),
+            Node::Element(_) => false,
+            _ => true,
+        })
+    }
+
+    fn local_name(&self) -> &str {
+        self.element().name()
+    }
+
+    fn id(&self) -> Option<&str> {
+        self.element().id()
+    }
+
+    fn has_class(&self, name : &str) -> bool {
+        self.element().has_class(name)
+    }
+
+    fn attribute(&self, name : &str) -> Option<&str> {
+        self.element().attribute(name)
+    }
+}
+
+impl Document {
+    // https://dom.spec.whatwg.org/#dom-parentnode-queryselector
+    /// The first element in document order that matches the selectors.
+    pub fn query_selector(&self, selectors : &str) -> Result<Option<ElementRef<'_>>, SelectorParseError> {
+        let selectors = selector::parse(selectors)?;
+        Ok(self.root().descendant_elements().find(|element| selector::matches(&selectors, element, None)))
+    }
+
+    // https://dom.spec.whatwg.org/#dom-parentnode-queryselectorall
+    /// Every element that matches the selectors, in document order.
+    pub fn query_selector_all(&self, selectors : &str) -> Result<Vec<ElementRef<'_>>, SelectorParseError> {
+        let selectors = selector::parse(selectors)?;
+        Ok(self.select(&selectors))
+    }
+
+    /// Like [`Document::query_selector_all`], for selectors that are already parsed.
+    pub fn select(&self, selectors : &SelectorList) -> Vec<ElementRef<'_>> {
+        self.root().descendant_elements().filter(|element| selector::matches(selectors, element, None)).collect()
+    }
+}
+
+impl<'a> ElementRef<'a> {
+    /// The first element below this one that matches the selectors. `:scope` stands for this element, but
+    /// the rest of the selector may match elements outside of it, as in the DOM.
+    pub fn query_selector(&self, selectors : &str) -> Result<Option<ElementRef<'a>>, SelectorParseError> {
+        let selectors = selector::parse(selectors)?;
+        Ok(self.descendant_elements().find(|element| selector::matches(&selectors, element, Some(self))))
+    }
+
+    pub fn query_selector_all(&self, selectors : &str) -> Result<Vec<ElementRef<'a>>, SelectorParseError> {
+        let selectors = selector::parse(selectors)?;
+        Ok(self.select(&selectors))
+    }
+
+    pub fn select(&self, selectors : &SelectorList) -> Vec<ElementRef<'a>> {
+        self.descendant_elements().filter(|element| selector::matches(selectors, element, Some(self))).collect()
+    }
+
+    // https://dom.spec.whatwg.org/#dom-element-matches
+    pub fn matches(&self, selectors : &str) -> Result<bool, SelectorParseError> {
+        let selectors = selector::parse(selectors)?;
+        Ok(selector::matches(&selectors, self, Some(self)))
+    }
+
+    // https://dom.spec.whatwg.org/#dom-element-closest
+    /// This element or the closest of its ancestors that matches the selectors.
+    pub fn closest(&self, selectors : &str) -> Result<Option<ElementRef<'a>>, SelectorParseError> {
+        let selectors = selector::parse(selectors)?;
+        let mut current = Some(*self);
+        while let Some(element) = current {
+            if selector::matches(&selectors, &element, Some(self)) {
+                return Ok(Some(element));
+            }
+            current = element.parent_element();
+        }
+        Ok(None)
+    }
+}
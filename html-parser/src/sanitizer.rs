@@ -0,0 +1,238 @@
+//! Cleaning untrusted markup with an allowlist.
+//!
+//! [`sanitize`] parses the input the way the body of a document would be parsed, removes everything the
+//! [`SanitizePolicy`] doesn't allow and serializes what is left. Because the output comes from the
+//! [`crate::serializer`], it is always well formed : text is escaped, attribute values are quoted and can't
+//! contain `<` or `>`, and every element is closed.
+//!
+//! Some elements are removed together with their content whatever the policy says, because their content
+//! is either not meant to be displayed or is parsed differently by a browser than by this parser :
+//! - `<script>`, `<style>` and the other elements with raw text, whose content would otherwise end up as
+//!   markup.
+//! - `<noscript>`, which browsers with scripting enabled parse as raw text. Markup hidden in an attribute
+//!   value inside of it would come alive there.
+//! - `<svg>` and `<math>`. The parser doesn't implement the rules for foreign content, so the tree it
+//!   builds for them isn't the one a browser would build, and the difference is what namespace confusion
+//!   attacks rely on.
+//! - `<template>`, `<iframe>`, `<object>`, `<embed>` and frames, which load or hold documents of their own.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{dom::{Element, ElementKind, Node}, limits::ParserLimits, serializer::{self, SerializeOptions}, HtmlParser, NodeId, ParseState, Tree};
+
+/// What happens to an element the policy doesn't allow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disallowed {
+    /// The element is replaced by its (sanitized) children, so its text is kept.
+    Unwrap,
+    /// The element is removed along with everything in it.
+    Drop,
+}
+
+/// Which elements, attributes and URLs [`sanitize`] keeps. Names are lower case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SanitizePolicy {
+    pub elements : HashSet<String>,
+    /// Attributes allowed on every allowed element.
+    pub attributes : HashSet<String>,
+    /// Attributes allowed on some elements only, by element name.
+    pub element_attributes : HashMap<String, HashSet<String>>,
+    /// The schemes allowed in attributes that hold a URL, like `href` and `src`. Attributes with any
+    /// other scheme are removed.
+    pub url_schemes : HashSet<String>,
+    /// Whether URLs without a scheme, like `/about` or `#top`, are allowed.
+    pub relative_urls : bool,
+    pub comments : bool,
+    pub disallowed : Disallowed,
+}
+
+// Elements that are removed with their content, see the module documentation.
+fn is_dropped_with_content(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    matches!(kind,
+        Script | Style | Xmp | Iframe | Noembed | Noframes | Plaintext | Noscript | Template | Title | Textarea
+        | Svg | Math | Object | Embed | Applet | Frame | Frameset)
+}
+
+// Attributes whose value is a URL.
+const URL_ATTRIBUTES : &[&str] = &["href", "src", "cite", "action", "formaction", "poster", "background", "longdesc", "xlink:href"];
+
+fn names(names : &[&str]) -> HashSet<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+impl SanitizePolicy {
+    /// Allows nothing but text. Every element is unwrapped and comments are removed.
+    pub fn new() -> Self {
+        Self {
+            elements : HashSet::new(),
+            attributes : HashSet::new(),
+            element_attributes : HashMap::new(),
+            url_schemes : HashSet::new(),
+            relative_urls : false,
+            comments : false,
+            disallowed : Disallowed::Unwrap,
+        }
+    }
+
+    /// Text formatting, paragraphs, lists, quotes and links to `http`, `https` and `mailto` URLs. Meant for
+    /// comments and other short user content.
+    pub fn basic() -> Self {
+        Self::new()
+            .allow_elements(&[
+                "a", "abbr", "b", "blockquote", "br", "cite", "code", "em", "i", "kbd", "li", "mark", "ol", "p",
+                "pre", "q", "s", "small", "span", "strong", "sub", "sup", "u", "ul",
+            ])
+            .allow_element_attributes("a", &["href", "title"])
+            .allow_element_attributes("abbr", &["title"])
+            .allow_element_attributes("blockquote", &["cite"])
+            .allow_element_attributes("q", &["cite"])
+            .allow_url_schemes(&["http", "https", "mailto"])
+            .allow_relative_urls(true)
+    }
+
+    /// [`SanitizePolicy::basic`] along with headings, tables, images, definition lists and the other
+    /// elements of a longer document, and the `title`, `lang` and `dir` attributes on all of them.
+    pub fn relaxed() -> Self {
+        Self::basic()
+            .allow_elements(&[
+                "h1", "h2", "h3", "h4", "h5", "h6", "div", "hr", "dl", "dt", "dd", "figure", "figcaption", "img",
+                "del", "ins", "details", "summary", "table", "caption", "colgroup", "col", "thead", "tbody", "tfoot",
+                "tr", "th", "td", "dfn", "samp", "var", "time", "bdi", "bdo", "ruby", "rt", "rp", "wbr",
+            ])
+            .allow_attributes(&["title", "lang", "dir"])
+            .allow_element_attributes("img", &["src", "alt", "width", "height"])
+            .allow_element_attributes("ol", &["start", "reversed"])
+            .allow_element_attributes("td", &["colspan", "rowspan"])
+            .allow_element_attributes("th", &["colspan", "rowspan", "scope"])
+            .allow_element_attributes("col", &["span"])
+            .allow_element_attributes("colgroup", &["span"])
+            .allow_element_attributes("del", &["cite", "datetime"])
+            .allow_element_attributes("ins", &["cite", "datetime"])
+            .allow_element_attributes("time", &["datetime"])
+    }
+
+    pub fn allow_elements(mut self, elements : &[&str]) -> Self {
+        self.elements.extend(names(elements));
+        self
+    }
+
+    pub fn allow_attributes(mut self, attributes : &[&str]) -> Self {
+        self.attributes.extend(names(attributes));
+        self
+    }
+
+    pub fn allow_element_attributes(mut self, element : &str, attributes : &[&str]) -> Self {
+        self.element_attributes.entry(element.to_string()).or_default().extend(names(attributes));
+        self
+    }
+
+    pub fn allow_url_schemes(mut self, schemes : &[&str]) -> Self {
+        self.url_schemes.extend(names(schemes));
+        self
+    }
+
+    pub fn allow_relative_urls(mut self, allow : bool) -> Self {
+        self.relative_urls = allow;
+        self
+    }
+
+    pub fn allow_comments(mut self, allow : bool) -> Self {
+        self.comments = allow;
+        self
+    }
+
+    pub fn disallowed(mut self, disallowed : Disallowed) -> Self {
+        self.disallowed = disallowed;
+        self
+    }
+
+    fn allows_element(&self, element : &Element) -> bool {
+        !is_dropped_with_content(&element.kind) && self.elements.contains(element.name())
+    }
+
+    fn allows_attribute(&self, element : &str, name : &str, value : &str) -> bool {
+        let allowed = self.attributes.contains(name)
+            || self.element_attributes.get(element).is_some_and(|attributes| attributes.contains(name));
+        allowed && (!URL_ATTRIBUTES.contains(&name) || self.allows_url(value))
+    }
+
+    // https://url.spec.whatwg.org/#concept-basic-url-parser
+    fn allows_url(&self, url : &str) -> bool {
+        // Browsers ignore leading and trailing control characters and spaces, and tabs and newlines anywhere.
+        let url : String = url.trim_matches(|c : char| c <= ' ').chars().filter(|c| !matches!(c, '\t' | '\n' | '\r')).collect();
+        match url_scheme(&url) {
+            Some(scheme) => self.url_schemes.contains(&scheme.to_ascii_lowercase()),
+            None => self.relative_urls,
+        }
+    }
+}
+
+impl Default for SanitizePolicy {
+    fn default() -> Self {
+        Self::basic()
+    }
+}
+
+// https://url.spec.whatwg.org/#scheme-state
+// The scheme of an absolute URL, `None` for a relative one.
+fn url_scheme(url : &str) -> Option<&str> {
+    let (scheme, _) = url.split_once(':')?;
+    let mut chars = scheme.chars();
+    let starts_with_letter = chars.next().is_some_and(|c| c.is_ascii_alphabetic());
+    match starts_with_letter && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')) {
+        true => Some(scheme),
+        // Something like `/a:b` or `?x=a:b` is a relative URL. Anything else that has a colon in it, like
+        // `1:x` or `a b:c`, isn't a valid URL at all.
+        false => match scheme.contains(['/', '?', '#']) {
+            true => None,
+            false => Some(scheme),
+        },
+    }
+}
+
+/// Parse `html` as the content of a `<body>` and serialize what `policy` allows of it. Input that goes
+/// over [`ParserLimits::untrusted`] is rejected.
+pub fn sanitize(html : &str, policy : &SanitizePolicy) -> Result<String, Box<dyn std::error::Error>> {
+    // The <body> start tag makes the parser put everything in the body, even elements like <style> that it
+    // would move into the head at the start of a document.
+    let input = format!("<body>{}", html);
+    let mut tree = HtmlParser::parse(&input, ParseState::new().with_limits(ParserLimits::untrusted()))?;
+    let body = tree.descendants(tree.root()).find(|id| matches!(tree.get(*id), Some(Node::Element(element)) if element.kind == ElementKind::Body));
+    let Some(body) = body else {
+        return Ok(String::new());
+    };
+    sanitize_tree(&mut tree, body, policy);
+    Ok(serializer::serialize_children(&tree, body, &SerializeOptions::default()))
+}
+
+/// Remove everything below `node` that `policy` doesn't allow. `node` itself is kept as it is.
+pub fn sanitize_tree(tree : &mut Tree<Node>, node : NodeId, policy : &SanitizePolicy) {
+    // Children are checked before their parent is done with, so that unwrapped children can be checked in
+    // the place they were moved to. Nothing is recursive, the tree can be arbitrarily deep.
+    let mut pending : Vec<NodeId> = tree.children(node).rev().collect();
+    while let Some(id) = pending.pop() {
+        let keep = match tree.get_mut(id) {
+            Some(Node::Text(_)) => true,
+            Some(Node::Comment(_)) => policy.comments,
+            Some(Node::Element(element)) if policy.allows_element(element) => {
+                let name = element.name().to_string();
+                element.attributes.retain(|attribute| policy.allows_attribute(&name, &attribute.name, &attribute.value));
+                pending.extend(tree.children(id).rev());
+                true
+            },
+            Some(Node::Element(element)) if policy.disallowed == Disallowed::Unwrap && !is_dropped_with_content(&element.kind) => {
+                let children : Vec<NodeId> = tree.children(id).collect();
+                for child in &children {
+                    let _ = tree.insert_before(id, *child);
+                }
+                pending.extend(children.into_iter().rev());
+                false
+            },
+            _ => false,
+        };
+        if !keep {
+            let _ = tree.remove(id);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Event based parsing.
+//!
+//! Instead of building a tree, the tree builder reports what it does to an [`EventHandler`]. The
+//! events include the elements that the tree construction rules insert or close implicitly, such as
+//! an implied `<tbody>` or a `<p>` that gets closed by the next block element. Only the stack of open
+//! elements is kept in memory, so together with [`parse_reader`] documents of any size can be processed.
+//!
+//! ```
+//! use html_parser::{sax::{self, EventHandler}, Element};
+//!
+//! #[derive(Default)]
+//! struct CountParagraphs(usize);
+//!
+//! impl EventHandler for CountParagraphs {
+//!     fn start_element(&mut self, element: &Element) {
+//!         if element.name() == "p" {
+//!             self.0 += 1;
+//!         }
+//!     }
+//! }
+//!
+//! let counter = sax::parse_str("<p>One<p>Two", CountParagraphs::default()).unwrap();
+//! assert_eq!(counter.0, 2);
+//! ```
+
+use std::io::Read;
+
+use crate::{dom::{Element, ElementKind}, sink::TreeSink, HtmlParser, ParseState};
+
+// Text is handed to the handler in pieces of at most this many bytes.
+const TEXT_BUFFER_SIZE : usize = 8 * 1024;
+
+/// Callbacks for the events produced while parsing. Every method does nothing by default.
+pub trait EventHandler {
+    fn start_element(&mut self, _element : &Element) {}
+
+    /// Called when an element is closed, whether by an end tag or implicitly.
+    fn end_element(&mut self, _kind : &ElementKind) {}
+
+    /// Consecutive text is usually reported in one call, but long runs of text can be split up.
+    fn text(&mut self, _text : &str) {}
+
+    fn comment(&mut self, _text : &str) {}
+
+    fn doctype(&mut self, _name : Option<&str>, _public_id : Option<&str>, _system_id : Option<&str>) {}
+}
+
+/// A [`TreeSink`] that forwards everything to an [`EventHandler`] instead of storing it.
+///
+/// Events are reported as the tree builder inserts nodes, which is in document order except for a few cases
+/// where it adds to an element that has already ended :
+/// - Elements such as `<meta>` that come after `</head>` belong in the head, but are reported after its end.
+/// - Comments after `</body>` belong at the end of `<html>`, but are reported before the elements that are
+///   still open end.
+/// - Past [`crate::limits::ParserLimits::max_depth`], an element ends as soon as the next one starts, and
+///   the elements that would be nested deeper are reported as its siblings.
+///
+/// NOTE: Attributes from a duplicate `<html>` or `<body>` tag are dropped.
+pub struct EventSink<H : EventHandler> {
+    handler : H,
+    text : String,
+}
+
+impl<H : EventHandler> EventSink<H> {
+    pub fn new(handler : H) -> Self {
+        Self {
+            handler,
+            text : String::new(),
+        }
+    }
+
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            self.handler.text(&self.text);
+            self.text.clear();
+        }
+    }
+}
+
+impl<H : EventHandler> TreeSink for EventSink<H> {
+    type Handle = ();
+    type Output = H;
+
+    fn document(&self) {}
+
+    fn append_element(&mut self, _parent : &(), element : Element) {
+        self.flush_text();
+        self.handler.start_element(&element);
+    }
+
+    fn append_text(&mut self, _parent : &(), text : &str) {
+        self.text.push_str(text);
+        if self.text.len() >= TEXT_BUFFER_SIZE {
+            self.flush_text();
+        }
+    }
+
+    fn append_comment(&mut self, _parent : &(), text : &str) {
+        self.flush_text();
+        self.handler.comment(text);
+    }
+
+    fn append_doctype(&mut self, name : Option<&str>, public_id : Option<&str>, system_id : Option<&str>) {
+        self.flush_text();
+        self.handler.doctype(name, public_id, system_id);
+    }
+
+    fn pop(&mut self, _handle : &(), kind : &ElementKind) {
+        self.flush_text();
+        self.handler.end_element(kind);
+    }
+
+    fn finish(mut self) -> H {
+        self.flush_text();
+        self.handler
+    }
+}
+
+/// Parse `input`, reporting events to `handler`. The handler is returned once parsing has finished.
+pub fn parse_str<H : EventHandler>(input : &str, handler : H) -> Result<H, Box<dyn std::error::Error>> {
+    HtmlParser::parse(input, ParseState::with_sink(EventSink::new(handler)))
+}
+
+/// Parse a document read in chunks from `reader`, reporting events to `handler`.
+pub fn parse_reader<R : Read, H : EventHandler>(reader : R, handler : H) -> Result<H, Box<dyn std::error::Error>> {
+    HtmlParser::parse_stream(reader, ParseState::with_sink(EventSink::new(handler)))
+}
@@ -0,0 +1,362 @@
+//! Turning a tree back into markup.
+//!
+//! This is the [HTML fragment serialization algorithm](https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments),
+//! so parsing the output of [`to_html`] gives back the same tree. With [`SerializeOptions::pretty`] the
+//! output is indented instead, which only ever adds or removes whitespace between elements where it
+//! doesn't change how the document renders.
+//!
+//! The parser doesn't implement the rules for foreign content, so every element is stored with its
+//! lower case HTML name. The serializer works out which elements are SVG or MathML from where they are in
+//! the tree, the way the parser would have, and writes their names in the case those namespaces use
+//! (`clipPath`, `viewBox`, `definitionURL`, ...).
+//!
+//! The contents of a `<template>` are stored as its children, so they are serialized like any other
+//! element's.
+
+use dds_utils::tree::Edge;
+
+use crate::{dom::{ElementKind, Node, HTML_NAMESPACE, MATHML_NAMESPACE, SVG_NAMESPACE}, NodeId, Tree};
+
+/// How [`serialize_children`] and [`serialize_node`] lay out their output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SerializeOptions {
+    /// The number of spaces to indent every level by. `None` writes the tree exactly as it is, without
+    /// adding any whitespace.
+    pub indent : Option<usize>,
+}
+
+impl SerializeOptions {
+    /// Every block is put on its own line and indented by `indent` spaces per level. Text and inline
+    /// elements stay on the line of the block they are in, and so does the content of `<pre>`, `<textarea>`
+    /// and the elements with raw text.
+    pub fn pretty(indent : usize) -> Self {
+        Self { indent : Some(indent) }
+    }
+}
+
+/// Serialize a whole document.
+pub fn to_html(tree : &Tree<Node>) -> String {
+    serialize_children(tree, tree.root(), &SerializeOptions::default())
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+/// Serialize the children of `node`, which is what `innerHTML` returns. Empty if the node isn't in the tree.
+pub fn serialize_children(tree : &Tree<Node>, node : NodeId, options : &SerializeOptions) -> String {
+    let mut serializer = Serializer::new(tree, options);
+    serializer.run(node, false);
+    serializer.output
+}
+
+/// Serialize `node` along with its children, which is what `outerHTML` returns.
+pub fn serialize_node(tree : &Tree<Node>, node : NodeId, options : &SerializeOptions) -> String {
+    let mut serializer = Serializer::new(tree, options);
+    serializer.run(node, true);
+    serializer.output
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#escapingString
+/// Escape text for use outside of an attribute.
+pub fn escape_text(text : &str, output : &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '\u{A0}' => output.push_str("&nbsp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            c => output.push(c),
+        }
+    }
+}
+
+/// Escape text for use in a double quoted attribute value. `<` and `>` are escaped as well, so that a value
+/// can never be mistaken for markup if the document is later parsed in a different context.
+pub fn escape_attribute(value : &str, output : &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '\u{A0}' => output.push_str("&nbsp;"),
+            '"' => output.push_str("&quot;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            c => output.push(c),
+        }
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#tree-construction
+/// The namespace an element with this name would be in, given the namespace and name of its parent. This
+/// follows where the parser switches between HTML and foreign content.
+pub fn namespace_in(parent_namespace : &'static str, parent_name : &str, name : &str) -> &'static str {
+    match parent_namespace {
+        // https://html.spec.whatwg.org/multipage/parsing.html#html-integration-point
+        SVG_NAMESPACE if !matches!(parent_name, "foreignobject" | "desc" | "title") => SVG_NAMESPACE,
+        MATHML_NAMESPACE if parent_name == "annotation-xml" && name == "svg" => SVG_NAMESPACE,
+        // https://html.spec.whatwg.org/multipage/parsing.html#mathml-text-integration-point
+        MATHML_NAMESPACE if !matches!(parent_name, "mi" | "mo" | "mn" | "ms" | "mtext") || matches!(name, "mglyph" | "malignmark") => MATHML_NAMESPACE,
+        _ => match name {
+            "svg" => SVG_NAMESPACE,
+            "math" => MATHML_NAMESPACE,
+            _ => HTML_NAMESPACE,
+        },
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#serializes-as-void
+fn is_void(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    matches!(kind, Area | Base | Basefont | Bgsound | Br | Col | Embed | Frame | Hr | Img | Input | Keygen | Link | Meta | Param | Source | Track | Wbr)
+}
+
+// The children of these are written out as they are, without escaping.
+fn has_raw_text(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    matches!(kind, Style | Script | Xmp | Iframe | Noembed | Noframes | Plaintext)
+}
+
+// The parser drops a newline right after the start tag of these, so one has to be added back.
+fn skips_leading_newline(kind : &ElementKind) -> bool {
+    matches!(kind, ElementKind::Pre | ElementKind::Textarea | ElementKind::Listing)
+}
+
+// Whitespace around these changes how the document renders, so pretty printing leaves them on the line
+// they are on.
+fn is_inline(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    matches!(kind,
+        A | Abbr | B | Bdi | Bdo | Big | Br | Button | Cite | Code | Data | Del | Dfn | Em | Font | I | Img
+        | Input | Ins | Kbd | Label | Map | Mark | Meter | Nobr | Output | Progress | Q | Ruby | S | Samp
+        | Select | Small | Span | Strike | Strong | Sub | Sup | Textarea | Time | Tt | U | Var | Wbr)
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes
+// The names in foreign content that aren't all lower case. The parser lower cases every name, so they are
+// looked up without regard to case.
+const SVG_TAG_NAMES : &[&str] = &[
+    "altGlyph", "altGlyphDef", "altGlyphItem", "animateColor", "animateMotion", "animateTransform", "clipPath",
+    "feBlend", "feColorMatrix", "feComponentTransfer", "feComposite", "feConvolveMatrix", "feDiffuseLighting",
+    "feDisplacementMap", "feDistantLight", "feDropShadow", "feFlood", "feFuncA", "feFuncB", "feFuncG", "feFuncR",
+    "feGaussianBlur", "feImage", "feMerge", "feMergeNode", "feMorphology", "feOffset", "fePointLight",
+    "feSpecularLighting", "feSpotLight", "feTile", "feTurbulence", "foreignObject", "glyphRef", "linearGradient",
+    "radialGradient", "textPath",
+];
+
+const SVG_ATTRIBUTE_NAMES : &[&str] = &[
+    "attributeName", "attributeType", "baseFrequency", "baseProfile", "calcMode", "clipPathUnits", "diffuseConstant",
+    "edgeMode", "filterUnits", "glyphRef", "gradientTransform", "gradientUnits", "kernelMatrix", "kernelUnitLength",
+    "keyPoints", "keySplines", "keyTimes", "lengthAdjust", "limitingConeAngle", "markerHeight", "markerUnits",
+    "markerWidth", "maskContentUnits", "maskUnits", "numOctaves", "pathLength", "patternContentUnits",
+    "patternTransform", "patternUnits", "pointsAtX", "pointsAtY", "pointsAtZ", "preserveAlpha", "preserveAspectRatio",
+    "primitiveUnits", "refX", "refY", "repeatCount", "repeatDur", "requiredExtensions", "requiredFeatures",
+    "specularConstant", "specularExponent", "spreadMethod", "startOffset", "stdDeviation", "stitchTiles",
+    "surfaceScale", "systemLanguage", "tableValues", "targetX", "targetY", "textLength", "viewBox", "viewTarget",
+    "xChannelSelector", "yChannelSelector", "zoomAndPan",
+];
+
+fn adjusted<'a>(name : &'a str, names : &[&'static str]) -> &'a str {
+    names.iter().find(|adjusted| adjusted.eq_ignore_ascii_case(name)).copied().unwrap_or(name)
+}
+
+fn tag_name<'a>(name : &'a str, namespace : &str) -> &'a str {
+    match namespace {
+        SVG_NAMESPACE => adjusted(name, SVG_TAG_NAMES),
+        _ => name,
+    }
+}
+
+fn attribute_name<'a>(name : &'a str, namespace : &str) -> &'a str {
+    match namespace {
+        SVG_NAMESPACE => adjusted(name, SVG_ATTRIBUTE_NAMES),
+        MATHML_NAMESPACE if name == "definitionurl" => "definitionURL",
+        _ => name,
+    }
+}
+
+// What the serializer needs to remember about an element while it writes its children.
+struct Frame {
+    namespace : &'static str,
+    name : String,
+    raw_text : bool,
+    // Whether the children are put on lines of their own, when pretty printing.
+    layout : bool,
+    has_children : bool,
+}
+
+struct Serializer<'a> {
+    tree : &'a Tree<Node>,
+    indent : Option<usize>,
+    output : String,
+    stack : Vec<Frame>,
+}
+
+impl<'a> Serializer<'a> {
+    fn new(tree : &'a Tree<Node>, options : &SerializeOptions) -> Self {
+        Self { tree, indent : options.indent, output : String::new(), stack : Vec::new() }
+    }
+
+    // The tree is walked without recursion, documents can be nested deeper than the stack allows.
+    fn run(&mut self, node : NodeId, include_node : bool) {
+        let Some(value) = self.tree.get(node) else {
+            return;
+        };
+        // There is nothing to write for the document node itself.
+        let include_node = include_node && !matches!(value, Node::Document);
+        // The node the serialization starts from is a context for its children, when it isn't written itself.
+        let context = match include_node {
+            true => self.tree.parent(node),
+            false => Some(node),
+        };
+        if let Some(context) = context {
+            let frame = self.frame(context, self.context_namespace(context));
+            self.stack.push(frame);
+        }
+
+        let mut skip = None;
+        for edge in self.tree.traverse(node) {
+            // The children of void elements can't be written, the parser would make them siblings.
+            if let Some(void) = skip {
+                if edge == Edge::Close(void) {
+                    skip = None;
+                    self.stack.pop();
+                }
+                continue;
+            }
+            match edge {
+                Edge::Open(id) | Edge::Close(id) if id == node && !include_node => (),
+                Edge::Open(id) => {
+                    if self.open(id) {
+                        skip = Some(id);
+                    }
+                },
+                Edge::Close(id) => self.close(id),
+            }
+        }
+    }
+
+    // The namespace of `id`, from the path to it. The document node counts as HTML.
+    fn context_namespace(&self, id : NodeId) -> &'static str {
+        let mut path : Vec<NodeId> = self.tree.ancestors(id).collect();
+        path.reverse();
+        path.push(id);
+        let mut namespace = HTML_NAMESPACE;
+        let mut parent = "";
+        for id in path {
+            if let Some(Node::Element(element)) = self.tree.get(id) {
+                namespace = namespace_in(namespace, parent, element.name());
+                parent = element.name();
+            }
+        }
+        namespace
+    }
+
+    fn frame(&self, id : NodeId, namespace : &'static str) -> Frame {
+        let (name, raw_text, pretty) = match self.tree.get(id) {
+            Some(Node::Element(element)) => {
+                let html = namespace == HTML_NAMESPACE;
+                let raw_text = html && has_raw_text(&element.kind);
+                let pretty = !raw_text && !(html && (skips_leading_newline(&element.kind) || is_inline(&element.kind)));
+                (element.name().to_string(), raw_text, pretty)
+            },
+            _ => (String::new(), false, true),
+        };
+        let layout = pretty && self.indent.is_some() && self.tree.children(id).all(|child| match self.tree.get(child) {
+            Some(Node::Text(text)) => text.chars().all(|c| c.is_ascii_whitespace()),
+            Some(Node::Element(child)) => namespace_in(namespace, &name, child.name()) != HTML_NAMESPACE || !is_inline(&child.kind),
+            _ => true,
+        });
+        Frame { namespace, name, raw_text, layout, has_children : false }
+    }
+
+    fn new_line(&mut self, depth : usize) {
+        if let Some(indent) = self.indent {
+            self.output.push('\n');
+            self.output.extend(std::iter::repeat_n(' ', indent * depth));
+        }
+    }
+
+    // Writes the start of a node, returns whether its children have to be skipped.
+    fn open(&mut self, id : NodeId) -> bool {
+        let Some(node) = self.tree.get(id) else {
+            return false;
+        };
+        let (parent_namespace, parent_name) = match self.stack.last() {
+            Some(frame) => (frame.namespace, frame.name.as_str()),
+            None => (HTML_NAMESPACE, ""),
+        };
+        let namespace = match node {
+            Node::Element(element) => namespace_in(parent_namespace, parent_name, element.name()),
+            _ => HTML_NAMESPACE,
+        };
+        let raw_text = self.stack.last().is_some_and(|frame| frame.raw_text);
+        let layout = self.stack.last().is_some_and(|frame| frame.layout);
+        if layout {
+            // Whitespace between blocks is replaced by the indentation.
+            if let Node::Text(text) = node {
+                if text.chars().all(|c| c.is_ascii_whitespace()) {
+                    return false;
+                }
+            }
+            if !self.output.is_empty() {
+                self.new_line(self.stack.len() - 1);
+            }
+        }
+        if let Some(frame) = self.stack.last_mut() {
+            frame.has_children = true;
+        }
+
+        match node {
+            Node::Document => (),
+            Node::Doctype { name, .. } => {
+                self.output.push_str("<!DOCTYPE ");
+                self.output.push_str(name.as_deref().unwrap_or(""));
+                self.output.push('>');
+            },
+            Node::Text(text) if raw_text => self.output.push_str(text),
+            Node::Text(text) => escape_text(text, &mut self.output),
+            Node::Comment(text) => {
+                self.output.push_str("<!--");
+                self.output.push_str(text);
+                self.output.push_str("-->");
+            },
+            Node::Element(element) => {
+                self.output.push('<');
+                self.output.push_str(tag_name(element.name(), namespace));
+                for attribute in &element.attributes {
+                    self.output.push(' ');
+                    self.output.push_str(attribute_name(&attribute.name, namespace));
+                    self.output.push_str("=\"");
+                    escape_attribute(&attribute.value, &mut self.output);
+                    self.output.push('"');
+                }
+                self.output.push('>');
+
+                let frame = self.frame(id, namespace);
+                self.stack.push(frame);
+                if namespace == HTML_NAMESPACE {
+                    if is_void(&element.kind) {
+                        return true;
+                    }
+                    let first_child = self.tree.first_child(id).and_then(|child| self.tree.get(child));
+                    if skips_leading_newline(&element.kind) && matches!(first_child, Some(Node::Text(text)) if text.starts_with('\n')) {
+                        self.output.push('\n');
+                    }
+                }
+            },
+        }
+        false
+    }
+
+    fn close(&mut self, id : NodeId) {
+        let Some(Node::Element(element)) = self.tree.get(id) else {
+            return;
+        };
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        if frame.layout && frame.has_children {
+            self.new_line(self.stack.len().saturating_sub(1));
+        }
+        self.output.push_str("</");
+        self.output.push_str(tag_name(element.name(), frame.namespace));
+        self.output.push('>');
+    }
+}
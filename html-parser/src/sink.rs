@@ -0,0 +1,312 @@
+//! The abstraction the tree builder constructs a document through.
+//!
+//! The insertion modes in [`crate::parser`] only ever talk to a [`TreeSink`], so the same parsing
+//! code can build an arena backed DOM, stream events to a handler (see [`crate::sax`]) or fill in
+//! any other data structure that implements the trait.
+
+use std::collections::HashMap;
+
+use dds_utils::tree::{persistent::PersistentTree, NodeId, Tree};
+
+use crate::{dom::{Element, ElementKind, Node}, tokenizer::{Attribute, SourcePosition}};
+
+pub trait TreeSink {
+    /// A reference to a node previously created by the sink.
+    type Handle : Clone;
+    /// The value handed back to the caller once parsing has finished.
+    type Output;
+
+    /// The root node that everything else gets appended to.
+    fn document(&self) -> Self::Handle;
+
+    /// Create `element` and append it as the last child of `parent`.
+    fn append_element(&mut self, parent : &Self::Handle, element : Element) -> Self::Handle;
+
+    /// Append text to `parent`. Consecutive calls should be merged into a single text node.
+    fn append_text(&mut self, parent : &Self::Handle, text : &str);
+
+    fn append_comment(&mut self, parent : &Self::Handle, text : &str);
+
+    fn append_doctype(&mut self, name : Option<&str>, public_id : Option<&str>, system_id : Option<&str>);
+
+    /// Add any attributes that `handle` doesn't have yet. Used when a document contains a second
+    /// `<html>` or `<body>` tag.
+    fn add_attributes(&mut self, _handle : &Self::Handle, _attributes : Vec<Attribute>) {}
+
+    /// Called whenever the tree builder pops an element off the stack of open elements.
+    fn pop(&mut self, _handle : &Self::Handle, _kind : &ElementKind) {}
+
+    /// Called with where each token starts in the input, before the tree builder processes it. The
+    /// nodes created until the next call come from that token, including the ones the tree builder
+    /// inserts on its own like an implied `<body>`.
+    fn set_source_position(&mut self, _position : SourcePosition) {}
+
+    fn finish(self) -> Self::Output;
+}
+
+/// Builds the DOM into a [`Tree`]. The document node is the root of the tree.
+#[derive(Clone)]
+pub struct ArenaSink {
+    tree : Tree<Node>,
+}
+
+impl ArenaSink {
+    pub fn new() -> Self {
+        Self { tree : Tree::new(Node::Document) }
+    }
+
+    // Continue building a tree that was built by an ArenaSink before.
+    pub(crate) fn from_tree(tree : Tree<Node>) -> Self {
+        Self { tree }
+    }
+
+    pub(crate) fn tree(&self) -> &Tree<Node> {
+        &self.tree
+    }
+
+    // Appending a node that was just created to a handle given out by this sink can't fail.
+    fn append(&mut self, parent : &NodeId, node : Node) -> NodeId {
+        let id = self.tree.new_node(node);
+        let _ = self.tree.append(*parent, id);
+        id
+    }
+}
+
+impl Default for ArenaSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TreeSink for ArenaSink {
+    type Handle = NodeId;
+    type Output = Tree<Node>;
+
+    fn document(&self) -> NodeId {
+        self.tree.root()
+    }
+
+    fn append_element(&mut self, parent : &NodeId, element : Element) -> NodeId {
+        self.append(parent, Node::Element(element))
+    }
+
+    fn append_text(&mut self, parent : &NodeId, text : &str) {
+        if let Some(last) = self.tree.last_child(*parent) {
+            if let Some(Node::Text(existing)) = self.tree.get_mut(last) {
+                existing.push_str(text);
+                return;
+            }
+        }
+        self.append(parent, Node::Text(text.to_string()));
+    }
+
+    fn append_comment(&mut self, parent : &NodeId, text : &str) {
+        self.append(parent, Node::Comment(text.to_string()));
+    }
+
+    fn append_doctype(&mut self, name : Option<&str>, public_id : Option<&str>, system_id : Option<&str>) {
+        let document = self.tree.root();
+        self.append(&document, Node::Doctype {
+            name : name.map(String::from),
+            public_id : public_id.map(String::from),
+            system_id : system_id.map(String::from),
+        });
+    }
+
+    fn add_attributes(&mut self, handle : &NodeId, attributes : Vec<Attribute>) {
+        if let Some(Node::Element(element)) = self.tree.get_mut(*handle) {
+            for attribute in attributes {
+                if element.attribute(&attribute.name).is_none() {
+                    element.attributes.push(attribute);
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> Tree<Node> {
+        self.tree
+    }
+}
+
+/// An [`ArenaSink`] that also records where in the input every node starts. Text nodes start with their
+/// first character, elements with their start tag.
+#[derive(Clone)]
+pub struct PositionSink {
+    arena : ArenaSink,
+    position : SourcePosition,
+    positions : HashMap<NodeId, SourcePosition>,
+}
+
+impl PositionSink {
+    pub fn new() -> Self {
+        Self { arena : ArenaSink::new(), position : SourcePosition::default(), positions : HashMap::new() }
+    }
+
+    // Continue building a tree that was built by a PositionSink before.
+    pub(crate) fn from_parts(tree : Tree<Node>, positions : HashMap<NodeId, SourcePosition>) -> Self {
+        Self { arena : ArenaSink { tree }, position : SourcePosition::default(), positions }
+    }
+
+    pub(crate) fn tree(&self) -> &Tree<Node> {
+        &self.arena.tree
+    }
+}
+
+impl Default for PositionSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TreeSink for PositionSink {
+    type Handle = NodeId;
+    type Output = (Tree<Node>, HashMap<NodeId, SourcePosition>);
+
+    fn document(&self) -> NodeId {
+        self.arena.document()
+    }
+
+    fn append_element(&mut self, parent : &NodeId, element : Element) -> NodeId {
+        let id = self.arena.append_element(parent, element);
+        self.positions.insert(id, self.position);
+        id
+    }
+
+    fn append_text(&mut self, parent : &NodeId, text : &str) {
+        let last = self.arena.tree.last_child(*parent);
+        self.arena.append_text(parent, text);
+        // Text that was merged into the previous text node doesn't change where that node starts.
+        if let Some(id) = self.arena.tree.last_child(*parent).filter(|id| Some(*id) != last) {
+            self.positions.insert(id, self.position);
+        }
+    }
+
+    fn append_comment(&mut self, parent : &NodeId, text : &str) {
+        self.arena.append_comment(parent, text);
+        if let Some(id) = self.arena.tree.last_child(*parent) {
+            self.positions.insert(id, self.position);
+        }
+    }
+
+    fn append_doctype(&mut self, name : Option<&str>, public_id : Option<&str>, system_id : Option<&str>) {
+        self.arena.append_doctype(name, public_id, system_id);
+        if let Some(id) = self.arena.tree.last_child(self.arena.tree.root()) {
+            self.positions.insert(id, self.position);
+        }
+    }
+
+    fn add_attributes(&mut self, handle : &NodeId, attributes : Vec<Attribute>) {
+        self.arena.add_attributes(handle, attributes);
+    }
+
+    fn set_source_position(&mut self, position : SourcePosition) {
+        self.position = position;
+    }
+
+    fn finish(self) -> Self::Output {
+        (self.arena.finish(), self.positions)
+    }
+}
+
+/// Builds the DOM into a [`PersistentTree`], whose snapshots can be shared with other threads.
+///
+/// Nodes are addressed by their path from the document. The tree builder only ever appends, so the path
+/// of a node doesn't change once it has been created.
+#[derive(Clone)]
+pub struct PersistentSink {
+    tree : PersistentTree<Node>,
+    // The text being added to the end of a node along with the path of that node. It only goes into the
+    // tree once something else happens, so that text coming one character at a time doesn't copy the whole
+    // path to the node for every character.
+    text : Option<(Vec<usize>, String)>,
+}
+
+impl PersistentSink {
+    pub fn new() -> Self {
+        Self { tree : PersistentTree::new(Node::Document), text : None }
+    }
+
+    // Put the pending text into the tree, merged with the text node that ends its parent if there is one.
+    fn flush_text(&mut self) {
+        let Some((parent, text)) = self.text.take() else {
+            return;
+        };
+        let last = self.tree.get(&parent).and_then(|node| node.children().len().checked_sub(1));
+        if let Some(last) = last {
+            let mut path = parent.clone();
+            path.push(last);
+            if let Some(Node::Text(existing)) = self.tree.get_mut(&path) {
+                existing.push_str(&text);
+                return;
+            }
+        }
+        self.append(&parent, Node::Text(text));
+    }
+
+    // The parent is a path given out by this sink, so appending to it can't fail.
+    fn append(&mut self, parent : &[usize], node : Node) -> Vec<usize> {
+        self.flush_text();
+        let mut path = parent.to_vec();
+        if let Some(index) = self.tree.append(parent, node) {
+            path.push(index);
+        }
+        path
+    }
+}
+
+impl Default for PersistentSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TreeSink for PersistentSink {
+    type Handle = Vec<usize>;
+    type Output = PersistentTree<Node>;
+
+    fn document(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    fn append_element(&mut self, parent : &Vec<usize>, element : Element) -> Vec<usize> {
+        self.append(parent, Node::Element(element))
+    }
+
+    fn append_text(&mut self, parent : &Vec<usize>, text : &str) {
+        match &mut self.text {
+            Some((pending_parent, pending)) if pending_parent == parent => pending.push_str(text),
+            _ => {
+                self.flush_text();
+                self.text = Some((parent.clone(), text.to_string()));
+            },
+        }
+    }
+
+    fn append_comment(&mut self, parent : &Vec<usize>, text : &str) {
+        self.append(parent, Node::Comment(text.to_string()));
+    }
+
+    fn append_doctype(&mut self, name : Option<&str>, public_id : Option<&str>, system_id : Option<&str>) {
+        self.append(&[], Node::Doctype {
+            name : name.map(String::from),
+            public_id : public_id.map(String::from),
+            system_id : system_id.map(String::from),
+        });
+    }
+
+    fn add_attributes(&mut self, handle : &Vec<usize>, attributes : Vec<Attribute>) {
+        self.flush_text();
+        if let Some(Node::Element(element)) = self.tree.get_mut(handle) {
+            for attribute in attributes {
+                if element.attribute(&attribute.name).is_none() {
+                    element.attributes.push(attribute);
+                }
+            }
+        }
+    }
+
+    fn finish(mut self) -> PersistentTree<Node> {
+        self.flush_text();
+        self.tree
+    }
+}
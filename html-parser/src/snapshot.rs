@@ -0,0 +1,235 @@
+//! Saving the state of the parser, to carry on with a parse later.
+//!
+//! [`Tokenizer`] and [`ParseState`] are `Clone`, so going back to an earlier state in memory, e.g. after
+//! parsing ahead speculatively, only takes a copy of both. To resume a long parse after the process
+//! restarts, [`save`] writes them to a file that [`load`] reads back, and [`crate::HtmlParser::resume_stream`]
+//! carries on from there with the rest of the input.
+//!
+//! Everything the parser needs to carry on is saved : the state and return state of the tokenizer along
+//! with the buffers of the token it is in the middle of, the input it hasn't tokenized yet, the insertion
+//! modes, the stack of open elements, the head and form element pointers and the document built so far.
+//! Parse errors that were recorded but not taken yet aren't saved.
+//!
+//! This tree builder doesn't keep a list of active formatting elements, so there is none to save, and a
+//! state can be saved at any checkpoint, even with a `<b>` or `<a>` open. Once the tree builder keeps that
+//! list, snapshots will have to hold it too, and the ones saved before then won't load anymore.
+//!
+//! The file has two lines : the state of the tokenizer and the tree builder as a JSON object, followed by
+//! the document as written by [`crate::json::to_json`]. Elements are referred to by their index in
+//! document order, starting with the document itself at 0. Only a [`ParseState`] that builds into an
+//! [`ArenaSink`] can be saved.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use miniserde::json::{self, Array, Number, Object, Value};
+
+use crate::{error::HtmlParseError, json::{from_json, to_json}, limits::ParserLimits, sink::ArenaSink,
+    states::{InsertionMode, TokenizationState}, tokenizer::{SourcePosition, Tokenizer}, Node, NodeId,
+    OpenElement, ParseState, Tree};
+
+/// Write the state of a parse, see the [module documentation](self).
+///
+/// This is meant to be called in between two chunks of input, once the tree builder has processed every
+/// token the tokenizer could produce, which is how [`crate::HtmlParser::resume_stream`] calls its checkpoint.
+pub fn to_string(tokens : &Tokenizer, state : &ParseState) -> Result<String, HtmlParseError> {
+    let tree = state.sink.tree();
+    let indices : HashMap<NodeId, usize> = std::iter::once(tree.root()).chain(tree.descendants(tree.root()))
+        .enumerate().map(|(index, id)| (id, index)).collect();
+    let index = |id : &NodeId| indices.get(id).map(|index| number(*index))
+        .ok_or(HtmlParseError::InvalidSnapshot("an element of the parser isn't in the document"));
+    let optional_index = |id : &Option<NodeId>| id.as_ref().map_or(Ok(Value::Null), index);
+    if state.reconsume {
+        return Err(HtmlParseError::InvalidSnapshot("the tree builder is in the middle of a token"));
+    }
+
+    let mut object = Object::new();
+    object.insert("tokenizer".to_string(), tokens.to_json()?);
+    object.insert("mode".to_string(), name(&state.mode));
+    object.insert("original_mode".to_string(), name(&state.original_mode));
+    object.insert("open_elements".to_string(), Value::Array(state.open_elements.iter()
+        .map(|element| index(&element.handle)).collect::<Result<Array, _>>()?));
+    object.insert("head_pointer".to_string(), optional_index(&state.head_pointer)?);
+    object.insert("form_pointer".to_string(), optional_index(&state.form_pointer)?);
+    object.insert("frame_set_ok".to_string(), Value::Bool(state.frame_set_ok));
+    object.insert("skip_newline".to_string(), Value::Bool(state.skip_newline));
+    object.insert("pending_table_text".to_string(), Value::String(state.pending_table_text.clone()));
+    object.insert("tokenizer_state".to_string(), state.tokenizer_state.as_ref().map_or(Value::Null, name));
+    object.insert("limits".to_string(), limits(&state.limits));
+    object.insert("node_count".to_string(), number(state.node_count));
+    object.insert("in_text".to_string(), Value::Bool(state.in_text));
+    object.insert("position".to_string(), position(&state.position));
+    object.insert("record_errors".to_string(), Value::Bool(state.record_errors));
+    Ok(format!("{}\n{}", json::to_string(&Value::Object(object)), to_json(tree)))
+}
+
+/// Read a state written by [`to_string`].
+pub fn from_str(text : &str) -> Result<(Tokenizer, ParseState), HtmlParseError> {
+    let invalid = HtmlParseError::InvalidSnapshot;
+    let (state_line, tree_line) = text.split_once('\n').ok_or(invalid("the document is missing"))?;
+    let value : Value = json::from_str(state_line).map_err(|_| invalid("the state isn't valid JSON"))?;
+    let tree = from_json(tree_line.trim_end()).map_err(|_| invalid("the document isn't valid JSON"))?;
+    let nodes : Vec<NodeId> = std::iter::once(tree.root()).chain(tree.descendants(tree.root())).collect();
+    let element = |value : &Value| -> Result<NodeId, HtmlParseError> {
+        let index = as_usize(value).ok_or(invalid("an element index isn't a number"))?;
+        nodes.get(index).copied().filter(|id| matches!(tree.get(*id), Some(Node::Element(_))))
+            .ok_or(invalid("an element index doesn't refer to an element"))
+    };
+    let optional_element = |value : &Value| match value {
+        Value::Null => Ok(None),
+        value => element(value).map(Some),
+    };
+
+    let fields = Fields::new(&value)?;
+    let tokens = Tokenizer::from_json(fields.get("tokenizer")?)?;
+    let mut open_elements = Vec::new();
+    for value in fields.array("open_elements")? {
+        let handle = element(value)?;
+        let Some(Node::Element(node)) = tree.get(handle) else {
+            return Err(invalid("an element index doesn't refer to an element"));
+        };
+        open_elements.push(OpenElement { handle, kind : node.kind.clone() });
+    }
+    let head_pointer = optional_element(fields.get("head_pointer")?)?;
+    let form_pointer = optional_element(fields.get("form_pointer")?)?;
+    let mut state = ParseState::with_sink(ArenaSink::from_tree(tree));
+    state.mode = fields.named("mode", &InsertionMode::ALL)?;
+    state.original_mode = fields.named("original_mode", &InsertionMode::ALL)?;
+    state.open_elements = open_elements;
+    state.head_pointer = head_pointer;
+    state.form_pointer = form_pointer;
+    state.frame_set_ok = fields.bool("frame_set_ok")?;
+    state.skip_newline = fields.bool("skip_newline")?;
+    state.pending_table_text = fields.string("pending_table_text")?;
+    state.tokenizer_state = match fields.get("tokenizer_state")? {
+        Value::Null => None,
+        _ => Some(fields.named("tokenizer_state", &TokenizationState::ALL)?),
+    };
+    state.limits = read_limits(fields.get("limits")?)?;
+    state.node_count = fields.usize("node_count")?;
+    state.in_text = fields.bool("in_text")?;
+    state.position = read_position(fields.get("position")?)?;
+    state.record_errors = fields.bool("record_errors")?;
+    Ok((tokens, state))
+}
+
+/// Save the state of a parse to `path`, see [`to_string`]. The file is replaced in one go, so that a
+/// crash while saving leaves the previous state behind.
+pub fn save(path : impl AsRef<Path>, tokens : &Tokenizer, state : &ParseState) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, to_string(tokens, state)?)?;
+    std::fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// Load a state saved by [`save`].
+pub fn load(path : impl AsRef<Path>) -> Result<(Tokenizer, ParseState), Box<dyn std::error::Error>> {
+    Ok(from_str(&std::fs::read_to_string(path)?)?)
+}
+
+// Values of the enums are written as their names.
+pub(crate) fn name<T : std::fmt::Debug>(value : &T) -> Value {
+    Value::String(format!("{:?}", value))
+}
+
+pub(crate) fn number(n : usize) -> Value {
+    Value::Number(Number::U64(n as u64))
+}
+
+fn as_usize(value : &Value) -> Option<usize> {
+    match value {
+        Value::Number(Number::U64(n)) => usize::try_from(*n).ok(),
+        _ => None,
+    }
+}
+
+pub(crate) fn position(position : &SourcePosition) -> Value {
+    let mut object = Object::new();
+    object.insert("offset".to_string(), number(position.offset));
+    object.insert("line".to_string(), number(position.line));
+    object.insert("column".to_string(), number(position.column));
+    Value::Object(object)
+}
+
+pub(crate) fn read_position(value : &Value) -> Result<SourcePosition, HtmlParseError> {
+    let fields = Fields::new(value)?;
+    Ok(SourcePosition { offset : fields.usize("offset")?, line : fields.usize("line")?, column : fields.usize("column")? })
+}
+
+pub(crate) fn limits(limits : &ParserLimits) -> Value {
+    let mut object = Object::new();
+    object.insert("max_depth".to_string(), number(limits.max_depth));
+    object.insert("max_attributes".to_string(), number(limits.max_attributes));
+    object.insert("max_name_length".to_string(), number(limits.max_name_length));
+    object.insert("max_attribute_length".to_string(), number(limits.max_attribute_length));
+    object.insert("max_nodes".to_string(), number(limits.max_nodes));
+    object.insert("max_input_size".to_string(), number(limits.max_input_size));
+    Value::Object(object)
+}
+
+pub(crate) fn read_limits(value : &Value) -> Result<ParserLimits, HtmlParseError> {
+    let fields = Fields::new(value)?;
+    Ok(ParserLimits {
+        max_depth : fields.usize("max_depth")?,
+        max_attributes : fields.usize("max_attributes")?,
+        max_name_length : fields.usize("max_name_length")?,
+        max_attribute_length : fields.usize("max_attribute_length")?,
+        max_nodes : fields.usize("max_nodes")?,
+        max_input_size : fields.usize("max_input_size")?,
+    })
+}
+
+// The fields of a JSON object in a saved state.
+pub(crate) struct Fields<'a>(&'a Object);
+
+impl<'a> Fields<'a> {
+    pub(crate) fn new(value : &'a Value) -> Result<Self, HtmlParseError> {
+        match value {
+            Value::Object(object) => Ok(Self(object)),
+            _ => Err(HtmlParseError::InvalidSnapshot("expected an object")),
+        }
+    }
+
+    pub(crate) fn get(&self, key : &str) -> Result<&'a Value, HtmlParseError> {
+        self.0.get(key).ok_or(HtmlParseError::InvalidSnapshot("a field is missing"))
+    }
+
+    pub(crate) fn usize(&self, key : &str) -> Result<usize, HtmlParseError> {
+        as_usize(self.get(key)?).ok_or(HtmlParseError::InvalidSnapshot("expected a number"))
+    }
+
+    pub(crate) fn bool(&self, key : &str) -> Result<bool, HtmlParseError> {
+        match self.get(key)? {
+            Value::Bool(value) => Ok(*value),
+            _ => Err(HtmlParseError::InvalidSnapshot("expected a boolean")),
+        }
+    }
+
+    pub(crate) fn string(&self, key : &str) -> Result<String, HtmlParseError> {
+        self.optional_string(key)?.ok_or(HtmlParseError::InvalidSnapshot("expected a string"))
+    }
+
+    pub(crate) fn optional_string(&self, key : &str) -> Result<Option<String>, HtmlParseError> {
+        match self.get(key)? {
+            Value::String(value) => Ok(Some(value.clone())),
+            Value::Null => Ok(None),
+            _ => Err(HtmlParseError::InvalidSnapshot("expected a string")),
+        }
+    }
+
+    pub(crate) fn array(&self, key : &str) -> Result<&'a Array, HtmlParseError> {
+        match self.get(key)? {
+            Value::Array(array) => Ok(array),
+            _ => Err(HtmlParseError::InvalidSnapshot("expected an array")),
+        }
+    }
+
+    // A value of an enum, written by `name`.
+    pub(crate) fn named<T : std::fmt::Debug + Copy>(&self, key : &str, all : &[T]) -> Result<T, HtmlParseError> {
+        let name = self.string(key)?;
+        all.iter().copied().find(|value| format!("{:?}", value) == name)
+            .ok_or(HtmlParseError::InvalidSnapshot("unknown name of a state"))
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InsertionMode {
     Initial,
     BeforeHtml,
@@ -24,7 +24,7 @@ pub enum InsertionMode {
     AfterAfterFrameset,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TokenizationState {
     Data,
     CharacterReferenceInData,
@@ -95,3 +95,43 @@ pub enum TokenizationState {
     BogusDOCTYPE,
     CDATASection,
 }
+
+impl InsertionMode {
+    /// Every variant, in the order they are declared.
+    pub const ALL : [InsertionMode; 22] = [
+        InsertionMode::Initial, InsertionMode::BeforeHtml, InsertionMode::BeforeHead, InsertionMode::InHead,
+        InsertionMode::InHeadNoScript, InsertionMode::AfterHead, InsertionMode::InBody, InsertionMode::Text,
+        InsertionMode::InTable, InsertionMode::InTableText, InsertionMode::InCaption, InsertionMode::InColumnGroup,
+        InsertionMode::InTableBody, InsertionMode::InRow, InsertionMode::InCell, InsertionMode::InSelect,
+        InsertionMode::InSelectTable, InsertionMode::AfterBody, InsertionMode::InFrameset,
+        InsertionMode::AfterFrameset, InsertionMode::AfterAfterBody, InsertionMode::AfterAfterFrameset,
+    ];
+}
+
+impl TokenizationState {
+    /// Every variant, in the order they are declared.
+    pub const ALL : [TokenizationState; 51] = [
+        TokenizationState::Data, TokenizationState::CharacterReferenceInData, TokenizationState::RCDATA,
+        TokenizationState::CharacterReferenceInRCDATA, TokenizationState::RAWTEXT, TokenizationState::ScriptData,
+        TokenizationState::PLAINTEXT, TokenizationState::TagOpen, TokenizationState::EndTagOpen,
+        TokenizationState::TagName, TokenizationState::RCDATALessThanSign, TokenizationState::RCDATAEndTagOpen,
+        TokenizationState::RCDATAEndTagName, TokenizationState::RAWTEXTLessThanSign,
+        TokenizationState::RAWTEXTEndTagOpen, TokenizationState::RAWTEXTEndTagName,
+        TokenizationState::BeforeAttributeName, TokenizationState::AttributeName,
+        TokenizationState::AfterAttributeName, TokenizationState::BeforeAttributeValue,
+        TokenizationState::AttributeValueDoubleQuoted, TokenizationState::AttributeValueSingleQuoted,
+        TokenizationState::AttributeValueUnquoted, TokenizationState::CharacterrReferenceInAttributeValue,
+        TokenizationState::AfterAttributeValueQuoted, TokenizationState::SelfClosingStartTag,
+        TokenizationState::BogusComment, TokenizationState::MarkupDeclarationOpen, TokenizationState::CommentStart,
+        TokenizationState::CommentStartDash, TokenizationState::Comment, TokenizationState::CommentEndDash,
+        TokenizationState::CommentEnd, TokenizationState::CommentEndBang, TokenizationState::DOCTYPE,
+        TokenizationState::BeforeDOCTYPEName, TokenizationState::DOCTYPEName, TokenizationState::AfterDOCTYPEName,
+        TokenizationState::AfterDOCTYPEPublicKeyword, TokenizationState::BeforeDOCTYPEPublicIdentifier,
+        TokenizationState::DOCTYPEPublicIdentifierDoubleQuoted, TokenizationState::DOCTYPEPublicIdentifierSingleQuoted,
+        TokenizationState::AfterDOCTYPEPublicIdentifier, TokenizationState::BetweenDOCTYPEPublicAndSystemIdentifiers,
+        TokenizationState::AfterDOCTYPESystemKeyword, TokenizationState::BeforeDOCTYPESystemIdentifier,
+        TokenizationState::DOCTYPESystemIdentifierDoubleQuoted, TokenizationState::DOCTYPESystemIdentifierSingleQuoted,
+        TokenizationState::AfterDOCTYPESystemIdentifier, TokenizationState::BogusDOCTYPE,
+        TokenizationState::CDATASection,
+    ];
+}
@@ -0,0 +1,23 @@
+use dds_utils::tree::{apply, diff, Edit};
+
+use crate::{HtmlParser, Node, ParseState, Tree};
+
+fn nodes(tree: &Tree<Node>) -> Vec<Node> {
+    std::iter::once(tree.root()).chain(tree.descendants(tree.root())).map(|id| tree.get(id).unwrap().clone()).collect()
+}
+
+#[test]
+fn diff_dom() {
+    let mut old = HtmlParser::parse("<ul id=list><li id=a>One<li id=b>Two<li id=c>Three</ul><p class=x>Text", ParseState::new()).unwrap();
+    let new = HtmlParser::parse("<ul id=list><li id=c>Three<li id=a>One!<li id=b>Two</ul><p class=y>Text<!-- new -->", ParseState::new()).unwrap();
+    let list = old.iter().find(|(_, node)| node.diff_key() == "ul#list").unwrap().0;
+
+    let edits = diff(&old, &new, Node::diff_key);
+    // Moving <li id=c>, updating "One" and the class, adding the comment.
+    assert_eq!(edits.len(), 4, "{:?}", edits);
+    assert!(!edits.iter().any(|edit| matches!(edit, Edit::Remove { .. })));
+
+    apply(&mut old, edits).unwrap();
+    assert_eq!(nodes(&old), nodes(&new));
+    assert_eq!(old.get(list).and_then(Node::as_element).map(|element| element.name()), Some("ul"));
+}
@@ -0,0 +1,89 @@
+use crate::{Document, ElementKind};
+
+const HTML: &str = "<!DOCTYPE html><html><head><title>  A   page </title></head><body>\
+    <div id=main class='card  wide card'><p class=wide>One <b>two</b></p><!-- note --><p>three</p></div>\
+    <ul><li class='item first'>a<li class=item>b<li class=other>c</ul></body></html>";
+
+fn texts(elements: &[crate::document::ElementRef]) -> Vec<String> {
+    elements.iter().map(|element| element.text_content()).collect()
+}
+
+#[test]
+fn document_queries() {
+    let document = Document::parse(HTML).unwrap();
+    assert_eq!(document.title().as_deref(), Some("A page"));
+    assert_eq!(document.document_element().unwrap().kind, ElementKind::Html);
+    assert_eq!(document.head().unwrap().name(), "head");
+    assert_eq!(document.body().unwrap().name(), "body");
+
+    let main = document.get_element_by_id("main").unwrap();
+    assert_eq!(main.name(), "div");
+    assert_eq!(main.classes(), ["card", "wide"]);
+    assert!(document.get_element_by_id("missing").is_none());
+    assert!(document.get_element_by_id("").is_none());
+
+    assert_eq!(texts(&document.get_elements_by_tag_name("LI")), ["a", "b", "c"]);
+    assert_eq!(texts(&main.get_elements_by_tag_name("p")), ["One two", "three"]);
+    assert_eq!(document.get_elements_by_tag_name("*").len(), 12);
+    assert_eq!(texts(&document.get_elements_by_class_name("item")), ["a", "b"]);
+    assert_eq!(texts(&document.get_elements_by_class_name(" first  item ")), ["a"]);
+    assert_eq!(document.get_elements_by_class_name("wide").len(), 2);
+    assert!(document.get_elements_by_class_name("  ").is_empty());
+}
+
+#[test]
+fn document_navigation() {
+    let document = Document::parse(HTML).unwrap();
+    let main = document.get_element_by_id("main").unwrap();
+    assert_eq!(main.text_content(), "One twothree");
+    assert_eq!(main.parent_element().unwrap().name(), "body");
+    assert_eq!(main.next_element_sibling().unwrap().name(), "ul");
+    assert!(main.previous_element_sibling().is_none());
+
+    let children: Vec<_> = main.children().collect();
+    assert_eq!(children.len(), 3);
+    assert_eq!(children[1].as_comment().unwrap().text(), " note ");
+    assert_eq!(children[1].text_content().as_deref(), Some(" note "));
+    assert!(children[1].as_element().is_none());
+    let p = children[0].as_element().unwrap();
+    assert_eq!(p.as_node().first_child().unwrap().as_text().unwrap().text(), "One ");
+    assert_eq!(p.as_node().last_child().unwrap().as_element().unwrap().name(), "b");
+    assert_eq!(children[0].next_sibling(), Some(children[1]));
+    assert_eq!(children[2].previous_sibling(), Some(children[1]));
+    assert_eq!(children[0].parent().unwrap(), main.as_node());
+    assert_eq!(children[0].ancestors().count(), 4);
+    assert_eq!(document.get(main.node_id()).unwrap(), main.as_node());
+
+    assert_eq!(document.root().text_content(), None);
+    assert!(document.root().first_child().unwrap().as_element().is_none());
+    assert!(document.root().parent().is_none());
+}
+
+#[test]
+fn document_changes() {
+    let mut document = Document::parse(HTML).unwrap();
+    let main = document.get_element_by_id("main").unwrap().node_id();
+    let body = document.body().unwrap().node_id();
+
+    assert!(document.set_attribute(main, "Data-X", "1"));
+    assert_eq!(document.get(main).unwrap().as_element().unwrap().attribute("data-x"), Some("1"));
+    assert!(document.set_attribute(main, "id", "other"));
+    assert!(document.get_element_by_id("main").is_none());
+    assert_eq!(document.get_element_by_id("other").unwrap().node_id(), main);
+    assert_eq!(document.remove_attribute(main, "data-x").as_deref(), Some("1"));
+    assert_eq!(document.remove_attribute(main, "data-x"), None);
+
+    let element = document.element_mut(main).unwrap();
+    element.add_class("new");
+    element.remove_class("card");
+    assert!(!element.toggle_class("wide"));
+    assert!(element.toggle_class("on"));
+    assert_eq!(element.attribute("class"), Some("new on"));
+
+    assert!(document.set_text_content(main, "replaced"));
+    assert_eq!(document.get(main).unwrap().text_content().as_deref(), Some("replaced"));
+    assert_eq!(document.get_elements_by_tag_name("p").len(), 0);
+    assert!(!document.set_text_content(document.tree().root(), "x"));
+    assert!(!document.set_attribute(document.tree().root(), "a", "b"));
+    assert!(document.get(body).is_some());
+}
@@ -0,0 +1,129 @@
+use dds_utils::tree::Edge;
+use proptest::prelude::*;
+
+use crate::{incremental::IncrementalDocument, limits::ParserLimits, sink::PositionSink, tokenizer::SourcePosition, Document, HtmlParser, ParseState};
+
+use super::no_panic::html_input;
+
+// Every node in document order, with its source position.
+fn nodes(document: &Document) -> Vec<(String, Option<SourcePosition>)> {
+    let tree = document.tree();
+    tree.traverse(tree.root()).filter_map(|edge| match edge {
+        Edge::Open(id) => document.get(id).map(|node| (format!("{:?}", node.node()), node.source_position())),
+        Edge::Close(_) => None,
+    }).collect()
+}
+
+// Edits `source` and checks that the result is the same as parsing the new source from scratch.
+fn check_edit(source: &str, range: std::ops::Range<usize>, replacement: &str) {
+    check_edit_with(source, range, replacement, ParserLimits::default());
+}
+
+fn check_edit_with(source: &str, range: std::ops::Range<usize>, replacement: &str, limits: ParserLimits) {
+    let Ok(mut document) = IncrementalDocument::parse(source, limits) else {
+        return;
+    };
+    let new_source = format!("{}{}{}", &source[..range.start], replacement, &source[range.end..]);
+    let expected = HtmlParser::parse(&new_source, ParseState::with_sink(PositionSink::new()).with_limits(limits))
+        .map(|(tree, positions)| Document::from_parts(tree, positions));
+    match (document.edit(range, replacement), expected) {
+        (Ok(changes), Ok(expected)) => {
+            assert_eq!(document.source(), new_source);
+            assert_eq!(document.document().to_html(), expected.to_html());
+            assert_eq!(nodes(document.document()), nodes(&expected));
+            for id in changes.inserted.iter().chain(&changes.updated) {
+                assert!(document.document().get(*id).is_some());
+            }
+        },
+        (Err(_), Err(_)) => {},
+        (result, expected) => panic!("{:?} but parsing from scratch gave {:?}", result.map(|_| ()), expected.map(|_| ())),
+    }
+}
+
+#[test]
+fn incremental_edit_in_paragraph() {
+    let paragraphs: String = (0..50).map(|i| format!("<p class=p{}>Paragraph <b>{}</b></p>\n", i, i)).collect();
+    let source = format!("<!DOCTYPE html><html><head><title>x</title></head><body>\n{}</body></html>", paragraphs);
+    let mut document = IncrementalDocument::parse(&source, ParserLimits::default()).unwrap();
+    let before: Vec<_> = document.document().get_elements_by_tag_name("p").iter().map(|p| p.node_id()).collect();
+
+    let at = source.find("<b>20</b>").unwrap() + 3;
+    let changes = document.edit(at..at + 2, "twenty\nlines").unwrap();
+    let paragraphs = document.document().get_elements_by_tag_name("p");
+    assert_eq!(paragraphs.len(), 50);
+    assert_eq!(paragraphs[20].text_content(), "Paragraph twenty\nlines");
+    // Parsing restarts after the `<b>`, so only its text is replaced and the paragraphs are the same nodes.
+    let bold = paragraphs[20].as_node().last_child().unwrap();
+    assert_eq!(changes.removed.len(), 1);
+    assert_eq!(changes.inserted, [bold.first_child().unwrap().id()]);
+    assert!(changes.updated.is_empty());
+    assert!(changes.reparsed.len() < 100);
+    for (index, paragraph) in paragraphs.iter().enumerate().filter(|(index, _)| *index != 20) {
+        assert_eq!(paragraph.node_id(), before[index]);
+    }
+    // The positions after the edit moved down a line.
+    let last = paragraphs[49].source_position().unwrap();
+    assert_eq!(last.line, 52);
+    assert_eq!(&document.source()[last.offset..last.offset + 10], "<p class=p");
+    check_edit(&source, at..at + 2, "twenty\nlines");
+}
+
+#[test]
+fn incremental_structure_changes() {
+    let source = "<body><div><p>a</p><p>b</p></div><ul><li>c<li>d</ul><!-- end --></body></html><!-- after -->";
+    for (target, replacement) in [
+        ("<p>b</p>", "<p>b"),
+        ("</div>", ""),
+        ("<ul>", "<table>"),
+        ("<li>d", "<li>d<body class=x>"),
+        ("<!-- end -->", "<!-- end"),
+        ("<p>a</p>", "<textarea>"),
+        ("</body>", "<script>"),
+        ("a", "<frameset>"),
+    ] {
+        let at = source.find(target).unwrap();
+        check_edit(source, at..at + target.len(), replacement);
+    }
+}
+
+#[test]
+fn incremental_edit_before_body() {
+    let source = "<html><head><title>a</title></head><body><p>b</p></body></html>";
+    let mut document = IncrementalDocument::parse(source, ParserLimits::default()).unwrap();
+    let at = source.find("a</title>").unwrap();
+    let changes = document.edit(at..at + 1, "c").unwrap();
+    // The head comes before the first checkpoint, so everything is parsed again.
+    assert_eq!(changes.reparsed, 0..document.source().len());
+    assert_eq!(document.document().title().as_deref(), Some("c"));
+    assert!(document.edit(std::ops::Range { start: 10, end: 5 }, "").is_err());
+    assert!(document.edit(0..1000, "").is_err());
+}
+
+#[test]
+fn incremental_deep_nesting() {
+    // Taking a checkpoint after every tag used to copy the whole stack of open elements every time.
+    let source = format!("<body>{}{}", "<div>".repeat(5_000), "<p>a</p>".repeat(100));
+    let at = source.rfind('a').unwrap();
+    let mut document = IncrementalDocument::parse(&source, ParserLimits::default()).unwrap();
+    let changes = document.edit(at..at + 1, "b").unwrap();
+    assert!(changes.reparsed.len() < 100);
+    assert_eq!(changes.inserted.len(), 1);
+
+    // The limits apply to the edits as well, the divs past the maximum depth end up next to each other.
+    let limits = ParserLimits { max_nodes: 12_000, ..ParserLimits::untrusted() };
+    check_edit_with(&source, at..at + 1, "b", limits);
+    check_edit_with(&source, at..at + 1, "<p>b".repeat(5_000).as_str(), limits);
+    let mut document = IncrementalDocument::parse(&source, limits).unwrap();
+    assert!(document.edit(at..at + 1, "<p>b".repeat(5_000).as_str()).is_err());
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig { cases : 500, ..ProptestConfig::default() })]
+
+    #[test]
+    fn incremental_any_edit(input in html_input(), a in any::<prop::sample::Index>(), b in any::<prop::sample::Index>(), replacement in html_input()) {
+        let boundaries: Vec<usize> = (0..=input.len()).filter(|i| input.is_char_boundary(*i)).collect();
+        let (a, b) = (*a.get(&boundaries), *b.get(&boundaries));
+        check_edit(&input, a.min(b)..a.max(b), &replacement);
+    }
+}
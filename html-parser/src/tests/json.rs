@@ -0,0 +1,45 @@
+use crate::{json::{from_json, to_flat_json, to_json}, HtmlParser, Node, ParseState, Tree};
+
+fn nodes(tree: &Tree<Node>) -> Vec<Node> {
+    std::iter::once(tree.root()).chain(tree.descendants(tree.root())).map(|id| tree.get(id).unwrap().clone()).collect()
+}
+
+#[test]
+fn json_dom() {
+    let dom = HtmlParser::parse("<!DOCTYPE html><p id=x class=\"a b\">Hi<!-- note --></p><svg></svg>", ParseState::new()).unwrap();
+    let text = to_json(&dom);
+    assert!(text.starts_with(r#"{"value":{"type":"document"},"children":[{"value":{"type":"doctype","name":"html","public_id":null,"system_id":null},"children":[]}"#), "{}", text);
+    assert!(text.contains(r#"{"type":"element","name":"p","namespace":"http://www.w3.org/1999/xhtml","attributes":[{"name":"id","value":"x"},{"name":"class","value":"a b"}]}"#), "{}", text);
+    assert!(text.contains(r#"{"value":{"type":"text","data":"Hi"},"children":[]},{"value":{"type":"comment","data":" note "},"children":[]}"#), "{}", text);
+    assert!(text.contains(r#""name":"svg","namespace":"http://www.w3.org/2000/svg""#), "{}", text);
+
+    assert_eq!(nodes(&from_json(&text).unwrap()), nodes(&dom));
+    assert_eq!(nodes(&from_json(&to_flat_json(&dom)).unwrap()), nodes(&dom));
+}
+
+#[test]
+fn json_dom_foreign_content() {
+    // Elements inside <svg> and <math> are in their namespace, except below the integration points.
+    let dom = HtmlParser::parse("<svg><g><circle/></g><foreignObject><p>x</p></foreignObject></svg><math><mi><b></b></mi></math>", ParseState::new()).unwrap();
+    for text in [to_json(&dom), to_flat_json(&dom)] {
+        for (name, namespace) in [("svg", "2000/svg"), ("g", "2000/svg"), ("circle", "2000/svg"), ("foreignobject", "2000/svg"),
+            ("p", "1999/xhtml"), ("math", "1998/Math/MathML"), ("mi", "1998/Math/MathML"), ("b", "1999/xhtml")] {
+            let field = format!(r#""name":"{}","namespace":"http://www.w3.org/{}""#, name, namespace);
+            assert!(text.contains(&field), "{} in {}", field, text);
+        }
+        assert_eq!(nodes(&from_json(&text).unwrap()), nodes(&dom));
+    }
+}
+
+#[test]
+fn json_dom_invalid() {
+    for text in [
+        r#"{"value":{"type":"element"}}"#,
+        r#"{"value":{"type":"text"}}"#,
+        r#"{"value":{"type":"unknown"}}"#,
+        r#"{"value":{"name":"p"}}"#,
+        r#"{"value":{"type":"element","name":"p","attributes":[{"value":"x"}]}}"#,
+    ] {
+        assert!(from_json(text).is_err(), "{}", text);
+    }
+}
@@ -0,0 +1,174 @@
+#[allow(unused)]
+use crate::{error::HtmlParseError, limits::ParserLimits, sax, tests::sax_events::Recorder, HtmlParser, Node, ParseState, Tree};
+
+fn limited(limits: ParserLimits) -> ParseState {
+    ParseState::new().with_limits(limits)
+}
+
+fn parse_error(result: Result<Tree<Node>, Box<dyn std::error::Error>>) -> HtmlParseError {
+    match result.err().expect("parsing should have failed").downcast::<HtmlParseError>() {
+        Ok(e) => *e,
+        Err(e) => panic!("unexpected error {}", e),
+    }
+}
+
+// The number of elements from the document down to the most deeply nested element.
+fn max_depth(tree: &Tree<Node>) -> usize {
+    tree.iter().filter(|(_, node)| matches!(node, Node::Element(_))).map(|(id, _)| {
+        let mut depth = 0;
+        let mut parent = tree.parent(id);
+        while let Some(id) = parent {
+            depth += 1;
+            parent = tree.parent(id);
+        }
+        depth
+    }).max().unwrap_or(0)
+}
+
+fn elements(tree: &Tree<Node>) -> impl Iterator<Item = &crate::Element> {
+    tree.iter().filter_map(|(_, node)| node.as_element())
+}
+
+#[test]
+fn limits_depth() {
+    let html = "<div>".repeat(1000) + "text";
+    let limits = ParserLimits { max_depth : 16, ..ParserLimits::unlimited() };
+    let tree = HtmlParser::parse(&html, limited(limits)).unwrap();
+    assert_eq!(max_depth(&tree), 16);
+    // Nothing gets lost, the extra elements are inserted next to each other.
+    assert_eq!(elements(&tree).filter(|e| e.name() == "div").count(), 1000);
+
+    let unlimited = HtmlParser::parse(&html, ParseState::new()).unwrap();
+    assert_eq!(max_depth(&unlimited), 1002);
+}
+
+#[test]
+fn limits_depth_keeps_the_stack_bounded() {
+    let depth = 40_000;
+    let html = "<div>".repeat(depth) + "text" + &"</div>".repeat(depth);
+    let mut state = limited(ParserLimits::untrusted());
+    let start = std::time::Instant::now();
+    state.process_token(crate::tokenizer::Token::StartTag("body".to_string(), false, Vec::new())).unwrap();
+    for _ in 0..depth {
+        state.process_token(crate::tokenizer::Token::StartTag("div".to_string(), false, Vec::new())).unwrap();
+        assert!(state.open_elements.len() <= ParserLimits::untrusted().max_depth);
+    }
+    let tree = HtmlParser::parse(&html, limited(ParserLimits::untrusted())).unwrap();
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    assert_eq!(max_depth(&tree), ParserLimits::untrusted().max_depth);
+    assert_eq!(elements(&tree).filter(|e| e.name() == "div").count(), depth);
+}
+
+#[test]
+fn limits_depth_events_stay_balanced() {
+    let html = "<div>".repeat(100) + "text" + &"</div>".repeat(100) + "<p>after";
+    let limits = ParserLimits { max_depth : 8, ..ParserLimits::unlimited() };
+    let events = HtmlParser::parse(&html, ParseState::with_sink(sax::EventSink::new(Recorder::default())).with_limits(limits)).unwrap().events;
+    let starts = events.iter().filter(|e| matches!(e, crate::tests::sax_events::Event::Start(_))).count();
+    let ends = events.iter().filter(|e| matches!(e, crate::tests::sax_events::Event::End(_))).count();
+    assert_eq!(starts, ends);
+}
+
+#[test]
+fn limits_depth_nested_tables() {
+    // The element that gets replaced at the maximum depth can be a cell or a select, after which the
+    // insertion mode has to match what is left on the stack.
+    let limits = ParserLimits::untrusted();
+    let cell = "<div>".repeat(limits.max_depth - 6) + "<table><tr><td><div>x</div></td></tr></table><p>after";
+    let select = "<div>".repeat(limits.max_depth - 3) + "<select><option>x</select><p>after";
+    for html in [cell, select] {
+        let tree = HtmlParser::parse(&html, limited(limits)).unwrap();
+        assert_eq!(max_depth(&tree), limits.max_depth);
+        let p = tree.iter().find(|(_, node)| node.as_element().is_some_and(|e| e.name() == "p")).unwrap().0;
+        assert!(matches!(tree.get(tree.first_child(p).unwrap()), Some(Node::Text(text)) if text == "after"));
+        assert!(elements(&tree).any(|e| e.name() == "body"));
+    }
+}
+
+#[test]
+fn limits_attributes() {
+    let attributes: String = (0..100).map(|i| format!(" a{}=\"{}\"", i, i)).collect();
+    let html = format!("<div{}></div>", attributes);
+    let limits = ParserLimits { max_attributes : 10, ..ParserLimits::unlimited() };
+    let tree = HtmlParser::parse(&html, limited(limits)).unwrap();
+    let div = elements(&tree).find(|e| e.name() == "div").unwrap();
+    assert_eq!(div.attributes.len(), 10);
+    assert_eq!(div.attribute("a9"), Some("9"));
+    assert_eq!(div.attribute("a10"), None);
+}
+
+#[test]
+fn limits_name_length() {
+    let html = format!("<{name} {name}=value></{name}>", name = "x".repeat(100));
+    let limits = ParserLimits { max_name_length : 8, ..ParserLimits::unlimited() };
+    let tree = HtmlParser::parse(&html, limited(limits)).unwrap();
+    let element = elements(&tree).find(|e| e.name().starts_with('x')).unwrap();
+    assert_eq!(element.name(), "xxxxxxxx");
+    assert_eq!(element.attribute("xxxxxxxx"), Some("value"));
+}
+
+#[test]
+fn limits_attribute_length() {
+    let html = format!("<a href=\"{}\" title=short>", "y".repeat(1000));
+    let limits = ParserLimits { max_attribute_length : 32, ..ParserLimits::unlimited() };
+    let tree = HtmlParser::parse(&html, limited(limits)).unwrap();
+    let a = elements(&tree).find(|e| e.name() == "a").unwrap();
+    assert_eq!(a.attribute("href"), Some("y".repeat(32).as_str()));
+    assert_eq!(a.attribute("title"), Some("short"));
+}
+
+#[test]
+fn limits_nodes() {
+    let html = "<p>text".repeat(100);
+    // document, html, head, body and the first few paragraphs with their text.
+    let limits = ParserLimits { max_nodes : 20, ..ParserLimits::unlimited() };
+    match parse_error(HtmlParser::parse(&html, limited(limits))) {
+        HtmlParseError::NodeLimitExceeded(20) => (),
+        e => panic!("unexpected error {}", e),
+    }
+
+    // A run of text only counts as a single node.
+    let html = "a".repeat(10_000);
+    let limits = ParserLimits { max_nodes : 4, ..ParserLimits::unlimited() };
+    assert!(HtmlParser::parse(&html, limited(limits)).is_ok());
+}
+
+#[test]
+fn limits_nodes_pipelined() {
+    let html = "<br>".repeat(10_000);
+    let limits = ParserLimits { max_nodes : 100, ..ParserLimits::unlimited() };
+    match parse_error(HtmlParser::parse_pipelined(&html, limited(limits))) {
+        HtmlParseError::NodeLimitExceeded(100) => (),
+        e => panic!("unexpected error {}", e),
+    }
+}
+
+#[test]
+fn limits_input_size() {
+    let html = "<p>".repeat(100);
+    let limits = ParserLimits { max_input_size : 100, ..ParserLimits::unlimited() };
+    match parse_error(HtmlParser::parse(&html, limited(limits))) {
+        HtmlParseError::InputTooLarge(100) => (),
+        e => panic!("unexpected error {}", e),
+    }
+    assert!(HtmlParser::parse(&html[..100], limited(limits)).is_ok());
+}
+
+#[test]
+fn limits_input_size_streaming() {
+    // The input is never held in memory as a whole, so the limit has to be checked while reading.
+    let html = "<p>".repeat(100_000);
+    let limits = ParserLimits { max_input_size : 1000, ..ParserLimits::unlimited() };
+    match parse_error(HtmlParser::parse_stream(html.as_bytes(), limited(limits))) {
+        HtmlParseError::InputTooLarge(1000) => (),
+        e => panic!("unexpected error {}", e),
+    }
+}
+
+#[test]
+fn limits_untrusted_allow_regular_documents() {
+    let html = include_str!("../../../resources/test.html");
+    let limited = HtmlParser::parse(html, limited(ParserLimits::untrusted())).unwrap();
+    let unlimited = HtmlParser::parse(html, ParseState::new()).unwrap();
+    assert_eq!(limited.iter().count(), unlimited.iter().count());
+}
@@ -0,0 +1,73 @@
+use crate::{error::{HtmlParseError, ParseErrorCode, TokenizerErrorCode}, lint::{lint, Diagnostic, Severity}, states::InsertionMode, tokenizer::SourcePosition, HtmlParser, ParseState};
+
+const PAGE: &str = "<!DOCTYPE html>\n<html lang=en><head><title>Page</title></head><body>\n";
+
+// The code and the line and column of every diagnostic.
+fn codes(html: &str) -> Vec<(&'static str, usize, usize)> {
+    lint(html).unwrap().iter()
+        .map(|diagnostic| (diagnostic.code, diagnostic.position.unwrap().line, diagnostic.position.unwrap().column))
+        .collect()
+}
+
+#[test]
+fn parse_errors_have_codes_and_positions() {
+    let (_, errors) = HtmlParser::parse_with_errors("<p>\n  <b a=1 a=2>x</i>", ParseState::new()).unwrap();
+    let errors: Vec<(Option<ParseErrorCode>, Option<SourcePosition>)> = errors.iter().map(|error| (error.code().cloned(), error.position())).collect();
+    assert_eq!(errors, vec![
+        (Some(ParseErrorCode::UnexpectedToken { mode: InsertionMode::Initial, token: "start tag <p>".to_string() }), Some(SourcePosition { offset: 0, line: 1, column: 1 })),
+        (Some(ParseErrorCode::Tokenizer(TokenizerErrorCode::DuplicateAttribute)), Some(SourcePosition { offset: 16, line: 2, column: 13 })),
+        (Some(ParseErrorCode::UnexpectedToken { mode: InsertionMode::InBody, token: "end tag </i>".to_string() }), Some(SourcePosition { offset: 18, line: 2, column: 15 })),
+    ]);
+    let error = HtmlParseError::ParseError { code: ParseErrorCode::Tokenizer(TokenizerErrorCode::EofInTag), position: SourcePosition { offset: 4, line: 2, column: 3 } };
+    assert_eq!(error.to_string(), "HtmlParseError: ParseError. eof-in-tag at 2:3.");
+    // Errors are only collected when asked for.
+    let mut state = ParseState::new();
+    state.process_token(crate::tokenizer::Token::EndTag("x".to_string(), false, Vec::new())).unwrap();
+    assert!(state.take_errors().is_empty());
+}
+
+#[test]
+fn lint_conforming_document() {
+    assert_eq!(lint(&format!("{}<p id=a>Some <b>text</b></p><ul>\n  <li>one</li>\n</ul><img src=a.png alt=''></body></html>", PAGE)).unwrap(), vec![]);
+}
+
+#[test]
+fn lint_conformance_problems() {
+    let html = format!("{}<center><font>a</font></center>\n<img src=a.png><table align=center bgcolor=red><tr><td>x</td></tr></table>\n\
+        <ul><div>x</div>text</ul><span><div>y</div></span>\n<a href=/><button>b</button></a>\n\
+        <i id=x></i><b id=x></b><s id=''></s>\n<svg><font></font></svg>", PAGE);
+    assert_eq!(codes(&html), vec![
+        ("obsolete-element", 3, 1),
+        ("obsolete-element", 3, 9),
+        ("missing-alt", 4, 1),
+        ("obsolete-attribute", 4, 16),
+        ("obsolete-attribute", 4, 16),
+        ("disallowed-child", 5, 5),
+        ("disallowed-child", 5, 17),
+        ("disallowed-child", 5, 32),
+        ("interactive-content", 6, 11),
+        ("duplicate-id", 7, 13),
+        ("invalid-id", 7, 25),
+    ]);
+}
+
+#[test]
+fn lint_document_level_problems() {
+    assert_eq!(codes("<!DOCTYPE html><html><body>x"), vec![("missing-lang", 1, 16), ("missing-title", 1, 22)]);
+    assert_eq!(codes("<title>x</title>"), vec![("unexpected-token", 1, 1), ("missing-lang", 1, 1)]);
+}
+
+#[test]
+fn lint_output_formats() {
+    let diagnostics = lint(&format!("{}\n<img src=a.png>", PAGE)).unwrap();
+    assert_eq!(diagnostics, vec![Diagnostic {
+        severity: Severity::Error,
+        code: "missing-alt",
+        message: "<img> without an alt attribute".to_string(),
+        position: Some(SourcePosition { offset: 70, line: 4, column: 1 }),
+    }]);
+    assert_eq!(diagnostics[0].to_human("index.html"), "index.html:4:1: error[missing-alt]: <img> without an alt attribute");
+    assert_eq!(miniserde::json::to_string(&diagnostics[0].to_json("index.html")),
+        r#"{"code":"missing-alt","column":1,"file":"index.html","line":4,"message":"<img> without an alt attribute","severity":"error"}"#);
+    assert!(Severity::Error > Severity::Warning);
+}
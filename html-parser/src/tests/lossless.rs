@@ -0,0 +1,109 @@
+use proptest::prelude::*;
+
+use crate::{highlight::{classify, to_ansi, to_html, Class}, lossless::{AttributeSpan, LosslessTokenizer, SourceToken}, tokenizer::{Attribute, Token}};
+
+use super::no_panic::html_input;
+
+fn tokens(source: &str) -> Vec<SourceToken<'_>> {
+    LosslessTokenizer::new(source).unwrap().collect()
+}
+
+fn reproduces_input(source: &str) {
+    let tokens = tokens(source);
+    assert_eq!(tokens.iter().map(|token| token.text).collect::<String>(), source);
+    let mut end = 0;
+    for token in &tokens {
+        assert_eq!(token.span.start, end);
+        assert_eq!(&source[token.span.clone()], token.text);
+        end = token.span.end;
+    }
+    let pieces = classify(source).unwrap();
+    assert_eq!(pieces.iter().map(|(_, range)| &source[range.clone()]).collect::<String>(), source);
+}
+
+#[test]
+fn lossless_tag() {
+    let source = "<DIV  Class = 'a b' id=x data-x=\"&amp;\" hidden/>";
+    let tag = tokens(source).remove(0);
+    assert_eq!((tag.text, tag.span.clone()), (source, 0..source.len()));
+    assert_eq!(tag.token, Some(Token::StartTag("div".to_string(), true, vec![
+        Attribute::new("class", "a b"), Attribute::new("id", "x"), Attribute::new("data-x", "&"), Attribute::new("hidden", ""),
+    ])));
+    assert_eq!(&source[tag.name.clone().unwrap()], "DIV");
+    let attributes: Vec<(&str, Option<&str>, Option<char>)> = tag.attributes.iter()
+        .map(|attribute| (&source[attribute.name.clone()], attribute.value.clone().map(|value| &source[value]), attribute.quote))
+        .collect();
+    assert_eq!(attributes, [
+        ("Class", Some("a b"), Some('\'')), ("id", Some("x"), None), ("data-x", Some("&amp;"), Some('"')), ("hidden", None, None),
+    ]);
+
+    let source = "</P ><a =b c= d=>";
+    let end_tag = tokens(source);
+    assert_eq!(&source[end_tag[0].name.clone().unwrap()], "P");
+    // A name can start with `=`, and an unquoted value runs up to whitespace or `>`.
+    assert_eq!(end_tag[1].attributes, [
+        AttributeSpan { name: 8..10, value: None, quote: None },
+        AttributeSpan { name: 11..12, value: Some(14..16), quote: None },
+    ]);
+    let empty = tokens("<a b=>");
+    assert_eq!(empty[0].attributes, [AttributeSpan { name: 3..4, value: Some(5..5), quote: None }]);
+}
+
+#[test]
+fn lossless_text() {
+    let source = "a&amp;b&notanentity;&#x41<title>x</b</title><script>if (a<b) {}</script></>c<p";
+    reproduces_input(source);
+    let tokens = tokens(source);
+    let texts: Vec<(&str, Option<Token>)> = tokens.iter().map(|token| (token.text, token.token.clone())).collect();
+    assert_eq!(texts[..4], [
+        ("a", Some(Token::Character('a'))), ("&amp;", Some(Token::Character('&'))), ("b", Some(Token::Character('b'))),
+        ("&", Some(Token::Character('&'))),
+    ]);
+    // The text of a title is RCDATA, so `</b` is text, a character at a time.
+    let title: Vec<&str> = tokens.iter().skip_while(|token| token.text != "<title>").skip(1).take(4).map(|token| token.text).collect();
+    assert_eq!(title, ["x", "<", "/", "b"]);
+    assert!(tokens.iter().any(|token| token.text == "<" && token.span.start == source.find("a<b").unwrap() + 1));
+    // `</>` and the tag cut off at the end don't produce tokens.
+    let dropped: Vec<&str> = tokens.iter().filter(|token| token.token.is_none()).map(|token| token.text).collect();
+    assert_eq!(dropped, ["</>", "<p"]);
+}
+
+#[test]
+fn lossless_markup() {
+    for source in [
+        "<!DOCTYPE html>\r\n<html>\r\n<!-- comment --><body>\n<textarea>\n&lt;b></textarea></body></html>\n",
+        "<!-- unclosed",
+        "<?xml version=\"1.0\"?><a href='x",
+        "<a b=\"\u{0}\">\u{0}<style></style ></STYLE>",
+        include_str!("../../fuzz/corpus/parse/test.html"),
+    ] {
+        reproduces_input(source);
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig { cases : 500, ..ProptestConfig::default() })]
+
+    #[test]
+    fn lossless_any_input(input in html_input()) {
+        reproduces_input(&input);
+    }
+}
+
+#[test]
+fn highlight() {
+    let source = "<!doctype html><p class=\"x\">a &amp; b<!--c--></p>";
+    let classes: Vec<(Class, &str)> = classify(source).unwrap().into_iter().map(|(class, range)| (class, &source[range])).collect();
+    assert_eq!(classes, [
+        (Class::Doctype, "<!doctype html>"), (Class::Tag, "<"), (Class::TagName, "p"), (Class::Tag, " "),
+        (Class::AttributeName, "class"), (Class::Tag, "="), (Class::AttributeValue, "\"x\""), (Class::Tag, ">"),
+        (Class::Text, "a "), (Class::CharacterReference, "&amp;"), (Class::Text, " b"), (Class::Comment, "<!--c-->"),
+        (Class::Tag, "</"), (Class::TagName, "p"), (Class::Tag, ">"),
+    ]);
+    assert_eq!(to_html("<b id=x>&amp;</b>").unwrap(),
+        "<span class=\"html-tag\">&lt;</span><span class=\"html-tag-name\">b</span><span class=\"html-tag\"> </span>\
+        <span class=\"html-attribute-name\">id</span><span class=\"html-tag\">=</span><span class=\"html-attribute-value\">x</span>\
+        <span class=\"html-tag\">&gt;</span><span class=\"html-reference\">&amp;amp;</span><span class=\"html-tag\">&lt;/</span>\
+        <span class=\"html-tag-name\">b</span><span class=\"html-tag\">&gt;</span>");
+    assert_eq!(to_ansi("<i>x</i>").unwrap(), "\x1b[34m<\x1b[0m\x1b[1;34mi\x1b[0m\x1b[34m>\x1b[0mx\x1b[34m</\x1b[0m\x1b[1;34mi\x1b[0m\x1b[34m>\x1b[0m");
+}
@@ -0,0 +1,74 @@
+use crate::{markdown::{element_to_markdown, to_markdown}, Document};
+
+fn markdown(html: &str) -> String {
+    to_markdown(&Document::parse(html).unwrap())
+}
+
+#[test]
+fn markdown_blocks() {
+    assert_eq!(markdown("<title>Skipped</title><h1>Title</h1><p>Some  <b>bold</b>,\n<em>emphasis</em> and <del>gone</del>.</p><hr><h3>Sub <br>heading</h3>"),
+        "# Title\n\nSome **bold**, *emphasis* and ~~gone~~.\n\n---\n\n### Sub heading\n");
+    assert_eq!(markdown("<div>Loose text<p>Para</p>more</div>"), "Loose text\n\nPara\n\nmore\n");
+    assert_eq!(markdown("<p>line<br>break<br></p>"), "line\\\nbreak\n");
+    assert_eq!(markdown("<blockquote><p>Quoted</p><p>Twice</p><blockquote>Nested</blockquote></blockquote>"),
+        "> Quoted\n>\n> Twice\n>\n> > Nested\n");
+    assert_eq!(markdown(""), "");
+}
+
+#[test]
+fn markdown_inline() {
+    assert_eq!(markdown("<p><b> spaced </b>x<i></i><a href='https://example.com' title='A \"t\"'>link</a> \
+        <a href='/a b'>spaces</a> <a>no href</a> <img src=cat.png alt='A *cat*'></p>"),
+        "**spaced** x[link](https://example.com \"A \\\"t\\\"\") [spaces](</a b>) no href ![A \\*cat\\*](cat.png)\n");
+    assert_eq!(markdown("<p>Use <code>a `b`</code> or <code>`x</code></p>"), "Use `` a `b` `` or `` `x ``\n");
+    assert_eq!(markdown("<p>H<sub>2</sub>O and <kbd>Ctrl</kbd></p>"), "H<sub>2</sub>O and <kbd>Ctrl</kbd>\n");
+}
+
+#[test]
+fn markdown_escaping() {
+    assert_eq!(markdown("<p>*not* _emphasis_ [x](y) &lt;tag&gt; a\\b &amp;copy; AT&amp;T</p>"),
+        "\\*not\\* \\_emphasis\\_ \\[x\\](y) \\<tag\\> a\\\\b \\&copy; AT&T\n");
+    assert_eq!(markdown("<p># not a heading</p><p>- not a list</p><p>1. not a list</p><p>2024 was</p>"),
+        "\\# not a heading\n\n\\- not a list\n\n1\\. not a list\n\n2024 was\n");
+}
+
+#[test]
+fn markdown_lists() {
+    assert_eq!(markdown("<ul><li>One</li><li>Two<ul><li>Nested</li><li>Also <b>bold</b></li></ul></li></ul>"),
+        "- One\n- Two\n  - Nested\n  - Also **bold**\n");
+    assert_eq!(markdown("<ol start=9><li>Nine</li><li>Ten<ol><li>Inner</li></ol></li></ol>"), "9. Nine\n10. Ten\n    1. Inner\n");
+    assert_eq!(markdown("<ul><li><p>First</p><p>More</p></li><li><p>Second</p></li></ul>"), "- First\n\n  More\n\n- Second\n");
+    assert_eq!(markdown("<ul><li>Item<pre><code>code\n  indented</code></pre></li></ul>"), "- Item\n  ```\n  code\n    indented\n  ```\n");
+}
+
+#[test]
+fn markdown_code_blocks() {
+    assert_eq!(markdown("<pre><code class='language-rust'>fn main() {\n    println!(\"*\");\n}\n</code></pre>"),
+        "```rust\nfn main() {\n    println!(\"*\");\n}\n```\n");
+    assert_eq!(markdown("<pre>has ``` fence</pre>"), "````\nhas ``` fence\n````\n");
+}
+
+#[test]
+fn markdown_tables() {
+    assert_eq!(markdown("<table><thead><tr><th>Name</th><th>Note</th></tr></thead><tbody><tr><td>a|b</td><td><b>x</b></td></tr><tr><td>only</td></tr></tbody></table>"),
+        "| Name | Note |\n| --- | --- |\n| a\\|b | **x** |\n| only |  |\n");
+    // Merged cells can't be written as Markdown, so the table stays HTML.
+    assert_eq!(markdown("<table><tr><td colspan=2>wide</td></tr></table>"),
+        "<table><tbody><tr><td colspan=\"2\">wide</td></tr></tbody></table>\n");
+}
+
+#[test]
+fn markdown_unsupported_elements() {
+    assert_eq!(markdown("<dl><dt>Term</dt><dd>Definition</dd></dl><details><summary>More</summary>Hidden</details>"),
+        "<dl><dt>Term</dt><dd>Definition</dd></dl>\n\n<details><summary>More</summary>Hidden</details>\n");
+    assert_eq!(markdown("<body><script>x()</script><style>p {}</style><template>t</template><!-- c -->text"), "text\n");
+}
+
+#[test]
+fn markdown_element_and_deep_nesting() {
+    let document = Document::parse("<div id=x><h2>Part</h2><p>Body</p></div><p>Other</p>").unwrap();
+    assert_eq!(element_to_markdown(document.get_element_by_id("x").unwrap()), "## Part\n\nBody\n");
+
+    let deep = "<div>".repeat(300) + "deep" + &"<b>".repeat(300) + "bold";
+    assert_eq!(markdown(&deep), "deepbold\n");
+}
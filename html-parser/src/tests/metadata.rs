@@ -0,0 +1,166 @@
+use crate::{metadata::{extract, PropertyValue}, tokenizer::SourcePosition, Document, HtmlParser, ParseState, sink::PositionSink};
+
+const PAGE: &str = "<!DOCTYPE html>
+<html lang=en dir=ltr>
+<head>
+  <title> Caf&#xE9;   &amp; Bar </title>
+  <!-- <meta name=description content=commented> -->
+  <base href='/root/'>
+  <meta charset=utf-8>
+  <meta name=description content='Coffee &amp; more'>
+  <meta property=og:title content='The Caf&#233;'>
+  <meta name=twitter:card content=summary>
+  <link rel=canonical href='https://cafe.example/'>
+  <link rel='icon shortcut' href=/favicon.ico sizes=32x32>
+  <link rel=alternate hreflang=fr href=/fr/>
+  <link rel=stylesheet href=style.css>
+  <script type='application/ld+json'>{\"@type\": \"Cafe\", \"name\": \"a &amp; b\"}</script>
+  <script>var meta = '<meta name=description content=script>';</script>
+</head>
+<body>
+  <template><meta name=description content=template></template>
+</body>
+</html>";
+
+#[test]
+fn metadata_head() {
+    let metadata = extract(&Document::parse_with_positions(PAGE).unwrap());
+    let title = metadata.title.as_ref().unwrap();
+    assert_eq!(title.value, "Café & Bar");
+    assert_eq!(title.position, Some(SourcePosition { offset: 48, line: 4, column: 3 }));
+    assert_eq!(metadata.lang.as_ref().unwrap().value, "en");
+    assert_eq!(metadata.dir.as_ref().unwrap().value, "ltr");
+    assert_eq!(metadata.base.as_ref().unwrap().value, "/root/");
+    // Comments, script content and templates aren't metadata, and <meta charset> has no content.
+    assert_eq!(metadata.metas.len(), 3);
+    assert_eq!(metadata.description(), Some("Coffee & more"));
+    assert_eq!(metadata.metas[0].position.map(|position| position.line), Some(8));
+    assert_eq!(metadata.open_graph().collect::<Vec<_>>(), vec![("og:title", "The Café")]);
+    assert_eq!(metadata.twitter().collect::<Vec<_>>(), vec![("twitter:card", "summary")]);
+
+    assert_eq!(metadata.canonical(), Some("https://cafe.example/"));
+    assert_eq!(metadata.links.len(), 4);
+    assert_eq!(metadata.links[1].rel, vec!["icon", "shortcut"]);
+    assert_eq!(metadata.links[1].sizes.as_deref(), Some("32x32"));
+    assert_eq!(metadata.links_with_rel("alternate").next().unwrap().hreflang.as_deref(), Some("fr"));
+    assert_eq!(metadata.links[3].position.map(|position| (position.line, position.column)), Some((14, 3)));
+
+    // Script content is raw text, character references in it are kept.
+    assert_eq!(metadata.json_ld.len(), 1);
+    assert_eq!(metadata.json_ld[0].value, "{\"@type\": \"Cafe\", \"name\": \"a &amp; b\"}");
+    assert_eq!(metadata.json_ld[0].position.map(|position| position.line), Some(15));
+}
+
+#[test]
+fn metadata_without_positions() {
+    let metadata = extract(&Document::parse("<title>x</title><meta name=a content=b>").unwrap());
+    assert_eq!(metadata.title.as_ref().unwrap().position, None);
+    assert_eq!(metadata.meta("A"), Some("b"));
+    assert_eq!(metadata.lang, None);
+}
+
+#[test]
+fn metadata_microdata() {
+    let html = "<body>
+<div itemscope itemtype='https://schema.org/Person' itemid=urn:p:1 itemref=extra>
+  <span itemprop='name alternateName'>Ann <b>Lee</b></span>
+  <a itemprop=url href=/ann>home</a>
+  <img itemprop=image src=ann.png>
+  <time itemprop=birthDate datetime=1990-01-01>New year</time>
+  <meta itemprop=gender content=female>
+  <div itemprop=address itemscope itemtype=https://schema.org/PostalAddress>
+    <span itemprop=locality>Paris</span>
+  </div>
+</div>
+<p id=extra><span itemprop=jobTitle>Baker</span></p>
+<div itemscope itemref=loop id=loop><span itemprop=x>y</span></div>
+</body>";
+    let metadata = extract(&Document::parse_with_positions(html).unwrap());
+    assert_eq!(metadata.items.len(), 2);
+    let person = &metadata.items[0];
+    assert_eq!(person.types, vec!["https://schema.org/Person"]);
+    assert_eq!(person.id.as_deref(), Some("urn:p:1"));
+    assert_eq!(person.position.map(|position| position.line), Some(2));
+    let text = |value: &str| PropertyValue::Text(value.to_string());
+    assert_eq!(person.property("name").collect::<Vec<_>>(), vec![&text("Ann Lee")]);
+    assert_eq!(person.property("alternateName").collect::<Vec<_>>(), vec![&text("Ann Lee")]);
+    assert_eq!(person.property("url").collect::<Vec<_>>(), vec![&PropertyValue::Url("/ann".to_string())]);
+    assert_eq!(person.property("image").collect::<Vec<_>>(), vec![&PropertyValue::Url("ann.png".to_string())]);
+    assert_eq!(person.property("birthDate").collect::<Vec<_>>(), vec![&text("1990-01-01")]);
+    assert_eq!(person.property("gender").collect::<Vec<_>>(), vec![&text("female")]);
+    assert_eq!(person.property("jobTitle").collect::<Vec<_>>(), vec![&text("Baker")]);
+    let Some(PropertyValue::Item(address)) = person.property("address").next() else {
+        panic!("the address is an item");
+    };
+    assert_eq!(address.property("locality").collect::<Vec<_>>(), vec![&text("Paris")]);
+    // The locality belongs to the address only.
+    assert_eq!(person.property("locality").count(), 0);
+    let address_property = person.properties.iter().find(|property| property.name == "address").unwrap();
+    assert_eq!(address_property.position.map(|position| position.line), Some(8));
+
+    // An item that refers to itself.
+    assert_eq!(metadata.items[1].property("x").collect::<Vec<_>>(), vec![&text("y")]);
+}
+
+#[test]
+fn metadata_deep_microdata() {
+    let depth = 5_000;
+    let html = format!("<body>{}{}", "<div itemscope itemprop=child>".repeat(depth), "</div>".repeat(depth));
+    let html = html.replacen(" itemprop=child", "", 1);
+    let metadata = extract(&Document::parse(&html).unwrap());
+    assert_eq!(metadata.items.len(), 1);
+}
+
+#[test]
+fn metadata_itemref_cycles() {
+    // Every item refers to all the others, so there are many paths through them.
+    let ids = ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l"];
+    let items: String = ids.iter().map(|id| {
+        let others: Vec<&str> = ids.iter().copied().filter(|other| other != id).collect();
+        format!("<div itemscope itemprop=p itemref='{}' id={}><span itemprop=name>{}</span></div>", others.join(" "), id, id)
+    }).collect();
+    let html = format!("<body><div itemscope itemref=a></div>{}", items);
+    let start = std::time::Instant::now();
+    let metadata = extract(&Document::parse(&html).unwrap());
+    assert!(start.elapsed() < std::time::Duration::from_secs(10));
+    assert_eq!(metadata.items.len(), 1);
+    let Some(PropertyValue::Item(a)) = metadata.items[0].property("p").next() else {
+        panic!("a is an item");
+    };
+    assert_eq!(a.property("name").collect::<Vec<_>>(), vec![&PropertyValue::Text("a".to_string())]);
+    // An item isn't a property of itself, even through the items it refers to.
+    let Some(PropertyValue::Item(b)) = a.property("p").next() else {
+        panic!("b is an item");
+    };
+    assert_eq!(b.property("name").collect::<Vec<_>>(), vec![&PropertyValue::Text("b".to_string())]);
+    assert!(b.property("p").all(|value| !matches!(value, PropertyValue::Item(item) if item.property("name").any(|name| *name == PropertyValue::Text("a".to_string())))));
+
+    let four = "<body><div itemscope itemref=a></div><div itemscope itemprop=p itemref='a b c' id=a></div>\
+        <div itemscope itemprop=p itemref='a b c' id=b></div><div itemscope itemprop=p itemref='a b c' id=c></div>\
+        <div itemscope itemprop=p itemref='a b c' id=d></div>";
+    let metadata = extract(&Document::parse(four).unwrap());
+    assert_eq!(metadata.items.len(), 1);
+}
+
+#[test]
+fn source_positions() {
+    let html = "<p>a\r\nb</p>\n<!--c-->\n  <é>x</é><div\nid=d>";
+    let document = Document::parse_with_positions(html).unwrap();
+    let p = document.get_elements_by_tag_name("p")[0];
+    assert_eq!(p.source_position(), Some(SourcePosition { offset: 0, line: 1, column: 1 }));
+    assert_eq!(p.as_node().first_child().unwrap().source_position(), Some(SourcePosition { offset: 3, line: 1, column: 4 }));
+    let comment = document.root().descendants().find(|node| node.as_comment().is_some()).unwrap();
+    assert_eq!(comment.source_position(), Some(SourcePosition { offset: 12, line: 3, column: 1 }));
+    // Columns count characters, offsets count bytes.
+    let div = document.get_element_by_id("d").unwrap();
+    assert_eq!(div.source_position(), Some(SourcePosition { offset: 33, line: 4, column: 11 }));
+
+    // Streaming gives the same positions, even for tags that are cut in half by a chunk.
+    let mut input = String::from("<p>");
+    input.push_str(&"x".repeat(64 * 1024 - 5));
+    input.push_str("<span\nclass=s>y</span>");
+    let (tree, positions) = HtmlParser::parse_stream(input.as_bytes(), ParseState::with_sink(PositionSink::new())).unwrap();
+    let document = Document::new(tree);
+    let span = document.get_elements_by_class_name("s")[0];
+    assert_eq!(positions.get(&span.node_id()), Some(&SourcePosition { offset: 64 * 1024 - 2, line: 1, column: 64 * 1024 - 1 }));
+}
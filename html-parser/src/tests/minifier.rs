@@ -0,0 +1,110 @@
+use crate::{dom::Node, minifier::{minify, MinifyOptions}, serializer, Document, NodeId, Tree};
+
+const DOCUMENTS: &[&str] = &[
+    "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n  <meta charset=\"utf-8\">\n  <title>  A   page </title>\n  <link rel=\"stylesheet\" href=\"style.css\">\n</head>\n<body>\n  <h1 class=\"title main\">Hello,   <em>world</em> !</h1>\n  <p>First paragraph.</p>\n  <p>Second <a href=\"/x?a=1&amp;b=2\">link</a> and <b>bold</b> <i>italic</i> text.</p>\n</body>\n</html>\n",
+    "<ul>\n  <li>One</li>\n  <li>Two <ul><li>Nested</li></ul></li>\n</ul>\n<ol start=\"3\"><li>a</li><li>b</li></ol>\n<dl><dt>Term</dt><dd>Definition</dd><dt>Other</dt><dd>More</dd></dl>",
+    "<table>\n  <caption>Caption</caption>\n  <colgroup><col span=\"2\"></colgroup>\n  <thead><tr><th>A</th><th>B</th></tr></thead>\n  <tbody><tr><td>1</td><td>2</td></tr><tr><td>3</td><td>4</td></tr></tbody>\n  <tfoot><tr><td>5</td><td>6</td></tr></tfoot>\n</table>",
+    "<body><pre>\n  keep   this\n    <b> and  this </b>\n</pre><textarea>\n  text  &amp; area </textarea><p>after</p></body>",
+    "<body><script>if (a < b && c) { x = \"</p>\"; }</script><style>p > a { color : red }</style><p>x</p></body>",
+    "<body><select><option value=\"1\" selected=\"selected\">One</option><optgroup label=\"g\"><option disabled=\"\">Two</option></optgroup></select>\
+        <input type=\"checkbox\" checked=\"checked\" value=\"a b\"><input value='say \"hi\"'><input value=\"it's\"></body>",
+    "<body><p>Text with &lt;tags&gt; &amp; entities &copy; &#169; and a&b and &amp;amp;</p><p title=\"a &amp; b &lt; c\">x</p><div><p>In a div</p></div><a href=#><p>In a link</p></a></body>",
+    "<!-- a comment --><!DOCTYPE html><html><!-- in html --><head></head><body><!--[if IE]><p>IE</p><![endif]--><p>a<!-- inside -->b</p></body></html><!-- after -->",
+    "<body><p>One<p>Two<div>Three</div><ruby>漢<rp>(</rp><rt>kan</rt><rp>)</rp></ruby> <span> spaced </span> <img src=\"a.png\" alt=\"\"> after</body>",
+];
+
+// What the tree looks like once whitespace that isn't rendered, comments and the values of boolean
+// attributes are left out, which is what the minifier is allowed to change.
+fn normalized(tree: &Tree<Node>, node: NodeId, output: &mut String, preformatted: bool) {
+    for child in tree.children(node) {
+        match tree.get(child) {
+            Some(Node::Element(element)) => {
+                output.push_str(&format!("<{}", element.name()));
+                for attribute in &element.attributes {
+                    let boolean = ["checked", "selected", "disabled"].contains(&attribute.name.as_str());
+                    output.push_str(&format!(" {}={:?}", attribute.name, if boolean { "" } else { &attribute.value }));
+                }
+                output.push('>');
+                let preformatted = preformatted || matches!(element.name(), "pre" | "textarea" | "script" | "style");
+                normalized(tree, child, output, preformatted);
+                output.push_str(&format!("</{}>", element.name()));
+            },
+            Some(Node::Text(text)) if preformatted => output.push_str(text),
+            Some(Node::Text(text)) => {
+                let words: Vec<&str> = text.split_ascii_whitespace().collect();
+                if !words.is_empty() {
+                    output.push_str(&format!("[{}]", words.join(" ")));
+                }
+            },
+            Some(Node::Doctype { name, .. }) => output.push_str(&format!("<!{:?}>", name)),
+            _ => (),
+        }
+    }
+}
+
+fn normalize(html: &str) -> String {
+    let tree = Document::parse(html).unwrap().into_tree();
+    let mut output = String::new();
+    normalized(&tree, tree.root(), &mut output, false);
+    // Text nodes that were split by a comment are merged once the comment is gone.
+    output.replace("][", "")
+}
+
+#[test]
+fn minify_round_trip() {
+    // Without the options that change the tree, the output parses to exactly the same tree.
+    let exact = MinifyOptions { collapse_whitespace: false, remove_comments: false, collapse_boolean_attributes: false, ..MinifyOptions::default() };
+    for html in DOCUMENTS {
+        let minified = minify(html, &exact).unwrap();
+        assert_eq!(Document::parse(&minified).unwrap().to_html(), Document::parse(html).unwrap().to_html(), "{}", minified);
+        assert!(minified.len() <= html.len());
+
+        let minified = minify(html, &MinifyOptions::default()).unwrap();
+        assert_eq!(normalize(&minified), normalize(html), "{}", minified);
+        assert!(minified.len() < html.len());
+        // Minifying again doesn't change anything.
+        assert_eq!(minify(&minified, &MinifyOptions::default()).unwrap(), minified);
+    }
+}
+
+#[test]
+fn minify_optional_tags() {
+    let minify = |html: &str| minify(html, &MinifyOptions::default()).unwrap();
+    assert_eq!(minify(DOCUMENTS[0]), "<!DOCTYPE html><html lang=en><meta charset=utf-8><title>A page</title>\
+        <link rel=stylesheet href=style.css><h1 class=\"title main\">Hello, <em>world</em> !</h1><p>First paragraph.\
+        <p>Second <a href=\"/x?a=1&amp;b=2\">link</a> and <b>bold</b> <i>italic</i> text.");
+    assert_eq!(minify(DOCUMENTS[1]), "<ul><li>One<li>Two<ul><li>Nested</ul></ul><ol start=3><li>a<li>b</ol>\
+        <dl><dt>Term<dd>Definition<dt>Other<dd>More</dl>");
+    assert_eq!(minify(DOCUMENTS[2]), "<table><caption>Caption<col span=2><thead><tr><th>A<th>B\
+        <tbody><tr><td>1<td>2<tr><td>3<td>4<tfoot><tr><td>5<td>6</table>");
+    // A <p> in a link has to be closed, and so do the ones that a start tag doesn't close.
+    assert_eq!(minify("<a href=/><p>x</p></a><p>y</p><span>z</span>"), "<a href=/><p>x</p></a><p>y</p><span>z</span>");
+    // The head and body start tags are needed when there are attributes, or when the body starts with an
+    // element that would otherwise go in the head.
+    // Here the <thead> is closed by the <tbody> start tag, which has to stay.
+    assert_eq!(minify("<table><thead><tr><td>a<tbody><tr><td>b</table>"), "<table><thead><tr><td>a<tbody><tr><td>b</table>");
+    assert_eq!(minify("<html><head><title>t</title></head><body class=x>b</body></html>"), "<title>t</title><body class=x>b");
+    assert_eq!(minify("<html><head></head><body><script>s()</script></body></html>"), "<body><script>s()</script>");
+}
+
+#[test]
+fn minify_whitespace_and_comments() {
+    let minify = |html: &str| minify(html, &MinifyOptions::default()).unwrap();
+    assert_eq!(minify(DOCUMENTS[3]), "<pre>\n  keep   this\n    <b> and  this </b>\n</pre><textarea>\n  text  & area </textarea><p>after");
+    assert_eq!(minify(DOCUMENTS[4]), "<body><script>if (a < b && c) { x = \"</p>\"; }</script><style>p > a { color : red }</style><p>x");
+    // Whitespace between inline elements is rendered, so it is collapsed instead of removed.
+    assert_eq!(minify("<div>\n  <b>a</b>\n  <i>b</i>  c \n</div>"), "<div><b>a</b> <i>b</i> c</div>");
+    assert_eq!(minify("<p>a <b> b </b> c</p>"), "<p>a <b>b </b>c");
+    // Conditional comments are kept.
+    assert_eq!(minify(DOCUMENTS[7]), "<!DOCTYPE html><body><!--[if IE]><p>IE</p><![endif]--><p>ab");
+}
+
+#[test]
+fn minify_attributes_and_text() {
+    let minify = |html: &str| minify(html, &MinifyOptions::default()).unwrap();
+    assert_eq!(minify(DOCUMENTS[5]), "<select><option value=1 selected>One<optgroup label=g><option disabled>Two</select>\
+        <input type=checkbox checked value=\"a b\"><input value='say \"hi\"'><input value=\"it's\">");
+    assert_eq!(minify(DOCUMENTS[6]), "<p>Text with &lt;tags> & entities © © and a&amp;b and &amp;amp;<p title=\"a & b < c\">x\
+        <div><p>In a div</div><a href=#><p>In a link</p></a>");
+    assert_eq!(minify("<a title=\"&quot;'\" href=\"a=b\">x</a>"), "<a title=\"&quot;'\" href=\"a=b\">x</a>");
+}
@@ -1,3 +1,43 @@
 //https://github.com/web-platform-tests/wpt
 #[cfg(test)]
 mod tokenizer_general_use_cases;
+#[cfg(test)]
+mod sax_events;
+#[cfg(test)]
+mod pipeline;
+#[cfg(test)]
+mod limits;
+#[cfg(test)]
+mod no_panic;
+#[cfg(test)]
+mod persistent_sink;
+#[cfg(test)]
+mod json;
+#[cfg(test)]
+mod diff;
+#[cfg(test)]
+mod document;
+#[cfg(test)]
+mod query;
+#[cfg(test)]
+mod serializer;
+#[cfg(test)]
+mod sanitizer;
+#[cfg(test)]
+mod text;
+#[cfg(test)]
+mod markdown;
+#[cfg(test)]
+mod metadata;
+#[cfg(test)]
+mod lint;
+#[cfg(test)]
+mod minifier;
+#[cfg(test)]
+mod lossless;
+#[cfg(test)]
+mod incremental;
+#[cfg(test)]
+mod snapshot;
+#[cfg(test)]
+mod token_stream;
@@ -0,0 +1,120 @@
+// The offline counterpart of the fuzz targets in `fuzz/`. Same seed corpus, but proptest generates
+// the inputs, so this runs on a stable toolchain as part of the regular tests.
+use proptest::prelude::*;
+use crate::{error::HtmlTokenizerError, limits::ParserLimits, preproccesor::PreProccessor, states::TokenizationState,
+    tokenizer::{Token, TokenStream, Tokenizer}, HtmlParser, ParseState};
+
+const SEED : &str = include_str!("../../fuzz/corpus/parse/test.html");
+
+// Pieces of markup that lead the tokenizer and the tree builder into most of their states.
+const FRAGMENTS : &[&str] = &[
+    "<", ">", "/", "</", "<!", "<!--", "-->", "--!>", "<!DOCTYPE", "<!doctype html>", "<?", "<![CDATA[", "]]>",
+    "&", "&amp;", "&amp", "&#", "&#x", "&#0;", "&#x110000;", "&#128;", "&#xD800;", "&notanentity;", "&copy=",
+    "=", "\"", "'", "`", " ", "\n", "\t", "\0", "\r", "\u{FFFD}", "é", "𝒳",
+    "<html>", "<head>", "<body>", "<title>", "</title>", "<textarea>", "</textarea>", "<style>", "</style>",
+    "<script>", "</script>", "<plaintext>", "<xmp>", "<iframe>", "<noembed>", "<noframes>", "<noscript>",
+    "<p>", "</p>", "<div>", "</div>", "<li>", "<dd>", "<dt>", "<pre>", "<listing>", "<form>", "</form>",
+    "<button>", "<a href=x>", "</a>", "<b>", "</b>", "<br>", "</br>", "<hr>", "<img src=x>", "<input>",
+    "<table>", "</table>", "<caption>", "<colgroup>", "<col>", "<tbody>", "<tr>", "</tr>", "<td>", "</td>", "<th>",
+    "<select>", "</select>", "<option>", "<optgroup>", "<frameset>", "</frameset>", "<frame>", "<template>", "</template>",
+    "<svg>", "<math>", "</html>", "</body>", "</head>", "<a b c=d e='f' g=\"h\" />",
+];
+
+fn markup() -> impl Strategy<Value = String> {
+    prop::collection::vec(prop_oneof![
+        4 => prop::sample::select(FRAGMENTS).prop_map(String::from),
+        1 => any::<char>().prop_map(String::from),
+        1 => "[a-z]{1,8}",
+    ], 0..64).prop_map(|pieces| pieces.concat())
+}
+
+// The seed document with a piece of markup spliced in somewhere.
+fn mutated_seed() -> impl Strategy<Value = String> {
+    (0..=SEED.len(), 0..=SEED.len(), markup()).prop_map(|(a, b, insert)| {
+        let (start, end) = (a.min(b), a.max(b));
+        format!("{}{}{}", &SEED[..start], insert, &SEED[end..])
+    })
+}
+
+pub(super) fn html_input() -> impl Strategy<Value = String> {
+    prop_oneof![any::<String>(), markup(), mutated_seed()]
+}
+
+fn check_no_panic(input: &str) {
+    if let Ok(html) = PreProccessor::new(input) {
+        for _ in Tokenizer::new(html) {}
+    }
+    if let Ok(html) = PreProccessor::new(input) {
+        let _ = TokenStream::try_from(Tokenizer::new(html));
+    }
+    let _ = HtmlParser::parse(input, ParseState::new());
+    let _ = HtmlParser::parse(input, ParseState::new().with_limits(ParserLimits::untrusted()));
+    let _ = HtmlParser::parse_stream(input.as_bytes(), ParseState::new());
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig { cases : 2000, ..ProptestConfig::default() })]
+
+    #[test]
+    fn no_panic_on_any_input(input in html_input()) {
+        check_no_panic(&input);
+    }
+}
+
+#[test]
+fn no_panic_seed_corpus() {
+    check_no_panic(SEED);
+    check_no_panic(include_str!("../../../resources/test.html"));
+}
+
+#[test]
+fn no_panic_character_references() {
+    let tokens = TokenStream::try_from(Tokenizer::new(PreProccessor::new("a &amp; b &lt&#x41;&#66&#0;&unknown; &").unwrap())).unwrap().tokens;
+    let text: String = tokens.iter().filter_map(|t| match t { Token::Character(c) => Some(*c), _ => None }).collect();
+    assert_eq!(text, "a & b <AB\u{FFFD}&unknown; &");
+
+    let tokens = TokenStream::try_from(Tokenizer::new(PreProccessor::new("<a href=\"?a=1&amp;b=2&copy=3\" title=&lt;>").unwrap())).unwrap().tokens;
+    match &tokens[0] {
+        Token::StartTag(_, _, attributes) => {
+            assert_eq!(attributes[0].value, "?a=1&b=2&copy=3");
+            assert_eq!(attributes[1].value, "<");
+        },
+        token => panic!("unexpected token {:?}", token),
+    }
+}
+
+#[test]
+fn no_panic_end_of_input() {
+    let tokens = |input: &str| TokenStream::try_from(Tokenizer::new(PreProccessor::new(input).unwrap())).unwrap().tokens;
+    assert_eq!(tokens("<!-- unterminated"), vec![Token::Comment(String::from(" unterminated"))]);
+    assert_eq!(tokens("<!DOCTYPE html"), vec![Token::DOCTYPE(Some(String::from("html")), None, None, true)]);
+    assert_eq!(tokens("a<"), vec![Token::Character('a'), Token::Character('<')]);
+    assert_eq!(tokens("<div class=\"unterminated"), vec![]);
+}
+
+#[test]
+fn no_panic_unsupported_states() {
+    let mut tokens = Tokenizer::new(PreProccessor::new("x]]>y").unwrap());
+    tokens.switch_to(TokenizationState::CDATASection);
+    assert!(matches!(tokens.next(), Some(Err(HtmlTokenizerError::Unsupported(TokenizationState::CDATASection)))));
+    // The tokenizer carries on afterwards.
+    assert_eq!(tokens.next().unwrap().unwrap(), Token::Character('x'));
+
+    let mut tokens = Tokenizer::new(PreProccessor::new("\"id\">").unwrap());
+    tokens.switch_to(TokenizationState::BeforeDOCTYPEPublicIdentifier);
+    assert!(matches!(tokens.next(), Some(Err(HtmlTokenizerError::Unsupported(_)))));
+    assert!(matches!(tokens.next(), Some(Ok(Token::DOCTYPE(..)))));
+    assert!(tokens.next().is_none());
+}
+
+#[test]
+fn no_panic_attribute_states_without_attribute() {
+    for state in [TokenizationState::AttributeName, TokenizationState::BeforeAttributeValue, TokenizationState::AttributeValueDoubleQuoted,
+        TokenizationState::AttributeValueSingleQuoted, TokenizationState::AttributeValueUnquoted] {
+        let mut tokens = Tokenizer::new(PreProccessor::new("x=\"y\">z").unwrap());
+        tokens.switch_to(state);
+        assert!(matches!(tokens.next(), Some(Err(HtmlTokenizerError::MissingAttribute(s))) if s == state));
+        // The tokenizer carries on afterwards.
+        assert!(tokens.all(|token| token.is_ok()));
+    }
+}
@@ -0,0 +1,36 @@
+use dds_utils::tree::persistent::{PersistentNode, PersistentTree};
+
+use crate::{sink::PersistentSink, HtmlParser, Node, ParseState, Tree};
+
+fn flatten(node: &PersistentNode<Node>, out: &mut Vec<Node>) {
+    out.push(node.value().clone());
+    for child in node.children() {
+        flatten(child, out);
+    }
+}
+
+#[test]
+fn persistent_sink_matches_arena() {
+    let html = "<!DOCTYPE html><html><body><p class=a>One<b>two</b>three<!-- c --><table><tr><td>x</table><p>four";
+    let persistent: PersistentTree<Node> = HtmlParser::parse(html, ParseState::with_sink(PersistentSink::new())).unwrap();
+    let arena: Tree<Node> = HtmlParser::parse(html, ParseState::new()).unwrap();
+
+    let mut nodes = Vec::new();
+    flatten(persistent.root(), &mut nodes);
+    let expected: Vec<Node> = std::iter::once(arena.root()).chain(arena.descendants(arena.root()))
+        .map(|id| arena.get(id).unwrap().clone())
+        .collect();
+    assert_eq!(nodes, expected);
+}
+
+#[test]
+fn persistent_sink_deep() {
+    let html = format!("{}{}", "<div>".repeat(20_000), "text ".repeat(1000));
+    let persistent: PersistentTree<Node> = HtmlParser::parse(&html, ParseState::with_sink(PersistentSink::new())).unwrap();
+    // html > body > 20000 divs > text
+    let mut path = vec![0, 1];
+    path.resize(2 + 20_001, 0);
+    let text = persistent.get(&path).unwrap();
+    assert_eq!(text.value(), &Node::Text("text ".repeat(1000)));
+    assert_eq!(text.children().len(), 0);
+}
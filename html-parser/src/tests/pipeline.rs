@@ -0,0 +1,52 @@
+#[allow(unused)]
+use crate::{sax::EventSink, sink::PositionSink, HtmlParser, ParseState};
+#[allow(unused)]
+use super::sax_events::Recorder;
+
+#[allow(unused)]
+fn assert_same_events(html: &str) {
+    let single_threaded = HtmlParser::parse(html, ParseState::with_sink(EventSink::new(Recorder::default()))).unwrap().events;
+    let pipelined = HtmlParser::parse_pipelined(html, ParseState::with_sink(EventSink::new(Recorder::default()))).unwrap().events;
+    assert_eq!(pipelined, single_threaded);
+}
+
+#[test]
+fn pipeline_basic() {
+    assert_same_events("<html><head></head><body><p>One<p>Two</body></html>");
+}
+
+#[test]
+fn pipeline_tokenizer_state_switches() {
+    // Each of these relies on the tree builder switching the tokenizer's state before it reads on.
+    assert_same_events("<title><b>not a tag</b></title><style>a < b {}</style><script>if (a</b) {}</script><textarea></p></textarea><plaintext></plaintext>");
+}
+
+#[test]
+fn pipeline_many_batches() {
+    let mut html = String::from("<!DOCTYPE html><table>");
+    for i in 0..2000 {
+        html.push_str(&format!("<tr><td>{}<td><title>{}</title><!-- row -->", i, i));
+    }
+    assert_same_events(&html);
+}
+
+#[test]
+fn pipeline_arena_output() {
+    let html = "<ul><li>a<li>b</ul>";
+    let single_threaded = HtmlParser::parse(html, ParseState::new()).unwrap();
+    let pipelined = HtmlParser::parse_pipelined(html, ParseState::new()).unwrap();
+    let nodes = |tree: &crate::Tree<crate::Node>| tree.iter().map(|(_, node)| node.clone()).collect::<Vec<_>>();
+    assert_eq!(nodes(&pipelined), nodes(&single_threaded));
+}
+
+#[test]
+fn pipeline_source_positions() {
+    let mut html = String::from("<!DOCTYPE html>\n<title>a</title>\n<table>");
+    for i in 0..2000 {
+        html.push_str(&format!("<tr>\n<td>{}<td><!-- row --></td>", i));
+    }
+    let single_threaded = HtmlParser::parse(&html, ParseState::with_sink(PositionSink::new())).unwrap();
+    let pipelined = HtmlParser::parse_pipelined(&html, ParseState::with_sink(PositionSink::new())).unwrap();
+    assert_eq!(pipelined.1.len(), single_threaded.1.len());
+    assert_eq!(pipelined.1, single_threaded.1);
+}
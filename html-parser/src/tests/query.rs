@@ -0,0 +1,79 @@
+use crate::Document;
+
+const HTML: &str = "<!DOCTYPE html><html><head><title>Cards</title></head><body>\
+    <div class='card' id=first><h2>One</h2><a href='https://example.com'>ext</a><a href='/local'>local</a></div>\
+    <div class='card wide'><h2 lang=en-GB>Two</h2><p></p><p><!-- only a comment --></p><a href=HTTP://X>upper</a></div>\
+    <ul><li>1</li><li class=x>2</li><li>3</li><li class=x>4</li><li>5</li></ul>\
+    <section><span></span><b>b</b><span>s</span></section></body></html>";
+
+fn select(document: &Document, selectors: &str) -> Vec<String> {
+    document.query_selector_all(selectors).unwrap().iter().map(|element| element.text_content()).collect()
+}
+
+#[test]
+fn query_selector_basic() {
+    let document = Document::parse(HTML).unwrap();
+    assert_eq!(select(&document, "div.card > a[href^=http]"), ["ext"]);
+    assert_eq!(select(&document, "div.card > a[href^=http i]"), ["ext", "upper"]);
+    assert_eq!(select(&document, "#first a"), ["ext", "local"]);
+    assert_eq!(select(&document, ".card.wide h2, #first h2"), ["One", "Two"]);
+    assert_eq!(select(&document, "[lang|=en]"), ["Two"]);
+    assert_eq!(select(&document, "[class~=wide] > *:empty").len(), 2);
+    assert_eq!(select(&document, "H2 + A"), ["ext"]);
+    assert_eq!(select(&document, "h2 ~ a:not([href*=local])"), ["ext", "upper"]);
+    assert_eq!(document.query_selector("html:root").unwrap().unwrap().name(), "html");
+    assert_eq!(document.query_selector("div").unwrap().unwrap().id(), Some("first"));
+    assert!(document.query_selector("table").unwrap().is_none());
+    assert!(document.query_selector("div >").is_err());
+}
+
+#[test]
+fn query_selector_structural() {
+    let document = Document::parse(HTML).unwrap();
+    assert_eq!(select(&document, "li:nth-child(2n+1)"), ["1", "3", "5"]);
+    assert_eq!(select(&document, "li:nth-child(even)"), ["2", "4"]);
+    assert_eq!(select(&document, "li:nth-last-child(-n+2)"), ["4", "5"]);
+    assert_eq!(select(&document, "li:nth-child(2 of .x)"), ["4"]);
+    assert_eq!(select(&document, "li:first-child, li:last-child"), ["1", "5"]);
+    assert_eq!(select(&document, "section span:first-of-type"), [""]);
+    assert_eq!(select(&document, "section > :last-of-type"), ["b", "s"]);
+    assert_eq!(select(&document, "section > :only-of-type"), ["b"]);
+    assert_eq!(select(&document, "title:only-child"), ["Cards"]);
+}
+
+#[test]
+fn query_selector_relational() {
+    let document = Document::parse(HTML).unwrap();
+    assert_eq!(document.query_selector_all("div:has(> p)").unwrap().len(), 1);
+    assert_eq!(select(&document, "h2:has(+ a)"), ["One"]);
+    assert_eq!(select(&document, "li:has(~ .x)"), ["1", "2", "3"]);
+    assert_eq!(select(&document, "body > :has(li.x):is(ul, ol) > :where(:nth-child(1))"), ["1"]);
+    assert!(document.query_selector("a:has(a)").unwrap().is_none());
+}
+
+#[test]
+fn query_selector_scoped() {
+    let document = Document::parse(HTML).unwrap();
+    let first = document.get_element_by_id("first").unwrap();
+    assert_eq!(first.query_selector_all("a").unwrap().len(), 2);
+    // Only the matched element has to be inside, as in the DOM.
+    assert_eq!(first.query_selector_all("body a").unwrap().len(), 2);
+    assert_eq!(first.query_selector_all(":scope > h2").unwrap().len(), 1);
+    assert!(first.query_selector(":scope > li").unwrap().is_none());
+
+    let link = first.query_selector("a[href='/local']").unwrap().unwrap();
+    assert!(link.matches("div > a").unwrap());
+    assert!(!link.matches("li a").unwrap());
+    assert_eq!(link.closest(".card").unwrap(), Some(first));
+    assert_eq!(link.closest("a").unwrap(), Some(link));
+    assert!(link.closest("ul").unwrap().is_none());
+}
+
+#[test]
+fn query_selector_extreme_nth() {
+    let document = Document::parse(HTML).unwrap();
+    for selectors in [":nth-child(-2147483647)", ":nth-child(2147483647n-2147483647)", ":nth-last-of-type(-99999999999n+99999999999)"] {
+        assert!(select(&document, selectors).is_empty(), "{}", selectors);
+    }
+    assert_eq!(select(&document, "li:nth-child(-2147483647n+1)"), ["1"]);
+}
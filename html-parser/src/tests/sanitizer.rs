@@ -0,0 +1,145 @@
+use crate::{dom::Node, sanitizer::{sanitize, Disallowed, SanitizePolicy}, Document};
+
+// https://cheatsheetseries.owasp.org/cheatsheets/XSS_Filter_Evasion_Cheat_Sheet.html
+// https://github.com/cure53/DOMPurify/blob/main/test/fixtures/expect.mjs
+const VECTORS: &[&str] = &[
+    "<script>alert(1)</script>",
+    "<SCRIPT SRC=http://xss.rocks/xss.js></SCRIPT>",
+    "<img src=x onerror=alert(1)>",
+    "<IMG SRC=\"javascript:alert('XSS');\">",
+    "<a href=\"javascript:alert(1)\">x</a>",
+    "<a href=\"  JaVaScRiPt:alert(1)\">x</a>",
+    "<a href=\"java&#x09;script:alert(1)\">x</a>",
+    "<a href=\"jav&#x0A;ascript:alert(1)\">x</a>",
+    "<a href=\"&#14;javascript:alert(1)\">x</a>",
+    "<a href=\"data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==\">x</a>",
+    "<a href=\"vbscript:msgbox(1)\">x</a>",
+    "<body onload=alert(1)>",
+    "<svg onload=alert(1)>",
+    "<svg><script>alert(1)</script></svg>",
+    "<iframe src=\"javascript:alert(1)\"></iframe>",
+    "<object data=\"javascript:alert(1)\"></object>",
+    "<embed src=\"javascript:alert(1)\">",
+    "<div style=\"background:url(javascript:alert(1))\">x</div>",
+    "<p onmouseover=\"alert(1)\">x</p>",
+    "<form action=\"javascript:alert(1)\"><button>x</button></form>",
+    "<button formaction=\"javascript:alert(1)\">x</button>",
+    "<meta http-equiv=\"refresh\" content=\"0;url=javascript:alert(1)\">",
+    "<base href=\"javascript:alert(1)//\">",
+    "<link rel=stylesheet href=\"javascript:alert(1)\">",
+    "<style>@import 'javascript:alert(1)';</style>",
+    "<!--<img src=x onerror=alert(1)>-->",
+    "<!--><img src=x onerror=alert(1)>-->",
+    "<img src=\"x` `<script>alert(1)</script>\"` `>",
+    "<img \"\"\"><script>alert(1)</script>\">",
+    "<<script>alert(1);//<</script>",
+    "<a href=x:alert(1)>x</a>",
+    "<img/src=x/onerror=alert(1)>",
+    "<details open ontoggle=alert(1)>",
+    "<video><source onerror=alert(1)></video>",
+    "<textarea></textarea><img src=x onerror=alert(1)>",
+    "<title><img src=x onerror=alert(1)></title>",
+    // Mutation XSS : markup that is harmless the way it is parsed here, but not once a browser parses
+    // the serialized output again.
+    "<noscript><p title=\"</noscript><img src=x onerror=alert(1)>\">",
+    "<noscript><style></noscript><img src=x onerror=alert(1)></style></noscript>",
+    "<svg></p><style><a id=\"</style><img src=1 onerror=alert(1)>\">",
+    "<math><mtext><table><mglyph><style><img src=x onerror=alert(1)>",
+    "<math><mi><mglyph><svg><mtext><textarea><path id=\"</textarea><img onerror=alert(1) src=1>\">",
+    "<form><math><mtext></form><form><mglyph><style></math><img src onerror=alert(1)>",
+    "<svg><foreignobject><p><style><img src=x onerror=alert(1)></style></p></foreignobject></svg>",
+    "<xmp><p title=\"</xmp><img src=x onerror=alert(1)>\">",
+    "<template><img src=x onerror=alert(1)></template>",
+    "<p title=\"</p><img src=x onerror=alert(1)>\">x</p>",
+    "<a title=\"&lt;img src=x onerror=alert(1)&gt;\">x</a>",
+];
+
+// Everything in `html` has to be allowed by the policy, after a browser parses it.
+fn assert_safe(html: &str, policy: &SanitizePolicy) {
+    let document = Document::parse(&format!("<body>{html}")).unwrap();
+    let body = document.body().unwrap();
+    for node in body.as_node().descendants() {
+        match node.node() {
+            Node::Element(element) => {
+                assert!(policy.elements.contains(element.name()), "<{}> in {html:?}", element.name());
+                for attribute in &element.attributes {
+                    assert!(!attribute.name.starts_with("on") && attribute.name != "style", "{} in {html:?}", attribute.name);
+                    let value = attribute.value.to_ascii_lowercase();
+                    assert!(!value.contains("script:") && !value.contains("data:"), "{} in {html:?}", attribute.value);
+                }
+            },
+            Node::Text(_) => (),
+            other => panic!("{other:?} in {html:?}"),
+        }
+    }
+    assert!(!html.contains('\u{0}'));
+}
+
+#[test]
+fn sanitize_xss_vectors() {
+    for policy in [SanitizePolicy::new(), SanitizePolicy::basic(), SanitizePolicy::relaxed(), SanitizePolicy::relaxed().disallowed(Disallowed::Drop)] {
+        for vector in VECTORS {
+            let clean = sanitize(vector, &policy).unwrap();
+            assert_safe(&clean, &policy);
+            // The output is stable, sanitizing it again doesn't change anything.
+            assert_eq!(sanitize(&clean, &policy).unwrap(), clean, "{vector:?}");
+            for forbidden in ["<script", "<img src=x", "<noscript", "<svg", "<math", "<style", "javascript"] {
+                assert!(!clean.to_ascii_lowercase().contains(forbidden), "{forbidden} in {clean:?} from {vector:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn sanitize_mutation_xss() {
+    let policy = SanitizePolicy::relaxed();
+    assert_eq!(sanitize("<noscript><p title=\"</noscript><img src=x onerror=alert(1)>\">", &policy).unwrap(), "");
+    assert_eq!(sanitize("<p title=\"</p><img src=x onerror=alert(1)>\">x</p>", &policy).unwrap(),
+        "<p title=\"&lt;/p&gt;&lt;img src=x onerror=alert(1)&gt;\">x</p>");
+    assert_eq!(sanitize("<svg></p><style><a id=\"</style><img src=1 onerror=alert(1)>\">", &policy).unwrap(), "");
+    assert_eq!(sanitize("<b>a<math><mi>b</mi></math>c</b>", &policy).unwrap(), "<b>ac</b>");
+}
+
+#[test]
+fn sanitize_elements_and_attributes() {
+    let policy = SanitizePolicy::basic();
+    assert_eq!(sanitize("<p class=x id=y><b onclick=z>bold</b> <font color=red>red</font></p>", &policy).unwrap(), "<p><b>bold</b> red</p>");
+    assert_eq!(sanitize("<div><h1>Title</h1><p>Text</p></div>", &policy).unwrap(), "Title<p>Text</p>");
+    assert_eq!(sanitize("<div><h1>Title</h1><p>Text</p></div>", &policy.clone().disallowed(Disallowed::Drop)).unwrap(), "");
+    assert_eq!(sanitize("<p>a<script>b</script><style>c</style>d</p>", &policy).unwrap(), "<p>ad</p>");
+    assert_eq!(sanitize("1 < 2 & <i>3</i> > 2", &SanitizePolicy::new()).unwrap(), "1 &lt; 2 &amp; 3 &gt; 2");
+
+    let custom = SanitizePolicy::new().allow_elements(&["p", "x-card"]).allow_attributes(&["data-id"]).allow_element_attributes("x-card", &["kind"]);
+    assert_eq!(sanitize("<x-card kind=a data-id=1 other=2><p kind=b data-id=3>x</p></x-card>", &custom).unwrap(),
+        "<x-card kind=\"a\" data-id=\"1\"><p data-id=\"3\">x</p></x-card>");
+    // Some elements can't be allowed.
+    assert_eq!(sanitize("<script>x</script><svg></svg>", &SanitizePolicy::new().allow_elements(&["script", "svg"])).unwrap(), "");
+}
+
+#[test]
+fn sanitize_urls() {
+    let policy = SanitizePolicy::relaxed();
+    let href = |url: &str| sanitize(&format!("<a href=\"{url}\">x</a>"), &policy).unwrap();
+    for allowed in ["https://example.com", "HTTP://example.com", "mailto:a@example.com", "/about", "#top", "?q=a:b", "page.html", "./a:b"] {
+        assert_eq!(href(allowed), format!("<a href=\"{allowed}\">x</a>"), "{allowed}");
+    }
+    for blocked in ["javascript:alert(1)", "JAVASCRIPT:alert(1)", " javascript:x", "java\tscript:x", "java&#10;script:x", "\u{1}javascript:x",
+        "data:text/html,x", "vbscript:x", "ftp://example.com", "a b:c", "1:x"] {
+        assert_eq!(href(blocked), "<a>x</a>", "{blocked:?}");
+    }
+    assert_eq!(sanitize("<img src=/a.png alt=a>", &policy).unwrap(), "<img src=\"/a.png\" alt=\"a\">");
+    assert_eq!(sanitize("<a href=/a>x</a>", &policy.clone().allow_relative_urls(false)).unwrap(), "<a>x</a>");
+}
+
+#[test]
+fn sanitize_comments() {
+    let html = "<p>a<!-- note -->b</p>";
+    assert_eq!(sanitize(html, &SanitizePolicy::basic()).unwrap(), "<p>ab</p>");
+    assert_eq!(sanitize(html, &SanitizePolicy::basic().allow_comments(true)).unwrap(), "<p>a<!-- note -->b</p>");
+}
+
+#[test]
+fn sanitize_deep_nesting() {
+    let html = "<div>".repeat(5_000) + "x";
+    assert_eq!(sanitize(&html, &SanitizePolicy::basic()).unwrap(), "x");
+}
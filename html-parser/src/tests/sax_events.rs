@@ -0,0 +1,102 @@
+#[allow(unused)]
+use crate::{sax::{self, EventHandler}, dom::{Element, ElementKind}};
+
+#[derive(Debug, PartialEq)]
+pub(super) enum Event {
+    Start(String),
+    End(String),
+    Text(String),
+    Comment(String),
+}
+
+#[derive(Default)]
+pub(super) struct Recorder {
+    pub(super) events: Vec<Event>,
+}
+
+impl EventHandler for Recorder {
+    fn start_element(&mut self, element: &Element) {
+        self.events.push(Event::Start(element.name().to_string()));
+    }
+    fn end_element(&mut self, kind: &ElementKind) {
+        self.events.push(Event::End(kind.name().to_string()));
+    }
+    fn text(&mut self, text: &str) {
+        // Text may be split into several events, merge it so the tests don't depend on buffer sizes.
+        if let Some(Event::Text(previous)) = self.events.last_mut() {
+            previous.push_str(text);
+        } else {
+            self.events.push(Event::Text(text.to_string()));
+        }
+    }
+    fn comment(&mut self, text: &str) {
+        self.events.push(Event::Comment(text.to_string()));
+    }
+}
+
+// A reader that hands out one byte per call, which splits multi-byte characters across reads.
+struct OneByteReader<'a>(&'a [u8]);
+
+impl<'a> std::io::Read for OneByteReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.split_first() {
+            Some((first, rest)) if !buf.is_empty() => {
+                buf[0] = *first;
+                self.0 = rest;
+                Ok(1)
+            }
+            _ => Ok(0),
+        }
+    }
+}
+
+fn start(name: &str) -> Event { Event::Start(name.to_string()) }
+fn end(name: &str) -> Event { Event::End(name.to_string()) }
+fn text(text: &str) -> Event { Event::Text(text.to_string()) }
+
+#[test]
+fn sax_implied_tags() {
+    const HTML: &str = "<p>One<p>Two<table><tr><td>Cell</table>";
+    let output = sax::parse_str(HTML, Recorder::default()).unwrap().events;
+    let correct_output = vec![
+        start("html"), start("head"), end("head"), start("body"),
+        start("p"), text("One"), end("p"),
+        start("p"), text("Two"), end("p"),
+        start("table"), start("tbody"), start("tr"), start("td"), text("Cell"), end("td"), end("tr"), end("tbody"), end("table"),
+        end("body"), end("html"),
+    ];
+    assert_eq!(output, correct_output);
+}
+
+#[test]
+fn sax_raw_text_and_comments() {
+    const HTML: &str = "<html><head><title>a <b> c</title></head><body><!-- note --><textarea>\nx</textarea></body></html>";
+    let output = sax::parse_str(HTML, Recorder::default()).unwrap().events;
+    let correct_output = vec![
+        start("html"), start("head"), start("title"), text("a <b> c"), end("title"), end("head"),
+        start("body"), Event::Comment(" note ".to_string()), start("textarea"), text("x"), end("textarea"), end("body"), end("html"),
+    ];
+    assert_eq!(output, correct_output);
+}
+
+#[test]
+fn sax_streaming_matches_in_memory() {
+    const HTML: &str = "<!DOCTYPE html><ul class='list'><li>Zażółć<li>gęślą <!-- jaźń --></ul><style>p > a {}</style>";
+    let in_memory = sax::parse_str(HTML, Recorder::default()).unwrap().events;
+    let streamed = sax::parse_reader(OneByteReader(HTML.as_bytes()), Recorder::default()).unwrap().events;
+    assert_eq!(streamed, in_memory);
+}
+
+#[test]
+fn sax_streaming_rejects_truncated_character() {
+    let bytes = "ż".as_bytes();
+    assert!(sax::parse_reader(&bytes[..1], Recorder::default()).is_err());
+}
+
+#[test]
+fn sax_out_of_order() {
+    // Elements and comments that belong to an element that has already ended are reported after its end.
+    let events = sax::parse_str("<head></head><meta><p>x</body><!--c-->", Recorder::default()).unwrap().events;
+    assert_eq!(events, vec![start("html"), start("head"), end("head"), start("meta"), end("meta"), start("body"), start("p"), text("x"),
+        Event::Comment(String::from("c")), end("p"), end("body"), end("html")]);
+}
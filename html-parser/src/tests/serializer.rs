@@ -0,0 +1,110 @@
+use crate::{serializer::{self, SerializeOptions}, Document};
+
+fn round_trip(html: &str) -> String {
+    let document = Document::parse(html).unwrap();
+    let serialized = document.to_html();
+    let reparsed = Document::parse(&serialized).unwrap();
+    assert!(reparsed.tree().iter().map(|(_, node)| node).eq(document.tree().iter().map(|(_, node)| node)), "{html:?} doesn't survive {serialized:?}");
+    assert_eq!(reparsed.to_html(), serialized);
+    serialized
+}
+
+fn body(html: &str) -> String {
+    Document::parse(&format!("<body>{html}")).unwrap().body().unwrap().inner_html()
+}
+
+#[test]
+fn serialize_document() {
+    assert_eq!(
+        round_trip("<!DOCTYPE html><title>T</title><!-- c --><p class=a id='b'>Hi<br>there"),
+        "<!DOCTYPE html><html><head><title>T</title><!-- c --></head><body><p class=\"a\" id=\"b\">Hi<br>there</p></body></html>",
+    );
+}
+
+#[test]
+fn serialize_escaping() {
+    assert_eq!(body("<p title='a \"b\" &amp; <c>'>1 &lt; 2 &amp;&amp; 3 &gt; 2&nbsp;'\"</p>"),
+        "<p title=\"a &quot;b&quot; &amp; &lt;c&gt;\">1 &lt; 2 &amp;&amp; 3 &gt; 2&nbsp;'\"</p>");
+    round_trip("<p title='a \"b\" &amp; <c>'>1 &lt; 2 &amp;&amp; 3 &gt; 2&nbsp;'\"</p>");
+}
+
+#[test]
+fn serialize_void_and_raw_text() {
+    assert_eq!(body("<img src=x><input><hr><wbr><br/>"), "<img src=\"x\"><input><hr><wbr><br>");
+    assert_eq!(body("<script>if (a < b && c) {}</script><style>a > b {}</style><xmp><b>&amp;</b></xmp>"),
+        "<script>if (a < b && c) {}</script><style>a > b {}</style><xmp><b>&amp;</b></xmp>");
+    // Without scripting, <noscript> is parsed as markup and escaped like any other element.
+    assert_eq!(body("<noscript><p>&lt;x&gt;</p></noscript>"), "<noscript><p>&lt;x&gt;</p></noscript>");
+    round_trip("<script>if (a < b && c) {}</script><style>a > b {}</style><xmp><b>&amp;</b></xmp>");
+}
+
+#[test]
+fn serialize_leading_newline() {
+    assert_eq!(body("<pre>\n\nindented</pre>"), "<pre>\n\nindented</pre>");
+    assert_eq!(body("<pre>\nnot kept</pre>"), "<pre>not kept</pre>");
+    assert_eq!(body("<textarea>\n\nx</textarea><listing>\n\ny</listing>"), "<textarea>\n\nx</textarea><listing>\n\ny</listing>");
+    round_trip("<pre>\n\nindented</pre><textarea>\n\nx</textarea>");
+}
+
+#[test]
+fn serialize_template_and_foreign_content() {
+    assert_eq!(body("<template><li>a</li><b>b</b></template>"), "<template><li>a</li><b>b</b></template>");
+    assert_eq!(
+        body("<svg viewbox='0 0 10 10' preserveAspectRatio=none><clipPath clippathunits=x></clipPath><foreignObject><title>t</title></foreignObject></svg>"),
+        "<svg viewBox=\"0 0 10 10\" preserveAspectRatio=\"none\"><clipPath clipPathUnits=\"x\"></clipPath><foreignObject><title>t</title></foreignObject></svg>",
+    );
+    // In foreign content nothing is void, and names that only HTML knows stay as they are.
+    assert_eq!(body("<svg><g><source></g><rect/></svg><source>"), "<svg><g><source></source></g><rect></rect></svg><source>");
+    assert_eq!(body("<math definitionURL=x><mi><clippath></clippath></mi></math>"), "<math definitionURL=\"x\"><mi><clippath></clippath></mi></math>");
+}
+
+#[test]
+fn serialize_fragments() {
+    let document = Document::parse("<div id=a><p>One<p>Two</div>").unwrap();
+    let div = document.get_element_by_id("a").unwrap();
+    assert_eq!(div.inner_html(), "<p>One</p><p>Two</p>");
+    assert_eq!(div.outer_html(), "<div id=\"a\"><p>One</p><p>Two</p></div>");
+    let text = div.children().next().unwrap().first_child().unwrap();
+    assert_eq!(serializer::serialize_node(document.tree(), text.id(), &SerializeOptions::default()), "One");
+    assert_eq!(serializer::serialize_children(document.tree(), text.id(), &SerializeOptions::default()), "");
+}
+
+#[test]
+fn serialize_pretty() {
+    let document = Document::parse("<!DOCTYPE html><html><head><title>T</title></head><body>\n<div>  <p>Some <b>bold</b> text</p><ul><li>a</li><li><p>b</p></li></ul></div><pre>\n x\n</pre>  \n</body></html>").unwrap();
+    assert_eq!(document.to_html_with(&SerializeOptions::pretty(2)), "\
+<!DOCTYPE html>
+<html>
+  <head>
+    <title>T</title>
+  </head>
+  <body>
+    <div>
+      <p>Some <b>bold</b> text</p>
+      <ul>
+        <li>a</li>
+        <li>
+          <p>b</p>
+        </li>
+      </ul>
+    </div>
+    <pre> x\n</pre>
+  </body>
+</html>");
+}
+
+#[test]
+fn serialize_round_trips() {
+    for html in [
+        "<!DOCTYPE html><html lang=en><head><meta charset=utf-8><link rel=stylesheet href='a.css'></head><body></body></html>",
+        "<table><caption>c</caption><tr><td>1<td>2</table>",
+        "<ul><li>a<li>b</ul><dl><dt>t<dd>d</dl>",
+        "<select><option selected>a<option>b</select>",
+        "<p>a<!--x--><b><i>bi</i></b>&amp;&lt;&gt;\u{A0}</p>",
+        "<iframe><b></iframe><noembed><i></noembed><noframes>&amp;</noframes>",
+        "<div title='\"&apos;<>&amp;'></div>",
+        "<svg><circle r=1 /></svg><math><mi>x</mi></math>",
+    ] {
+        round_trip(html);
+    }
+}
@@ -0,0 +1,126 @@
+use std::io::Read;
+
+use crate::{json::to_json, limits::ParserLimits, snapshot, HtmlParser, ParseState};
+
+// Hands out the input a few bytes at a time, so that checkpoints fall in the middle of tokens and characters.
+struct Trickle<'a> {
+    input: &'a [u8],
+    chunk: usize,
+}
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let length = self.chunk.min(buf.len()).min(self.input.len());
+        buf[..length].copy_from_slice(&self.input[..length]);
+        self.input = &self.input[length..];
+        Ok(length)
+    }
+}
+
+const DOCUMENT: &str = "<!DOCTYPE html><html lang=en><head><title>Café &amp; co</title><style>p > a {}</style></head>\n\
+    <body class=x><form id=f><p>One <b>two<i>three</b></i> <a href='/x?a=1&b=2'>four</a><!-- five -->\n\
+    <table><tr><td>six<td>seven</table><textarea>\n&lt;eight</textarea><select><option>nine</select>\n\
+    <pre>\nten</pre><svg><path d=M0/></svg><ul><li>eleven<li>twelve</ul></form></body></html>\n";
+
+#[test]
+fn snapshot_resume_anywhere() {
+    let expected = to_json(&HtmlParser::parse(DOCUMENT, ParseState::new()).unwrap());
+    let mut saved = Vec::new();
+    let tree = HtmlParser::resume_stream(Trickle { input: DOCUMENT.as_bytes(), chunk: 7 }, streaming(), ParseState::new(), |tokens, state| {
+        saved.push(snapshot::to_string(tokens, state)?);
+        Ok(())
+    }).unwrap();
+    assert_eq!(to_json(&tree), expected);
+    assert!(saved.len() > 50);
+
+    for text in saved {
+        let (tokens, state) = snapshot::from_str(&text).unwrap();
+        // Reading a state back and writing it again gives the same state.
+        assert_eq!(snapshot::to_string(&tokens, &state).unwrap(), text);
+        let rest = Trickle { input: &DOCUMENT.as_bytes()[tokens.input_offset()..], chunk: 5 };
+        let tree = HtmlParser::resume_stream(rest, tokens, state, |_, _| Ok(())).unwrap();
+        assert_eq!(to_json(&tree), expected);
+    }
+}
+
+fn streaming() -> crate::tokenizer::Tokenizer {
+    crate::tokenizer::Tokenizer::new_streaming(crate::preproccesor::PreProccessor::new("").unwrap())
+}
+
+#[test]
+fn snapshot_clone() {
+    // A copy of the parser carries on independently of the original.
+    let mut copies = Vec::new();
+    let state = ParseState::new().with_limits(ParserLimits::untrusted());
+    let expected = HtmlParser::resume_stream(Trickle { input: DOCUMENT.as_bytes(), chunk: 64 }, streaming(), state, |tokens, state| {
+        copies.push((tokens.clone(), state.clone()));
+        Ok(())
+    }).unwrap();
+    for (tokens, state) in copies {
+        let rest = &DOCUMENT.as_bytes()[tokens.input_offset()..];
+        let tree = HtmlParser::resume_stream(rest, tokens, state, |_, _| Ok(())).unwrap();
+        assert_eq!(to_json(&tree), to_json(&expected));
+    }
+}
+
+#[test]
+fn snapshot_input_size() {
+    // The bytes read before the parse was stopped count towards the limit, the ones cut off in the middle of
+    // a character only once.
+    for (max_input_size, fits) in [(DOCUMENT.len(), true), (DOCUMENT.len() - 1, false)] {
+        let state = ParseState::new().with_limits(ParserLimits { max_input_size, ..ParserLimits::unlimited() });
+        let mut copies = Vec::new();
+        let result = HtmlParser::resume_stream(Trickle { input: DOCUMENT.as_bytes(), chunk: 7 }, streaming(), state, |tokens, state| {
+            copies.push((tokens.clone(), state.clone()));
+            Ok(())
+        });
+        assert_eq!(result.is_ok(), fits);
+        for (tokens, state) in copies {
+            let rest = Trickle { input: &DOCUMENT.as_bytes()[tokens.input_offset()..], chunk: 7 };
+            assert_eq!(HtmlParser::resume_stream(rest, tokens, state, |_, _| Ok(())).is_ok(), fits);
+        }
+    }
+}
+
+#[test]
+fn snapshot_file() {
+    let path = std::env::temp_dir().join(format!("html-parser-snapshot-{}.json", std::process::id()));
+    let half = DOCUMENT.len() / 2;
+    let mut saved = false;
+    // Stop the parse half way through, the way a crash would.
+    let stopped = HtmlParser::resume_stream(Trickle { input: DOCUMENT.as_bytes(), chunk: 16 }, streaming(), ParseState::new(), |tokens, state| {
+        snapshot::save(&path, tokens, state)?;
+        saved = true;
+        match tokens.input_offset() > half {
+            true => Err("stopped".into()),
+            false => Ok(()),
+        }
+    });
+    assert!(stopped.is_err() && saved);
+
+    let (tokens, state) = snapshot::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let offset = tokens.input_offset();
+    assert!(offset > half && offset < DOCUMENT.len());
+    let tree = HtmlParser::resume_stream(&DOCUMENT.as_bytes()[offset..], tokens, state, |_, _| Ok(())).unwrap();
+    assert_eq!(to_json(&tree), to_json(&HtmlParser::parse(DOCUMENT, ParseState::new()).unwrap()));
+}
+
+#[test]
+fn snapshot_invalid() {
+    assert!(snapshot::from_str("").is_err());
+    assert!(snapshot::from_str("{}\n{\"value\":{\"type\":\"document\"},\"children\":[]}").is_err());
+    let (tokens, state) = {
+        let mut saved = None;
+        HtmlParser::resume_stream(Trickle { input: DOCUMENT.as_bytes(), chunk: 100 }, streaming(), ParseState::new(), |tokens, state| {
+            saved.get_or_insert_with(|| snapshot::to_string(tokens, state).unwrap());
+            Ok(())
+        }).unwrap();
+        snapshot::from_str(&saved.unwrap()).unwrap()
+    };
+    let text = snapshot::to_string(&tokens, &state).unwrap();
+    // The open elements refer to nodes of the document, which has to be there.
+    let (state_line, _) = text.split_once('\n').unwrap();
+    assert!(snapshot::from_str(&format!("{}\n{{\"value\":{{\"type\":\"document\"}},\"children\":[]}}", state_line)).is_err());
+    assert!(snapshot::from_str(&text.replacen("\"mode\":\"", "\"mode\":\"Not", 1)).is_err());
+}
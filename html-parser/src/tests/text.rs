@@ -0,0 +1,45 @@
+use crate::{text::{element_to_plain_text, to_plain_text, PlainTextOptions}, Document};
+
+fn text(html: &str) -> String {
+    to_plain_text(&Document::parse(html).unwrap(), &PlainTextOptions::default())
+}
+
+#[test]
+fn plain_text_blocks_and_whitespace() {
+    assert_eq!(text("<title>Skipped</title><h1>  Title </h1>\n<p>First   paragraph\nwith <b>bold</b>  text.</p><p>Second</p><div>A<div>B</div>C</div>"),
+        "Title\n\nFirst paragraph with bold text.\n\nSecond\n\nA\nB\nC");
+    assert_eq!(text("<ul>\n  <li>one</li>\n  <li> two </li>\n</ul>"), "one\ntwo");
+    assert_eq!(text("a<br>b<br><br>c"), "a\nb\n\nc");
+    assert_eq!(text("  <span> a </span> <span> b </span>  "), "a b");
+}
+
+#[test]
+fn plain_text_skips_hidden_content() {
+    assert_eq!(text("<head><style>p {}</style><script>x()</script></head><body>a<script>y()</script><style>b</style>\
+        <template>t</template><p hidden>h</p><noembed>n</noembed>b</body>"), "ab");
+    // The element innerText is called on is rendered even if it would be hidden otherwise.
+    let document = Document::parse("<body><div hidden id=x>shown <span hidden>not</span></div>").unwrap();
+    assert_eq!(element_to_plain_text(document.get_element_by_id("x").unwrap(), &PlainTextOptions::default()), "shown");
+}
+
+#[test]
+fn plain_text_preformatted() {
+    assert_eq!(text("<p>a    b</p><pre>  keep\n    this   </pre><p>c</p>"), "a b\n\n  keep\n    this   \n\nc");
+    assert_eq!(text("<pre>x <b>  y</b>\n</pre>after"), "x   y\n\nafter");
+}
+
+#[test]
+fn plain_text_tables() {
+    assert_eq!(text("<table><tr><th>Name</th><th> Age </th></tr><tr><td>Ann</td><td>31</td></tr></table>after"),
+        "Name\tAge\nAnn\t31\nafter");
+}
+
+#[test]
+fn plain_text_options() {
+    let document = Document::parse("<p>See <a href='https://a.example'>the docs</a>, <a href='/b'>this</a> and \
+        <a href='https://a.example'>again</a>. <a>No link</a></p><img src=x alt='A cat'><img src=y>").unwrap();
+    assert_eq!(to_plain_text(&document, &PlainTextOptions::default()), "See the docs, this and again. No link");
+    let options = PlainTextOptions { link_footnotes : true, image_alt : true };
+    assert_eq!(to_plain_text(&document, &options),
+        "See the docs [1], this [2] and again [1]. No link\n\nA cat\n\n[1] https://a.example\n[2] /b");
+}
@@ -0,0 +1,93 @@
+use proptest::prelude::*;
+
+use crate::{error::{HtmlParseError, HtmlTokenizerError}, json::to_json, preproccesor::PreProccessor, states::TokenizationState,
+    tokenizer::{Attribute, Token, TokenStream, Tokenizer}, HtmlParser, ParseState};
+
+use super::no_panic::html_input;
+
+// Records the tokens of a parse, writes and reads them back, and replays them through the tree builder.
+fn check_replay(input: &str) {
+    let (result, recorded) = HtmlParser::parse_recording(input, ParseState::new());
+    let loaded = TokenStream::from_json_lines(&recorded.to_json_lines()).unwrap();
+    assert_eq!(loaded, recorded);
+    let replayed = HtmlParser::parse_tokens(loaded, ParseState::new());
+    match (result, replayed) {
+        (Ok(tree), Ok(replayed)) => assert_eq!(to_json(&replayed), to_json(&tree)),
+        (Err(_), Err(_)) => {},
+        (result, replayed) => panic!("{:?} but the replay gave {:?}", result.map(|_| ()), replayed.map(|_| ())),
+    }
+}
+
+#[test]
+fn token_stream_replay() {
+    for input in [
+        "<!DOCTYPE html><title>a <b> c</title><p class=x>One<br/>two\nthree<!-- c --><textarea>\n<i></textarea>",
+        "<table><tr><td>a<td>b</table><select><option>c</select><pre>\n\nd</pre><script>if (a < b) {}</script>",
+        include_str!("../../fuzz/corpus/parse/test.html"),
+    ] {
+        check_replay(input);
+        let (tree, _) = HtmlParser::parse_recording(input, ParseState::new());
+        assert_eq!(to_json(&tree.unwrap()), to_json(&HtmlParser::parse(input, ParseState::new()).unwrap()));
+    }
+}
+
+#[test]
+fn token_stream_format() {
+    let stream = TokenStream { tokens: vec![
+        Token::DOCTYPE(Some("html".to_string()), None, None, false),
+        Token::StartTag("a".to_string(), false, vec![Attribute::new("href", "/")]),
+        Token::Character('a'), Token::Character('\n'), Token::Character('"'),
+        Token::EndTag("a".to_string(), false, Vec::new()),
+        Token::Comment(" c ".to_string()),
+        Token::EOF,
+    ] };
+    let text = stream.to_json_lines();
+    assert_eq!(text, "{\"force_quirks\":false,\"name\":\"html\",\"public_id\":null,\"system_id\":null,\"type\":\"doctype\"}\n\
+        {\"attributes\":[{\"name\":\"href\",\"value\":\"/\"}],\"name\":\"a\",\"self_closing\":false,\"type\":\"start_tag\"}\n\
+        {\"data\":\"a\\n\",\"type\":\"text\"}\n\
+        {\"data\":\"\\\"\",\"type\":\"text\"}\n\
+        {\"attributes\":[],\"name\":\"a\",\"self_closing\":false,\"type\":\"end_tag\"}\n\
+        {\"data\":\" c \",\"type\":\"comment\"}\n\
+        {\"type\":\"eof\"}\n");
+    assert_eq!(TokenStream::from_json_lines(&text).unwrap(), stream);
+
+    // Deleting lines still leaves a stream that can be replayed, which is how a stream is minimized.
+    let minimized: String = text.lines().filter(|line| !line.contains("start_tag")).map(|line| format!("{}\n\n", line)).collect();
+    let tokens = TokenStream::from_json_lines(&minimized).unwrap();
+    assert_eq!(tokens.tokens.len(), stream.tokens.len() - 1);
+    let tree = HtmlParser::parse_tokens(tokens, ParseState::new()).unwrap();
+    assert!(to_json(&tree).contains("\"data\":\"a\\n\\\"\""));
+
+    let path = std::env::temp_dir().join(format!("html-parser-tokens-{}.jsonl", std::process::id()));
+    stream.save(&path).unwrap();
+    let loaded = TokenStream::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, stream);
+}
+
+#[test]
+fn token_stream_errors() {
+    let error = TokenStream::from_json_lines("{\"type\":\"eof\"}\n\n{\"type\":\"start_tag\",\"name\":\"a\"}").unwrap_err();
+    assert!(matches!(error, HtmlParseError::InvalidTokenStream { line: 3, .. }), "{:?}", error);
+    assert!(matches!(TokenStream::from_json_lines("{\"type\":\"eof\"").unwrap_err(), HtmlParseError::InvalidTokenStream { line: 1, .. }));
+    assert!(matches!(TokenStream::from_json_lines("{\"type\":\"other\"}").unwrap_err(), HtmlParseError::InvalidTokenStream { line: 1, .. }));
+
+    // The errors that stop the tree builder stop the conversion as well.
+    let mut tokenizer = Tokenizer::new(PreProccessor::new("x]]>").unwrap());
+    tokenizer.switch_to(TokenizationState::CDATASection);
+    assert!(matches!(TokenStream::try_from(tokenizer), Err(HtmlTokenizerError::Unsupported(TokenizationState::CDATASection))));
+
+    // Tokens after EOF are ignored.
+    let tokens = vec![Token::Character('a'), Token::EOF, Token::Character('b')];
+    let tree = HtmlParser::parse_tokens(tokens, ParseState::new()).unwrap();
+    assert_eq!(to_json(&tree), to_json(&HtmlParser::parse("a", ParseState::new()).unwrap()));
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig { cases : 500, ..ProptestConfig::default() })]
+
+    #[test]
+    fn token_stream_any_input(input in html_input()) {
+        check_replay(&input);
+    }
+}
@@ -5,7 +5,7 @@ use crate::{preproccesor::PreProccessor, tokenizer::Attribute};
 
 #[test]
 fn tokenizer_basic() {
-    const TAG: &'static str = r#"<html></html>"#;
+    const TAG: &str = r#"<html></html>"#;
     let document = PreProccessor::new(TAG).unwrap();
     let output = Tokenizer::new(document)
         .map(|wrapped_token| -> Token {wrapped_token.unwrap()}).collect::<Vec<Token>>();
@@ -18,7 +18,7 @@ fn tokenizer_basic() {
 
 #[test]
 fn tokenizer_multiple_tags() {
-    const TAG: &'static str = r#"<html>a<div>b</div>c</html>"#;
+    const TAG: &str = r#"<html>a<div>b</div>c</html>"#;
     let document = PreProccessor::new(TAG).unwrap();
     let output = Tokenizer::new(document)
         .map(|wrapped_token| -> Token {wrapped_token.unwrap()}).collect::<Vec<Token>>();
@@ -36,7 +36,7 @@ fn tokenizer_multiple_tags() {
 
 #[test]
 fn tokenizer_self_closing_tag() {
-    const TAG: &'static str = r#"<html><img/></html>"#;
+    const TAG: &str = r#"<html><img/></html>"#;
     let document = PreProccessor::new(TAG).unwrap();
     let output = Tokenizer::new(document)
         .map(|wrapped_token| -> Token {wrapped_token.unwrap()}).collect::<Vec<Token>>();
@@ -52,7 +52,7 @@ fn tokenizer_self_closing_tag() {
 
 #[test]
 fn tokenizer_tag_attribute() {
-    const TAG: &'static str = "<html this=100 other ohYeah=yes ></html>";
+    const TAG: &str = "<html this=100 other ohYeah=yes ></html>";
     let document = PreProccessor::new(TAG).unwrap();
     let output = Tokenizer::new(document)
         .map(|wrapped_token| -> Token {wrapped_token.unwrap()}).collect::<Vec<Token>>();
@@ -72,3 +72,20 @@ fn tokenizer_tag_attribute() {
     assert_eq!(output, correct_output);
 }
 
+
+#[test]
+fn tokenizer_streaming_lookahead() {
+    // A chunk that ends in the middle of "<!--" or a character reference leaves the tokenizer waiting,
+    // the token still starts where it did in the first chunk.
+    for (first, second) in [("a<!-", "- b -->"), ("a<x y=\"&am", "p;\">")] {
+        let mut tokenizer = Tokenizer::new_streaming(PreProccessor::new("").unwrap());
+        tokenizer.feed(first);
+        assert_eq!(tokenizer.next().unwrap().unwrap(), Token::Character('a'));
+        assert!(tokenizer.next().is_none());
+        assert!(tokenizer.snapshot().is_none());
+        tokenizer.feed(second);
+        tokenizer.close();
+        assert!(tokenizer.next().unwrap().is_ok());
+        assert_eq!(tokenizer.token_position().offset, 1);
+    }
+}
@@ -0,0 +1,222 @@
+//! Readable text from a document.
+//!
+//! [`to_plain_text`] approximates what [`innerText`](https://html.spec.whatwg.org/multipage/dom.html#the-innertext-idl-attribute)
+//! returns in a browser, using the default styles of the HTML elements instead of computed CSS :
+//! - Elements that aren't rendered, like `<head>`, `<script>`, `<style>`, `<template>` and anything with a
+//!   `hidden` attribute, are skipped.
+//! - Block elements start on a new line, and there is an empty line around paragraphs. `<br>` breaks
+//!   the line.
+//! - Table cells are separated by tabs and every row gets its own line.
+//! - Whitespace is collapsed the way `white-space: normal` does it, except inside `<pre>`, `<listing>`,
+//!   `<plaintext>`, `<xmp>` and `<textarea>`, whose text is kept as it is.
+
+use dds_utils::tree::Edge;
+
+use crate::{document::{Document, ElementRef}, dom::{Element, ElementKind, Node}, NodeId, Tree};
+
+/// What [`to_plain_text`] adds to the text of the document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PlainTextOptions {
+    /// Number every link with an `href`, e.g. `the docs [1]`, and list the URLs at the end as `[1] https://...`.
+    /// Links to the same URL share a number.
+    pub link_footnotes : bool,
+    /// Put the `alt` text of images where the images are.
+    pub image_alt : bool,
+}
+
+/// The text of the whole document.
+pub fn to_plain_text(document : &Document, options : &PlainTextOptions) -> String {
+    plain_text(document.tree(), document.tree().root(), options)
+}
+
+/// The text of an element and everything in it, which is what `innerText` returns.
+pub fn element_to_plain_text(element : ElementRef<'_>, options : &PlainTextOptions) -> String {
+    plain_text(element.as_node().document().tree(), element.node_id(), options)
+}
+
+// https://html.spec.whatwg.org/multipage/rendering.html#hidden-elements
+fn is_hidden(element : &Element) -> bool {
+    use ElementKind::*;
+    element.attribute("hidden").is_some() || matches!(element.kind,
+        Area | Base | Basefont | Datalist | Head | Link | Meta | Noembed | Noframes | Param | Rp | Script | Style
+        | Template | Title | Iframe | Object | Embed | Audio | Video | Canvas)
+}
+
+// https://html.spec.whatwg.org/multipage/rendering.html#flow-content-3
+fn is_block(kind : &ElementKind) -> bool {
+    use ElementKind::*;
+    matches!(kind,
+        Address | Article | Aside | Blockquote | Body | Caption | Center | Dd | Details | Dialog | Dir | Div | Dl
+        | Dt | Fieldset | Figcaption | Figure | Footer | Form | Frameset | H1 | H2 | H3 | H4 | H5 | H6 | Header
+        | Hgroup | Hr | Html | Legend | Li | Listing | Main | Menu | Nav | Ol | Optgroup | Option | P | Plaintext
+        | Pre | Search | Section | Select | Summary | Table | Tbody | Tfoot | Thead | Tr | Ul | Xmp)
+}
+
+// https://html.spec.whatwg.org/multipage/rendering.html#preformatted-text
+fn is_preformatted(kind : &ElementKind) -> bool {
+    matches!(kind, ElementKind::Pre | ElementKind::Listing | ElementKind::Plaintext | ElementKind::Xmp | ElementKind::Textarea)
+}
+
+// https://html.spec.whatwg.org/multipage/dom.html#rendered-text-collection-steps
+// The text is collected as strings and the number of line breaks required between them. Breaks next to
+// each other are merged and the ones at the start and the end are dropped, as innerText does.
+enum Item {
+    Text(String),
+    Break(usize),
+}
+
+struct Collector {
+    items : Vec<Item>,
+    // Collapsed whitespace that becomes a single space if more text follows on the same line.
+    space : bool,
+    line_start : bool,
+    links : Vec<String>,
+}
+
+impl Collector {
+    fn push(&mut self, text : &str) {
+        match self.items.last_mut() {
+            Some(Item::Text(last)) => last.push_str(text),
+            _ => self.items.push(Item::Text(text.to_string())),
+        }
+    }
+
+    fn text(&mut self, text : &str) {
+        for c in text.chars() {
+            match c {
+                ' ' | '\t' | '\n' | '\r' | '\x0C' => self.space = true,
+                c => {
+                    if std::mem::replace(&mut self.space, false) && !self.line_start {
+                        self.push(" ");
+                    }
+                    let mut buffer = [0; 4];
+                    self.push(c.encode_utf8(&mut buffer));
+                    self.line_start = false;
+                },
+            }
+        }
+    }
+
+    fn preformatted(&mut self, text : &str) {
+        if text.is_empty() {
+            return;
+        }
+        if std::mem::replace(&mut self.space, false) && !self.line_start {
+            self.push(" ");
+        }
+        self.push(text);
+        self.line_start = text.ends_with('\n');
+    }
+
+    // Text that ends a line or a cell, so that whitespace after it is dropped like at the start of a line.
+    fn separator(&mut self, text : &str) {
+        self.space = false;
+        self.push(text);
+        self.line_start = true;
+    }
+
+    fn line_break(&mut self, count : usize) {
+        self.space = false;
+        self.line_start = true;
+        match self.items.last_mut() {
+            Some(Item::Break(last)) => *last = count.max(*last),
+            _ => self.items.push(Item::Break(count)),
+        }
+    }
+
+    fn finish(mut self) -> String {
+        if !self.links.is_empty() {
+            self.line_break(2);
+            for (index, link) in std::mem::take(&mut self.links).iter().enumerate() {
+                self.push(&format!("[{}] {}", index + 1, link));
+                self.line_break(1);
+            }
+        }
+        let mut output = String::new();
+        let mut pending = 0;
+        for item in self.items {
+            match item {
+                Item::Break(count) => pending = count,
+                Item::Text(text) => {
+                    if !output.is_empty() {
+                        output.extend(std::iter::repeat_n('\n', pending));
+                    }
+                    pending = 0;
+                    output.push_str(&text);
+                },
+            }
+        }
+        output
+    }
+}
+
+// Walks the tree without recursion, like the serializer.
+fn plain_text(tree : &Tree<Node>, node : NodeId, options : &PlainTextOptions) -> String {
+    let mut collector = Collector { items : Vec::new(), space : false, line_start : true, links : Vec::new() };
+    // The number of preformatted elements the walk is in, and the hidden element it is skipping.
+    let mut preformatted = 0;
+    let mut skip = None;
+    for edge in tree.traverse(node) {
+        if let Some(hidden) = skip {
+            if edge == Edge::Close(hidden) {
+                skip = None;
+            }
+            continue;
+        }
+        match edge {
+            Edge::Open(id) => match tree.get(id) {
+                Some(Node::Text(text)) if preformatted > 0 => collector.preformatted(text),
+                Some(Node::Text(text)) => collector.text(text),
+                // The node the text is asked for is always rendered, like an element innerText is called on.
+                Some(Node::Element(element)) if id != node && is_hidden(element) => skip = Some(id),
+                Some(Node::Element(element)) => {
+                    match element.kind {
+                        ElementKind::P => collector.line_break(2),
+                        ElementKind::Br => collector.separator("\n"),
+                        ElementKind::Img if options.image_alt => collector.text(element.attribute("alt").unwrap_or("")),
+                        ref kind if is_block(kind) => collector.line_break(1),
+                        _ => (),
+                    }
+                    if is_preformatted(&element.kind) {
+                        preformatted += 1;
+                    }
+                },
+                _ => (),
+            },
+            Edge::Close(id) => {
+                let Some(Node::Element(element)) = tree.get(id) else {
+                    continue;
+                };
+                if is_preformatted(&element.kind) {
+                    preformatted -= 1;
+                }
+                match element.kind {
+                    ElementKind::P => collector.line_break(2),
+                    ElementKind::Td | ElementKind::Th => {
+                        // Every cell but the last one in its row is followed by a tab.
+                        let last = tree.following_siblings(id).all(|sibling| !matches!(tree.get(sibling),
+                            Some(Node::Element(element)) if matches!(element.kind, ElementKind::Td | ElementKind::Th)));
+                        if !last {
+                            collector.separator("\t");
+                        }
+                    },
+                    ElementKind::A if options.link_footnotes && id != node => {
+                        if let Some(href) = element.attribute("href") {
+                            let index = match collector.links.iter().position(|link| link == href) {
+                                Some(index) => index,
+                                None => {
+                                    collector.links.push(href.to_string());
+                                    collector.links.len() - 1
+                                },
+                            };
+                            collector.text(&format!(" [{}]", index + 1));
+                        }
+                    },
+                    ref kind if is_block(kind) => collector.line_break(1),
+                    _ => (),
+                }
+            },
+        }
+    }
+    collector.finish()
+}
@@ -1,10 +1,18 @@
+use std::collections::{HashSet, VecDeque};
+
 use log::*;
 
-use crate::error::HtmlTokenizerError;
+use crate::error::{HtmlParseError, HtmlTokenizerError, ParseErrorCode, TokenizerErrorCode};
 use crate::states::*;
 
 use crate::preproccesor::PreProccessor;
+use crate::character_references::{match_character_reference, CharacterReference};
+use crate::limits::ParserLimits;
+use crate::snapshot;
+
+use miniserde::json::{self, Object, Value};
 
+#[derive(Clone)]
 pub struct Tokenizer {
     document: PreProccessor,
     state: TokenizationState,
@@ -15,23 +23,219 @@ pub struct Tokenizer {
     is_self_closing: bool,
     tag_kind: TagKind,
     return_state : Option<TokenizationState>,
+    comment_buf: String,
+    doctype_name_buf: Option<String>,
+    force_quirks: bool,
+    temp_buf: String,
+    last_start_tag: String,
+    // Tokens that have already been produced but not yet returned, used when a single character
+    // needs to emit more than one token.
+    pending: VecDeque<Token>,
+    // Set to false while more input may still arrive through Tokenizer::feed.
+    input_closed: bool,
+    limits: ParserLimits,
+    // Parse errors are only kept when asked for, see Tokenizer::record_errors.
+    record_errors: bool,
+    errors: Vec<(TokenizerErrorCode, SourcePosition)>,
+    // Number of bytes that Tokenizer::feed has dropped from the start of the input.
+    drained: usize,
+    // The line and column of the byte at line_cursor in the current buffer. The cursor only moves
+    // forward, so counting lines costs one pass over the input.
+    line_cursor: usize,
+    line: usize,
+    column: usize,
+    token_start: SourcePosition,
+    // Set when the tokenizer ran out of input in the middle of a token, which then still starts at
+    // token_start once more input has been fed.
+    waiting: bool,
+    // The offset of the character that started the current token, which is the last character consumed
+    // in one of the text states. Unlike token_start, input dropped without a token (like `</>`) isn't part of it.
+    token_offset: usize,
+}
+
+/// A place in the input of the tokenizer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourcePosition {
+    /// In bytes from the start of the input.
+    pub offset: usize,
+    /// Starting at 1.
+    pub line: usize,
+    /// In characters from the start of the line, starting at 1.
+    pub column: usize,
 }
 
+impl Default for SourcePosition {
+    fn default() -> Self {
+        Self { offset: 0, line: 1, column: 1 }
+    }
+}
+
+impl std::fmt::Display for SourcePosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// The state of a tokenizer in between two tokens, see [`Tokenizer::snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenizerSnapshot {
+    pub state: TokenizationState,
+    /// The name of the last start tag, which decides where the text of elements like `<title>` ends.
+    pub last_start_tag: String,
+    /// Where the next token starts.
+    pub position: SourcePosition,
+}
+
+/// A recorded sequence of tokens, which [`crate::HtmlParser::parse_tokens`] can replay through the tree builder.
+///
+/// [`TokenStream::to_json_lines`] writes the tokens as one JSON object per line, so that a token sequence
+/// that trips up the tree builder can be minimized by deleting lines :
+///
+/// - `{"type":"doctype","name":"html","public_id":null,"system_id":null,"force_quirks":false}`
+/// - `{"type":"start_tag","name":"a","self_closing":false,"attributes":[{"name":"href","value":"/"}]}`
+/// - `{"type":"end_tag","name":"a","self_closing":false,"attributes":[]}`
+/// - `{"type":"text","data":"Hello\n"}`, a character token for every character of the data
+/// - `{"type":"comment","data":" note "}`
+/// - `{"type":"eof"}`
 #[derive(Debug, PartialEq)]
 pub struct TokenStream {
     pub tokens: Vec<Token>,
 }
 
-impl From<Tokenizer> for TokenStream {
-    fn from(input: Tokenizer) -> Self {
-        let mut output = Vec::new();
+impl TryFrom<Tokenizer> for TokenStream {
+    type Error = HtmlTokenizerError;
+
+    /// Tokens are recovered from parse errors the same way the tree builder does it, the errors that stop
+    /// the tree builder stop the conversion as well.
+    fn try_from(input: Tokenizer) -> Result<Self, Self::Error> {
+        let mut tokens = Vec::new();
         for wrapped_token in input {
-            output.push(wrapped_token.unwrap());
+            match wrapped_token {
+                Ok(token) | Err(HtmlTokenizerError::UndefinedError(token)) => tokens.push(token),
+                Err(error) => return Err(error),
+            }
         }
-        Self { tokens: output }
+        Ok(Self { tokens })
+    }
+}
+
+impl IntoIterator for TokenStream {
+    type Item = Token;
+    type IntoIter = std::vec::IntoIter<Token>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tokens.into_iter()
     }
 }
 
+impl TokenStream {
+    /// Write the tokens as JSON, one token per line. Consecutive characters are written as a single
+    /// text up to and including a newline.
+    pub fn to_json_lines(&self) -> String {
+        let mut output = String::new();
+        let mut text = String::new();
+        let mut write = |output: &mut String, kind: &str, fields: Vec<(&str, Value)>| {
+            let mut object = Object::new();
+            object.insert("type".to_string(), Value::String(kind.to_string()));
+            object.extend(fields.into_iter().map(|(key, value)| (key.to_string(), value)));
+            output.push_str(&json::to_string(&Value::Object(object)));
+            output.push('\n');
+        };
+        let optional_string = |value: &Option<String>| value.clone().map_or(Value::Null, Value::String);
+        for token in &self.tokens {
+            if let Token::Character(c) = token {
+                text.push(*c);
+                if *c != '\n' {
+                    continue;
+                }
+            }
+            if !text.is_empty() {
+                write(&mut output, "text", vec![("data", Value::String(std::mem::take(&mut text)))]);
+            }
+            match token {
+                Token::Character(_) => {},
+                Token::DOCTYPE(name, public_id, system_id, force_quirks) => write(&mut output, "doctype", vec![
+                    ("name", optional_string(name)), ("public_id", optional_string(public_id)),
+                    ("system_id", optional_string(system_id)), ("force_quirks", Value::Bool(*force_quirks)),
+                ]),
+                Token::StartTag(name, self_closing, attributes) | Token::EndTag(name, self_closing, attributes) => {
+                    let attributes = attributes.iter().map(|attribute| {
+                        let mut object = Object::new();
+                        object.insert("name".to_string(), Value::String(attribute.name.clone()));
+                        object.insert("value".to_string(), Value::String(attribute.value.clone()));
+                        Value::Object(object)
+                    }).collect();
+                    let kind = if matches!(token, Token::StartTag(..)) { "start_tag" } else { "end_tag" };
+                    write(&mut output, kind, vec![
+                        ("name", Value::String(name.clone())), ("self_closing", Value::Bool(*self_closing)),
+                        ("attributes", Value::Array(attributes)),
+                    ]);
+                },
+                Token::Comment(data) => write(&mut output, "comment", vec![("data", Value::String(data.clone()))]),
+                Token::EOF => write(&mut output, "eof", Vec::new()),
+            }
+        }
+        if !text.is_empty() {
+            write(&mut output, "text", vec![("data", Value::String(text))]);
+        }
+        output
+    }
+
+    /// Read tokens written by [`TokenStream::to_json_lines`]. Empty lines are skipped.
+    pub fn from_json_lines(text: &str) -> Result<Self, HtmlParseError> {
+        let mut tokens = Vec::new();
+        for (index, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let invalid = |reason| HtmlParseError::InvalidTokenStream { line: index + 1, reason };
+            let value: Value = json::from_str(line).map_err(|_| invalid("not valid JSON"))?;
+            read_token(&value, &mut tokens).map_err(|error| match error {
+                HtmlParseError::InvalidSnapshot(reason) => invalid(reason),
+                error => error,
+            })?;
+        }
+        Ok(Self { tokens })
+    }
+
+    /// Save the tokens to `path`, see [`TokenStream::to_json_lines`].
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(std::fs::write(path, self.to_json_lines())?)
+    }
+
+    /// Load tokens saved by [`TokenStream::save`].
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::from_json_lines(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+// Read a line of a token stream, text is read as a character token for every character.
+fn read_token(value: &Value, tokens: &mut Vec<Token>) -> Result<(), HtmlParseError> {
+    let fields = snapshot::Fields::new(value)?;
+    let tag = |fields: &snapshot::Fields| -> Result<(String, bool, Vec<Attribute>), HtmlParseError> {
+        let mut attributes = Vec::new();
+        for attribute in fields.array("attributes")? {
+            let attribute = snapshot::Fields::new(attribute)?;
+            attributes.push(Attribute { name: attribute.string("name")?, value: attribute.string("value")? });
+        }
+        Ok((fields.string("name")?, fields.bool("self_closing")?, attributes))
+    };
+    match fields.string("type")?.as_str() {
+        "text" => tokens.extend(fields.string("data")?.chars().map(Token::Character)),
+        "doctype" => tokens.push(Token::DOCTYPE(fields.optional_string("name")?, fields.optional_string("public_id")?,
+            fields.optional_string("system_id")?, fields.bool("force_quirks")?)),
+        "start_tag" => {
+            let (name, self_closing, attributes) = tag(&fields)?;
+            tokens.push(Token::StartTag(name, self_closing, attributes));
+        },
+        "end_tag" => {
+            let (name, self_closing, attributes) = tag(&fields)?;
+            tokens.push(Token::EndTag(name, self_closing, attributes));
+        },
+        "comment" => tokens.push(Token::Comment(fields.string("data")?)),
+        "eof" => tokens.push(Token::EOF),
+        _ => return Err(HtmlParseError::InvalidSnapshot("unknown type of token")),
+    }
+    Ok(())
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Attribute {
     pub name: String,
@@ -57,6 +261,7 @@ pub enum Token {
     EOF,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum TagKind {
     StartTag,
     EndTag,
@@ -74,51 +279,480 @@ impl Tokenizer {
             is_self_closing: false,
             tag_kind: TagKind::StartTag,
             return_state : None,
+            comment_buf: String::new(),
+            doctype_name_buf: None,
+            force_quirks: false,
+            temp_buf: String::new(),
+            last_start_tag: String::new(),
+            pending: VecDeque::new(),
+            input_closed: true,
+            limits: ParserLimits::unlimited(),
+            record_errors: false,
+            errors: Vec::new(),
+            drained: 0,
+            line_cursor: 0,
+            line: 1,
+            column: 1,
+            token_start: SourcePosition::default(),
+            waiting: false,
+            token_offset: 0,
         }
     }
+
+    /// Create a tokenizer that expects more input to be passed in through [`Tokenizer::feed`].
+    /// Running out of characters then only means that the tokenizer has to wait, not that the
+    /// document has ended. Call [`Tokenizer::close`] once the last chunk has been fed.
+    pub fn new_streaming(document: PreProccessor) -> Self {
+        Self {
+            input_closed: false,
+            ..Self::new(document)
+        }
+    }
+
     pub fn reset(&mut self) {
         self.tag_name_buf.clear();
         self.attributes_buf.clear();
         self.is_self_closing = false;
     }
+
+    /// Append a chunk of input. Everything that has already been tokenized is discarded, so the
+    /// memory used by a streaming tokenizer only depends on the size of the chunks.
+    pub fn feed(&mut self, chunk: &str) {
+        self.count_lines(self.position);
+        self.document.raw.drain(..self.position);
+        self.drained += self.position;
+        self.line_cursor = 0;
+        self.position = 0;
+        self.document.raw.push_str(chunk);
+    }
+
+    /// Number of bytes fed so far, which is where the next chunk of input starts.
+    pub fn input_offset(&self) -> usize {
+        self.drained + self.document.raw.len()
+    }
+
+    /// Mark the end of the input.
+    pub fn close(&mut self) {
+        self.input_closed = true;
+    }
+
+    /// Switch to a different tokenization state. This is how the tree builder tells the tokenizer
+    /// that the contents of elements such as `<title>` or `<style>` are text.
+    pub fn switch_to(&mut self, state: TokenizationState) {
+        self.state = state;
+    }
+
+    /// Only the limits on attributes and name lengths apply to the tokenizer.
+    pub fn set_limits(&mut self, limits: ParserLimits) {
+        self.limits = limits;
+    }
+
+    /// Keep track of parse errors from now on, they can be read with [`Tokenizer::take_errors`].
+    pub fn record_errors(&mut self) {
+        self.record_errors = true;
+    }
+
+    /// Returns the parse errors recorded since the last call.
+    pub fn take_errors(&mut self) -> Vec<TokenizerErrorCode> {
+        std::mem::take(&mut self.errors).into_iter().map(|(code, _)| code).collect()
+    }
+
+    /// Like [`Tokenizer::take_errors`], along with where in the input each error is.
+    pub fn take_parse_errors(&mut self) -> Vec<HtmlParseError> {
+        std::mem::take(&mut self.errors).into_iter()
+            .map(|(code, position)| HtmlParseError::ParseError { code: ParseErrorCode::Tokenizer(code), position })
+            .collect()
+    }
+
+    /// Where the token last returned by the tokenizer starts. Tokens that come out of the same
+    /// characters, like the two characters of an unknown `&` reference, share a position.
+    pub fn token_position(&self) -> SourcePosition {
+        self.token_start
+    }
+
+    /// The bytes of the input the token last returned comes from, e.g. all of `&amp;` for a `&` character
+    /// or the whole tag for a start tag. Tokens that come out of the same characters share a span.
+    pub fn token_span(&self) -> std::ops::Range<usize> {
+        self.token_offset..self.drained + self.position
+    }
+
+    /// Take a snapshot to continue from the next token later on, see [`Tokenizer::resume`]. Returns `None`
+    /// while there are tokens left from the characters read last or the input ran out in the middle of a token.
+    pub fn snapshot(&self) -> Option<TokenizerSnapshot> {
+        if self.has_pending() || self.waiting {
+            return None;
+        }
+        Some(TokenizerSnapshot {
+            state: self.state,
+            last_start_tag: self.last_start_tag.clone(),
+            position: self.position_of(self.position),
+        })
+    }
+
+    /// Continue tokenizing from a snapshot. `document` is the whole input, which has to be the same as the one
+    /// the snapshot was taken from up to where the snapshot was taken, what comes after can be different.
+    pub fn resume(document: PreProccessor, snapshot: &TokenizerSnapshot) -> Self {
+        let offset = snapshot.position.offset;
+        Self {
+            state: snapshot.state,
+            last_start_tag: snapshot.last_start_tag.clone(),
+            position: offset,
+            line_cursor: offset,
+            line: snapshot.position.line,
+            column: snapshot.position.column,
+            token_start: snapshot.position,
+            token_offset: offset,
+            ..Self::new(document)
+        }
+    }
+
+    // Whether the next token comes out of the same characters as the last one.
+    pub(crate) fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    // Everything but the recorded errors as JSON, see crate::snapshot. The tokens left from the characters
+    // read last can't be saved, the tree builder has to take them first.
+    pub(crate) fn to_json(&self) -> Result<Value, HtmlParseError> {
+        if self.has_pending() {
+            return Err(HtmlParseError::InvalidSnapshot("the tokenizer has tokens left to hand out"));
+        }
+        let attributes = self.attributes_buf.iter().map(|attribute| {
+            let mut object = Object::new();
+            object.insert("name".to_string(), Value::String(attribute.name.clone()));
+            object.insert("value".to_string(), Value::String(attribute.value.clone()));
+            Value::Object(object)
+        }).collect();
+        let optional_string = |value: &Option<String>| value.clone().map_or(Value::Null, Value::String);
+        let mut object = Object::new();
+        object.insert("input".to_string(), Value::String(self.document.raw.clone()));
+        object.insert("state".to_string(), snapshot::name(&self.state));
+        object.insert("return_state".to_string(), self.return_state.as_ref().map_or(Value::Null, snapshot::name));
+        object.insert("position".to_string(), snapshot::number(self.position));
+        object.insert("previous".to_string(), optional_string(&self.previous.map(String::from)));
+        object.insert("tag_name_buf".to_string(), Value::String(self.tag_name_buf.clone()));
+        object.insert("attributes_buf".to_string(), Value::Array(attributes));
+        object.insert("is_self_closing".to_string(), Value::Bool(self.is_self_closing));
+        object.insert("end_tag".to_string(), Value::Bool(self.tag_kind == TagKind::EndTag));
+        object.insert("comment_buf".to_string(), Value::String(self.comment_buf.clone()));
+        object.insert("doctype_name_buf".to_string(), optional_string(&self.doctype_name_buf));
+        object.insert("force_quirks".to_string(), Value::Bool(self.force_quirks));
+        object.insert("temp_buf".to_string(), Value::String(self.temp_buf.clone()));
+        object.insert("last_start_tag".to_string(), Value::String(self.last_start_tag.clone()));
+        object.insert("input_closed".to_string(), Value::Bool(self.input_closed));
+        object.insert("limits".to_string(), snapshot::limits(&self.limits));
+        object.insert("record_errors".to_string(), Value::Bool(self.record_errors));
+        object.insert("drained".to_string(), snapshot::number(self.drained));
+        object.insert("line_cursor".to_string(), snapshot::number(self.line_cursor));
+        object.insert("line".to_string(), snapshot::number(self.line));
+        object.insert("column".to_string(), snapshot::number(self.column));
+        object.insert("token_start".to_string(), snapshot::position(&self.token_start));
+        object.insert("waiting".to_string(), Value::Bool(self.waiting));
+        object.insert("token_offset".to_string(), snapshot::number(self.token_offset));
+        Ok(Value::Object(object))
+    }
+
+    // Read a tokenizer written by Tokenizer::to_json.
+    pub(crate) fn from_json(value: &Value) -> Result<Self, HtmlParseError> {
+        let fields = snapshot::Fields::new(value)?;
+        let input = fields.string("input")?;
+        let position = fields.usize("position")?;
+        let line_cursor = fields.usize("line_cursor")?;
+        if !input.is_char_boundary(position) || line_cursor > position {
+            return Err(HtmlParseError::InvalidSnapshot("the position isn't in the input"));
+        }
+        let mut attributes_buf = Vec::new();
+        for attribute in fields.array("attributes_buf")? {
+            let attribute = snapshot::Fields::new(attribute)?;
+            attributes_buf.push(Attribute { name: attribute.string("name")?, value: attribute.string("value")? });
+        }
+        Ok(Self {
+            document: PreProccessor::new(&input).map_err(|_| HtmlParseError::InvalidSnapshot("the input can't be tokenized"))?,
+            state: fields.named("state", &TokenizationState::ALL)?,
+            return_state: match fields.get("return_state")? {
+                Value::Null => None,
+                _ => Some(fields.named("return_state", &TokenizationState::ALL)?),
+            },
+            position,
+            previous: fields.optional_string("previous")?.and_then(|previous| previous.chars().next()),
+            tag_name_buf: fields.string("tag_name_buf")?,
+            attributes_buf,
+            is_self_closing: fields.bool("is_self_closing")?,
+            tag_kind: if fields.bool("end_tag")? { TagKind::EndTag } else { TagKind::StartTag },
+            comment_buf: fields.string("comment_buf")?,
+            doctype_name_buf: fields.optional_string("doctype_name_buf")?,
+            force_quirks: fields.bool("force_quirks")?,
+            temp_buf: fields.string("temp_buf")?,
+            last_start_tag: fields.string("last_start_tag")?,
+            pending: VecDeque::new(),
+            input_closed: fields.bool("input_closed")?,
+            limits: snapshot::read_limits(fields.get("limits")?)?,
+            record_errors: fields.bool("record_errors")?,
+            errors: Vec::new(),
+            drained: fields.usize("drained")?,
+            line_cursor,
+            line: fields.usize("line")?,
+            column: fields.usize("column")?,
+            token_start: snapshot::read_position(fields.get("token_start")?)?,
+            waiting: fields.bool("waiting")?,
+            token_offset: fields.usize("token_offset")?,
+        })
+    }
+
+    // Move the line cursor up to `index` in the current buffer.
+    fn count_lines(&mut self, index: usize) {
+        if index > self.line_cursor {
+            let position = self.position_of(index);
+            (self.line, self.column, self.line_cursor) = (position.line, position.column, index);
+        }
+    }
+
+    // The position of `index` in the current buffer, which can't be before the line cursor.
+    fn position_of(&self, index: usize) -> SourcePosition {
+        let (mut line, mut column) = (self.line, self.column);
+        for c in self.document.raw[self.line_cursor..index.max(self.line_cursor)].chars() {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        SourcePosition { offset: self.drained + index.max(self.line_cursor), line, column }
+    }
+
+    fn error(&mut self, code: TokenizerErrorCode) {
+        if cfg!(feature = "tokenizer-log") {debug!("Parse error : {}", code);}
+        if self.record_errors {
+            // The error is about the character that was consumed last.
+            let position = self.position_of(self.position.saturating_sub(self.previous.map_or(0, char::len_utf8)));
+            self.errors.push((code, position));
+        }
+    }
+
+    pub fn set_last_start_tag(&mut self, name: &str) {
+        self.last_start_tag.clear();
+        self.last_start_tag.push_str(name);
+    }
+
+    // https://html.spec.whatwg.org/multipage/parsing.html#appropriate-end-tag-token
+    fn is_appropriate_end_tag(&self) -> bool {
+        !self.last_start_tag.is_empty() && self.tag_name_buf == self.last_start_tag
+    }
+
+    fn emit_doctype(&mut self) -> Token {
+        Token::DOCTYPE(self.doctype_name_buf.take(), None, None, std::mem::replace(&mut self.force_quirks, false))
+    }
+
+    // Returns the first of `tokens` and queues up the rest.
+    fn emit_all(&mut self, mut tokens: impl Iterator<Item = Token>) -> Option<Result<Token, HtmlTokenizerError>> {
+        let first = tokens.next()?;
+        self.pending.extend(tokens);
+        Some(Ok(first))
+    }
+
+    // Called when the input has run out. Whatever is left over in the current state is emitted, which
+    // is only done once the input is closed, a streaming tokenizer may still get more of it.
+    // https://html.spec.whatwg.org/multipage/parsing.html#tokenization (the "EOF" case of every state)
+    fn end_of_input(&mut self) -> Option<Result<Token, HtmlTokenizerError>> {
+        if !self.input_closed {
+            self.waiting = true;
+            return None;
+        }
+        let state = std::mem::replace(&mut self.state, TokenizationState::Data);
+        match state {
+            TokenizationState::TagOpen | TokenizationState::EndTagOpen => self.error(TokenizerErrorCode::EofBeforeTagName),
+            TokenizationState::MarkupDeclarationOpen => self.error(TokenizerErrorCode::IncorrectlyOpenedComment),
+            TokenizationState::CommentStart | TokenizationState::CommentStartDash | TokenizationState::Comment
+            | TokenizationState::CommentEndDash | TokenizationState::CommentEnd | TokenizationState::CommentEndBang => self.error(TokenizerErrorCode::EofInComment),
+            TokenizationState::DOCTYPE | TokenizationState::BeforeDOCTYPEName | TokenizationState::DOCTYPEName
+            | TokenizationState::AfterDOCTYPEName => self.error(TokenizerErrorCode::EofInDoctype),
+            TokenizationState::TagName | TokenizationState::SelfClosingStartTag | TokenizationState::BeforeAttributeName
+            | TokenizationState::AttributeName | TokenizationState::AfterAttributeName | TokenizationState::BeforeAttributeValue
+            | TokenizationState::AttributeValueDoubleQuoted | TokenizationState::AttributeValueSingleQuoted
+            | TokenizationState::AttributeValueUnquoted | TokenizationState::AfterAttributeValueQuoted
+            | TokenizationState::CharacterrReferenceInAttributeValue => self.error(TokenizerErrorCode::EofInTag),
+            _ => (),
+        }
+        match state {
+            TokenizationState::TagOpen | TokenizationState::RCDATALessThanSign | TokenizationState::RAWTEXTLessThanSign => Some(Ok(Token::Character('<'))),
+            TokenizationState::EndTagOpen | TokenizationState::RCDATAEndTagOpen | TokenizationState::RAWTEXTEndTagOpen => self.emit_all("</".chars().map(Token::Character)),
+            TokenizationState::RCDATAEndTagName | TokenizationState::RAWTEXTEndTagName => {
+                self.reset();
+                self.tag_kind = TagKind::StartTag;
+                let text = format!("</{}", self.temp_buf);
+                self.emit_all(text.chars().map(Token::Character))
+            },
+            // eof-in-tag parse error, the tag is dropped.
+            TokenizationState::TagName | TokenizationState::SelfClosingStartTag | TokenizationState::BeforeAttributeName
+            | TokenizationState::AttributeName | TokenizationState::AfterAttributeName | TokenizationState::BeforeAttributeValue
+            | TokenizationState::AttributeValueDoubleQuoted | TokenizationState::AttributeValueSingleQuoted
+            | TokenizationState::AttributeValueUnquoted | TokenizationState::AfterAttributeValueQuoted
+            | TokenizationState::CharacterrReferenceInAttributeValue => {
+                self.reset();
+                self.tag_kind = TagKind::StartTag;
+                None
+            },
+            TokenizationState::CharacterReferenceInData | TokenizationState::CharacterReferenceInRCDATA => Some(Ok(Token::Character('&'))),
+            TokenizationState::MarkupDeclarationOpen | TokenizationState::BogusComment | TokenizationState::CommentStart
+            | TokenizationState::CommentStartDash | TokenizationState::Comment | TokenizationState::CommentEndDash
+            | TokenizationState::CommentEnd | TokenizationState::CommentEndBang => Some(Ok(Token::Comment(std::mem::take(&mut self.comment_buf)))),
+            TokenizationState::DOCTYPE | TokenizationState::BeforeDOCTYPEName | TokenizationState::DOCTYPEName
+            | TokenizationState::AfterDOCTYPEName | TokenizationState::BogusDOCTYPE => {
+                if state != TokenizationState::BogusDOCTYPE {
+                    self.force_quirks = true;
+                }
+                if state == TokenizationState::DOCTYPE {
+                    self.doctype_name_buf = None;
+                }
+                Some(Ok(self.emit_doctype()))
+            },
+            _ => None,
+        }
+    }
+
+    // When an attribute name is used twice, only the first attribute is kept.
+    fn remove_duplicate_attributes(&mut self) {
+        if self.attributes_buf.len() < 2 {
+            return;
+        }
+        let mut names = HashSet::new();
+        let before = self.attributes_buf.len();
+        self.attributes_buf.retain(|attribute| names.insert(attribute.name.clone()));
+        for _ in self.attributes_buf.len()..before {
+            self.error(TokenizerErrorCode::DuplicateAttribute);
+        }
+    }
+
+    fn push_to_name(&mut self, c: char) {
+        if let Some(attribute) = self.attributes_buf.last_mut() {
+            push_limited(&mut attribute.name, c, self.limits.max_name_length);
+        }
+    }
+
+    fn push_to_value(&mut self, c: char) {
+        if let Some(attribute) = self.attributes_buf.last_mut() {
+            push_limited(&mut attribute.value, c, self.limits.max_attribute_length);
+        }
+    }
+
+    fn in_attribute_value(&self) -> bool {
+        matches!(self.state, TokenizationState::AttributeValueDoubleQuoted
+            | TokenizationState::AttributeValueSingleQuoted | TokenizationState::AttributeValueUnquoted)
+    }
+
+    // Shared by the three character reference states, the current character is the first one after the '&'.
+    // Switches back to the return state and returns the referenced character, or None if there isn't
+    // enough input to tell yet.
+    // https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+    fn character_reference(&mut self, current: char) -> Option<char> {
+        let start = self.position - current.len_utf8();
+        self.state = self.return_state.take().unwrap_or(TokenizationState::Data);
+        let mut errors = Vec::new();
+        let reference = match_character_reference(&self.document.raw[start..], self.in_attribute_value(), self.input_closed, &mut errors);
+        if !matches!(reference, CharacterReference::Incomplete) {
+            errors.into_iter().for_each(|code| self.error(code));
+        }
+        match reference {
+            CharacterReference::Found(c, consumed) => {
+                self.position = start + consumed;
+                Some(c)
+            },
+            // The '&' is text, and whatever follows it is tokenized as usual.
+            CharacterReference::NotFound => {
+                self.position = start;
+                Some('&')
+            },
+            // Start over from the '&' once the next chunk has been fed.
+            CharacterReference::Incomplete => {
+                self.position = start - '&'.len_utf8();
+                self.waiting = true;
+                None
+            },
+        }
+    }
+}
+
+// Characters that would make the name or value longer than `max` bytes are dropped.
+fn push_limited(buf: &mut String, c: char, max: usize) {
+    if buf.len() + c.len_utf8() <= max {
+        buf.push(c);
+    }
+}
+
+// Once a tag has more than `max` attributes, the extra slot is reused for every following attribute
+// and dropped when the tag is emitted. This way a tag with lots of attributes can't use up memory.
+fn start_attribute(attributes: &mut Vec<Attribute>, max: usize, name: String) {
+    if attributes.len() > max {
+        let last = attributes.last_mut().unwrap();
+        last.name = name;
+        last.value.clear();
+    } else {
+        attributes.push(Attribute { name, value: String::new() });
+    }
 }
 
 impl Iterator for Tokenizer {
     type Item = Result<Token, HtmlTokenizerError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut chars = self.document.raw[self.position..].chars();
+        if let Some(token) = self.pending.pop_front() {
+            return Some(Ok(token));
+        }
+
+        if !std::mem::replace(&mut self.waiting, false) {
+            self.count_lines(self.position);
+            self.token_start = self.position_of(self.position);
+        }
         let mut reconsume = false;
 
         loop {
-            // If the current character is re-consumed, reset the iterator back one character (which is self.position).
+            // If the current character is re-consumed, move the position back one character.
             if reconsume {
-                chars =
-                    self.document.raw[self.position - self.previous.unwrap().len_utf8()..].chars();
+                self.position -= self.previous.map_or(0, char::len_utf8);
                 reconsume = false;
             }
 
-            let result = chars.next();
+            let result = self.document.raw[self.position..].chars().next();
 
             let current: char = if let Some(c) = result {
+                if matches!(self.state, TokenizationState::Data | TokenizationState::RCDATA | TokenizationState::RAWTEXT
+                    | TokenizationState::ScriptData | TokenizationState::PLAINTEXT) {
+                    self.token_offset = self.drained + self.position;
+                }
                 self.previous = Some(c);
                 self.position += c.len_utf8();
                 c
             } else {
-                return None;
+                return self.end_of_input();
             };
 
             if cfg!(feature = "tokenizer-log") {debug!("State : {:?}", self.state);}
+
+            // The attribute states add to the last attribute, which isn't there when the state was set
+            // through Tokenizer::switch_to or a snapshot.
+            if self.attributes_buf.is_empty() && matches!(self.state, TokenizationState::AttributeName
+                | TokenizationState::BeforeAttributeValue | TokenizationState::AttributeValueDoubleQuoted
+                | TokenizationState::AttributeValueSingleQuoted | TokenizationState::AttributeValueUnquoted) {
+                let state = std::mem::replace(&mut self.state, TokenizationState::BeforeAttributeName);
+                self.position -= current.len_utf8();
+                return Some(Err(HtmlTokenizerError::MissingAttribute(state)));
+            }
             
             match self.state {
                 //https://html.spec.whatwg.org/multipage/parsing.html#data-state
                 TokenizationState::Data => {
                     match current {
-                        '&' => self.state = TokenizationState::CharacterReferenceInData, // &
+                        '&' => {
+                            self.return_state = Some(TokenizationState::Data);
+                            self.state = TokenizationState::CharacterReferenceInData;
+                        } // &
                         '<' => self.state = TokenizationState::TagOpen,                  // <
-                        '\0' => return Some(Err(HtmlTokenizerError::UndefinedError(Token::Character(current)))), // NULL, Parse error
+                        '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
+                            return Some(Err(HtmlTokenizerError::UndefinedError(Token::Character(current))));
+                        } // NULL, Parse error
                         _ => return Some(Ok(Token::Character(current))),
-                        // TODO : EOF
                     }
                 }
                 TokenizationState::TagOpen => {
@@ -129,30 +763,48 @@ impl Iterator for Tokenizer {
                         } // /
                         'A'..='Z' => {
                             self.state = TokenizationState::TagName;
-                            self.tag_name_buf.push(char::to_ascii_lowercase(&current));
+                            push_limited(&mut self.tag_name_buf, char::to_ascii_lowercase(&current), self.limits.max_name_length);
                         } // A - Z
                         'a'..='z' => {
                             self.state = TokenizationState::TagName;
-                            self.tag_name_buf.push(current);
+                            push_limited(&mut self.tag_name_buf, current, self.limits.max_name_length);
                         } // a - z
-                        '?' => self.state = TokenizationState::BogusComment, // ?, Parse error.
-                        _ => return Some(Err(HtmlTokenizerError::UndefinedError(Token::Character('<')))), // Parse error. TODO: Doesn't make sense.
+                        '?' => {
+                            self.error(TokenizerErrorCode::UnexpectedQuestionMarkInsteadOfTagName);
+                            self.comment_buf.clear();
+                            self.state = TokenizationState::BogusComment;
+                            reconsume = true;
+                        } // ?, Parse error.
+                        _ => {
+                            self.error(TokenizerErrorCode::InvalidFirstCharacterOfTagName);
+                            self.state = TokenizationState::Data;
+                            self.position -= current.len_utf8();
+                            return Some(Err(HtmlTokenizerError::UndefinedError(Token::Character('<'))));
+                        } // Parse error, emit '<' and reconsume in the data state.
                     }
                 }
                 TokenizationState::EndTagOpen => {
-                    self.tag_kind = TagKind::EndTag;
                     match current {
                         'A'..='Z' => {
+                            self.tag_kind = TagKind::EndTag;
                             self.state = TokenizationState::TagName;
-                            self.tag_name_buf.push(char::to_ascii_lowercase(&current));
+                            push_limited(&mut self.tag_name_buf, char::to_ascii_lowercase(&current), self.limits.max_name_length);
                         } // A - Z
                         'a'..='z' => {
+                            self.tag_kind = TagKind::EndTag;
                             self.state = TokenizationState::TagName;
-                            self.tag_name_buf.push(current);
+                            push_limited(&mut self.tag_name_buf, current, self.limits.max_name_length);
                         } // a - z
-                        '>' => self.state = TokenizationState::Data, // >, Parse error.
-                        _ => self.state = TokenizationState::BogusComment,  // Parse error.
-                                                                             //TODO: EOF
+                        '>' => {
+                            self.error(TokenizerErrorCode::MissingEndTagName);
+                            self.state = TokenizationState::Data;
+                        } // >, Parse error.
+                        _ => {
+                            self.error(TokenizerErrorCode::InvalidFirstCharacterOfTagName);
+                            self.comment_buf.clear();
+                            self.state = TokenizationState::BogusComment;
+                            reconsume = true;
+                        } // Parse error.
                     }
                 }
                 TokenizationState::TagName => {
@@ -166,11 +818,13 @@ impl Iterator for Tokenizer {
                             break;
                         } // >
                         'A'..='Z' => {
-                            self.tag_name_buf.push(char::to_ascii_lowercase(&current))
+                            push_limited(&mut self.tag_name_buf, char::to_ascii_lowercase(&current), self.limits.max_name_length)
                         } // A - Z
-                        '\0' => self.tag_name_buf.push('\u{FFFD}'), // NULL Parse error.
-                        _ => self.tag_name_buf.push(current),
-                        // TODO : EOF
+                        '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
+                            push_limited(&mut self.tag_name_buf, '\u{FFFD}', self.limits.max_name_length);
+                        } // NULL Parse error.
+                        _ => push_limited(&mut self.tag_name_buf, current, self.limits.max_name_length),
                     }
                 }
                 TokenizationState::SelfClosingStartTag => {
@@ -181,9 +835,10 @@ impl Iterator for Tokenizer {
                             break;
                         } // >
                         _ => {
+                            self.error(TokenizerErrorCode::UnexpectedSolidusInTag);
                             self.state = TokenizationState::BeforeAttributeName;
                             reconsume = true;
-                        } // TODO : EOF
+                        }
                     }
                 }
                 TokenizationState::BeforeAttributeName => {
@@ -196,32 +851,22 @@ impl Iterator for Tokenizer {
                         } // >
                         'A'..='Z' => {
                             self.state = TokenizationState::AttributeName;
-                            self.attributes_buf.push(Attribute {
-                                name: String::from(char::to_ascii_lowercase(&current)),
-                                value: String::new(),
-                            });
+                            start_attribute(&mut self.attributes_buf, self.limits.max_attributes, String::from(char::to_ascii_lowercase(&current)));
                         } // A - Z
                         '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
                             self.state = TokenizationState::AttributeName;
-                            self.attributes_buf.push(Attribute {
-                                name: String::from('\u{FFFD}'),
-                                value: String::new(),
-                            });
-                        } // >
+                            start_attribute(&mut self.attributes_buf, self.limits.max_attributes, String::from('\u{FFFD}'));
+                        } // NULL, Parse error
                         '\u{0022}' | '\u{0027}' | '<' | '\u{003D}' => {
+                            self.error(if current == '=' { TokenizerErrorCode::UnexpectedEqualsSignBeforeAttributeName } else { TokenizerErrorCode::UnexpectedCharacterInAttributeName });
                             self.state = TokenizationState::AttributeName;
-                            self.attributes_buf.push(Attribute {
-                                name: String::from(current),
-                                value: String::new(),
-                            });
+                            start_attribute(&mut self.attributes_buf, self.limits.max_attributes, String::from(current));
                         } // " ' < = Parse error
                         _ => {
                             self.state = TokenizationState::AttributeName;
-                            self.attributes_buf.push(Attribute {
-                                name: String::from(current),
-                                value: String::new(),
-                            });
-                        } // TODO : EOF
+                            start_attribute(&mut self.attributes_buf, self.limits.max_attributes, String::from(current));
+                        }
                     }
                 }
                 TokenizationState::AttributeName => {
@@ -236,27 +881,21 @@ impl Iterator for Tokenizer {
                             break;
                         } // >
                         'A'..='Z' => {
-                            self.attributes_buf
-                                .last_mut()
-                                .unwrap()
-                                .name
-                                .push(char::to_ascii_lowercase(&current));
+                            self.push_to_name(char::to_ascii_lowercase(&current));
                         } // A - Z
                         '\0' => {
-                            self.attributes_buf
-                                .last_mut()
-                                .unwrap()
-                                .name
-                                .push('\u{FFFD}');
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
+                            self.push_to_name('\u{FFFD}');
                         } // NULL, Parse error
                         '\u{0022}' | '\u{0027}' | '<' => {
-                            self.attributes_buf.last_mut().unwrap().name.push(current);
+                            self.error(TokenizerErrorCode::UnexpectedCharacterInAttributeName);
+                            self.push_to_name(current);
                         } // " ' < = Parse error
                         _ => {
-                            self.attributes_buf.last_mut().unwrap().name.push(current);
-                        } // TODO : EOF
+                            self.push_to_name(current);
+                        }
                     }
-                    // TODO: Check if attribute name already exists, and if it does, emit a parse error.
+                    // NOTE: Duplicate attributes are removed when the tag is emitted.
                 }
                 TokenizationState::AfterAttributeName => {
                     match current {
@@ -268,19 +907,17 @@ impl Iterator for Tokenizer {
                             break;
                         } // >
                         '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
                             self.state = TokenizationState::AttributeName;
-                            self.attributes_buf.push(Attribute {
-                                name: String::from('\u{FFFD}'),
-                                value: String::new(),
-                            });
+                            start_attribute(&mut self.attributes_buf, self.limits.max_attributes, String::from('\u{FFFD}'));
                         } // NULL, Parse error
                         _ => {
+                            if matches!(current, '\u{0022}' | '\u{0027}' | '<') {
+                                self.error(TokenizerErrorCode::UnexpectedCharacterInAttributeName);
+                            }
                             self.state = TokenizationState::AttributeName;
-                            self.attributes_buf.push(Attribute {
-                                name: String::from(char::to_ascii_lowercase(&current)),
-                                value: String::new(),
-                            });
-                        } // TODO : EOF
+                            start_attribute(&mut self.attributes_buf, self.limits.max_attributes, String::from(char::to_ascii_lowercase(&current)));
+                        }
                     }
                 }
                 TokenizationState::BeforeAttributeValue => {
@@ -291,111 +928,445 @@ impl Iterator for Tokenizer {
                             self.state = TokenizationState::AttributeValueUnquoted;
                             reconsume = true;
                         } // &
-                        '\u{0027}' => self.state = TokenizationState::AttributeValueUnquoted, // '
+                        '\u{0027}' => self.state = TokenizationState::AttributeValueSingleQuoted, // '
                         '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
                             self.state = TokenizationState::AttributeValueUnquoted;
-                            self.attributes_buf.last_mut().unwrap().value.push(current);
+                            self.push_to_value('\u{FFFD}');
                         } // NULL, Parse error
                         '>' => {
+                            self.error(TokenizerErrorCode::MissingAttributeValue);
                             self.state = TokenizationState::Data;
                             break;
-                        } // NULL, Parse error
+                        } // >, Parse error
                         '<' | '\u{003D}' | '\u{0060}' => {
+                            self.error(TokenizerErrorCode::UnexpectedCharacterInUnquotedAttributeValue);
                             self.state = TokenizationState::AttributeValueUnquoted;
-                            self.attributes_buf.last_mut().unwrap().value.push(current);
+                            self.push_to_value(current);
                         } // < = `
                         _ => {
                             self.state = TokenizationState::AttributeValueUnquoted;
-                            self.attributes_buf.last_mut().unwrap().value.push(current);
-                        } // TODO : EOF
+                            self.push_to_value(current);
+                        }
                     }
                 }
-                TokenizationState::AttributeValueDoubleQuoted => {
+                TokenizationState::AttributeValueDoubleQuoted | TokenizationState::AttributeValueSingleQuoted => {
+                    let quote = if let TokenizationState::AttributeValueDoubleQuoted = self.state { '\"' } else { '\'' };
                     match current {
-                        '\"' => self.state = TokenizationState::AfterAttributeValueQuoted,
-                        '&' => self.return_state = Some(TokenizationState::AttributeValueDoubleQuoted),
-                        '\n' => return Some(Err(HtmlTokenizerError::UnexpectedNullCharacter)),
+                        c if c == quote => self.state = TokenizationState::AfterAttributeValueQuoted,
+                        '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
+                            self.push_to_value('\u{FFFD}');
+                        } // NULL, Parse error
+                        '&' => {
+                            self.return_state = Some(self.state);
+                            self.state = TokenizationState::CharacterrReferenceInAttributeValue;
+                        } // &
                         _ => {
-                            
-                        },
-                        //TODO : EOF
+                            self.push_to_value(current);
+                        }
                     }
                 },
-                TokenizationState::AttributeValueSingleQuoted => {
-
-                },
+                TokenizationState::AfterAttributeValueQuoted => {
+                    match current {
+                        '\t' | '\u{000A}' | '\u{000C}' | ' ' => {
+                            self.state = TokenizationState::BeforeAttributeName
+                        } // tab, LF, FF, Space
+                        '/' => self.state = TokenizationState::SelfClosingStartTag, // /
+                        '>' => {
+                            self.state = TokenizationState::Data;
+                            break;
+                        } // >
+                        _ => {
+                            self.error(TokenizerErrorCode::MissingWhitespaceBetweenAttributes);
+                            self.state = TokenizationState::BeforeAttributeName;
+                            reconsume = true;
+                        } // Parse error.
+                    }
+                }
                 TokenizationState::AttributeValueUnquoted => {
                     match current {
                         '\t' | '\u{000A}' | '\u{000C}' | ' ' => {
                             self.state = TokenizationState::BeforeAttributeName
                         } // tab, LF, FF, Space
                         '&' => {
-                            self.state = TokenizationState::CharacterrReferenceInAttributeValue
-                        } // & TODO : What is an allowed additional character in a character reference?
+                            self.return_state = Some(TokenizationState::AttributeValueUnquoted);
+                            self.state = TokenizationState::CharacterrReferenceInAttributeValue;
+                        } // &
                         '>' => {
                             self.state = TokenizationState::Data;
                             break;
                         }
                         '\0' => {
-                            self.attributes_buf
-                                .last_mut()
-                                .unwrap()
-                                .value
-                                .push('\u{FFFD}');
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
+                            self.push_to_value('\u{FFFD}');
                         } // NULL, Parse error
                         '\u{0022}' | '\u{0027}' | '<' | '\u{003D}' | '\u{0060}' => {
-                            self.state = TokenizationState::BeforeAttributeName
+                            self.error(TokenizerErrorCode::UnexpectedCharacterInUnquotedAttributeValue);
+                            self.push_to_value(current);
                         } // " ' < = ` Parse error
                         _ => {
-                            self.attributes_buf.last_mut().unwrap().value.push(current);
-                        } //TODO : EOF
+                            self.push_to_value(current);
+                        }
                     }
                 }
-                TokenizationState::CharacterReferenceInData => todo!(),
-                TokenizationState::RCDATA => todo!(),
-                TokenizationState::CharacterReferenceInRCDATA => todo!(),
-                TokenizationState::RAWTEXT => todo!(),
-                TokenizationState::ScriptData => todo!(),
-                TokenizationState::PLAINTEXT => todo!(),
-                TokenizationState::RCDATALessThanSign => todo!(),
-                TokenizationState::RCDATAEndTagOpen => todo!(),
-                TokenizationState::RCDATAEndTagName => todo!(),
-                TokenizationState::RAWTEXTLessThanSign => todo!(),
-                TokenizationState::RAWTEXTEndTagOpen => todo!(),
-                TokenizationState::RAWTEXTEndTagName => todo!(),
-                TokenizationState::AttributeValueDoubleQuoted => todo!(),
-                TokenizationState::AttributeValueSingleQuoted => todo!(),
-                TokenizationState::CharacterrReferenceInAttributeValue => todo!(),
-                TokenizationState::AfterAttributeValueQuoted => todo!(),
-                TokenizationState::BogusComment => todo!(),
-                TokenizationState::MarkupDeclarationOpen => todo!(),
-                TokenizationState::CommentStart => todo!(),
-                TokenizationState::CommentStartDash => todo!(),
-                TokenizationState::Comment => todo!(),
-                TokenizationState::CommentEndDash => todo!(),
-                TokenizationState::CommentEnd => todo!(),
-                TokenizationState::CommentEndBang => todo!(),
-                TokenizationState::DOCTYPE => todo!(),
-                TokenizationState::BeforeDOCTYPEName => todo!(),
-                TokenizationState::DOCTYPEName => todo!(),
-                TokenizationState::AfterDOCTYPEName => todo!(),
-                TokenizationState::AfterDOCTYPEPublicKeyword => todo!(),
-                TokenizationState::BeforeDOCTYPEPublicIdentifier => todo!(),
-                TokenizationState::DOCTYPEPublicIdentifierDoubleQuoted => todo!(),
-                TokenizationState::DOCTYPEPublicIdentifierSingleQuoted => todo!(),
-                TokenizationState::AfterDOCTYPEPublicIdentifier => todo!(),
-                TokenizationState::BetweenDOCTYPEPublicAndSystemIdentifiers => todo!(),
-                TokenizationState::AfterDOCTYPESystemKeyword => todo!(),
-                TokenizationState::BeforeDOCTYPESystemIdentifier => todo!(),
-                TokenizationState::DOCTYPESystemIdentifierDoubleQuoted => todo!(),
-                TokenizationState::DOCTYPESystemIdentifierSingleQuoted => todo!(),
-                TokenizationState::AfterDOCTYPESystemIdentifier => todo!(),
-                TokenizationState::BogusDOCTYPE => todo!(),
-                TokenizationState::CDATASection => todo!(),
+                TokenizationState::CharacterReferenceInData | TokenizationState::CharacterReferenceInRCDATA
+                | TokenizationState::CharacterrReferenceInAttributeValue => {
+                    let c = self.character_reference(current)?;
+                    if !self.in_attribute_value() {
+                        return Some(Ok(Token::Character(c)));
+                    }
+                    self.push_to_value(c);
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#rcdata-state
+                TokenizationState::RCDATA => {
+                    match current {
+                        '&' => {
+                            self.return_state = Some(TokenizationState::RCDATA);
+                            self.state = TokenizationState::CharacterReferenceInRCDATA;
+                        } // &
+                        '<' => self.state = TokenizationState::RCDATALessThanSign, // <
+                        '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
+                            return Some(Err(HtmlTokenizerError::UndefinedError(Token::Character('\u{FFFD}'))));
+                        } // NULL, Parse error
+                        _ => return Some(Ok(Token::Character(current))),
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#rawtext-state
+                // NOTE: The script data escape states are not implemented, so script data is tokenized the same way as RAWTEXT.
+                TokenizationState::RAWTEXT | TokenizationState::ScriptData => {
+                    match current {
+                        '<' => self.state = TokenizationState::RAWTEXTLessThanSign, // <
+                        '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
+                            return Some(Err(HtmlTokenizerError::UndefinedError(Token::Character('\u{FFFD}'))));
+                        } // NULL, Parse error
+                        _ => return Some(Ok(Token::Character(current))),
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#plaintext-state
+                TokenizationState::PLAINTEXT => {
+                    match current {
+                        '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
+                            return Some(Err(HtmlTokenizerError::UndefinedError(Token::Character('\u{FFFD}'))));
+                        } // NULL, Parse error
+                        _ => return Some(Ok(Token::Character(current))),
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#rcdata-less-than-sign-state
+                //https://html.spec.whatwg.org/multipage/parsing.html#rawtext-less-than-sign-state
+                TokenizationState::RCDATALessThanSign | TokenizationState::RAWTEXTLessThanSign => {
+                    let rcdata = matches!(self.state, TokenizationState::RCDATALessThanSign);
+                    match current {
+                        '/' => {
+                            self.temp_buf.clear();
+                            self.state = if rcdata { TokenizationState::RCDATAEndTagOpen } else { TokenizationState::RAWTEXTEndTagOpen };
+                        } // /
+                        _ => {
+                            self.state = if rcdata { TokenizationState::RCDATA } else { TokenizationState::RAWTEXT };
+                            self.position -= current.len_utf8();
+                            return Some(Ok(Token::Character('<')));
+                        } // Emit '<' and reconsume
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#rcdata-end-tag-open-state
+                //https://html.spec.whatwg.org/multipage/parsing.html#rawtext-end-tag-open-state
+                TokenizationState::RCDATAEndTagOpen | TokenizationState::RAWTEXTEndTagOpen => {
+                    let rcdata = matches!(self.state, TokenizationState::RCDATAEndTagOpen);
+                    match current {
+                        'A'..='Z' | 'a'..='z' => {
+                            self.tag_kind = TagKind::EndTag;
+                            self.tag_name_buf.clear();
+                            self.state = if rcdata { TokenizationState::RCDATAEndTagName } else { TokenizationState::RAWTEXTEndTagName };
+                            reconsume = true;
+                        } // A - Z, a - z
+                        _ => {
+                            self.state = if rcdata { TokenizationState::RCDATA } else { TokenizationState::RAWTEXT };
+                            self.pending.push_back(Token::Character('/'));
+                            self.position -= current.len_utf8();
+                            return Some(Ok(Token::Character('<')));
+                        } // Emit '</' and reconsume
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#rcdata-end-tag-name-state
+                //https://html.spec.whatwg.org/multipage/parsing.html#rawtext-end-tag-name-state
+                TokenizationState::RCDATAEndTagName | TokenizationState::RAWTEXTEndTagName => {
+                    let rcdata = matches!(self.state, TokenizationState::RCDATAEndTagName);
+                    match current {
+                        '\t' | '\u{000A}' | '\u{000C}' | ' ' if self.is_appropriate_end_tag() => {
+                            self.state = TokenizationState::BeforeAttributeName
+                        } // tab, LF, FF, SPACE
+                        '/' if self.is_appropriate_end_tag() => self.state = TokenizationState::SelfClosingStartTag, // /
+                        '>' if self.is_appropriate_end_tag() => {
+                            self.state = TokenizationState::Data;
+                            break;
+                        } // >
+                        'A'..='Z' => {
+                            push_limited(&mut self.tag_name_buf, char::to_ascii_lowercase(&current), self.limits.max_name_length);
+                            self.temp_buf.push(current);
+                        } // A - Z
+                        'a'..='z' => {
+                            push_limited(&mut self.tag_name_buf, current, self.limits.max_name_length);
+                            self.temp_buf.push(current);
+                        } // a - z
+                        _ => {
+                            // Not the end of the text after all, so everything since the '<' is emitted as text.
+                            self.state = if rcdata { TokenizationState::RCDATA } else { TokenizationState::RAWTEXT };
+                            self.tag_kind = TagKind::StartTag;
+                            self.tag_name_buf.clear();
+                            self.pending.push_back(Token::Character('/'));
+                            self.pending.extend(self.temp_buf.chars().map(Token::Character));
+                            self.position -= current.len_utf8();
+                            return Some(Ok(Token::Character('<')));
+                        }
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#bogus-comment-state
+                TokenizationState::BogusComment => {
+                    match current {
+                        '>' => {
+                            self.state = TokenizationState::Data;
+                            return Some(Ok(Token::Comment(std::mem::take(&mut self.comment_buf))));
+                        } // >
+                        '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
+                            self.comment_buf.push('\u{FFFD}');
+                        } // NULL, Parse error
+                        _ => self.comment_buf.push(current),
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#markup-declaration-open-state
+                TokenizationState::MarkupDeclarationOpen => {
+                    // The current character is the first one after "<!", so look ahead starting from it.
+                    let start = self.position - current.len_utf8();
+                    let rest = &self.document.raw.as_bytes()[start..];
+                    let starts_with = |keyword: &[u8]| rest.len() >= keyword.len() && rest[..keyword.len()].eq_ignore_ascii_case(keyword);
+                    if rest.starts_with(b"--") {
+                        self.comment_buf.clear();
+                        self.position = start + 2;
+                            self.state = TokenizationState::CommentStart;
+                    } else if starts_with(b"doctype") {
+                        self.position = start + 7;
+                            self.state = TokenizationState::DOCTYPE;
+                    } else if !self.input_closed && rest.len() < 7
+                        && (b"--".starts_with(rest) || b"doctype"[..rest.len()].eq_ignore_ascii_case(rest) || b"[CDATA[".starts_with(rest)) {
+                        // Not enough input to decide yet, wait for the next chunk.
+                        self.position = start;
+                        self.waiting = true;
+                        return None;
+                    } else {
+                        // NOTE: CDATA sections are only allowed in foreign content, which the tree builder doesn't support,
+                        // so "[CDATA[" always ends up as a bogus comment.
+                        self.error(if rest.starts_with(b"[CDATA[") { TokenizerErrorCode::CdataInHtmlContent } else { TokenizerErrorCode::IncorrectlyOpenedComment });
+                        self.comment_buf.clear();
+                        self.state = TokenizationState::BogusComment;
+                        reconsume = true;
+                    } // Parse error
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#comment-start-state
+                TokenizationState::CommentStart => {
+                    match current {
+                        '-' => self.state = TokenizationState::CommentStartDash, // -
+                        '>' => {
+                            self.error(TokenizerErrorCode::AbruptClosingOfEmptyComment);
+                            self.state = TokenizationState::Data;
+                            return Some(Ok(Token::Comment(std::mem::take(&mut self.comment_buf))));
+                        } // >, Parse error
+                        _ => {
+                            self.state = TokenizationState::Comment;
+                            reconsume = true;
+                        }
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#comment-start-dash-state
+                TokenizationState::CommentStartDash => {
+                    match current {
+                        '-' => self.state = TokenizationState::CommentEnd, // -
+                        '>' => {
+                            self.error(TokenizerErrorCode::AbruptClosingOfEmptyComment);
+                            self.state = TokenizationState::Data;
+                            return Some(Ok(Token::Comment(std::mem::take(&mut self.comment_buf))));
+                        } // >, Parse error
+                        _ => {
+                            self.comment_buf.push('-');
+                            self.state = TokenizationState::Comment;
+                            reconsume = true;
+                        }
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#comment-state
+                TokenizationState::Comment => {
+                    match current {
+                        '-' => self.state = TokenizationState::CommentEndDash, // -
+                        '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
+                            self.comment_buf.push('\u{FFFD}');
+                        } // NULL, Parse error
+                        _ => self.comment_buf.push(current),
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#comment-end-dash-state
+                TokenizationState::CommentEndDash => {
+                    match current {
+                        '-' => self.state = TokenizationState::CommentEnd, // -
+                        _ => {
+                            self.comment_buf.push('-');
+                            self.state = TokenizationState::Comment;
+                            reconsume = true;
+                        }
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#comment-end-state
+                TokenizationState::CommentEnd => {
+                    match current {
+                        '>' => {
+                            self.state = TokenizationState::Data;
+                            return Some(Ok(Token::Comment(std::mem::take(&mut self.comment_buf))));
+                        } // >
+                        '!' => self.state = TokenizationState::CommentEndBang, // !
+                        '-' => self.comment_buf.push('-'), // -
+                        _ => {
+                            self.comment_buf.push_str("--");
+                            self.state = TokenizationState::Comment;
+                            reconsume = true;
+                        }
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#comment-end-bang-state
+                TokenizationState::CommentEndBang => {
+                    match current {
+                        '-' => {
+                            self.comment_buf.push_str("--!");
+                            self.state = TokenizationState::CommentEndDash;
+                        } // -
+                        '>' => {
+                            self.error(TokenizerErrorCode::IncorrectlyClosedComment);
+                            self.state = TokenizationState::Data;
+                            return Some(Ok(Token::Comment(std::mem::take(&mut self.comment_buf))));
+                        } // >, Parse error
+                        _ => {
+                            self.comment_buf.push_str("--!");
+                            self.state = TokenizationState::Comment;
+                            reconsume = true;
+                        }
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#doctype-state
+                TokenizationState::DOCTYPE => {
+                    self.doctype_name_buf = None;
+                    self.force_quirks = false;
+                    match current {
+                        '\t' | '\u{000A}' | '\u{000C}' | ' ' => self.state = TokenizationState::BeforeDOCTYPEName, // tab, LF, FF, Space
+                        _ => {
+                            if current != '>' {
+                                self.error(TokenizerErrorCode::MissingWhitespaceBeforeDoctypeName);
+                            }
+                            self.state = TokenizationState::BeforeDOCTYPEName;
+                            reconsume = true;
+                        } // Parse error if not '>'.
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#before-doctype-name-state
+                TokenizationState::BeforeDOCTYPEName => {
+                    match current {
+                        '\t' | '\u{000A}' | '\u{000C}' | ' ' => (), // tab, LF, FF, Space
+                        '>' => {
+                            self.error(TokenizerErrorCode::MissingDoctypeName);
+                            self.force_quirks = true;
+                            self.state = TokenizationState::Data;
+                            return Some(Ok(self.emit_doctype()));
+                        } // >, Parse error
+                        '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
+                            self.doctype_name_buf = Some(String::from('\u{FFFD}'));
+                            self.state = TokenizationState::DOCTYPEName;
+                        } // NULL, Parse error
+                        _ => {
+                            self.doctype_name_buf = Some(String::from(char::to_ascii_lowercase(&current)));
+                            self.state = TokenizationState::DOCTYPEName;
+                        }
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#doctype-name-state
+                TokenizationState::DOCTYPEName => {
+                    match current {
+                        '\t' | '\u{000A}' | '\u{000C}' | ' ' => self.state = TokenizationState::AfterDOCTYPEName, // tab, LF, FF, Space
+                        '>' => {
+                            self.state = TokenizationState::Data;
+                            return Some(Ok(self.emit_doctype()));
+                        } // >
+                        '\0' => {
+                            self.error(TokenizerErrorCode::UnexpectedNullCharacter);
+                            self.doctype_name_buf.get_or_insert_with(String::new).push('\u{FFFD}');
+                        } // NULL, Parse error
+                        _ => self.doctype_name_buf.get_or_insert_with(String::new).push(char::to_ascii_lowercase(&current)),
+                    }
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#after-doctype-name-state
+                TokenizationState::AfterDOCTYPEName => {
+                    match current {
+                        '\t' | '\u{000A}' | '\u{000C}' | ' ' => (), // tab, LF, FF, Space
+                        '>' => {
+                            self.state = TokenizationState::Data;
+                            return Some(Ok(self.emit_doctype()));
+                        } // >
+                        // TODO : PUBLIC and SYSTEM identifiers, for now they are skipped over like a bogus DOCTYPE.
+                        _ => {
+                            let start = self.position - current.len_utf8();
+                            let keyword = self.document.raw.as_bytes().get(start..start + 6);
+                            if !matches!(keyword, Some(keyword) if keyword.eq_ignore_ascii_case(b"public") || keyword.eq_ignore_ascii_case(b"system")) {
+                                self.error(TokenizerErrorCode::InvalidCharacterSequenceAfterDoctypeName);
+                                self.force_quirks = true;
+                            }
+                            self.state = TokenizationState::BogusDOCTYPE;
+                        }
+                    }
+                }
+                // These states are only reachable through Tokenizer::switch_to. The rest of the DOCTYPE is skipped.
+                TokenizationState::AfterDOCTYPEPublicKeyword | TokenizationState::BeforeDOCTYPEPublicIdentifier
+                | TokenizationState::DOCTYPEPublicIdentifierDoubleQuoted | TokenizationState::DOCTYPEPublicIdentifierSingleQuoted
+                | TokenizationState::AfterDOCTYPEPublicIdentifier | TokenizationState::BetweenDOCTYPEPublicAndSystemIdentifiers
+                | TokenizationState::AfterDOCTYPESystemKeyword | TokenizationState::BeforeDOCTYPESystemIdentifier
+                | TokenizationState::DOCTYPESystemIdentifierDoubleQuoted | TokenizationState::DOCTYPESystemIdentifierSingleQuoted
+                | TokenizationState::AfterDOCTYPESystemIdentifier => {
+                    let state = std::mem::replace(&mut self.state, TokenizationState::BogusDOCTYPE);
+                    self.position -= current.len_utf8();
+                    return Some(Err(HtmlTokenizerError::Unsupported(state)));
+                }
+                //https://html.spec.whatwg.org/multipage/parsing.html#bogus-doctype-state
+                TokenizationState::BogusDOCTYPE => {
+                    match current {
+                        '>' => {
+                            self.state = TokenizationState::Data;
+                            return Some(Ok(self.emit_doctype()));
+                        } // >
+                        '\0' => self.error(TokenizerErrorCode::UnexpectedNullCharacter), // NULL, Parse error
+                        _ => (),
+                    }
+                }
+                // Only reachable through Tokenizer::switch_to, see the markup declaration open state.
+                TokenizationState::CDATASection => {
+                    self.state = TokenizationState::Data;
+                    self.position -= current.len_utf8();
+                    return Some(Err(HtmlTokenizerError::Unsupported(TokenizationState::CDATASection)));
+                }
             }
         }
 
+        self.attributes_buf.truncate(self.limits.max_attributes);
+        self.remove_duplicate_attributes();
+        if let TagKind::EndTag = self.tag_kind {
+            if !self.attributes_buf.is_empty() {
+                self.error(TokenizerErrorCode::EndTagWithAttributes);
+            }
+            if self.is_self_closing {
+                self.error(TokenizerErrorCode::EndTagWithTrailingSolidus);
+            }
+        }
         let output = if let TagKind::StartTag = self.tag_kind {
+            self.last_start_tag.clone_from(&self.tag_name_buf);
             Some(Ok(Token::StartTag(self.tag_name_buf.clone(), self.is_self_closing, Vec::from(&mut self.attributes_buf[..]))))
         } else {            
             // The tag kind needs to be reset after every end tag.
@@ -404,6 +1375,6 @@ impl Iterator for Tokenizer {
             Some(Ok(Token::EndTag(self.tag_name_buf.clone(), self.is_self_closing, Vec::from(&mut self.attributes_buf[..]))))
         };
         self.reset();
-        return output;
+        output
     }
 }
@@ -0,0 +1,292 @@
+// Runs the html5lib tokenizer tests : https://github.com/html5lib/html5lib-tests/tree/master/tokenizer
+//
+// The files in `tests/html5lib-tests/tokenizer` are read by default, set HTML5LIB_TESTS to the root of
+// an html5lib-tests checkout to run the full suite instead. The harness only reports how many tests
+// pass, it doesn't fail on conformance failures. Set HTML5LIB_VERBOSE to list the failing tests.
+//
+// Of the vendored tests, these fail :
+// - test1.test "Entity with trailing semicolon (1)" : `&not;` isn't in the partial table of named character
+//   references, so it is left as text and reported as an unknown reference.
+// - domjs.test "CR in comment" : the input isn't preprocessed, so carriage returns aren't turned into line feeds.
+// domjs.test "Lone surrogate" is skipped, a lone surrogate can't be put in a Rust string.
+//
+// `cargo test --test html5lib_tokenizer -- --nocapture`
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use html_parser::{error::HtmlTokenizerError, preproccesor::PreProccessor, states::TokenizationState, tokenizer::{Token, Tokenizer}};
+use miniserde::json::{self, Value};
+
+#[derive(Debug, PartialEq)]
+enum TestToken {
+    // Name, public id, system id, correctness (the opposite of force-quirks).
+    Doctype(Option<String>, Option<String>, Option<String>, bool),
+    StartTag(String, BTreeMap<String, String>, bool),
+    EndTag(String),
+    Comment(String),
+    Character(String),
+}
+
+struct TestCase {
+    description : String,
+    input : String,
+    output : Vec<TestToken>,
+    errors : Vec<String>,
+    initial_states : Vec<TokenizationState>,
+    last_start_tag : Option<String>,
+}
+
+#[derive(Default)]
+struct Summary {
+    total : usize,
+    output_passed : usize,
+    errors_passed : usize,
+    skipped : usize,
+}
+
+// Cases that can't be run, with the reason.
+type Skip = String;
+
+fn tests_dir() -> PathBuf {
+    match std::env::var_os("HTML5LIB_TESTS") {
+        Some(root) => PathBuf::from(root).join("tokenizer"),
+        None => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/html5lib-tests/tokenizer"),
+    }
+}
+
+fn initial_state(name : &str) -> Option<TokenizationState> {
+    match name {
+        "Data state" => Some(TokenizationState::Data),
+        "PLAINTEXT state" => Some(TokenizationState::PLAINTEXT),
+        "RCDATA state" => Some(TokenizationState::RCDATA),
+        "RAWTEXT state" => Some(TokenizationState::RAWTEXT),
+        "Script data state" => Some(TokenizationState::ScriptData),
+        "CDATA section state" => Some(TokenizationState::CDATASection),
+        _ => None,
+    }
+}
+
+// Undo the extra level of escaping of `doubleEscaped` tests. Lone surrogates can't be put in a Rust string,
+// so tests that contain them are skipped.
+fn unescape(value : &str) -> Result<String, Skip> {
+    let mut units = Vec::new();
+    let mut rest = value;
+    while let Some(index) = rest.find("\\u") {
+        units.extend(rest[..index].encode_utf16());
+        let code = rest.get(index + 2..index + 6).and_then(|hex| u16::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| format!("bad escape in {:?}", value))?;
+        units.push(code);
+        rest = &rest[index + 6..];
+    }
+    units.extend(rest.encode_utf16());
+    String::from_utf16(&units).map_err(|_| String::from("contains a lone surrogate"))
+}
+
+fn string(value : &Value, double_escaped : bool) -> Result<String, Skip> {
+    match value {
+        Value::String(s) if double_escaped => unescape(s),
+        Value::String(s) => Ok(s.clone()),
+        _ => Err(format!("expected a string, found {:?}", value)),
+    }
+}
+
+fn optional_string(value : &Value, double_escaped : bool) -> Result<Option<String>, Skip> {
+    match value {
+        Value::Null => Ok(None),
+        value => string(value, double_escaped).map(Some),
+    }
+}
+
+fn expected_token(value : &Value, double_escaped : bool) -> Result<TestToken, Skip> {
+    let fields = match value {
+        Value::Array(fields) if !fields.is_empty() => fields,
+        _ => return Err(format!("expected a token, found {:?}", value)),
+    };
+    let field = |index : usize| fields.get(index).unwrap_or(&Value::Null);
+    let token = match string(field(0), false)?.as_str() {
+        "DOCTYPE" => TestToken::Doctype(
+            optional_string(field(1), double_escaped)?,
+            optional_string(field(2), double_escaped)?,
+            optional_string(field(3), double_escaped)?,
+            matches!(field(4), Value::Bool(true)),
+        ),
+        "StartTag" => {
+            let mut attributes = BTreeMap::new();
+            if let Value::Object(object) = field(2) {
+                for (name, value) in object.iter() {
+                    attributes.insert(if double_escaped { unescape(name)? } else { name.clone() }, string(value, double_escaped)?);
+                }
+            }
+            TestToken::StartTag(string(field(1), double_escaped)?, attributes, matches!(field(3), Value::Bool(true)))
+        },
+        "EndTag" => TestToken::EndTag(string(field(1), double_escaped)?),
+        "Comment" => TestToken::Comment(string(field(1), double_escaped)?),
+        "Character" => TestToken::Character(string(field(1), double_escaped)?),
+        kind => return Err(format!("unknown token kind {}", kind)),
+    };
+    Ok(token)
+}
+
+fn test_case(value : &Value) -> Result<TestCase, Skip> {
+    let object = match value {
+        Value::Object(object) => object,
+        _ => return Err(String::from("test is not an object")),
+    };
+    let double_escaped = matches!(object.get("doubleEscaped"), Some(Value::Bool(true)));
+    let description = match object.get("description") {
+        Some(Value::String(description)) => description.clone(),
+        _ => String::new(),
+    };
+    let input = string(object.get("input").unwrap_or(&Value::Null), double_escaped)?;
+
+    let mut output = Vec::new();
+    if let Some(Value::Array(tokens)) = object.get("output") {
+        for token in tokens.iter() {
+            push_token(&mut output, expected_token(token, double_escaped)?);
+        }
+    }
+    let mut errors = Vec::new();
+    if let Some(Value::Array(list)) = object.get("errors") {
+        for error in list.iter() {
+            if let Value::Object(error) = error {
+                errors.push(string(error.get("code").unwrap_or(&Value::Null), false)?);
+            }
+        }
+    }
+    let mut initial_states = Vec::new();
+    match object.get("initialStates") {
+        Some(Value::Array(states)) => for state in states.iter() {
+            let name = string(state, false)?;
+            initial_states.push(initial_state(&name).ok_or_else(|| format!("unknown initial state {:?}", name))?);
+        },
+        _ => initial_states.push(TokenizationState::Data),
+    }
+    let last_start_tag = match object.get("lastStartTag") {
+        Some(Value::String(tag)) => Some(tag.clone()),
+        _ => None,
+    };
+    Ok(TestCase { description, input, output, errors, initial_states, last_start_tag })
+}
+
+// Consecutive characters are a single token in the expected output.
+fn push_token(tokens : &mut Vec<TestToken>, token : TestToken) {
+    if let (Some(TestToken::Character(text)), TestToken::Character(more)) = (tokens.last_mut(), &token) {
+        text.push_str(more);
+    } else {
+        tokens.push(token);
+    }
+}
+
+fn convert(token : Token) -> Option<TestToken> {
+    match token {
+        Token::DOCTYPE(name, public, system, force_quirks) => Some(TestToken::Doctype(name, public, system, !force_quirks)),
+        Token::Character(c) => Some(TestToken::Character(String::from(c))),
+        Token::StartTag(name, self_closing, attributes) => {
+            let attributes = attributes.into_iter().map(|attribute| (attribute.name, attribute.value)).collect();
+            Some(TestToken::StartTag(name, attributes, self_closing))
+        },
+        Token::EndTag(name, _, _) => Some(TestToken::EndTag(name)),
+        Token::Comment(data) => Some(TestToken::Comment(data)),
+        Token::EOF => None,
+    }
+}
+
+// Returns the tokens, or the reason the tokenizer gave up, and the parse errors.
+fn run(test : &TestCase, state : TokenizationState) -> (Result<Vec<TestToken>, String>, Vec<String>) {
+    let mut tokenizer = Tokenizer::new(PreProccessor::new(&test.input).unwrap());
+    tokenizer.record_errors();
+    tokenizer.switch_to(state);
+    if let Some(tag) = &test.last_start_tag {
+        tokenizer.set_last_start_tag(tag);
+    }
+
+    let mut tokens = Vec::new();
+    let mut failure = None;
+    for result in tokenizer.by_ref() {
+        match result {
+            Ok(token) | Err(HtmlTokenizerError::UndefinedError(token)) => {
+                if let Some(token) = convert(token) {
+                    push_token(&mut tokens, token);
+                }
+            },
+            Err(error) => {
+                failure.get_or_insert_with(|| error.to_string());
+            },
+        }
+    }
+    let errors = tokenizer.take_errors().iter().map(|code| code.as_str().to_string()).collect();
+    (failure.map_or(Ok(tokens), Err), errors)
+}
+
+fn run_file(path : &PathBuf, verbose : bool) -> Summary {
+    let source = std::fs::read_to_string(path).unwrap();
+    let root : Value = json::from_str(&source).unwrap_or_else(|_| panic!("{} is not valid JSON", path.display()));
+    let tests = match &root {
+        Value::Object(root) => match root.get("tests") {
+            Some(Value::Array(tests)) => tests.iter().collect(),
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    };
+
+    let mut summary = Summary::default();
+    for value in tests {
+        let test = match test_case(value) {
+            Ok(test) => test,
+            Err(reason) => {
+                summary.skipped += 1;
+                if verbose {println!("  skipped : {}", reason);}
+                continue;
+            },
+        };
+        for state in &test.initial_states {
+            summary.total += 1;
+            let (output, mut errors) = run(&test, *state);
+            match output {
+                Ok(output) if output == test.output => summary.output_passed += 1,
+                Ok(output) => if verbose {
+                    println!("  FAIL {} ({:?}) {:?}\n    expected {:?}\n    actual   {:?}", test.description, state, test.input, test.output, output);
+                },
+                Err(error) => if verbose {
+                    println!("  FAIL {} ({:?}) {:?}\n    {}", test.description, state, test.input, error);
+                },
+            }
+            // Only the error codes are compared, the tokenizer doesn't keep track of positions.
+            let mut expected = test.errors.clone();
+            expected.sort();
+            errors.sort();
+            if errors == expected {
+                summary.errors_passed += 1;
+            } else if verbose {
+                println!("  ERRORS {} ({:?}) {:?}\n    expected {:?}\n    actual   {:?}", test.description, state, test.input, expected, errors);
+            }
+        }
+    }
+    summary
+}
+
+#[test]
+fn html5lib_tokenizer() {
+    let verbose = std::env::var_os("HTML5LIB_VERBOSE").is_some();
+    let mut files : Vec<PathBuf> = std::fs::read_dir(tests_dir()).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "test"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "no tokenizer tests in {}", tests_dir().display());
+
+    let mut all = Summary::default();
+    for path in &files {
+        let name = path.file_name().unwrap().to_string_lossy();
+        if verbose {println!("{}", name);}
+        let summary = run_file(path, verbose);
+        println!("{:<32} output {:>5}/{:<5} errors {:>5}/{:<5} skipped {}",
+            name, summary.output_passed, summary.total, summary.errors_passed, summary.total, summary.skipped);
+        all.total += summary.total;
+        all.output_passed += summary.output_passed;
+        all.errors_passed += summary.errors_passed;
+        all.skipped += summary.skipped;
+    }
+    println!("{:<32} output {:>5}/{:<5} errors {:>5}/{:<5} skipped {}",
+        "total", all.output_passed, all.total, all.errors_passed, all.total, all.skipped);
+}
@@ -0,0 +1,323 @@
+// Runs the html5lib tree construction tests : https://github.com/html5lib/html5lib-tests/tree/master/tree-construction
+//
+// Every test is parsed with HtmlParser::parse and the resulting tree is dumped in the same indented format as
+// the `#document` section. Tests that are known to fail are listed in `tests/html5lib_known_failures.txt` by
+// a hash of their `#data`, so that entries don't depend on which file a test is in or where. The harness fails
+// if any other test fails, or if one of the known failures starts passing so that it can be taken off the
+// list. Set HTML5LIB_UPDATE_KNOWN_FAILURES to rewrite the list from the current results.
+//
+// The `#errors` sections are not checked, the tree builder only reports a few of the parse errors the
+// specification names. Fragment cases and `#script-on` cases are skipped and counted apart, neither is
+// supported by the parser.
+//
+// Every entry in the list of known failures says why the test fails, currently :
+// - DOCTYPEs with a public or system identifier, which the tokenizer drops.
+// - Elements inside <svg> and <math>, foreign content isn't implemented.
+// - The contents of <template>, templates aren't implemented.
+// - Misnested formatting elements, the list of active formatting elements and the adoption agency
+//   algorithm aren't implemented.
+// - Documents without a DOCTYPE, quirks mode isn't implemented, e.g. <table> closes an open <p>.
+//
+// `tests/tree-construction` holds this crate's own cases in the same format, which are always run. Set
+// HTML5LIB_TESTS to the root of an html5lib-tests checkout to run the full suite instead of the vendored
+// subset. The known failures apply to the full suite as well, which fails until its failures are on the list.
+// Entries for tests that didn't run are left alone. Set HTML5LIB_VERBOSE to list the failing tests.
+//
+// `cargo test --test html5lib_tree_construction -- --nocapture`
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use html_parser::{dom::{Element, ElementKind}, sink::TreeSink, tokenizer::Attribute, HtmlParser, ParseState};
+
+const KNOWN_FAILURES : &str = "tests/html5lib_known_failures.txt";
+const SECTIONS : &[&str] = &["data", "errors", "new-errors", "document-fragment", "script-off", "script-on", "document"];
+
+#[derive(Default)]
+struct TestCase {
+    data : String,
+    fragment : Option<String>,
+    script_on : bool,
+    document : String,
+}
+
+enum DumpNode {
+    Document,
+    Doctype(Option<String>, Option<String>, Option<String>),
+    Element(Element),
+    Text(String),
+    Comment(String),
+}
+
+// Builds a plain tree that can be dumped, so the harness doesn't depend on how the default sink stores the DOM.
+struct DumpSink {
+    nodes : Vec<(DumpNode, Vec<usize>)>,
+}
+
+impl DumpSink {
+    fn new() -> Self {
+        Self { nodes : vec![(DumpNode::Document, Vec::new())] }
+    }
+
+    fn append(&mut self, parent : usize, node : DumpNode) -> usize {
+        self.nodes.push((node, Vec::new()));
+        let handle = self.nodes.len() - 1;
+        self.nodes[parent].1.push(handle);
+        handle
+    }
+
+    // https://github.com/html5lib/html5lib-tests/blob/master/tree-construction/README.md
+    fn dump(&self, handle : usize, depth : usize, out : &mut String) {
+        let indent = format!("| {}", "  ".repeat(depth));
+        match &self.nodes[handle].0 {
+            DumpNode::Document => (),
+            DumpNode::Doctype(name, public_id, system_id) => {
+                out.push_str(&format!("{}<!DOCTYPE {}", indent, name.as_deref().unwrap_or("")));
+                if public_id.is_some() || system_id.is_some() {
+                    out.push_str(&format!(" \"{}\" \"{}\"", public_id.as_deref().unwrap_or(""), system_id.as_deref().unwrap_or("")));
+                }
+                out.push_str(">\n");
+            },
+            DumpNode::Element(element) => {
+                let name = match element.kind {
+                    ElementKind::Svg => String::from("svg svg"),
+                    ElementKind::Math => String::from("math math"),
+                    _ => element.name().to_string(),
+                };
+                out.push_str(&format!("{}<{}>\n", indent, name));
+                let mut attributes : Vec<&Attribute> = element.attributes.iter().collect();
+                attributes.sort_by(|a, b| a.name.cmp(&b.name));
+                for attribute in attributes {
+                    out.push_str(&format!("{}  {}=\"{}\"\n", indent, attribute.name, attribute.value));
+                }
+            },
+            DumpNode::Text(text) => out.push_str(&format!("{}\"{}\"\n", indent, text)),
+            DumpNode::Comment(text) => out.push_str(&format!("{}<!-- {} -->\n", indent, text)),
+        }
+        let depth = if handle == 0 { depth } else { depth + 1 };
+        for child in &self.nodes[handle].1 {
+            self.dump(*child, depth, out);
+        }
+    }
+}
+
+impl TreeSink for DumpSink {
+    type Handle = usize;
+    type Output = String;
+
+    fn document(&self) -> usize {
+        0
+    }
+
+    fn append_element(&mut self, parent : &usize, element : Element) -> usize {
+        self.append(*parent, DumpNode::Element(element))
+    }
+
+    fn append_text(&mut self, parent : &usize, text : &str) {
+        if let Some(&last) = self.nodes[*parent].1.last() {
+            if let DumpNode::Text(existing) = &mut self.nodes[last].0 {
+                existing.push_str(text);
+                return;
+            }
+        }
+        self.append(*parent, DumpNode::Text(text.to_string()));
+    }
+
+    fn append_comment(&mut self, parent : &usize, text : &str) {
+        self.append(*parent, DumpNode::Comment(text.to_string()));
+    }
+
+    fn append_doctype(&mut self, name : Option<&str>, public_id : Option<&str>, system_id : Option<&str>) {
+        self.append(0, DumpNode::Doctype(name.map(String::from), public_id.map(String::from), system_id.map(String::from)));
+    }
+
+    fn add_attributes(&mut self, handle : &usize, attributes : Vec<Attribute>) {
+        if let DumpNode::Element(element) = &mut self.nodes[*handle].0 {
+            for attribute in attributes {
+                if element.attribute(&attribute.name).is_none() {
+                    element.attributes.push(attribute);
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> String {
+        let mut out = String::new();
+        self.dump(0, 0, &mut out);
+        out
+    }
+}
+
+// Splits a .dat file into its tests. Every section runs until the next line starting with '#', the newline
+// before the next section (or the blank line before the next test) isn't part of it.
+fn parse_dat(source : &str) -> Vec<TestCase> {
+    let mut tests = Vec::new();
+    let mut current : Option<(TestCase, String, String)> = None;
+
+    fn finish_section(test : &mut TestCase, section : &str, mut text : String) {
+        if text.ends_with('\n') {
+            text.pop();
+        }
+        match section {
+            "data" => test.data = text,
+            "document-fragment" => test.fragment = Some(text),
+            "script-on" => test.script_on = true,
+            "document" => test.document = text,
+            _ => (),
+        }
+    }
+
+    for line in source.split_inclusive('\n') {
+        let name = line.trim_end_matches('\n').strip_prefix('#');
+        match (name, &mut current) {
+            (Some("data"), _) => {
+                if let Some((mut test, section, text)) = current.take() {
+                    finish_section(&mut test, &section, text);
+                    tests.push(test);
+                }
+                current = Some((TestCase::default(), String::from("data"), String::new()));
+            },
+            (Some(name), Some((test, section, text))) if SECTIONS.contains(&name) => {
+                finish_section(test, section, std::mem::take(text));
+                *section = name.to_string();
+            },
+            (_, Some((_, _, text))) => text.push_str(line),
+            (_, None) => (),
+        }
+    }
+    if let Some((mut test, section, text)) = current.take() {
+        finish_section(&mut test, &section, text);
+        tests.push(test);
+    }
+    // The blank line that separates two tests ends up at the end of the previous test's document.
+    for test in &mut tests {
+        if test.document.ends_with('\n') {
+            test.document.pop();
+        }
+    }
+    tests
+}
+
+enum Outcome {
+    Pass,
+    Fail(String),
+    SkipFragment,
+    SkipScriptOn,
+}
+
+fn run(test : &TestCase) -> Outcome {
+    if test.fragment.is_some() {
+        return Outcome::SkipFragment;
+    }
+    if test.script_on {
+        return Outcome::SkipScriptOn;
+    }
+    match HtmlParser::parse(&test.data, ParseState::with_sink(DumpSink::new())) {
+        Ok(dump) if dump.trim_end_matches('\n') == test.document => Outcome::Pass,
+        Ok(dump) => Outcome::Fail(dump),
+        Err(error) => Outcome::Fail(format!("error : {}", error)),
+    }
+}
+
+// The directories of tests that are run, with the name they are reported under.
+fn tests_dirs() -> [(&'static str, PathBuf); 2] {
+    let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let upstream = match std::env::var_os("HTML5LIB_TESTS") {
+        Some(root) => PathBuf::from(root).join("tree-construction"),
+        None => manifest.join("tests/html5lib-tests/tree-construction"),
+    };
+    [("html5lib-tests/tree-construction", upstream), ("tree-construction", manifest.join("tests/tree-construction"))]
+}
+
+// The entry of a test in the known failures : the 64 bit FNV-1a hash of its `#data`, which unlike the hasher
+// of the standard library is the same on every Rust version.
+fn entry(test : &TestCase) -> String {
+    let hash = test.data.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    format!("{:016x}", hash)
+}
+
+// The known failures, each with the reason it fails. Lines are `<entry> # <reason>`.
+fn read_known_failures(path : &Path) -> BTreeMap<String, String> {
+    std::fs::read_to_string(path).unwrap_or_default().lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.split_once('#') {
+            Some((entry, reason)) => (entry.trim().to_string(), reason.trim().to_string()),
+            None => (line.to_string(), String::new()),
+        })
+        .collect()
+}
+
+// Keeps the reasons of the entries that are still on the list, new entries need one added by hand.
+fn write_known_failures(path : &Path, failures : &BTreeSet<String>) {
+    let known = read_known_failures(path);
+    let mut out = String::from("# Tree construction tests that are known to fail, see tests/html5lib_tree_construction.rs.\n");
+    out.push_str("# Entries are <FNV-1a hash of the #data of the test> # <why it fails>.\n");
+    for failure in failures {
+        out.push_str(failure);
+        if let Some(reason) = known.get(failure).filter(|reason| !reason.is_empty()) {
+            out.push_str(" # ");
+            out.push_str(reason);
+        }
+        out.push('\n');
+    }
+    std::fs::write(path, out).unwrap();
+}
+
+#[test]
+fn html5lib_tree_construction() {
+    let verbose = std::env::var_os("HTML5LIB_VERBOSE").is_some();
+    let mut failures = BTreeSet::new();
+    // The entries of every test that ran, passed or not.
+    let mut ran = BTreeSet::new();
+    let (mut total, mut passed, mut fragments, mut script_on) = (0, 0, 0, 0);
+    for (prefix, dir) in &tests_dirs() {
+        let mut files : Vec<PathBuf> = std::fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "dat"))
+            .collect();
+        files.sort();
+        assert!(!files.is_empty(), "no tree construction tests in {}", dir.display());
+
+        for path in &files {
+            let name = format!("{}/{}", prefix, path.file_name().unwrap().to_string_lossy());
+            let tests = parse_dat(&std::fs::read_to_string(path).unwrap());
+            let (mut file_passed, mut file_fragments, mut file_script_on) = (0, 0, 0);
+            for (index, test) in tests.iter().enumerate() {
+                let outcome = run(test);
+                if matches!(outcome, Outcome::Pass | Outcome::Fail(_)) {
+                    ran.insert(entry(test));
+                }
+                match outcome {
+                    Outcome::Pass => file_passed += 1,
+                    Outcome::SkipFragment => file_fragments += 1,
+                    Outcome::SkipScriptOn => file_script_on += 1,
+                    Outcome::Fail(actual) => {
+                        failures.insert(entry(test));
+                        if verbose {
+                            println!("FAIL {}:{} ({})\n{}\nexpected :\n{}\nactual :\n{}", name, index, entry(test), test.data, test.document, actual);
+                        }
+                    },
+                }
+            }
+            let file_total = tests.len() - file_fragments - file_script_on;
+            println!("{:<48} passed {:>5}/{:<5} skipped {} fragment, {} script-on", name, file_passed, file_total, file_fragments, file_script_on);
+            total += file_total;
+            passed += file_passed;
+            fragments += file_fragments;
+            script_on += file_script_on;
+        }
+    }
+    println!("{:<48} passed {:>5}/{:<5} skipped {} fragment, {} script-on", "total", passed, total, fragments, script_on);
+
+    let known_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(KNOWN_FAILURES);
+    let known = read_known_failures(&known_path);
+    if std::env::var_os("HTML5LIB_UPDATE_KNOWN_FAILURES").is_some() {
+        failures.extend(known.keys().filter(|entry| !ran.contains(*entry)).cloned());
+        write_known_failures(&known_path, &failures);
+        return;
+    }
+    let new_failures : Vec<&String> = failures.iter().filter(|test| !known.contains_key(*test)).collect();
+    let fixed : Vec<&String> = known.keys().filter(|test| ran.contains(*test) && !failures.contains(*test)).collect();
+    assert!(new_failures.is_empty(), "tests that are not on the list of known failures failed : {:?}", new_failures);
+    assert!(fixed.is_empty(), "these tests pass now, take them off {} : {:?}", KNOWN_FAILURES, fixed);
+}
@@ -1,4 +1,4 @@
-use html_parser::{tokenizer::Tokenizer, preproccesor::PreProccessor};
+use html_parser::{tokenizer::Tokenizer, preproccesor::PreProccessor, HtmlParser, ParseState};
 use simplelog::*;
 use log::*;
 
@@ -16,13 +16,13 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("{:?}", token.unwrap());
     }
 
-    /* Parser Test
+    // Parser Test
     let doc = HtmlParser::parse("<html><head></head><body></body></html>", ParseState::new())?;
-    for node in doc.iter() {
-        info!("{:#?}", node);
-        debug!("{:?}", node.get());
+    for node in doc.descendants(doc.root()) {
+        debug!("{:?}", doc.get(node).unwrap());
     }
-    */
+    let names: Vec<&str> = doc.descendants(doc.root()).filter_map(|node| doc.get(node)?.as_element()).map(|e| e.name()).collect();
+    assert_eq!(names, ["html", "head", "body"]);
 
     Ok(())
 }
\ No newline at end of file